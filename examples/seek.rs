@@ -1,6 +1,6 @@
 use std::fs::File;
-use std::io::{self, Seek, SeekFrom};
-use tokio::io::AsyncWriteExt;
+use std::io::{self, SeekFrom};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -9,9 +9,9 @@ async fn main() -> io::Result<()> {
     let mut file = tokio_file_unix::File::new_nb(file)?;
 
     file.write_all(b"aaaaAAAAaaaaAAAA\n").await?;
-    file.get_mut().seek(SeekFrom::Start(8))?;
+    file.seek(SeekFrom::Start(8)).await?;
     file.write_all(&[b'b'; 8]).await?;
-    file.get_mut().seek(SeekFrom::Start(2))?;
+    file.seek(SeekFrom::Start(2)).await?;
     file.write_all(&[b'c'; 4]).await?;
 
     Ok(())
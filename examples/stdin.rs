@@ -1,6 +1,5 @@
+use futures::StreamExt;
 use std::io;
-use tokio::stream::StreamExt;
-use tokio_util::codec::{FramedRead, LinesCodec};
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
@@ -9,10 +8,10 @@ async fn main() -> io::Result<()> {
     let file = tokio_file_unix::raw_stdin()?;
     let file = tokio_file_unix::File::new_nb(file)?;
 
-    let mut framed = FramedRead::new(file, LinesCodec::new());
+    let mut lines = file.lines(64 * 1024);
 
     println!("Type something and hit enter!");
-    while let Some(got) = framed.next().await {
+    while let Some(got) = lines.next().await {
         println!("Got: {:?}", got);
     }
 
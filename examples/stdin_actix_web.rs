@@ -1,13 +1,13 @@
-use actix_web::client::Client;
 use actix_web::{get, web, App, HttpServer, Responder};
+use awc::Client;
 use futures::future::FutureExt;
+use futures::StreamExt;
 use futures::{pin_mut, select};
 use std::{error, io};
-use tokio::stream::StreamExt;
 use tokio_util::codec::{FramedRead, LinesCodec};
 
 fn stringify_error<E: error::Error>(e: E) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, e.to_string())
+    io::Error::other(e.to_string())
 }
 
 #[get("/{something}")]
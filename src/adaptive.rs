@@ -0,0 +1,146 @@
+//! Read buffers that adapt their size to the traffic.
+
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::RawFd;
+
+/// The number of consecutive reads that fill less than a quarter of the
+/// buffer after which it shrinks.
+const SHRINK_AFTER: u32 = 4;
+
+/// A read buffer that starts small, doubles whenever a read fills it, and
+/// halves again after a few reads that use little of it, so that thousands
+/// of mostly idle pipes do not each hold a large buffer while a busy one
+/// still gets few, large reads.
+///
+/// Resizing only happens in `prepare`, which must be called while the
+/// buffer holds no unconsumed data.
+#[derive(Debug)]
+pub(crate) struct AdaptiveBuf {
+    buf: Box<[u8]>,
+    target: usize,
+    min: usize,
+    max: usize,
+    small_reads: u32,
+}
+
+impl AdaptiveBuf {
+    /// Creates a buffer of `min` bytes that may grow up to `max` bytes.
+    pub(crate) fn new(min: usize, max: usize) -> Self {
+        let min = min.clamp(1, max.max(1));
+        AdaptiveBuf {
+            buf: vec![0; min].into_boxed_slice(),
+            target: min,
+            min,
+            max: max.max(min),
+            small_reads: 0,
+        }
+    }
+
+    /// Applies any pending resize before the next read.  If `fd` is given,
+    /// the number of bytes it has waiting (`FIONREAD`) lets the buffer grow
+    /// right away instead of over several reads.
+    pub(crate) fn prepare(&mut self, fd: Option<RawFd>) {
+        if let Some(available) = fd.and_then(pending_bytes) {
+            if available > self.target {
+                self.target = available.next_power_of_two().min(self.max);
+            }
+        }
+        self.resize(self.target);
+    }
+
+    /// Grows the buffer to at least `len` bytes, within the maximum.
+    pub(crate) fn ensure(&mut self, len: usize) {
+        if self.buf.len() < len {
+            self.target = len.min(self.max);
+            self.resize(self.target);
+        }
+    }
+
+    /// Records that a read returned `n` bytes.
+    pub(crate) fn record(&mut self, n: usize) {
+        let len = self.buf.len();
+        if n >= len {
+            self.small_reads = 0;
+            self.target = (len * 2).min(self.max);
+        } else if n < len / 4 {
+            self.small_reads += 1;
+            if self.small_reads >= SHRINK_AFTER {
+                self.small_reads = 0;
+                self.target = (len / 2).max(self.min);
+            }
+        } else {
+            self.small_reads = 0;
+        }
+    }
+
+    fn resize(&mut self, len: usize) {
+        if self.buf.len() != len {
+            self.buf = vec![0; len].into_boxed_slice();
+        }
+    }
+}
+
+impl Deref for AdaptiveBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for AdaptiveBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+/// The number of bytes that can be read from `fd` right away, if known.
+fn pending_bytes(fd: RawFd) -> Option<usize> {
+    let mut n: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, libc::FIONREAD, &mut n) } < 0 || n < 0 {
+        return None;
+    }
+    Some(n as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_buf() {
+        let mut buf = AdaptiveBuf::new(512, 4096);
+        assert_eq!(buf.len(), 512);
+        buf.record(512);
+        buf.prepare(None);
+        assert_eq!(buf.len(), 1024);
+        for _ in 0..3 {
+            let len = buf.len();
+            buf.record(len);
+            buf.prepare(None);
+        }
+        assert_eq!(buf.len(), 4096);
+        for _ in 0..SHRINK_AFTER {
+            buf.record(10);
+            buf.prepare(None);
+        }
+        assert_eq!(buf.len(), 2048);
+        for _ in 0..10 * SHRINK_AFTER {
+            buf.record(0);
+            buf.prepare(None);
+        }
+        assert_eq!(buf.len(), 512);
+    }
+
+    #[test]
+    fn test_adaptive_buf_hint() -> std::io::Result<()> {
+        use std::io::Write;
+        use std::os::unix::io::AsRawFd;
+        let (mut a, b) = std::os::unix::net::UnixStream::pair()?;
+        a.write_all(&[0; 3000])?;
+        let mut buf = AdaptiveBuf::new(512, 65536);
+        buf.prepare(Some(b.as_raw_fd()));
+        assert_eq!(buf.len(), 4096);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Preallocating space and advising the page cache (`fallocate(2)` and
+//! `posix_fadvise(2)`).
+
+use crate::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+flags! {
+    /// Modes for [`File::allocate`](struct.File.html#method.allocate),
+    /// combined with `|`.
+    pub struct AllocateMode(libc::c_int);
+
+    /// Allocate the range, growing the file if it extends past the end.
+    const NONE = 0;
+    /// Do not change the size of the file, even if the range extends past
+    /// the end (`FALLOC_FL_KEEP_SIZE`).
+    const KEEP_SIZE = libc::FALLOC_FL_KEEP_SIZE;
+    /// Deallocate the range, which then reads as zeros; must be combined
+    /// with `KEEP_SIZE` (`FALLOC_FL_PUNCH_HOLE`).
+    const PUNCH_HOLE = libc::FALLOC_FL_PUNCH_HOLE;
+    /// Zero the range, allocating it if need be (`FALLOC_FL_ZERO_RANGE`).
+    const ZERO_RANGE = libc::FALLOC_FL_ZERO_RANGE;
+}
+
+/// How a range of a file is going to be accessed, for
+/// [`File::advise`](struct.File.html#method.advise).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Advice {
+    /// No particular pattern (`POSIX_FADV_NORMAL`).
+    Normal,
+    /// From start to end, so read ahead more (`POSIX_FADV_SEQUENTIAL`).
+    Sequential,
+    /// In no particular order, so do not read ahead (`POSIX_FADV_RANDOM`).
+    Random,
+    /// Only once (`POSIX_FADV_NOREUSE`).
+    NoReuse,
+    /// Soon, so start reading it in (`POSIX_FADV_WILLNEED`).
+    WillNeed,
+    /// Not again, so drop it from the page cache once written back
+    /// (`POSIX_FADV_DONTNEED`).
+    DontNeed,
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Allocates or, depending on `mode`, deallocates or zeroes `len` bytes
+    /// of the file starting at `offset`, on the blocking thread pool.
+    ///
+    /// Preallocating the space a log is going to take up means that writes
+    /// cannot fail for lack of space and that the file is less fragmented.
+    /// With `RegularFileMode::ThreadPool`, pending writes are flushed first.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn allocate(&self, offset: u64, len: u64, mode: AllocateMode) -> io::Result<()> {
+        self.offload(move |file| {
+            let r = unsafe {
+                libc::fallocate(
+                    file.as_raw_fd(),
+                    mode.0,
+                    offset as libc::off_t,
+                    len as libc::off_t,
+                )
+            };
+            if r < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Tells the kernel how `len` bytes of the file starting at `offset`, or
+    /// everything after `offset` if `len` is 0, are going to be accessed.
+    ///
+    /// This is only a hint and returns right away, except that
+    /// `Advice::WillNeed` may start reading in the data.
+    pub fn advise(&self, offset: u64, len: u64, advice: Advice) -> io::Result<()> {
+        let advice = match advice {
+            Advice::Normal => libc::POSIX_FADV_NORMAL,
+            Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Advice::Random => libc::POSIX_FADV_RANDOM,
+            Advice::NoReuse => libc::POSIX_FADV_NOREUSE,
+            Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+            Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+        };
+        // reports the error number instead of setting errno
+        let e = unsafe {
+            libc::posix_fadvise(
+                self.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                advice,
+            )
+        };
+        if e != 0 {
+            return Err(io::Error::from_raw_os_error(e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+
+    #[tokio::test]
+    async fn test_allocate() -> io::Result<()> {
+        let file = File::new_nb(tempfile("allocate")?)?;
+        file.allocate(0, 4096, AllocateMode::NONE).await?;
+        assert_eq!(file.get_ref().metadata()?.len(), 4096);
+        file.allocate(4096, 4096, AllocateMode::KEEP_SIZE).await?;
+        assert_eq!(file.get_ref().metadata()?.len(), 4096);
+        file.advise(0, 0, Advice::Sequential)?;
+
+        let (reader, _writer) = crate::pipe()?;
+        let err = reader
+            .into_inner()
+            .advise(0, 0, Advice::Random)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ESPIPE));
+        Ok(())
+    }
+}
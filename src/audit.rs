@@ -0,0 +1,31 @@
+//! Audit logging of descriptor state changes (the `audit` feature).
+//!
+//! Every change the crate makes to a descriptor (nonblocking mode,
+//! registration with the reactor, duplication, and closing) is logged at
+//! debug level to the `tokio_file_unix::audit` target of the `log` crate,
+//! along with the descriptor number and the public function responsible.
+
+use std::os::unix::io::RawFd;
+
+/// Logs the closing of the descriptor owned by a `File` when dropped, unless
+/// the descriptor was handed back with `into_inner`.
+#[derive(Debug)]
+pub(crate) struct CloseLog(Option<RawFd>);
+
+impl CloseLog {
+    pub(crate) fn new(fd: RawFd) -> Self {
+        CloseLog(Some(fd))
+    }
+
+    pub(crate) fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CloseLog {
+    fn drop(&mut self) {
+        if let Some(fd) = self.0 {
+            audit!("File::drop", fd, "deregistered and closed");
+        }
+    }
+}
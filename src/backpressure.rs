@@ -0,0 +1,132 @@
+//! Measuring where a data flow waits.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Which end of a flow it is waiting for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Side {
+    Input,
+    Output,
+}
+
+/// How long a flow has waited for each of its ends, as returned by
+/// [`BackpressureMonitor::stats`](struct.BackpressureMonitor.html#method.stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BackpressureStats {
+    /// Time spent with nothing to do because the input had no data.
+    pub input_starved: Duration,
+    /// Time spent unable to move data because the output would not accept
+    /// it.
+    pub output_blocked: Duration,
+    /// The number of times the flow started waiting for the input.
+    pub input_waits: u64,
+    /// The number of times the flow started waiting for the output.
+    pub output_waits: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    stats: BackpressureStats,
+    /// The end being waited for and since when.
+    waiting: Option<(Side, Instant)>,
+}
+
+impl State {
+    fn account(&mut self, now: Instant) {
+        if let Some((side, since)) = self.waiting.take() {
+            let waited = now.saturating_duration_since(since);
+            match side {
+                Side::Input => self.stats.input_starved += waited,
+                Side::Output => self.stats.output_blocked += waited,
+            }
+        }
+    }
+}
+
+/// Records where the backpressure in a flow of data comes from: whether it
+/// is starved by its input or blocked by its output, and for how long.
+///
+/// An operator can use this to tell which side of a pipeline is the slow
+/// one.  The monitor is a cheap handle that can be cloned and read from
+/// another task while the flow is running.  Accepted by
+/// [`CopyOptions`](struct.CopyOptions.html),
+/// [`Router::monitor`](struct.Router.html#method.monitor), and
+/// [`Merger::monitor`](struct.Merger.html#method.monitor).
+#[derive(Clone, Debug, Default)]
+pub struct BackpressureMonitor {
+    state: Arc<Mutex<State>>,
+}
+
+impl BackpressureMonitor {
+    /// Creates a monitor with nothing recorded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The time waited so far, including any wait still in progress.
+    pub fn stats(&self) -> BackpressureStats {
+        let state = self.state.lock().unwrap();
+        let mut stats = state.stats;
+        if let Some((side, since)) = state.waiting {
+            match side {
+                Side::Input => stats.input_starved += since.elapsed(),
+                Side::Output => stats.output_blocked += since.elapsed(),
+            }
+        }
+        stats
+    }
+
+    /// Notes that the flow is waiting for `side`, unless it already was.
+    pub(crate) fn wait(&self, side: Side) {
+        let mut state = self.state.lock().unwrap();
+        if state.waiting.map(|(s, _)| s) == Some(side) {
+            return;
+        }
+        let now = Instant::now();
+        state.account(now);
+        state.waiting = Some((side, now));
+        match side {
+            Side::Input => state.stats.input_waits += 1,
+            Side::Output => state.stats.output_waits += 1,
+        }
+    }
+
+    /// Notes that the flow is making progress again.
+    pub(crate) fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.waiting.is_some() {
+            state.account(Instant::now());
+        }
+    }
+}
+
+/// Records a wait or progress on `monitor`, if there is one.
+pub(crate) fn note(monitor: &Option<BackpressureMonitor>, waiting: Option<Side>) {
+    if let Some(ref monitor) = *monitor {
+        match waiting {
+            Some(side) => monitor.wait(side),
+            None => monitor.resume(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monitor() {
+        let monitor = BackpressureMonitor::new();
+        monitor.wait(Side::Input);
+        std::thread::sleep(Duration::from_millis(10));
+        monitor.wait(Side::Input);
+        monitor.wait(Side::Output);
+        let stats = monitor.stats();
+        assert!(stats.input_starved >= Duration::from_millis(10));
+        assert_eq!((stats.input_waits, stats.output_waits), (1, 1));
+        monitor.resume();
+        let stats = monitor.stats();
+        assert_eq!(monitor.stats(), stats);
+    }
+}
@@ -0,0 +1,262 @@
+//! Running a future that only waits on a single descriptor, without a Tokio
+//! runtime.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::{fmt, io};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A nonblocking descriptor for use with
+/// [`block_on_file`](fn.block_on_file.html), in place of a
+/// [`File`](struct.File.html), which needs a Tokio runtime.
+///
+/// Implements `AsyncRead` and `AsyncWrite`, also through shared references,
+/// so the crate's codecs and helpers can be used on it.  When an operation
+/// would block, it notes what it is waiting for, and `block_on_file` then
+/// waits for that with `poll(2)`.
+pub struct PolledFile<F> {
+    file: F,
+    /// The `poll` events that operations are waiting for.
+    events: Cell<libc::c_short>,
+}
+
+impl<F: AsRawFd> PolledFile<F> {
+    /// Wraps `file`, turning on its nonblocking mode, which is left on.
+    pub fn new(file: F) -> io::Result<Self> {
+        crate::set_nonblocking_for("PolledFile::new", file.as_raw_fd(), true)?;
+        Ok(PolledFile {
+            file,
+            events: Cell::new(0),
+        })
+    }
+}
+
+impl<F> PolledFile<F> {
+    /// Gets a reference to the underlying file.
+    pub fn get_ref(&self) -> &F {
+        &self.file
+    }
+
+    /// Returns the underlying file.
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    fn wait_for(&self, events: libc::c_short) {
+        self.events.set(self.events.get() | events);
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for PolledFile<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PolledFile")
+            .field("file", &self.file)
+            .finish()
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for PolledFile<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// Performs a nonblocking operation, noting `events` if it would block.
+fn poll_op<F, T>(
+    file: &PolledFile<F>,
+    events: libc::c_short,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> Poll<io::Result<T>> {
+    loop {
+        match op() {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                file.wait_for(events);
+                return Poll::Pending;
+            }
+            r => return Poll::Ready(r),
+        }
+    }
+}
+
+impl<'a, F> AsyncRead for &'a PolledFile<F>
+where
+    &'a F: io::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this: &'a PolledFile<F> = *self;
+        let mut file = &this.file;
+        let n = ready!(poll_op(this, libc::POLLIN, || {
+            io::Read::read(&mut file, buf.initialize_unfilled())
+        }))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, F> AsyncWrite for &'a PolledFile<F>
+where
+    &'a F: io::Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this: &'a PolledFile<F> = *self;
+        let mut file = &this.file;
+        poll_op(this, libc::POLLOUT, || io::Write::write(&mut file, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this: &'a PolledFile<F> = *self;
+        let mut file = &this.file;
+        poll_op(this, libc::POLLOUT, || io::Write::flush(&mut file))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<F> AsyncRead for PolledFile<F>
+where
+    for<'a> &'a F: io::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self).poll_read(cx, buf)
+    }
+}
+
+impl<F> AsyncWrite for PolledFile<F>
+where
+    for<'a> &'a F: io::Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut &*self).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self).poll_shutdown(cx)
+    }
+}
+
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Runs `fut` to completion on the current thread without a Tokio runtime,
+/// for small synchronous tools that want to reuse the crate's codecs and
+/// helpers.
+///
+/// The future must only wait on `file`: whenever it is pending, this blocks
+/// in `poll(2)` until `file` is ready for what the future tried to do.  If
+/// the future is pending without having waited on `file`, for example on
+/// a timer or on a [`File`](struct.File.html), this fails with `Other`
+/// rather than block forever.
+///
+/// ```no_run
+/// # fn run() -> std::io::Result<()> {
+/// use tokio::io::AsyncBufReadExt;
+/// use tokio_file_unix::{block_on_file, PolledFile};
+///
+/// let stdin = PolledFile::new(tokio_file_unix::raw_stdin()?)?;
+/// let line = block_on_file(&stdin, async {
+///     let mut line = String::new();
+///     tokio::io::BufReader::new(&stdin).read_line(&mut line).await?;
+///     Ok::<_, std::io::Error>(line)
+/// })??;
+/// # Ok(())
+/// # }
+/// ```
+pub fn block_on_file<F, Fut>(file: &PolledFile<F>, fut: Fut) -> io::Result<Fut::Output>
+where
+    F: AsRawFd,
+    Fut: Future,
+{
+    let woken = Arc::new(Woken(AtomicBool::new(false)));
+    let waker = Waker::from(woken.clone());
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = Box::pin(fut);
+    loop {
+        file.events.set(0);
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return Ok(output);
+        }
+        if woken.0.swap(false, Ordering::SeqCst) {
+            continue;
+        }
+        let events = file.events.get();
+        if events == 0 {
+            return Err(io::Error::other(
+                "future is waiting on something other than the file",
+            ));
+        }
+        let mut pollfd = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pollfd, 1, -1) } < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BoundedLines;
+    use futures::StreamExt;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn test_block_on_file() -> io::Result<()> {
+        let (a, mut b) = UnixStream::pair()?;
+        let a = PolledFile::new(a)?;
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            b.write_all(b"one\ntwo\n")
+        });
+        let lines = block_on_file(&a, async {
+            let lines = BoundedLines::new(&a, 16).take(2);
+            lines.collect::<Vec<_>>().await
+        })?;
+        writer.join().unwrap()?;
+        let lines: Vec<_> = lines.into_iter().collect::<io::Result<_>>()?;
+        assert_eq!(lines, vec![b"one".to_vec(), b"two".to_vec()]);
+
+        let err = block_on_file(&a, futures::future::pending::<()>()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+//! Servicing regular files on Tokio's blocking thread pool.
+
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::{cmp, fs, mem};
+use tokio::io::ReadBuf;
+use tokio::task::JoinHandle;
+
+#[cfg(all(feature = "uring", target_os = "linux"))]
+use crate::uring;
+
+/// The largest amount of data handed to a single blocking operation.
+const MAX_BUF: usize = 2 * 1024 * 1024;
+
+/// How a [`File`](struct.File.html) services files that cannot be polled,
+/// such as regular files.
+///
+/// The kernel always reports regular files as ready, so reading or writing
+/// them "asynchronously" really blocks whichever thread does it.
+///
+/// With the `uring` feature, such files are instead read and written
+/// through an io_uring where the kernel supports it, buffered like
+/// `ThreadPool`, and the mode only applies where it does not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RegularFileMode {
+    /// Perform the operations directly on the reactor thread.  This is the
+    /// traditional behavior and is fine for local disks, but a slow disk or
+    /// network filesystem stalls every other task on that thread.
+    #[default]
+    Inline,
+    /// Perform the operations on Tokio's blocking thread pool via
+    /// `spawn_blocking`, in the manner of `tokio::fs::File`.  Writes are
+    /// buffered and complete in the background; errors are reported by the
+    /// next operation, so call `flush` to observe them.
+    ///
+    /// On Linux, reads first try `preadv2` with `RWF_NOWAIT`, which succeeds
+    /// right away if the data is in the page cache, and only go to the pool
+    /// if it would have to wait for the disk.
+    ThreadPool,
+}
+
+#[derive(Debug)]
+pub(crate) enum Op {
+    Read(io::Result<usize>),
+    Write(io::Result<()>),
+    Seek(io::Result<u64>),
+    Sync(io::Result<()>),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Buf {
+    pub(crate) data: Vec<u8>,
+    pub(crate) pos: usize,
+}
+
+/// An operation under way, either on the pool or on an io_uring.
+#[derive(Debug)]
+enum Pending {
+    Pool(JoinHandle<(Op, Buf)>),
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    Ring(uring::InFlight),
+}
+
+impl Pending {
+    fn poll(&mut self, waiters: &Waiters, cx: &mut Context<'_>) -> Poll<io::Result<(Op, Buf)>> {
+        // a `JoinHandle` only keeps the most recent waker, so it is polled
+        // with one that wakes every task waiting on the operation
+        waiters.register(cx.waker());
+        let waker = Waker::from(waiters.0.clone());
+        let cx = &mut Context::from_waker(&waker);
+        match *self {
+            Pending::Pool(ref mut handle) => {
+                Poll::Ready(ready!(Pin::new(handle).poll(cx)).map_err(join_error))
+            }
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            Pending::Ring(ref mut op) => op.poll(cx),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Idle(Buf),
+    Busy(Pending),
+}
+
+/// The tasks waiting for the operation under way, which need not be the
+/// task that started it when the file is shared through `&File` or split.
+#[derive(Debug, Default)]
+struct Waiters(Arc<WakerList>);
+
+#[derive(Debug, Default)]
+struct WakerList(Mutex<Vec<Waker>>);
+
+impl Waiters {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = (self.0).0.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl Wake for WakerList {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        for waker in mem::take(&mut *self.0.lock().unwrap()) {
+            waker.wake();
+        }
+    }
+}
+
+/// The state shared by reads, writes, flushes, and seeks.
+#[derive(Debug)]
+struct Inner {
+    state: State,
+    /// The error of a read completed on behalf of another operation.
+    read_error: Option<io::Error>,
+    /// The error of a write or sync completed on behalf of another
+    /// operation.
+    write_error: Option<io::Error>,
+    /// The result of a seek not yet collected by `poll_complete_seek`.
+    seek: Option<io::Result<u64>>,
+    waiters: Waiters,
+}
+
+impl Inner {
+    /// Waits for the operation under way, if any.  Its outcome is kept for
+    /// the direction it belongs to, since the task that polls it to
+    /// completion may not be the one that started it.  Returns the
+    /// operation if it completed successfully.
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Op>>> {
+        let pending = match self.state {
+            State::Idle(_) => return Poll::Ready(Ok(None)),
+            State::Busy(ref mut pending) => pending,
+        };
+        let (op, buf) = ready!(pending.poll(&self.waiters, cx))?;
+        self.state = State::Idle(buf);
+        Poll::Ready(Ok(match op {
+            Op::Read(Err(e)) => {
+                self.read_error = Some(e);
+                None
+            }
+            Op::Write(Err(e)) | Op::Sync(Err(e)) => {
+                self.write_error = Some(e);
+                None
+            }
+            Op::Seek(r) => {
+                self.seek = Some(r);
+                None
+            }
+            op => Some(op),
+        }))
+    }
+}
+
+/// Offloads operations on a duplicate of the descriptor to the blocking
+/// thread pool.  The duplicate shares the file offset with the original.
+#[derive(Debug)]
+pub(crate) struct Blocking {
+    file: Arc<fs::File>,
+    inner: Mutex<Inner>,
+    /// Whether reads should try `RWF_NOWAIT` first.
+    nowait: AtomicBool,
+    /// The ring that reads, writes and syncs are submitted to instead of the
+    /// pool, if any.
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    ring: Option<Arc<uring::Ring>>,
+}
+
+fn join_error(e: tokio::task::JoinError) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Reads at the current offset, but only if that can be done without
+/// waiting for the disk; fails with `WouldBlock` otherwise.
+#[cfg(target_os = "linux")]
+fn read_nowait(file: &fs::File, dst: &mut ReadBuf<'_>) -> io::Result<()> {
+    let unfilled = dst.initialize_unfilled();
+    let iov = libc::iovec {
+        iov_base: unfilled.as_mut_ptr() as *mut libc::c_void,
+        iov_len: unfilled.len(),
+    };
+    // an offset of -1 means the current offset, which is then advanced
+    let n = unsafe { libc::preadv2(file.as_raw_fd(), &iov, 1, -1, libc::RWF_NOWAIT) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    dst.advance(n as usize);
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_nowait(_file: &fs::File, _dst: &mut ReadBuf<'_>) -> io::Result<()> {
+    Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP))
+}
+
+impl Blocking {
+    pub(crate) fn new<F: AsRawFd>(file: &F) -> io::Result<Self> {
+        let file = unsafe { crate::dupe_file_from_fd(file.as_raw_fd())? };
+        Ok(Blocking {
+            file: Arc::new(file),
+            inner: Mutex::new(Inner {
+                state: State::Idle(Buf::default()),
+                read_error: None,
+                write_error: None,
+                seek: None,
+                waiters: Waiters::default(),
+            }),
+            nowait: AtomicBool::new(cfg!(target_os = "linux")),
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            ring: None,
+        })
+    }
+
+    /// Sets up the servicing of a file that cannot be polled, or returns
+    /// `None` if it is to be read and written inline.  With the `uring`
+    /// feature, an io_uring is used if the kernel supports it, whatever the
+    /// mode.
+    pub(crate) fn for_mode<F: AsRawFd>(
+        file: &F,
+        mode: RegularFileMode,
+    ) -> io::Result<Option<Self>> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        {
+            if let Ok(ring) = uring::Ring::new() {
+                let mut blocking = Blocking::new(file)?;
+                blocking.ring = Some(Arc::new(ring));
+                return Ok(Some(blocking));
+            }
+        }
+        match mode {
+            RegularFileMode::Inline => Ok(None),
+            RegularFileMode::ThreadPool => Blocking::new(file).map(Some),
+        }
+    }
+
+    /// Whether operations are submitted to an io_uring.
+    #[cfg(all(test, feature = "uring", target_os = "linux"))]
+    pub(crate) fn has_ring(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    pub(crate) fn poll_read(
+        &self,
+        cx: &mut Context<'_>,
+        dst: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        loop {
+            if let Some(e) = inner.read_error.take() {
+                return Poll::Ready(Err(e));
+            }
+            if let Some(Op::Read(Ok(0))) = ready!(inner.poll_idle(cx))? {
+                return Poll::Ready(Ok(()));
+            }
+            let buf = match inner.state {
+                State::Idle(ref mut buf) => buf,
+                State::Busy(_) => continue,
+            };
+            if buf.pos < buf.data.len() {
+                let n = cmp::min(dst.remaining(), buf.data.len() - buf.pos);
+                dst.put_slice(&buf.data[buf.pos..buf.pos + n]);
+                buf.pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if self.nowait.load(Ordering::Relaxed) {
+                match read_nowait(&self.file, dst) {
+                    Ok(()) => return Poll::Ready(Ok(())),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    // not supported by this kernel or filesystem
+                    Err(ref e)
+                        if e.raw_os_error() == Some(libc::EOPNOTSUPP)
+                            || e.raw_os_error() == Some(libc::ENOSYS)
+                            || e.raw_os_error() == Some(libc::EINVAL) =>
+                    {
+                        self.nowait.store(false, Ordering::Relaxed);
+                    }
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+            let mut buf = mem::take(buf);
+            let len = cmp::min(dst.remaining(), MAX_BUF);
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            {
+                if let Some(ref ring) = self.ring {
+                    let op = uring::InFlight::read(ring, &self.file, buf, len)?;
+                    inner.state = State::Busy(Pending::Ring(op));
+                    continue;
+                }
+            }
+            let file = self.file.clone();
+            inner.state = State::Busy(Pending::Pool(tokio::task::spawn_blocking(move || {
+                buf.data.resize(len, 0);
+                buf.pos = 0;
+                let r = loop {
+                    match (&*file).read(&mut buf.data) {
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        r => break r,
+                    }
+                };
+                buf.data.truncate(*r.as_ref().unwrap_or(&0));
+                (Op::Read(r), buf)
+            })));
+        }
+    }
+
+    pub(crate) fn poll_write(&self, cx: &mut Context<'_>, src: &[u8]) -> Poll<io::Result<usize>> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        loop {
+            if let Some(e) = inner.write_error.take() {
+                return Poll::Ready(Err(e));
+            }
+            ready!(inner.poll_idle(cx))?;
+            let buf = match inner.state {
+                State::Idle(ref mut buf) if inner.write_error.is_none() => buf,
+                _ => continue,
+            };
+            // data that was read ahead but never consumed must not shift the
+            // position of the write
+            let unread = buf.data.len() - buf.pos;
+            if unread > 0 {
+                let offset = -(unread as libc::off_t);
+                let fd = self.file.as_raw_fd();
+                if unsafe { libc::lseek(fd, offset, libc::SEEK_CUR) } < 0 {
+                    let e = io::Error::last_os_error();
+                    if e.raw_os_error() != Some(libc::ESPIPE) {
+                        return Poll::Ready(Err(e));
+                    }
+                }
+            }
+            let mut buf = mem::take(buf);
+            let n = cmp::min(src.len(), MAX_BUF);
+            buf.data.clear();
+            buf.data.extend_from_slice(&src[..n]);
+            buf.pos = 0;
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            {
+                if let Some(ref ring) = self.ring {
+                    let op = uring::InFlight::write(ring, &self.file, buf)?;
+                    inner.state = State::Busy(Pending::Ring(op));
+                    return Poll::Ready(Ok(n));
+                }
+            }
+            let file = self.file.clone();
+            inner.state = State::Busy(Pending::Pool(tokio::task::spawn_blocking(move || {
+                let r = (&*file).write_all(&buf.data);
+                buf.data.clear();
+                (Op::Write(r), buf)
+            })));
+            return Poll::Ready(Ok(n));
+        }
+    }
+
+    /// Waits for pending writes, then if `sync` is set, runs `fdatasync` on
+    /// the thread pool as well.
+    pub(crate) fn poll_flush(&self, cx: &mut Context<'_>, sync: bool) -> Poll<io::Result<()>> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        loop {
+            if let Some(e) = inner.write_error.take() {
+                return Poll::Ready(Err(e));
+            }
+            if let Some(Op::Sync(Ok(()))) = ready!(inner.poll_idle(cx))? {
+                return Poll::Ready(Ok(()));
+            }
+            let buf = match inner.state {
+                State::Idle(ref mut buf) if inner.write_error.is_none() => buf,
+                _ => continue,
+            };
+            if !sync {
+                return Poll::Ready(Ok(()));
+            }
+            let buf = mem::take(buf);
+            #[cfg(all(feature = "uring", target_os = "linux"))]
+            {
+                if let Some(ref ring) = self.ring {
+                    let op = uring::InFlight::sync(ring, &self.file, buf)?;
+                    inner.state = State::Busy(Pending::Ring(op));
+                    continue;
+                }
+            }
+            let file = self.file.clone();
+            inner.state = State::Busy(Pending::Pool(tokio::task::spawn_blocking(move || {
+                (Op::Sync(file.sync_data()), buf)
+            })));
+        }
+    }
+
+    /// Starts seeking on the thread pool.  Data that was read ahead is
+    /// discarded, and a relative seek is taken from the position of the
+    /// data actually consumed.
+    pub(crate) fn start_seek(&self, mut pos: SeekFrom) -> io::Result<()> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let buf = match inner.state {
+            State::Idle(ref mut buf) => buf,
+            State::Busy(_) => {
+                return Err(io::Error::other(
+                    "another operation is pending, call poll_complete first",
+                ))
+            }
+        };
+        if let SeekFrom::Current(ref mut offset) = pos {
+            *offset -= (buf.data.len() - buf.pos) as i64;
+        }
+        let mut buf = mem::take(buf);
+        buf.data.clear();
+        buf.pos = 0;
+        let file = self.file.clone();
+        inner.state = State::Busy(Pending::Pool(tokio::task::spawn_blocking(move || {
+            (Op::Seek((&*file).seek(pos)), buf)
+        })));
+        Ok(())
+    }
+
+    /// Waits for any pending operation, returning the result of the seek if
+    /// that is what it was, and the current position otherwise.
+    pub(crate) fn poll_complete_seek(&self, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let mut guard = self.inner.lock().unwrap();
+        let inner = &mut *guard;
+        loop {
+            ready!(inner.poll_idle(cx))?;
+            if let Some(r) = inner.seek.take() {
+                return Poll::Ready(r);
+            }
+            if let Some(e) = inner.write_error.take() {
+                return Poll::Ready(Err(e));
+            }
+            if let State::Idle(ref buf) = inner.state {
+                let unread = (buf.data.len() - buf.pos) as u64;
+                let pos = (&*self.file).stream_position()?;
+                return Poll::Ready(Ok(pos - unread));
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+    use std::io::{Seek, SeekFrom};
+
+    #[test]
+    fn test_read_nowait() -> io::Result<()> {
+        let mut file = tempfile("nowait")?;
+        file.write_all(b"cached")?;
+        file.seek(SeekFrom::Start(0))?;
+        let blocking = Blocking::new(&file)?;
+        // there is no runtime, so this would panic if it went to the pool
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut data = [0; 16];
+        let mut dst = ReadBuf::new(&mut data);
+        match blocking.poll_read(&mut cx, &mut dst) {
+            Poll::Ready(r) => r?,
+            Poll::Pending => panic!("read went to the pool"),
+        }
+        assert_eq!(dst.filled(), b"cached");
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+//! Wrapping descriptors owned by someone else.
+
+use crate::File;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+
+/// A descriptor borrowed for the lifetime `'a`, read and written with plain
+/// `read` and `write`, as wrapped by
+/// [`File::borrowed`](struct.File.html#method.borrowed).
+#[derive(Clone, Copy, Debug)]
+pub struct BorrowedFile<'a>(BorrowedFd<'a>);
+
+impl BorrowedFile<'_> {
+    /// Runs `f` on a `fs::File` for the descriptor that is never closed.
+    fn with_file<T>(&self, f: impl FnOnce(&fs::File) -> T) -> T {
+        let file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(self.0.as_raw_fd()) });
+        f(&file)
+    }
+}
+
+impl AsRawFd for BorrowedFile<'_> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsFd for BorrowedFile<'_> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0
+    }
+}
+
+impl Read for BorrowedFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        (&*self).read_vectored(bufs)
+    }
+}
+
+impl Read for &BorrowedFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.with_file(|mut file| file.read(buf))
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.with_file(|mut file| file.read_vectored(bufs))
+    }
+}
+
+impl Write for BorrowedFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &BorrowedFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.with_file(|mut file| file.write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.with_file(|mut file| file.write_vectored(bufs))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> File<BorrowedFile<'a>> {
+    /// Wraps the descriptor of `file` without taking ownership of it, for
+    /// descriptors that belong to other code, such as a socket held by a
+    /// library or a descriptor inherited from the parent process.
+    ///
+    /// The descriptor is deregistered from the reactor when the `File` is
+    /// dropped, which the borrow ensures happens before `file` can be
+    /// closed, and it is never closed by the `File`.  Nonblocking mode and,
+    /// for terminals, the terminal settings are restored at that point too,
+    /// as by [`new_nb_restoring`](#method.new_nb_restoring), so `file` is
+    /// left as it was found.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn borrowed<T: AsFd + ?Sized>(file: &'a T) -> io::Result<Self> {
+        let mut file = File::new_nb_restoring(BorrowedFile(file.as_fd()))?;
+        file.close.disarm();
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::File;
+    use std::io;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_borrowed() -> io::Result<()> {
+        let (a, mut b) = UnixStream::pair()?;
+        for _ in 0..2 {
+            // registering again shows that the first registration is gone
+            let mut file = File::borrowed(&a)?;
+            file.write_all(b"ping").await?;
+            let mut buf = [0; 4];
+            io::Read::read_exact(&mut b, &mut buf)?;
+            io::Write::write_all(&mut b, b"pong")?;
+            file.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"pong");
+        }
+        assert!(!crate::get_nonblocking(&a)?);
+        io::Write::write_all(&mut &a, b"still open")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+//! Bridges between the standard streams and channels.
+
+use crate::File;
+use bytes::Bytes;
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc;
+
+/// Spawns a task that reads standard input line by line and sends each line,
+/// without the line terminator, to the returned channel.  At most `buffer`
+/// lines are queued before reading pauses.
+///
+/// The channel is closed when standard input reaches EOF or fails to read.
+/// Dropping the receiver stops the task without waiting for another line.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn stdin_lines_channel(buffer: usize) -> io::Result<mpsc::Receiver<String>> {
+    let stdin = File::new_nb(crate::raw_stdin()?)?;
+    Ok(lines_channel(stdin, buffer))
+}
+
+fn lines_channel<R>(reader: R, buffer: usize) -> mpsc::Receiver<String>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(buffer);
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        loop {
+            let line = tokio::select! {
+                line = lines.next_line() => line,
+                _ = tx.closed() => break,
+            };
+            match line {
+                Ok(Some(line)) => {
+                    if tx.send(line).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+    });
+    rx
+}
+
+/// Spawns a task that writes everything sent to the returned channel to
+/// standard output, in order.  At most `buffer` chunks are queued before
+/// sending waits, so a slow terminal slows down the senders only once the
+/// queue is full.
+///
+/// Chunks that arrive in quick succession are coalesced, and output is
+/// flushed whenever the queue runs empty.  Once all senders are dropped, the
+/// remaining chunks are written and the task ends.  If writing fails, the
+/// channel is closed and further sends fail.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn stdout_sink_channel(buffer: usize) -> io::Result<mpsc::Sender<Bytes>> {
+    let stdout = File::new_nb(crate::raw_stdout()?)?;
+    Ok(sink_channel(stdout, buffer))
+}
+
+fn sink_channel<W>(writer: W, buffer: usize) -> mpsc::Sender<Bytes>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (tx, mut rx) = mpsc::channel::<Bytes>(buffer);
+    tokio::spawn(async move {
+        let mut writer = BufWriter::new(writer);
+        while let Some(chunk) = rx.recv().await {
+            writer.write_all(&chunk).await?;
+            while let Ok(chunk) = rx.try_recv() {
+                writer.write_all(&chunk).await?;
+            }
+            writer.flush().await?;
+        }
+        Ok::<_, io::Error>(())
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_lines_channel() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let mut rx = lines_channel(reader, 1);
+        writer.write_all(b"first\nsecond\r\nthird").await?;
+        drop(writer);
+        assert_eq!(rx.recv().await.as_deref(), Some("first"));
+        assert_eq!(rx.recv().await.as_deref(), Some("second"));
+        assert_eq!(rx.recv().await.as_deref(), Some("third"));
+        assert_eq!(rx.recv().await, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sink_channel() -> io::Result<()> {
+        let (mut reader, writer) = crate::pipe()?;
+        let tx = sink_channel(writer, 1);
+        tx.send(Bytes::from_static(b"in ")).await.unwrap();
+        tx.send(Bytes::from_static(b"order")).await.unwrap();
+        drop(tx);
+        let mut received = String::new();
+        reader.read_to_string(&mut received).await?;
+        assert_eq!(received, "in order");
+        Ok(())
+    }
+}
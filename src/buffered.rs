@@ -0,0 +1,231 @@
+//! Buffered writing with high and low watermarks.
+
+use crate::{BufferPool, File};
+use bytes::{Buf, BytesMut};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{io, mem};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+impl<F> File<F> {
+    /// Wraps the file in a writer that accepts data into a buffer of up to
+    /// `high` bytes, passing it on to the file as fast as the file takes it.
+    /// Once the buffer reaches `high` bytes, writes wait until the file has
+    /// taken enough of it that no more than `low` bytes are left.
+    ///
+    /// This smooths out a producer that writes in bursts to a slow consumer,
+    /// such as a FIFO, without waking it for every few bytes the consumer
+    /// reads.
+    ///
+    /// The buffer is only held while it has data in it.  If `high` is the
+    /// buffer size of the [`BufferPool`](struct.BufferPool.html), which is
+    /// the global one unless set with
+    /// [`buffer_pool`](struct.BufferedWriter.html#method.buffer_pool), it is
+    /// taken from the pool.
+    ///
+    /// Panics if `high` is zero or `low` is not less than `high`.
+    pub fn into_buffered_writer(self, high: usize, low: usize) -> BufferedWriter<F> {
+        assert!(high > 0, "high watermark must not be zero");
+        assert!(low < high, "low watermark must be below the high watermark");
+        BufferedWriter {
+            inner: self,
+            buf: BytesMut::new(),
+            pool: BufferPool::global(),
+            high,
+            low,
+            blocked: false,
+            error: None,
+        }
+    }
+}
+
+/// A [`File`](struct.File.html) whose writes are buffered up to a high
+/// watermark, created by
+/// [`File::into_buffered_writer`](struct.File.html#method.into_buffered_writer).
+///
+/// Buffered data is not written out when the writer is dropped, so call
+/// `flush` or `shutdown` first.  Reads go straight to the file.
+#[derive(Debug)]
+pub struct BufferedWriter<F> {
+    inner: File<F>,
+    buf: BytesMut,
+    pool: Arc<BufferPool>,
+    high: usize,
+    low: usize,
+    /// Whether the high watermark was reached and the buffer has not yet
+    /// drained to the low watermark.
+    blocked: bool,
+    /// An error from passing on data after it was accepted, reported by the
+    /// next operation.
+    error: Option<io::Error>,
+}
+
+impl<F> BufferedWriter<F> {
+    /// Takes the buffer from `pool` instead of the global pool, if its
+    /// buffer size is the high watermark.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the file.
+    ///
+    /// Writing to it directly goes ahead of the data in the buffer.
+    pub fn get_mut(&mut self) -> &mut File<F> {
+        &mut self.inner
+    }
+
+    /// The data written but not passed on to the file yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Whether writes are being held back, because the buffer reached the
+    /// high watermark and has not yet drained to the low watermark.
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+
+    /// Returns the file.  Any data in the buffer is lost, so flush first.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> BufferedWriter<F> {
+    /// Passes on as much of the buffer as the file takes, completing once
+    /// it is all written out.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(e) = self.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        while !self.buf.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.buf.advance(n);
+        }
+        if self.buf.capacity() > 0 && self.pool.serves(self.high) {
+            self.pool.put(mem::take(&mut self.buf));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for BufferedWriter<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncRead for BufferedWriter<F> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> AsyncWrite for BufferedWriter<F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        src: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // if this is pending, the task is woken once the file takes more
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        if this.blocked {
+            if this.buf.len() > this.low {
+                return Poll::Pending;
+            }
+            this.blocked = false;
+        }
+        if src.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if this.buf.capacity() == 0 && this.pool.serves(this.high) {
+            this.buf = this.pool.get();
+        }
+        let n = src.len().min(this.high - this.buf.len());
+        this.buf.extend_from_slice(&src[..n]);
+        if this.buf.len() >= this.high {
+            this.blocked = true;
+        }
+        // start on the new data right away; the data has been accepted, so
+        // an error has to wait for the next operation
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            this.error = Some(e);
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.blocked = false;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.blocked = false;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_buffered_writer() -> io::Result<()> {
+        let (reader, writer) = crate::pipe()?;
+        let mut reader = reader.into_inner();
+        let writer = writer.into_inner();
+        // fill the pipe, so that nothing more is passed on for now
+        let mut filled = 0;
+        loop {
+            match writer.try_write(&[0; 4096]) {
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let mut writer = writer.into_buffered_writer(16, 4);
+
+        assert_eq!(writer.write(b"first part").await?, 10);
+        assert_eq!(writer.write(b" and more").await?, 6);
+        assert_eq!(writer.buffer(), b"first part and m");
+        assert!(writer.is_blocked());
+        assert!(writer.write(b"ore").now_or_never().is_none());
+
+        // draining the pipe lets the buffer drain below the low watermark
+        let write = tokio::spawn(async move {
+            writer.write_all(b"ore").await?;
+            writer.flush().await?;
+            assert!(!writer.is_blocked());
+            Ok::<_, io::Error>(writer)
+        });
+        let mut data = vec![0; filled + 19];
+        reader.read_exact(&mut data).await?;
+        assert_eq!(&data[filled..], b"first part and more");
+        write.await.unwrap()?;
+        Ok(())
+    }
+}
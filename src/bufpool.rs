@@ -0,0 +1,192 @@
+//! Sharing the buffers of adapters between files.
+
+use bytes::BytesMut;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The size of the buffers of [`BufferPool::global`](struct.BufferPool.html#method.global).
+const GLOBAL_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The most idle buffers kept by the global pool.
+const GLOBAL_MAX_IDLE: usize = 64;
+
+/// A pool of recycled buffers of one size, shared by the adapters that
+/// buffer data, so that thousands of mostly idle files need not each hold
+/// a buffer of their own.
+///
+/// [`BytesStream`](struct.BytesStream.html),
+/// [`BytesSink`](struct.BytesSink.html), and
+/// [`BufferedWriter`](struct.BufferedWriter.html) take a buffer from their
+/// pool when they have data to hold and give it back once it is empty.
+/// They use [`BufferPool::global`](#method.global) unless given another
+/// with `buffer_pool`, and only if their buffer size, such as the capacity
+/// of a `BytesStream`, is the same as that of the pool; otherwise they
+/// allocate their own buffers as usual.
+#[derive(Debug)]
+pub struct BufferPool {
+    idle: Mutex<Vec<BytesMut>>,
+    buffer_size: usize,
+    max_idle: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    returned: AtomicU64,
+    discarded: AtomicU64,
+}
+
+/// Counters of a [`BufferPool`](struct.BufferPool.html), returned by
+/// [`BufferPool::stats`](struct.BufferPool.html#method.stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// The number of buffers handed out from among the idle ones.
+    pub hits: u64,
+    /// The number of buffers allocated because none was idle.
+    pub misses: u64,
+    /// The number of buffers given back and kept.
+    pub returned: u64,
+    /// The number of buffers given back but dropped, because the pool was
+    /// full or they had become too small.
+    pub discarded: u64,
+}
+
+impl BufferPool {
+    /// Creates an empty pool of buffers of `buffer_size` bytes that keeps
+    /// at most `max_idle` unused buffers.
+    ///
+    /// Panics if `buffer_size` is 0.
+    pub fn new(buffer_size: usize, max_idle: usize) -> Self {
+        assert!(buffer_size > 0, "zero buffer size");
+        BufferPool {
+            idle: Mutex::new(Vec::new()),
+            buffer_size,
+            max_idle,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            returned: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+        }
+    }
+
+    /// The pool shared by the whole process, which keeps up to 64 idle
+    /// buffers of 64 KiB.
+    pub fn global() -> Arc<BufferPool> {
+        static GLOBAL: OnceLock<Arc<BufferPool>> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| Arc::new(BufferPool::new(GLOBAL_BUFFER_SIZE, GLOBAL_MAX_IDLE)))
+            .clone()
+    }
+
+    /// The size of the buffers in the pool.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// The most idle buffers the pool keeps.
+    pub fn max_idle(&self) -> usize {
+        self.max_idle
+    }
+
+    /// The number of idle buffers currently held by the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// The counters of the pool so far.
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            returned: self.returned.load(Ordering::Relaxed),
+            discarded: self.discarded.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Takes an empty buffer with room for at least `buffer_size` bytes
+    /// from the pool, allocating one if none is idle.
+    pub fn get(&self) -> BytesMut {
+        match self.idle.lock().unwrap().pop() {
+            Some(buf) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                buf
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                BytesMut::with_capacity(self.buffer_size)
+            }
+        }
+    }
+
+    /// Gives a buffer back to the pool, discarding its contents.  It is
+    /// dropped instead if the pool is full or it no longer has room for
+    /// `buffer_size` bytes, as when part of it was split off and is still
+    /// in use.
+    pub fn put(&self, mut buf: BytesMut) {
+        buf.clear();
+        if buf.try_reclaim(self.buffer_size) {
+            let mut idle = self.idle.lock().unwrap();
+            if idle.len() < self.max_idle {
+                idle.push(buf);
+                self.returned.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        }
+        self.discarded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether an adapter with buffers of `size` bytes draws from the pool.
+    pub(crate) fn serves(&self, size: usize) -> bool {
+        self.buffer_size == size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(16, 1);
+        let mut a = pool.get();
+        assert!(a.capacity() >= 16);
+        a.put_slice(b"dirty");
+        let b = pool.get();
+        pool.put(a);
+        pool.put(b);
+        assert_eq!(pool.idle(), 1);
+        let a = pool.get();
+        assert!(a.is_empty());
+        // too small while the part split off is in use
+        let mut c = pool.get();
+        c.put_slice(b"split off");
+        let split = c.split().freeze();
+        pool.put(c);
+        drop(split);
+        assert_eq!(
+            pool.stats(),
+            BufferPoolStats {
+                hits: 1,
+                misses: 3,
+                returned: 1,
+                discarded: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_adapter_buffers() -> std::io::Result<()> {
+        let pool = Arc::new(BufferPool::new(16, 4));
+        let (_reader, writer) = crate::pipe()?;
+        let mut writer = writer
+            .into_inner()
+            .into_buffered_writer(16, 4)
+            .buffer_pool(pool.clone());
+        writer.write_all(b"pooled").await?;
+        writer.flush().await?;
+        // the buffer went back once it was written out
+        assert_eq!(pool.idle(), 1);
+        writer.write_all(b"again").await?;
+        assert_eq!(pool.stats().hits, 1);
+        Ok(())
+    }
+}
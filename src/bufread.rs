@@ -0,0 +1,169 @@
+//! Buffered reading that keeps the descriptor accessible.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+impl<F> File<F> {
+    /// Wraps the file in a reader with a buffer of `capacity` bytes that
+    /// implements `AsyncBufRead`, so that `read_line` and `lines` work
+    /// without reading one byte at a time.
+    ///
+    /// Unlike wrapping it in `tokio::io::BufReader`, the file stays
+    /// accessible through [`get_ref`](struct.BufFile.html#method.get_ref),
+    /// and the reader implements `AsRawFd`.
+    pub fn into_buf_reader(self, capacity: usize) -> BufFile<F> {
+        BufFile {
+            inner: self,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+}
+
+/// A [`File`](struct.File.html) with a read buffer, created by
+/// [`File::into_buf_reader`](struct.File.html#method.into_buf_reader).
+///
+/// Writes go straight to the file.
+#[derive(Debug)]
+pub struct BufFile<F> {
+    inner: File<F>,
+    buf: Box<[u8]>,
+    /// The buffered data is `buf[pos..filled]`.
+    pos: usize,
+    filled: usize,
+}
+
+impl<F> BufFile<F> {
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the file.
+    ///
+    /// Reading from it directly skips over the data in the buffer.
+    pub fn get_mut(&mut self) -> &mut File<F> {
+        &mut self.inner
+    }
+
+    /// The data read from the file but not consumed yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Returns the file.  Any data in the buffer is lost; use
+    /// [`into_parts`](#method.into_parts) to keep it.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+
+    /// Returns the file and the data read from it but not consumed yet.
+    pub fn into_parts(self) -> (File<F>, Vec<u8>) {
+        let rest = self.buffer().to_vec();
+        (self.inner, rest)
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for BufFile<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncRead for BufFile<F> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        // large reads bypass an empty buffer
+        if self.pos == self.filled && buf.remaining() >= self.buf.len() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let data = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = data.len().min(buf.remaining());
+        buf.put_slice(&data[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncBufRead for BufFile<F> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos == this.filled {
+            let mut buf = ReadBuf::new(&mut this.buf);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf))?;
+            this.filled = buf.filled().len();
+            this.pos = 0;
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.pos = (self.pos + amt).min(self.filled);
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> AsyncWrite for BufFile<F> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_buf_reader() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        writer.write_all(b"one\ntwo\nthree and more").await?;
+        drop(writer);
+        let mut reader = reader.into_inner().into_buf_reader(8);
+        assert_eq!(reader.as_raw_fd(), reader.get_ref().as_raw_fd());
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        assert_eq!(line, "one\n");
+        assert_eq!(reader.buffer(), b"two\n");
+        let mut lines = reader.lines();
+        assert_eq!(lines.next_line().await?.as_deref(), Some("two"));
+        let mut reader = lines.into_inner();
+        let mut rest = [0; 5];
+        reader.read_exact(&mut rest).await?;
+        assert_eq!(&rest, b"three");
+        let (_, buffered) = reader.into_parts();
+        assert_eq!(buffered, b" an");
+        Ok(())
+    }
+}
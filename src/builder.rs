@@ -0,0 +1,406 @@
+//! Configurable construction of wrapped files.
+
+use crate::{DirectoryPolicy, File, RegularFileMode, RetryPolicy, ZeroLengthMode};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use tokio::io::Interest;
+
+/// Builds a [`File`](struct.File.html) with explicit options, as an
+/// alternative to the `File::new_nb` family of constructors.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio::io::Interest;
+/// use tokio_file_unix::FileBuilder;
+///
+/// let stdin = FileBuilder::new()
+///     .interest(Interest::READABLE)
+///     .build(tokio_file_unix::raw_stdin()?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FileBuilder {
+    nonblocking: bool,
+    verify_nonblocking: bool,
+    cloexec: Option<bool>,
+    interest: Interest,
+    mode: RegularFileMode,
+    directories: DirectoryPolicy,
+    fallback: bool,
+    sync_on_flush: bool,
+    retry_interrupted: bool,
+    suppress_sigpipe: bool,
+    edge_triggered: bool,
+    zero_length: ZeroLengthMode,
+    repoll: Option<(u32, RetryPolicy)>,
+    stats: bool,
+}
+
+impl Default for FileBuilder {
+    fn default() -> Self {
+        FileBuilder {
+            nonblocking: true,
+            verify_nonblocking: cfg!(debug_assertions),
+            cloexec: None,
+            interest: Interest::READABLE | Interest::WRITABLE,
+            mode: RegularFileMode::default(),
+            directories: DirectoryPolicy::default(),
+            fallback: true,
+            sync_on_flush: false,
+            retry_interrupted: true,
+            suppress_sigpipe: false,
+            edge_triggered: false,
+            zero_length: ZeroLengthMode::default(),
+            repoll: None,
+            stats: false,
+        }
+    }
+}
+
+impl FileBuilder {
+    /// Equivalent to `FileBuilder::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to enable nonblocking mode on the file first, as
+    /// `File::new_nb` does.  If `false`, the file is used as is, like
+    /// `File::raw_new`.  Defaults to `true`.
+    pub fn nonblocking(mut self, nonblocking: bool) -> Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Whether to fail with `InvalidInput` if nonblocking mode is not
+    /// [enabled](#method.nonblocking) by the builder and the file can be
+    /// polled but is in blocking mode, as `File::raw_new` does.  Defaults to
+    /// `true` in debug builds and `false` otherwise.
+    pub fn verify_nonblocking(mut self, verify: bool) -> Self {
+        self.verify_nonblocking = verify;
+        self
+    }
+
+    /// Sets or clears close-on-exec on the descriptor, like
+    /// [`set_cloexec`](fn.set_cloexec.html).  Pass `false` for descriptors
+    /// meant to be inherited by a child process.  By default it is left
+    /// alone.
+    pub fn cloexec(mut self, cloexec: bool) -> Self {
+        self.cloexec = Some(cloexec);
+        self
+    }
+
+    /// Sets the interest the file is registered with.  Defaults to both
+    /// reading and writing; see
+    /// [`File::with_interest`](struct.File.html#method.with_interest).
+    /// Include `Interest::PRIORITY` to use
+    /// [`File::priority`](struct.File.html#method.priority).
+    pub fn interest(mut self, interest: Interest) -> Self {
+        self.interest = interest;
+        self
+    }
+
+    /// Sets the treatment of files that cannot be polled, such as regular
+    /// files.  Defaults to `RegularFileMode::Inline`.
+    pub fn regular_file_mode(mut self, mode: RegularFileMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets what to do if the file is a directory.  Defaults to
+    /// `DirectoryPolicy::Allow`.
+    pub fn directory_policy(mut self, policy: DirectoryPolicy) -> Self {
+        self.directories = policy;
+        self
+    }
+
+    /// Whether a file that cannot be polled, such as a regular file, is
+    /// wrapped anyway and treated as always ready.  If `false`, `build`
+    /// fails with a [`NotPollable`](struct.NotPollable.html) error instead,
+    /// for callers that would rather not have reads and writes block.
+    /// Defaults to `true`.
+    ///
+    /// Whether a wrapped file ended up this way can be checked with
+    /// [`File::is_fallback_active`](struct.File.html#method.is_fallback_active).
+    pub fn allow_fallback(mut self, allow: bool) -> Self {
+        self.fallback = allow;
+        self
+    }
+
+    /// Whether flushing a file that cannot be polled, such as a regular
+    /// file, also makes the data durable with `fdatasync`, like
+    /// [`File::sync_data`](struct.File.html#method.sync_data).  The sync
+    /// runs where the writes do, according to the
+    /// [regular file mode](#method.regular_file_mode).  Defaults to `false`.
+    pub fn sync_on_flush(mut self, sync: bool) -> Self {
+        self.sync_on_flush = sync;
+        self
+    }
+
+    /// Whether reads and writes that fail with `EINTR`, because a signal
+    /// handler ran while they were waiting, are retried instead of failing
+    /// with `Interrupted`.  This mostly matters for files that cannot be
+    /// polled, since nonblocking operations do not wait.  Operations on the
+    /// blocking thread pool are always retried.  Defaults to `true`.
+    pub fn retry_interrupted(mut self, retry: bool) -> Self {
+        self.retry_interrupted = retry;
+        self
+    }
+
+    /// Whether writes to a socket whose peer is gone fail with `BrokenPipe`
+    /// without raising `SIGPIPE`, which kills the process unless it is
+    /// ignored.  Defaults to `false`, which leaves it up to the process, see
+    /// [`ignore_sigpipe`](fn.ignore_sigpipe.html).
+    ///
+    /// This has no effect on other kinds of files, such as pipes.  On
+    /// platforms that cannot suppress the signal for a single socket,
+    /// `build` fails with `Unsupported`.
+    ///
+    /// Implementation detail: writes go through `send` with `MSG_NOSIGNAL`
+    /// on Linux, while elsewhere the socket gets `SO_NOSIGPIPE`.
+    pub fn suppress_sigpipe(mut self, suppress: bool) -> Self {
+        self.suppress_sigpipe = suppress;
+        self
+    }
+
+    /// Whether a read that fills only part of the buffer, or a write that
+    /// takes only part of the data, clears the readiness of the file, so
+    /// that the next one waits for the reactor instead of first trying the
+    /// system call.  Defaults to `false`.
+    ///
+    /// The reactor is always edge-triggered, so by default a reader that
+    /// drains a pipe in batches makes one extra read per batch, which fails
+    /// with `WouldBlock`.  With this set, a short read is taken to mean the
+    /// pipe is empty, which holds for pipes, sockets and terminals but not
+    /// for every kind of file.  Readers using
+    /// [`File::try_read`](struct.File.html#method.try_read) can do the same
+    /// with [`File::clear_read_ready`](struct.File.html#method.clear_read_ready).
+    pub fn edge_triggered(mut self, edge_triggered: bool) -> Self {
+        self.edge_triggered = edge_triggered;
+        self
+    }
+
+    /// Sets what reads into an empty buffer and writes of no data do.
+    /// Defaults to `ZeroLengthMode::Probe`.
+    pub fn zero_length(mut self, mode: ZeroLengthMode) -> Self {
+        self.zero_length = mode;
+        self
+    }
+
+    /// Falls back to trying reads and writes on a timer once the reactor
+    /// has reported the file ready `false_wakeups` times in a row only for
+    /// the operation to fail with `WouldBlock`, for devices such as some
+    /// USB CDC drivers that fail with `EAGAIN` but then never signal
+    /// readiness again.  The delays between attempts follow `policy`,
+    /// whose maximum number of attempts is ignored, and are cut short by
+    /// any readiness reported in the meantime.  Once an operation goes
+    /// through, the count starts over.  By default there is no fallback.
+    ///
+    /// This applies to reads and writes through `AsyncRead` and
+    /// `AsyncWrite`.  With `false_wakeups` set to 0, the file is re-polled
+    /// from the start.
+    pub fn repoll_after(mut self, false_wakeups: u32, policy: RetryPolicy) -> Self {
+        self.repoll = Some((false_wakeups, policy));
+        self
+    }
+
+    /// Whether to count the bytes, system calls, `WouldBlock` results and
+    /// wakeups of the file, for [`File::stats`](struct.File.html#method.stats)
+    /// to return.  Defaults to `false`, which costs nothing.
+    pub fn collect_stats(mut self, collect: bool) -> Self {
+        self.stats = collect;
+        self
+    }
+
+    /// Applies the options to `file` and wraps it.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn build<F: AsRawFd>(&self, file: F) -> io::Result<File<F>> {
+        let fd = file.as_raw_fd();
+        if let Some(cloexec) = self.cloexec {
+            crate::set_cloexec_for("FileBuilder::build", fd, cloexec)?;
+        }
+        if self.nonblocking {
+            crate::enable_nonblocking("FileBuilder::build", fd)?;
+        } else if self.verify_nonblocking {
+            crate::check_nonblocking("FileBuilder::build", fd)?;
+        }
+        let mut file = File::register(
+            file,
+            self.mode,
+            self.directories,
+            self.fallback,
+            self.interest,
+        )?;
+        if self.suppress_sigpipe && crate::FdKind::of(&fd)? == crate::FdKind::Socket {
+            file.nosignal = crate::sigpipe::suppress(fd)?;
+        }
+        file.sync_on_flush = self.sync_on_flush;
+        file.retry_interrupted = self.retry_interrupted;
+        file.edge_triggered = self.edge_triggered;
+        file.zero_length = self.zero_length;
+        file.repoll = self
+            .repoll
+            .map(|(after, policy)| crate::repoll::Repoll::new(after, policy));
+        if self.stats {
+            file.stats = Some(Default::default());
+        }
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::RawFd;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_build() -> io::Result<()> {
+        let (a, _b) = UnixStream::pair()?;
+        let fd = a.as_raw_fd();
+        let file = FileBuilder::new()
+            .cloexec(false)
+            .interest(Interest::READABLE)
+            .build(a)?;
+        assert!(crate::get_nonblocking(&file)?);
+        assert_eq!(
+            unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC,
+            0
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_nonblocking() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let builder = FileBuilder::new()
+            .nonblocking(false)
+            .verify_nonblocking(true);
+        let e = builder.build(a).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        b.set_nonblocking(true)?;
+        builder.build(b)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_allow_fallback() -> io::Result<()> {
+        let file = FileBuilder::new().build(std::fs::File::open("Cargo.toml")?)?;
+        assert!(file.is_fallback_active());
+        let e = FileBuilder::new()
+            .allow_fallback(false)
+            .build(file.into_inner())
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        assert!(e.get_ref().unwrap().is::<crate::NotPollable>());
+
+        let (a, _b) = UnixStream::pair()?;
+        let file = FileBuilder::new().allow_fallback(false).build(a)?;
+        assert!(!file.is_fallback_active());
+        Ok(())
+    }
+
+    /// A socket whose first read fails with `EINTR`.
+    struct Interrupting(UnixStream, bool);
+
+    impl AsRawFd for Interrupting {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    impl io::Read for Interrupting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !std::mem::replace(&mut self.1, true) {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            io::Read::read(&mut self.0, buf)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_interrupted() -> io::Result<()> {
+        use std::io::Write;
+        use tokio::io::AsyncReadExt;
+        for &retry in &[true, false] {
+            let (a, mut b) = UnixStream::pair()?;
+            b.write_all(b"x")?;
+            let mut file = FileBuilder::new()
+                .retry_interrupted(retry)
+                .build(Interrupting(a, false))?;
+            let mut buf = [0; 1];
+            match file.read(&mut buf).await {
+                Ok(n) => assert!(retry && n == 1),
+                Err(e) => assert!(!retry && e.kind() == io::ErrorKind::Interrupted),
+            }
+        }
+        Ok(())
+    }
+
+    /// A socket that counts its reads.
+    struct Counting(UnixStream, usize);
+
+    impl AsRawFd for Counting {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    impl io::Read for Counting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.1 += 1;
+            io::Read::read(&mut self.0, buf)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_edge_triggered() -> io::Result<()> {
+        use std::io::Write;
+        use std::time::Duration;
+        use tokio::io::AsyncReadExt;
+        for &edge in &[false, true] {
+            let (a, mut b) = UnixStream::pair()?;
+            b.write_all(b"batch")?;
+            let mut file = FileBuilder::new()
+                .edge_triggered(edge)
+                .build(Counting(a, 0))?;
+            let mut buf = [0; 16];
+            assert_eq!(file.read(&mut buf).await?, 5);
+            let wait = Duration::from_millis(20);
+            assert!(tokio::time::timeout(wait, file.read(&mut buf))
+                .await
+                .is_err());
+            // the short read showed that there was nothing left
+            assert_eq!(file.get_ref().1, if edge { 1 } else { 2 });
+            b.write_all(b"more")?;
+            assert_eq!(file.read(&mut buf).await?, 4);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_repoll_after() -> io::Result<()> {
+        use crate::RetryPolicy;
+        use std::io::Write;
+        use std::time::Duration;
+        use tokio::io::AsyncReadExt;
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(5))
+            .max_delay(Duration::from_millis(10));
+        for &repoll in &[false, true] {
+            let (a, mut b) = UnixStream::pair()?;
+            // read readiness is never reported, as with a broken driver
+            let mut builder = FileBuilder::new().interest(Interest::WRITABLE);
+            if repoll {
+                builder = builder.repoll_after(0, policy);
+            }
+            let mut file = builder.build(a)?;
+            b.write_all(b"late")?;
+            let mut buf = [0; 16];
+            let read = tokio::time::timeout(Duration::from_millis(100), file.read(&mut buf)).await;
+            assert_eq!(read.ok().transpose()?, if repoll { Some(4) } else { None });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+//! Reading a file as a stream of byte chunks and writing it as a sink.
+
+use crate::{BufferPool, File};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::mem::MaybeUninit;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{io, mem};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The default number of bytes a [`BytesSink`](struct.BytesSink.html)
+/// buffers before it waits for the file.
+const DEFAULT_BUFFER_LIMIT: usize = 64 * 1024;
+
+impl<F> File<F> {
+    /// Turns the file into a stream of the chunks read from it, each at most
+    /// `capacity` bytes, ending at EOF.
+    ///
+    /// The chunks are carved out of a single `BytesMut`, whose memory is
+    /// reused once the chunks handed out earlier are dropped, so passing
+    /// binary data on to a channel needs no read loop and little
+    /// allocation.  If `capacity` is the buffer size of the
+    /// [`BufferPool`](struct.BufferPool.html), which is the global one
+    /// unless set with
+    /// [`buffer_pool`](struct.BytesStream.html#method.buffer_pool), the
+    /// buffer is instead taken from the pool, and given back while the
+    /// stream waits for data if no chunk has been carved out of it.
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn into_bytes_stream(self, capacity: usize) -> BytesStream<F> {
+        assert!(capacity > 0, "zero capacity");
+        BytesStream {
+            inner: self,
+            buf: BytesMut::new(),
+            pool: BufferPool::global(),
+            capacity,
+            eof: false,
+        }
+    }
+
+    /// Turns the file into a sink that writes the chunks sent to it, for
+    /// example as the target of `StreamExt::forward`.
+    ///
+    /// Chunks are buffered until the
+    /// [buffer limit](struct.BytesSink.html#method.buffer_limit) is reached,
+    /// after which the sink is not ready until the file has accepted enough
+    /// of them.  Flushing the sink writes out the buffer and flushes the
+    /// file, and closing it also shuts down the writing side of the file.
+    /// If the buffer limit is the buffer size of the
+    /// [`BufferPool`](struct.BufferPool.html), the buffer is taken from the
+    /// pool while it has data in it.
+    pub fn into_bytes_sink(self) -> BytesSink<F> {
+        BytesSink {
+            inner: self,
+            buf: BytesMut::new(),
+            pool: BufferPool::global(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+        }
+    }
+}
+
+/// A stream of the chunks read from a [`File`](struct.File.html), created by
+/// [`File::into_bytes_stream`](struct.File.html#method.into_bytes_stream).
+#[derive(Debug)]
+pub struct BytesStream<F> {
+    inner: File<F>,
+    buf: BytesMut,
+    pool: Arc<BufferPool>,
+    capacity: usize,
+    eof: bool,
+}
+
+impl<F> BytesStream<F> {
+    /// Takes the buffer from `pool` instead of the global pool, if its
+    /// buffer size is the capacity of the stream.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Returns the file.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for BytesStream<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> Stream for BytesStream<F> {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.eof {
+            return Poll::Ready(None);
+        }
+        let pooled = this.pool.serves(this.capacity);
+        if pooled && this.buf.capacity() < this.capacity {
+            this.buf = this.pool.get();
+        } else {
+            // reclaims the memory of earlier chunks if they were all dropped
+            this.buf.reserve(this.capacity);
+        }
+        let dst = this.buf.chunk_mut();
+        let dst = unsafe { &mut *(dst as *mut _ as *mut [MaybeUninit<u8>]) };
+        let mut buf = ReadBuf::uninit(&mut dst[..this.capacity]);
+        let n = match Pin::new(&mut this.inner).poll_read(cx, &mut buf) {
+            Poll::Ready(Ok(())) => buf.filled().len(),
+            r => {
+                if pooled {
+                    this.pool.put(mem::take(&mut this.buf));
+                }
+                return r.map(|r| r.err().map(Err));
+            }
+        };
+        if n == 0 {
+            if pooled {
+                this.pool.put(mem::take(&mut this.buf));
+            }
+            this.eof = true;
+            return Poll::Ready(None);
+        }
+        // the first `n` bytes were initialized by the read
+        unsafe { this.buf.advance_mut(n) };
+        Poll::Ready(Some(Ok(this.buf.split().freeze())))
+    }
+}
+
+/// A sink of byte chunks written to a [`File`](struct.File.html), created
+/// by [`File::into_bytes_sink`](struct.File.html#method.into_bytes_sink).
+#[derive(Debug)]
+pub struct BytesSink<F> {
+    inner: File<F>,
+    buf: BytesMut,
+    pool: Arc<BufferPool>,
+    buffer_limit: usize,
+}
+
+impl<F> BytesSink<F> {
+    /// Sets how many bytes may be buffered before the sink waits for the
+    /// file.  Defaults to 64 KiB.
+    pub fn buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+
+    /// Takes the buffer from `pool` instead of the global pool, if its
+    /// buffer size is the buffer limit.
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Returns the file.  Any buffered data is lost.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> BytesSink<F> {
+    /// Writes from the buffer until no more than `limit` bytes are left.
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>, limit: usize) -> Poll<io::Result<()>> {
+        while self.buf.len() > limit {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.buf.advance(n);
+        }
+        if self.buf.is_empty() && self.buf.capacity() > 0 && self.pool.serves(self.buffer_limit) {
+            self.pool.put(mem::take(&mut self.buf));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for BytesSink<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> Sink<Bytes> for BytesSink<F> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let limit = this.buffer_limit.saturating_sub(1);
+        this.poll_write_buf(cx, limit)
+    }
+
+    fn start_send(self: Pin<&mut Self>, chunk: Bytes) -> io::Result<()> {
+        let this = self.get_mut();
+        if this.buf.capacity() == 0 && this.pool.serves(this.buffer_limit) {
+            this.buf = this.pool.get();
+        }
+        this.buf.extend_from_slice(&chunk);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx, 0))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_bytes_stream() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        writer.write_all(&[7; 64]).await?;
+        drop(writer);
+        let mut chunks = reader.into_inner().into_bytes_stream(4);
+        let mut addrs = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            assert_eq!(chunk, &[7; 4][..]);
+            addrs.push(chunk.as_ptr() as usize);
+        }
+        assert_eq!(addrs.len(), 16);
+        // the chunks were dropped right away, so the memory was reused
+        let span = addrs.iter().max().unwrap() - addrs.iter().min().unwrap();
+        assert!(span < 16, "chunks spread over {} bytes", span);
+        assert!(chunks.next().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bytes_sink() -> io::Result<()> {
+        let (a_reader, mut a_writer) = crate::pipe()?;
+        let (mut b_reader, b_writer) = crate::pipe()?;
+        let data: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        let expected = data.clone();
+        let writer = tokio::spawn(async move {
+            a_writer.write_all(&data).await?;
+            a_writer.shutdown().await
+        });
+        let chunks = a_reader.into_inner().into_bytes_stream(1000);
+        let sink = b_writer.into_inner().into_bytes_sink().buffer_limit(4096);
+        let forward = tokio::spawn(chunks.forward(sink));
+        let mut received = Vec::new();
+        b_reader.read_to_end(&mut received).await?;
+        writer.await.unwrap()?;
+        forward.await.unwrap()?;
+        assert_eq!(received, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+//! Wrapping the standard streams of child processes.
+
+use crate::File;
+use std::os::unix::io::OwnedFd;
+use std::process::{ChildStderr, ChildStdin, ChildStdout};
+use std::{fs, io};
+
+impl File<fs::File> {
+    /// Wraps the standard input of a child process spawned with
+    /// `Stdio::piped()`, *enabling nonblocking mode* on it.
+    ///
+    /// The pipe is not shared with anyone else, so this cannot affect other
+    /// processes the way wrapping the standard streams of this process can.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn from_child_stdin(stdin: ChildStdin) -> io::Result<Self> {
+        File::from_owned_fd(OwnedFd::from(stdin))
+    }
+
+    /// Wraps the standard output of a child process, like
+    /// [`from_child_stdin`](#method.from_child_stdin).
+    pub fn from_child_stdout(stdout: ChildStdout) -> io::Result<Self> {
+        File::from_owned_fd(OwnedFd::from(stdout))
+    }
+
+    /// Wraps the standard error of a child process, like
+    /// [`from_child_stdin`](#method.from_child_stdin).
+    pub fn from_child_stderr(stderr: ChildStderr) -> io::Result<Self> {
+        File::from_owned_fd(OwnedFd::from(stderr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_child_pipes() -> io::Result<()> {
+        let mut child = Command::new("sh")
+            .args(["-c", "cat; echo oops >&2"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = File::from_child_stdin(child.stdin.take().unwrap())?;
+        let mut stdout = File::from_child_stdout(child.stdout.take().unwrap())?;
+        let mut stderr = File::from_child_stderr(child.stderr.take().unwrap())?;
+        assert!(crate::get_nonblocking(&stdout)?);
+        stdin.write_all(b"echo").await?;
+        drop(stdin);
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).await?;
+        assert_eq!(output, b"echo");
+        let mut errors = Vec::new();
+        stderr.read_to_end(&mut errors).await?;
+        assert_eq!(errors, b"oops\n");
+        assert!(child.wait()?.success());
+        Ok(())
+    }
+}
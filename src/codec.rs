@@ -0,0 +1,235 @@
+//! A codec for frames separated by a delimiter, for use with
+//! `tokio_util::codec::{FramedRead, FramedWrite}`.
+//!
+//! Requires the `codec` feature.
+//!
+//! ```
+//! # async fn run() -> std::io::Result<()> {
+//! use futures::StreamExt;
+//! use tokio_file_unix::codec::DelimCodec;
+//! use tokio_util::codec::FramedRead;
+//!
+//! let file = tokio_file_unix::File::new_nb(tokio_file_unix::raw_stdin()?)?;
+//! let mut framed = FramedRead::new(file, DelimCodec::nul().max_frame_len(4096));
+//! while let Some(frame) = framed.next().await {
+//!     println!("{:?}", frame?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::records::RecordTooLong;
+use bytes::{BufMut, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Splits a byte stream into frames separated by a delimiter, which may be
+/// a single byte or a sequence of bytes, and writes frames followed by the
+/// delimiter.
+///
+/// Frames are decoded without the delimiter.  A final frame that is not
+/// followed by a delimiter is decoded at EOF.
+///
+/// By default, frames may be of any length.  With a
+/// [maximum frame length](#method.max_frame_len), a longer frame is
+/// reported as a [`RecordTooLong`](../struct.RecordTooLong.html) error of
+/// kind `InvalidData` as soon as it exceeds the limit, and the rest of it is
+/// discarded, so the decoder never buffers much more than the limit and can
+/// keep being used afterwards.  Encoding a longer frame fails with
+/// `InvalidInput`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DelimCodec {
+    delimiter: Vec<u8>,
+    max_len: usize,
+    /// Where to resume searching for the delimiter.
+    next_index: usize,
+    /// Whether the rest of an overlong frame is being discarded.
+    discarding: bool,
+}
+
+impl DelimCodec {
+    /// Creates a codec for frames separated by `delimiter`.
+    ///
+    /// Panics if `delimiter` is empty.
+    pub fn new<D: Into<Vec<u8>>>(delimiter: D) -> Self {
+        let delimiter = delimiter.into();
+        assert!(!delimiter.is_empty(), "empty delimiter");
+        DelimCodec {
+            delimiter,
+            max_len: usize::MAX,
+            next_index: 0,
+            discarding: false,
+        }
+    }
+
+    /// Creates a codec for lines separated by `\n`.
+    pub fn newline() -> Self {
+        Self::new(&b"\n"[..])
+    }
+
+    /// Creates a codec for frames separated by NUL bytes, as produced by
+    /// `find -print0`.
+    pub fn nul() -> Self {
+        Self::new(&b"\0"[..])
+    }
+
+    /// Sets the maximum length of a frame, excluding the delimiter.
+    pub fn max_frame_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// The delimiter.
+    pub fn delimiter(&self) -> &[u8] {
+        &self.delimiter
+    }
+
+    fn find_delimiter(&self, buf: &[u8]) -> Option<usize> {
+        buf[self.next_index..]
+            .windows(self.delimiter.len())
+            .position(|window| window == &self.delimiter[..])
+            .map(|i| self.next_index + i)
+    }
+}
+
+impl Decoder for DelimCodec {
+    type Item = BytesMut;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        loop {
+            let delim_len = self.delimiter.len();
+            match self.find_delimiter(buf) {
+                Some(end) => {
+                    self.next_index = 0;
+                    let mut frame = buf.split_to(end + delim_len);
+                    if self.discarding {
+                        self.discarding = false;
+                        continue;
+                    }
+                    frame.truncate(end);
+                    if end > self.max_len {
+                        return Err(RecordTooLong {
+                            max_len: self.max_len,
+                        }
+                        .into());
+                    }
+                    return Ok(Some(frame));
+                }
+                None => {
+                    // the delimiter may start in the last few bytes
+                    let searched = buf.len().saturating_sub(delim_len - 1);
+                    if self.discarding {
+                        let _ = buf.split_to(searched);
+                        self.next_index = 0;
+                        return Ok(None);
+                    }
+                    if searched > self.max_len {
+                        let _ = buf.split_to(searched);
+                        self.next_index = 0;
+                        self.discarding = true;
+                        return Err(RecordTooLong {
+                            max_len: self.max_len,
+                        }
+                        .into());
+                    }
+                    self.next_index = searched;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if let Some(frame) = self.decode(buf)? {
+            return Ok(Some(frame));
+        }
+        self.next_index = 0;
+        if std::mem::replace(&mut self.discarding, false) || buf.is_empty() {
+            buf.clear();
+            return Ok(None);
+        }
+        if buf.len() > self.max_len {
+            buf.clear();
+            return Err(RecordTooLong {
+                max_len: self.max_len,
+            }
+            .into());
+        }
+        Ok(Some(buf.split()))
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for DelimCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: T, buf: &mut BytesMut) -> io::Result<()> {
+        let frame = frame.as_ref();
+        if frame.len() > self.max_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                RecordTooLong {
+                    max_len: self.max_len,
+                },
+            ));
+        }
+        buf.reserve(frame.len() + self.delimiter.len());
+        buf.put_slice(frame);
+        buf.put_slice(&self.delimiter);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use tokio_util::codec::{FramedRead, FramedWrite};
+
+    #[test]
+    fn test_decode() {
+        let mut codec = DelimCodec::new(&b"\r\n"[..]).max_frame_len(4);
+        let mut buf = BytesMut::from(&b"one\r"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"\ntwo\r\nmuch");
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b"one"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b"two"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        // the overlong frame is reported before its end arrives
+        buf.put_slice(b" too long");
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert!(err.get_ref().unwrap().is::<RecordTooLong>());
+        assert!(buf.len() < 4);
+        buf.put_slice(b"\r\n\r\nend");
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b""[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap().unwrap(), &b"end"[..]);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_delim_codec() -> io::Result<()> {
+        let input = &b"a\0\0end\0much too long"[..];
+        let frames: Vec<_> = FramedRead::new(input, DelimCodec::nul().max_frame_len(4))
+            .map(|r| r.map_err(|e| e.kind()))
+            .collect()
+            .await;
+        assert_eq!(
+            frames,
+            vec![
+                Ok(BytesMut::from(&b"a"[..])),
+                Ok(BytesMut::new()),
+                Ok(BytesMut::from(&b"end"[..])),
+                Err(io::ErrorKind::InvalidData),
+            ]
+        );
+
+        let mut output = FramedWrite::new(Vec::new(), DelimCodec::newline().max_frame_len(4));
+        output.send(&b"one"[..]).await?;
+        output.send(&b""[..]).await?;
+        let err = output.send(&b"three"[..]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(output.get_ref(), b"one\n\n");
+        Ok(())
+    }
+}
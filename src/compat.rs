@@ -0,0 +1,142 @@
+//! The `AsyncRead` and `AsyncWrite` traits of `futures-io`, for libraries
+//! written against them rather than Tokio's.
+
+use crate::reactor::{Evented, Reactor};
+use crate::{File, PipeReader, PipeWriter};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::ReadBuf;
+
+/// Implements `futures_io::AsyncRead` in terms of `tokio::io::AsyncRead`.
+fn poll_read<R: tokio::io::AsyncRead>(
+    reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>> {
+    let mut buf = ReadBuf::new(buf);
+    ready!(reader.poll_read(cx, &mut buf))?;
+    Poll::Ready(Ok(buf.filled().len()))
+}
+
+/// Implements `futures_io::AsyncWrite` in terms of `tokio::io::AsyncWrite`.
+macro_rules! forward_write {
+    () => {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            tokio::io::AsyncWrite::poll_write(self, cx, buf)
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            bufs: &[io::IoSlice<'_>],
+        ) -> Poll<io::Result<usize>> {
+            tokio::io::AsyncWrite::poll_write_vectored(self, cx, bufs)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            tokio::io::AsyncWrite::poll_flush(self, cx)
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            tokio::io::AsyncWrite::poll_shutdown(self, cx)
+        }
+    };
+}
+
+impl<F: AsRawFd + io::Read + Unpin> futures_io::AsyncRead for File<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_read(self, cx, buf)
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> futures_io::AsyncWrite for File<F> {
+    forward_write!();
+}
+
+impl<'a, F> futures_io::AsyncRead for &'a File<F>
+where
+    F: AsRawFd,
+    &'a F: io::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_read(self, cx, buf)
+    }
+}
+
+impl<'a, F> futures_io::AsyncWrite for &'a File<F>
+where
+    F: AsRawFd,
+    &'a F: io::Write,
+{
+    forward_write!();
+}
+
+impl futures_io::AsyncRead for PipeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_read(self, cx, buf)
+    }
+}
+
+impl futures_io::AsyncWrite for PipeWriter {
+    forward_write!();
+}
+
+impl<F: io::Read + Unpin, R: Reactor> futures_io::AsyncRead for Evented<F, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        poll_read(self, cx, buf)
+    }
+}
+
+impl<F: io::Write + Unpin, R: Reactor> futures_io::AsyncWrite for Evented<F, R> {
+    forward_write!();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::File;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+    use std::io;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_futures_io() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let b = File::new_nb(b)?;
+        a.write_all(b"futures").await?;
+        a.close().await?;
+        let mut received = Vec::new();
+        (&b).read_to_end(&mut received).await?;
+        assert_eq!(received, b"futures");
+
+        let (mut reader, mut writer) = crate::pipe()?;
+        writer.write_all(b"pipe").await?;
+        drop(writer);
+        received.clear();
+        reader.read_to_end(&mut received).await?;
+        assert_eq!(received, b"pipe");
+        Ok(())
+    }
+}
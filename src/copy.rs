@@ -0,0 +1,621 @@
+//! Bidirectional copying between two wrapped files.
+
+use crate::adaptive::AdaptiveBuf;
+use crate::backpressure::{self, BackpressureMonitor, Side};
+use crate::pipe::{PipePool, PooledPipe};
+use crate::{poll_now, FdKind, File, ReadyGuard};
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// What to do once one direction of a bidirectional copy reaches EOF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnEof {
+    /// Shut down the writing side of the destination, as with
+    /// `AsyncWrite::poll_shutdown`, and keep copying in the other direction.
+    /// This half-closes sockets and closes the writing end of pipes, so that
+    /// the peer sees EOF.
+    Shutdown,
+    /// Leave the destination untouched and keep copying in the other
+    /// direction.
+    Linger,
+    /// Stop copying in both directions as soon as either one reaches EOF.
+    CloseBoth,
+}
+
+/// Options for [`copy_bidirectional_with`](fn.copy_bidirectional_with.html).
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    on_eof: [OnEof; 2],
+    budget: usize,
+    buffer_size: usize,
+    adaptive: bool,
+    splice: bool,
+    monitors: [Option<BackpressureMonitor>; 2],
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            on_eof: [OnEof::Shutdown, OnEof::Shutdown],
+            budget: 128 * 1024,
+            buffer_size: 8 * 1024,
+            adaptive: true,
+            splice: true,
+            monitors: [None, None],
+        }
+    }
+}
+
+impl CopyOptions {
+    /// Equivalent to `CopyOptions::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the EOF behavior of both directions.  Defaults to
+    /// `OnEof::Shutdown`.
+    pub fn on_eof(mut self, on_eof: OnEof) -> Self {
+        self.on_eof = [on_eof, on_eof];
+        self
+    }
+
+    /// Sets the behavior when `a` reaches EOF (i.e. the `a` to `b` direction
+    /// is finished).
+    pub fn on_a_eof(mut self, on_eof: OnEof) -> Self {
+        self.on_eof[0] = on_eof;
+        self
+    }
+
+    /// Sets the behavior when `b` reaches EOF (i.e. the `b` to `a` direction
+    /// is finished).
+    pub fn on_b_eof(mut self, on_eof: OnEof) -> Self {
+        self.on_eof[1] = on_eof;
+        self
+    }
+
+    /// Sets the number of bytes a single direction may transfer before the
+    /// copy yields back to the executor, so that a busy pipe cannot starve
+    /// other tasks.  Defaults to 128 KiB.
+    pub fn budget(mut self, budget: usize) -> Self {
+        self.budget = budget.max(1);
+        self
+    }
+
+    /// Sets the size of the intermediate buffer of each direction (or the
+    /// maximum length of each `splice` call).  With an adaptive buffer, this
+    /// is the size it may grow to.  Defaults to 8 KiB.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Enables or disables adaptive sizing of the intermediate buffers.  An
+    /// adaptive buffer starts at 512 bytes and grows up to the buffer size
+    /// while reads keep filling it, or when more data than fits is waiting,
+    /// and shrinks again once reads stay small, so that many slow copies
+    /// hold little memory.  Defaults to `true`.
+    pub fn adaptive_buffer(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Enables or disables the `splice` fast path on Linux.  If neither side
+    /// is a pipe, data is spliced through a scratch pipe taken from
+    /// [`PipePool::global`](struct.PipePool.html#method.global).  Files that
+    /// cannot be spliced automatically fall back to an ordinary copy, as do
+    /// files offloaded to the thread pool or with a quota, observer, or
+    /// statistics, which splicing would bypass.  Defaults to `true`.
+    pub fn splice(mut self, splice: bool) -> Self {
+        self.splice = splice;
+        self
+    }
+
+    /// Records the backpressure of the `a` to `b` direction in `monitor`:
+    /// waiting for `a` counts as input starvation and waiting for `b` as a
+    /// blocked output.
+    pub fn monitor_a_to_b(mut self, monitor: BackpressureMonitor) -> Self {
+        self.monitors[0] = Some(monitor);
+        self
+    }
+
+    /// Records the backpressure of the `b` to `a` direction in `monitor`.
+    pub fn monitor_b_to_a(mut self, monitor: BackpressureMonitor) -> Self {
+        self.monitors[1] = Some(monitor);
+        self
+    }
+}
+
+/// Copies data in both directions between `a` and `b` until both directions
+/// reach EOF, returning the number of bytes copied from `a` to `b` and from
+/// `b` to `a` respectively.
+///
+/// Unlike `tokio::io::copy`, this understands pipes and terminals: a pty
+/// master whose slave has hung up reports `EIO`, which is treated as EOF
+/// rather than an error.
+///
+/// See [`copy_bidirectional_with`](fn.copy_bidirectional_with.html) to
+/// customize the behavior.
+pub async fn copy_bidirectional<A, B>(a: &mut File<A>, b: &mut File<B>) -> io::Result<(u64, u64)>
+where
+    A: AsRawFd + Read + Write + Unpin,
+    B: AsRawFd + Read + Write + Unpin,
+{
+    copy_bidirectional_with(a, b, &CopyOptions::default()).await
+}
+
+/// Like [`copy_bidirectional`](fn.copy_bidirectional.html) but with custom
+/// options.
+pub async fn copy_bidirectional_with<A, B>(
+    a: &mut File<A>,
+    b: &mut File<B>,
+    options: &CopyOptions,
+) -> io::Result<(u64, u64)>
+where
+    A: AsRawFd + Read + Write + Unpin,
+    B: AsRawFd + Read + Write + Unpin,
+{
+    let options = &if !a.is_spliceable() || !b.is_spliceable() {
+        options.clone().splice(false)
+    } else {
+        options.clone()
+    };
+    let direct = FdKind::of(&*a)? == FdKind::Pipe || FdKind::of(&*b)? == FdKind::Pipe;
+    CopyBidirectional {
+        a,
+        b,
+        a_to_b: Direction::new(options, 0, direct)?,
+        b_to_a: Direction::new(options, 1, direct)?,
+    }
+    .await
+}
+
+struct CopyBidirectional<'a, A: AsRawFd, B: AsRawFd> {
+    a: &'a mut File<A>,
+    b: &'a mut File<B>,
+    a_to_b: Direction,
+    b_to_a: Direction,
+}
+
+impl<'a, A, B> Future for CopyBidirectional<'a, A, B>
+where
+    A: AsRawFd + Read + Write + Unpin,
+    B: AsRawFd + Read + Write + Unpin,
+{
+    type Output = io::Result<(u64, u64)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if !this.a_to_b.done {
+            if let Poll::Ready(r) = this.a_to_b.poll_copy(cx, this.a, this.b) {
+                r?;
+                if let Poll::Ready(r) = this.a_to_b.poll_finish(cx, this.b) {
+                    if r? {
+                        return Poll::Ready(Ok((this.a_to_b.amt, this.b_to_a.amt)));
+                    }
+                }
+            }
+        }
+        if !this.b_to_a.done {
+            if let Poll::Ready(r) = this.b_to_a.poll_copy(cx, this.b, this.a) {
+                r?;
+                if let Poll::Ready(r) = this.b_to_a.poll_finish(cx, this.a) {
+                    if r? {
+                        return Poll::Ready(Ok((this.a_to_b.amt, this.b_to_a.amt)));
+                    }
+                }
+            }
+        }
+        if this.a_to_b.done && this.b_to_a.done {
+            Poll::Ready(Ok((this.a_to_b.amt, this.b_to_a.amt)))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+enum Splice {
+    Off,
+    /// One side is a pipe, so data can be spliced directly.
+    Direct,
+    /// Neither side is a pipe, so data is spliced through a scratch pipe
+    /// currently holding the given number of bytes.
+    Scratch(PooledPipe<'static>, usize),
+}
+
+/// The initial size of an adaptive buffer.
+const MIN_ADAPTIVE_BUFFER: usize = 512;
+
+struct Direction {
+    buf: AdaptiveBuf,
+    splice_len: usize,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    budget: usize,
+    on_eof: OnEof,
+    splice: Splice,
+    read_done: bool,
+    done: bool,
+    monitor: Option<BackpressureMonitor>,
+    /// The end the last poll was left waiting for, if any.
+    waiting: Option<Side>,
+}
+
+impl Direction {
+    fn new(options: &CopyOptions, index: usize, direct: bool) -> io::Result<Self> {
+        let splice = if !options.splice || !cfg!(any(target_os = "linux", target_os = "android")) {
+            Splice::Off
+        } else if direct {
+            Splice::Direct
+        } else {
+            Splice::Scratch(PipePool::global().get()?, 0)
+        };
+        Ok(Direction {
+            buf: if options.adaptive {
+                AdaptiveBuf::new(MIN_ADAPTIVE_BUFFER, options.buffer_size)
+            } else {
+                AdaptiveBuf::new(options.buffer_size, options.buffer_size)
+            },
+            splice_len: options.buffer_size,
+            pos: 0,
+            cap: 0,
+            amt: 0,
+            budget: options.budget,
+            on_eof: options.on_eof[index],
+            splice,
+            read_done: false,
+            done: false,
+            monitor: options.monitors[index].clone(),
+            waiting: None,
+        })
+    }
+
+    fn poll_copy<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &mut File<R>,
+        dst: &mut File<W>,
+    ) -> Poll<io::Result<()>>
+    where
+        R: AsRawFd + Read + Unpin,
+        W: AsRawFd + Write + Unpin,
+    {
+        if self.monitor.is_none() {
+            return self.poll_copy_inner(cx, src, dst, &mut 0);
+        }
+        let mut moved = 0;
+        self.waiting = None;
+        let r = self.poll_copy_inner(cx, src, dst, &mut moved);
+        if moved > 0 || r.is_ready() {
+            backpressure::note(&self.monitor, None);
+        }
+        if r.is_pending() && self.waiting.is_some() {
+            backpressure::note(&self.monitor, self.waiting);
+        }
+        r
+    }
+
+    /// Copies until blocked, adding the number of bytes moved to `moved`.
+    /// Before anything that might block, notes in `waiting` which end it
+    /// waits for.
+    fn poll_copy_inner<R, W>(
+        &mut self,
+        cx: &mut Context<'_>,
+        src: &mut File<R>,
+        dst: &mut File<W>,
+        moved: &mut usize,
+    ) -> Poll<io::Result<()>>
+    where
+        R: AsRawFd + Read + Unpin,
+        W: AsRawFd + Write + Unpin,
+    {
+        loop {
+            if self.pos == self.cap && !self.read_done {
+                self.waiting = Some(Side::Input);
+                match self.splice {
+                    Splice::Direct => {
+                        let mut src_ready = ready!(src.poll_read_ready(cx))?;
+                        self.waiting = Some(Side::Output);
+                        let mut dst_ready = ready!(dst.poll_write_ready(cx))?;
+                        match splice(src.as_raw_fd(), dst.as_raw_fd(), self.splice_len) {
+                            Ok(0) => self.read_done = true,
+                            Ok(n) => {
+                                self.amt += n as u64;
+                                *moved += n;
+                            }
+                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                                match clear_splice_blocked(
+                                    (src.as_raw_fd(), &mut src_ready),
+                                    (dst.as_raw_fd(), &mut dst_ready),
+                                )? {
+                                    (false, false) => {
+                                        self.waiting = None;
+                                        cx.waker().wake_by_ref();
+                                        return Poll::Pending;
+                                    }
+                                    (true, _) => self.waiting = Some(Side::Input),
+                                    (false, true) => {}
+                                }
+                            }
+                            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                                self.splice = Splice::Off;
+                            }
+                            Err(ref e) if is_hangup(e, src.as_raw_fd()) => self.read_done = true,
+                            Err(e) => return Poll::Ready(Err(e)),
+                        }
+                    }
+                    Splice::Scratch(ref pipe, ref mut pending) if *pending == 0 => {
+                        let mut src_ready = ready!(src.poll_read_ready(cx))?;
+                        match splice(src.as_raw_fd(), pipe.writer(), self.splice_len) {
+                            Ok(0) => self.read_done = true,
+                            Ok(n) => *pending = n,
+                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                                src_ready.clear_ready();
+                            }
+                            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                                self.splice = Splice::Off;
+                            }
+                            Err(ref e) if is_hangup(e, src.as_raw_fd()) => self.read_done = true,
+                            Err(e) => return Poll::Ready(Err(e)),
+                        }
+                    }
+                    Splice::Scratch(..) => {}
+                    Splice::Off => {
+                        self.buf.prepare(Some(src.as_raw_fd()));
+                        let mut buf = ReadBuf::new(&mut self.buf);
+                        match ready!(Pin::new(&mut *src).poll_read(cx, &mut buf)) {
+                            Ok(()) if buf.filled().is_empty() => self.read_done = true,
+                            Ok(()) => {
+                                let n = buf.filled().len();
+                                self.buf.record(n);
+                                self.pos = 0;
+                                self.cap = n;
+                            }
+                            Err(ref e) if is_hangup(e, src.as_raw_fd()) => self.read_done = true,
+                            Err(e) => return Poll::Ready(Err(e)),
+                        }
+                    }
+                }
+            }
+
+            let mut unspliceable = false;
+            if let Splice::Scratch(ref pipe, ref mut pending) = self.splice {
+                self.waiting = Some(Side::Output);
+                while *pending > 0 {
+                    let mut dst_ready = ready!(dst.poll_write_ready(cx))?;
+                    match splice(pipe.reader(), dst.as_raw_fd(), *pending) {
+                        Ok(0) => return Poll::Ready(Err(write_zero())),
+                        Ok(n) => {
+                            *pending -= n;
+                            self.amt += n as u64;
+                            *moved += n;
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            dst_ready.clear_ready();
+                        }
+                        Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                            // move what is stranded in the pipe into the buffer
+                            self.buf.ensure(*pending);
+                            let n = unsafe {
+                                libc::read(
+                                    pipe.reader(),
+                                    self.buf.as_mut_ptr() as *mut libc::c_void,
+                                    *pending,
+                                )
+                            };
+                            if n < 0 {
+                                return Poll::Ready(Err(io::Error::last_os_error()));
+                            }
+                            self.pos = 0;
+                            self.cap = n as usize;
+                            unspliceable = true;
+                            break;
+                        }
+                        Err(e) => return Poll::Ready(Err(e)),
+                    }
+                }
+            }
+            if unspliceable {
+                self.splice = Splice::Off;
+            }
+
+            self.waiting = Some(Side::Output);
+            while self.pos < self.cap {
+                let n = ready!(Pin::new(&mut *dst).poll_write(cx, &self.buf[self.pos..self.cap]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(write_zero()));
+                }
+                self.pos += n;
+                self.amt += n as u64;
+                *moved += n;
+            }
+
+            if self.read_done {
+                ready!(Pin::new(&mut *dst).poll_flush(cx))?;
+                return Poll::Ready(Ok(()));
+            }
+            if *moved >= self.budget {
+                self.waiting = None;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Applies the EOF behavior to the destination and marks the direction
+    /// as done.  Returns whether the whole copy should stop.
+    fn poll_finish<W>(&mut self, cx: &mut Context<'_>, dst: &mut File<W>) -> Poll<io::Result<bool>>
+    where
+        W: AsRawFd + Write + Unpin,
+    {
+        if self.on_eof == OnEof::Shutdown {
+            ready!(Pin::new(dst).poll_shutdown(cx))?;
+        }
+        self.done = true;
+        Poll::Ready(Ok(self.on_eof == OnEof::CloseBoth))
+    }
+}
+
+fn write_zero() -> io::Error {
+    io::Error::new(io::ErrorKind::WriteZero, "write zero byte into writer")
+}
+
+/// A pty master reports `EIO` once its slave has been closed.
+fn is_hangup(e: &io::Error, fd: RawFd) -> bool {
+    e.raw_os_error() == Some(libc::EIO) && unsafe { libc::isatty(fd) } == 1
+}
+
+/// Clears the readiness of whichever of the source and destination made a
+/// `splice` fail with `WouldBlock`, since the kernel does not say, and
+/// returns which ones did.  Both may still look ready, for example if the
+/// destination has room but less than a page, in which case the caller
+/// should yield rather than retry right away.
+pub(crate) fn clear_splice_blocked(
+    (src, src_ready): (RawFd, &mut ReadyGuard<'_>),
+    (dst, dst_ready): (RawFd, &mut ReadyGuard<'_>),
+) -> io::Result<(bool, bool)> {
+    let src_blocked = !poll_now(src, libc::POLLIN)?;
+    if src_blocked {
+        src_ready.clear_ready();
+    }
+    let dst_blocked = !poll_now(dst, libc::POLLOUT)?;
+    if dst_blocked {
+        dst_ready.clear_ready();
+    }
+    Ok((src_blocked, dst_blocked))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn splice(src: RawFd, dst: RawFd, len: usize) -> io::Result<usize> {
+    let n = unsafe {
+        libc::splice(
+            src,
+            std::ptr::null_mut(),
+            dst,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn splice(_src: RawFd, _dst: RawFd, _len: usize) -> io::Result<usize> {
+    Err(io::Error::from_raw_os_error(libc::EINVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_copy_bidirectional() -> io::Result<()> {
+        let (a, mut a_peer) = UnixStream::pair()?;
+        let (b, mut b_peer) = UnixStream::pair()?;
+        a_peer.write_all(b"hello")?;
+        a_peer.shutdown(Shutdown::Write)?;
+        b_peer.shutdown(Shutdown::Write)?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        assert_eq!(copy_bidirectional(&mut a, &mut b).await?, (5, 0));
+        let mut received = Vec::new();
+        b_peer.read_to_end(&mut received)?;
+        assert_eq!(received, b"hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_pty_to_socket() -> io::Result<()> {
+        let (master, slave) = crate::terminal::tests::open_pty()?;
+        let (b, mut b_peer) = UnixStream::pair()?;
+        (&slave).write_all(b"prompt> ")?;
+        // the master reports EIO once the slave hangs up
+        drop(slave);
+        b_peer.shutdown(Shutdown::Write)?;
+        let mut a = File::new_nb(master)?;
+        let mut b = File::new_nb(b)?;
+        assert_eq!(copy_bidirectional(&mut a, &mut b).await?, (8, 0));
+        // the socket was half-closed after the data
+        let mut received = Vec::new();
+        b_peer.read_to_end(&mut received)?;
+        assert_eq!(received, b"prompt> ");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_bidirectional_buffered() -> io::Result<()> {
+        let (a, mut a_peer) = UnixStream::pair()?;
+        let (b, mut b_peer) = UnixStream::pair()?;
+        b_peer.write_all(b"world")?;
+        b_peer.shutdown(Shutdown::Write)?;
+        a_peer.shutdown(Shutdown::Write)?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        let options = CopyOptions::new().splice(false);
+        assert_eq!(
+            copy_bidirectional_with(&mut a, &mut b, &options).await?,
+            (0, 5)
+        );
+        let mut received = Vec::new();
+        a_peer.read_to_end(&mut received)?;
+        assert_eq!(received, b"world");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_with_stats() -> io::Result<()> {
+        let (a, mut a_peer) = UnixStream::pair()?;
+        let (b, mut b_peer) = UnixStream::pair()?;
+        a_peer.write_all(b"counted")?;
+        a_peer.shutdown(Shutdown::Write)?;
+        b_peer.shutdown(Shutdown::Write)?;
+        let mut a = crate::FileBuilder::new().collect_stats(true).build(a)?;
+        let mut b = File::new_nb(b)?;
+        assert_eq!(copy_bidirectional(&mut a, &mut b).await?, (7, 0));
+        assert_eq!(a.stats().unwrap().bytes_read, 7);
+        let mut received = Vec::new();
+        b_peer.read_to_end(&mut received)?;
+        assert_eq!(received, b"counted");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_monitor() -> io::Result<()> {
+        use std::time::Duration;
+        let (a, mut a_peer) = UnixStream::pair()?;
+        let (b, mut b_peer) = UnixStream::pair()?;
+        b_peer.shutdown(Shutdown::Write)?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        let monitor = BackpressureMonitor::new();
+        let options = CopyOptions::new().monitor_a_to_b(monitor.clone());
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            a_peer.write_all(b"late")?;
+            a_peer.shutdown(Shutdown::Write)
+        });
+        assert_eq!(
+            copy_bidirectional_with(&mut a, &mut b, &options).await?,
+            (4, 0)
+        );
+        writer.join().unwrap()?;
+        let stats = monitor.stats();
+        assert!(stats.input_starved >= Duration::from_millis(40));
+        assert!(stats.input_waits >= 1);
+        assert!(stats.output_blocked < stats.input_starved);
+        let mut received = Vec::new();
+        b_peer.read_to_end(&mut received)?;
+        assert_eq!(received, b"late");
+        Ok(())
+    }
+}
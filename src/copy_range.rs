@@ -0,0 +1,80 @@
+//! Copying between regular files with `copy_file_range(2)`.
+
+use crate::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::{io, ptr};
+
+/// Copies up to `len` bytes with as many `copy_file_range` calls as needed,
+/// stopping early only at EOF.
+fn copy_range(src: RawFd, dst: RawFd, len: u64) -> io::Result<u64> {
+    let mut copied = 0;
+    while copied < len {
+        let chunk = (len - copied).min(isize::MAX as u64) as usize;
+        let n =
+            unsafe { libc::copy_file_range(src, ptr::null_mut(), dst, ptr::null_mut(), chunk, 0) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            // report what was copied before the failure
+            if copied > 0 {
+                break;
+            }
+            return Err(e);
+        }
+        if n == 0 {
+            break;
+        }
+        copied += n as u64;
+    }
+    Ok(copied)
+}
+
+/// Copies up to `len` bytes from the current offset of `src` to the current
+/// offset of `dst`, advancing both, and returns the number of bytes copied,
+/// which is less than `len` only if `src` reached EOF.
+///
+/// The copy happens inside the kernel, which on filesystems such as Btrfs
+/// and XFS may share the extents instead of copying the data (a reflink).
+/// Since it can still take as long as an ordinary copy, it runs on Tokio's
+/// blocking thread pool.  Both files must be regular files; to move data in
+/// and out of pipes, see [`splice`](fn.splice.html).
+///
+/// Only available on Linux.
+pub async fn copy_file_range<R, W>(src: &File<R>, dst: &File<W>, len: u64) -> io::Result<u64>
+where
+    R: AsRawFd,
+    W: AsRawFd,
+{
+    // own the descriptors, since the copy outlives the future if it is
+    // cancelled
+    let src = unsafe { crate::dupe_file_from_fd(src.as_raw_fd())? };
+    let dst = unsafe { crate::dupe_file_from_fd(dst.as_raw_fd())? };
+    tokio::task::spawn_blocking(move || copy_range(src.as_raw_fd(), dst.as_raw_fd(), len))
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[tokio::test]
+    async fn test_copy_file_range() -> io::Result<()> {
+        let mut src = tempfile("copy-src")?;
+        src.write_all(b"copied in the kernel")?;
+        src.seek(SeekFrom::Start(10))?;
+        let src = File::raw_new(src)?;
+        let dst = File::raw_new(tempfile("copy-dst")?)?;
+        assert_eq!(copy_file_range(&src, &dst, 100).await?, 10);
+        let mut dst = dst.into_inner();
+        dst.seek(SeekFrom::Start(0))?;
+        let mut copied = String::new();
+        dst.read_to_string(&mut copied)?;
+        assert_eq!(copied, "the kernel");
+        Ok(())
+    }
+}
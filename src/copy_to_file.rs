@@ -0,0 +1,193 @@
+//! Saving a stream to a regular file without blocking the reactor.
+
+use crate::{BufferPool, FdKind, File};
+use bytes::BytesMut;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+
+/// The most bytes moved by each `splice`.
+const SPLICE_LEN: usize = 64 * 1024;
+
+/// Copies everything from `src` to `dst` until `src` reaches EOF and
+/// returns the number of bytes copied, for saving a stream such as the
+/// output of a child process to disk.
+///
+/// Regular files are always ready, so writing to one through `AsyncWrite`
+/// blocks the thread of the reactor for as long as the disk takes.  Here,
+/// `src` is waited for on the reactor, while `dst` is only ever written on
+/// Tokio's blocking thread pool, with a strategy chosen from the
+/// [`FdKind`](enum.FdKind.html) of both files:
+///
+/// - from a pipe to a regular file on Linux, the data is moved with
+///   `splice(2)`, without copying it through user space;
+/// - otherwise, or if the kernel refuses to splice, as it does for files
+///   opened for appending, it is read into a buffer from
+///   [`BufferPool::global`](struct.BufferPool.html#method.global) and
+///   written from there;
+/// - if `src` is not pollable either, as when it is a regular file too,
+///   the whole copy runs on the blocking pool.
+///
+/// Both files keep their offsets, which advance by the data copied.
+pub async fn copy_to_file<R, W>(src: &File<R>, dst: &File<W>) -> io::Result<u64>
+where
+    R: AsRawFd,
+    W: AsRawFd,
+{
+    src.check_pushback()?;
+    // own the descriptors, since the writes outlive the future if it is
+    // cancelled
+    let dst_file = unsafe { crate::dupe_file_from_fd(dst.as_raw_fd())? };
+    if src.is_fallback_active() {
+        let mut src_file = unsafe { crate::dupe_file_from_fd(src.as_raw_fd())? };
+        audit!(
+            "copy_to_file",
+            dst.as_raw_fd(),
+            "copying on the blocking pool"
+        );
+        return blocking(move || io::copy(&mut src_file, &mut &dst_file)).await;
+    }
+    let mut total = 0;
+    let splice = cfg!(any(target_os = "linux", target_os = "android"))
+        && FdKind::of(src)? == FdKind::Pipe
+        && FdKind::of(dst)? == FdKind::RegularFile;
+    let dst_file = if splice {
+        let src_file = unsafe { crate::dupe_file_from_fd(src.as_raw_fd())? };
+        match splice_to_file(src, src_file, dst_file, &mut total).await? {
+            Some(dst_file) => dst_file,
+            None => return Ok(total),
+        }
+    } else {
+        dst_file
+    };
+    let pool = BufferPool::global();
+    let mut buf = pool.get();
+    buf.resize(pool.buffer_size(), 0);
+    let r = copy_buffered(src, dst_file, &mut buf, &mut total).await;
+    pool.put(buf);
+    r.map(|()| total)
+}
+
+/// Splices from `src` into `dst_file` until EOF, returning `dst_file` back
+/// if the kernel cannot splice into it after all.
+async fn splice_to_file<R: AsRawFd>(
+    src: &File<R>,
+    src_file: fs::File,
+    dst_file: fs::File,
+    total: &mut u64,
+) -> io::Result<Option<fs::File>> {
+    let mut files = (src_file, dst_file);
+    loop {
+        let mut guard = src.readable().await?;
+        let ((moved, r), returned) = blocking(move || Ok((splice_all(&files), files))).await?;
+        files = returned;
+        *total += moved;
+        match r {
+            Ok(true) => return Ok(None),
+            Ok(false) => guard.clear_ready(),
+            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                audit!(
+                    "copy_to_file",
+                    src.as_raw_fd(),
+                    "cannot splice, copying instead"
+                );
+                return Ok(Some(files.1));
+            }
+            Err(e) => return crate::check_stale(src.as_raw_fd(), src.identity, Err(e)),
+        }
+    }
+}
+
+/// Splices from the first file to the second until the pipe is empty,
+/// returning how much was moved and whether EOF was reached.
+fn splice_all((src, dst): &(fs::File, fs::File)) -> (u64, io::Result<bool>) {
+    let mut moved = 0;
+    loop {
+        match crate::copy::splice(src.as_raw_fd(), dst.as_raw_fd(), SPLICE_LEN) {
+            Ok(0) => return (moved, Ok(true)),
+            Ok(n) => moved += n as u64,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return (moved, Ok(false)),
+            Err(e) => return (moved, Err(e)),
+        }
+    }
+}
+
+async fn copy_buffered<R: AsRawFd>(
+    src: &File<R>,
+    mut dst_file: fs::File,
+    buf: &mut BytesMut,
+    total: &mut u64,
+) -> io::Result<()> {
+    loop {
+        let n = loop {
+            let mut guard = src.readable().await?;
+            match src.try_read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                r => break r?,
+            }
+        };
+        if n == 0 {
+            return Ok(());
+        }
+        let data = std::mem::take(buf);
+        let (r, file, data) = blocking(move || {
+            let r = dst_file.write_all(&data[..n]);
+            Ok((r, dst_file, data))
+        })
+        .await?;
+        *buf = data;
+        dst_file = file;
+        r?;
+        *total += n as u64;
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile_with;
+    use std::io::{Read, Seek, SeekFrom};
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_copy_to_file() -> io::Result<()> {
+        // appending rules out splice, so this covers both strategies
+        for &append in &[false, true] {
+            let (reader, mut writer) = crate::pipe()?;
+            let reader = reader.into_inner();
+            let dst = tempfile_with(
+                "copy-to-file",
+                fs::OpenOptions::new()
+                    .read(true)
+                    .write(!append)
+                    .append(append)
+                    .truncate(!append),
+            )?;
+            let dst = File::raw_new(dst)?;
+            let writing = tokio::spawn(async move {
+                for _ in 0..100 {
+                    writer.write_all(&[b'x'; 1000]).await?;
+                }
+                writer.write_all(b"end").await
+            });
+            assert_eq!(copy_to_file(&reader, &dst).await?, 100_003);
+            writing.await.unwrap()?;
+            let mut dst = dst.into_inner();
+            dst.seek(SeekFrom::Start(0))?;
+            let mut saved = Vec::new();
+            dst.read_to_end(&mut saved)?;
+            assert_eq!(saved.len(), 100_003);
+            assert!(saved.ends_with(b"xend"));
+        }
+        Ok(())
+    }
+}
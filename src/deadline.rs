@@ -0,0 +1,248 @@
+//! Giving up on reads and writes that make no progress.
+
+use crate::File;
+use std::future::Future;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+impl<F> File<F> {
+    /// Wraps the file so that a read fails with `TimedOut` if no data
+    /// arrives within `read` of when it started waiting, and likewise a
+    /// write, flush or shutdown that makes no progress within `write`.
+    ///
+    /// Unlike wrapping each call in `tokio::time::timeout`, this applies to
+    /// every operation, including those made by `AsyncReadExt` and
+    /// `AsyncWriteExt` helpers, so a `read_exact` from a serial port keeps
+    /// going as long as bytes trickle in.
+    ///
+    /// An operation that is dropped while waiting, as when it loses a
+    /// `select!`, does not pass its deadline on to the next one.  The next
+    /// one is told apart by being polled without having been woken, so an
+    /// operation that is also polled whenever other futures of its task are
+    /// woken gets a fresh deadline each time.
+    pub fn with_timeouts(self, read: Duration, write: Duration) -> TimeoutFile<F> {
+        TimeoutFile {
+            inner: self,
+            read: Timer::new(read),
+            write: Timer::new(write),
+        }
+    }
+}
+
+/// A [`File`](struct.File.html) whose reads and writes time out, created by
+/// [`File::with_timeouts`](struct.File.html#method.with_timeouts).
+#[derive(Debug)]
+pub struct TimeoutFile<F> {
+    inner: File<F>,
+    read: Timer,
+    /// Shared by writes, flushes, and shutdowns.
+    write: Timer,
+}
+
+/// The deadline of the waiting read or write, if one is waiting.
+#[derive(Debug)]
+struct Timer {
+    timeout: Duration,
+    sleep: Option<Pin<Box<Sleep>>>,
+    /// Passed in place of the waker of the task, to tell whether the
+    /// waiting operation was woken before it is polled again.
+    waker: Arc<Wakeup>,
+}
+
+#[derive(Debug)]
+struct Wakeup {
+    woken: AtomicBool,
+    task: Mutex<Option<Waker>>,
+}
+
+impl Wake for Wakeup {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Release);
+        if let Some(ref waker) = *self.task.lock().unwrap() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+impl<F> TimeoutFile<F> {
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the file.
+    pub fn get_mut(&mut self) -> &mut File<F> {
+        &mut self.inner
+    }
+
+    /// Returns the file.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl Timer {
+    fn new(timeout: Duration) -> Self {
+        Timer {
+            timeout,
+            sleep: None,
+            waker: Arc::new(Wakeup {
+                woken: AtomicBool::new(false),
+                task: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Polls an operation with `poll`, failing with `TimedOut` if it has
+    /// been pending for the timeout, starting the clock the first time it is
+    /// pending and stopping it once it is ready.
+    fn poll<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        what: &str,
+        poll: impl FnOnce(&mut Context<'_>) -> Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        // a waiting operation is only polled again once woken, so this is a
+        // new one and the last was dropped while waiting
+        if !self.waker.woken.swap(false, Ordering::AcqRel) {
+            self.sleep = None;
+        }
+        {
+            let mut task = self.waker.task.lock().unwrap();
+            if !task.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+                *task = Some(cx.waker().clone());
+            }
+        }
+        let waker = Waker::from(self.waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        let result = poll(&mut cx);
+        if result.is_ready() {
+            self.sleep = None;
+            return result;
+        }
+        let timeout = self.timeout;
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        ready!(sleep.as_mut().poll(&mut cx));
+        self.sleep = None;
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{} made no progress for {:?}", what, timeout),
+        )))
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for TimeoutFile<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncRead for TimeoutFile<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = Pin::new(&mut this.inner);
+        this.read.poll(cx, "read", |cx| inner.poll_read(cx, buf))
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> AsyncWrite for TimeoutFile<F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let inner = Pin::new(&mut this.inner);
+        this.write.poll(cx, "write", |cx| inner.poll_write(cx, buf))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let inner = Pin::new(&mut this.inner);
+        this.write
+            .poll(cx, "write", |cx| inner.poll_write_vectored(cx, bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = Pin::new(&mut this.inner);
+        this.write.poll(cx, "flush", |cx| inner.poll_flush(cx))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = Pin::new(&mut this.inner);
+        this.write
+            .poll(cx, "shutdown", |cx| inner.poll_shutdown(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_timeouts() -> io::Result<()> {
+        let timeout = Duration::from_millis(50);
+        let (reader, writer) = crate::pipe()?;
+        let mut reader = reader.into_inner().with_timeouts(timeout, timeout);
+        let mut writer = writer.into_inner().with_timeouts(timeout, timeout);
+
+        let mut data = [0; 4];
+        let err = reader.read(&mut data).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        writer.write_all(b"data").await?;
+        reader.read_exact(&mut data).await?;
+        assert_eq!(&data, b"data");
+
+        // nobody reads, so the pipe fills up
+        let err = writer.write_all(&[0; 1 << 20]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dropped_read() -> io::Result<()> {
+        let timeout = Duration::from_millis(200);
+        let (reader, mut writer) = crate::pipe()?;
+        let mut reader = reader.into_inner().with_timeouts(timeout, timeout);
+        let mut data = [0; 4];
+        // the read gives up waiting before it times out
+        let read = tokio::time::timeout(Duration::from_millis(150), reader.read(&mut data));
+        assert!(read.await.is_err());
+        // past the deadline of the dropped read, but not of this one
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            writer.write_all(b"data").await
+        });
+        reader.read_exact(&mut data).await?;
+        assert_eq!(&data, b"data");
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+//! A registry of the files currently wrapped by this crate, for inspecting
+//! hangs in production (the `registry` feature).
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! // print the wrapped files to stderr whenever the process gets SIGUSR1
+//! tokio_file_unix::debug::dump_on_sigusr1()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::Interest;
+
+pub use crate::FdKind;
+
+/// A snapshot of one wrapped file.  Returned by [`list`](fn.list.html).
+#[derive(Clone, Debug)]
+pub struct FdInfo {
+    fd: RawFd,
+    kind: FdKind,
+    interest: Option<Interest>,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+impl FdInfo {
+    /// The descriptor number.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// The kind of file.
+    pub fn kind(&self) -> FdKind {
+        self.kind
+    }
+
+    /// The interest registered with the reactor, or `None` if the file
+    /// is not pollable and treated as always ready.
+    pub fn interest(&self) -> Option<Interest> {
+        self.interest
+    }
+
+    /// The number of bytes read through the wrapper so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// The number of bytes written through the wrapper so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+impl fmt::Display for FdInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fd {}: {:?}, ", self.fd, self.kind)?;
+        match self.interest {
+            Some(interest) => write!(f, "{:?}", interest)?,
+            None => write!(f, "always ready")?,
+        }
+        write!(
+            f,
+            ", {} bytes read, {} bytes written",
+            self.bytes_read, self.bytes_written
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    fd: RawFd,
+    kind: FdKind,
+    interest: Option<Interest>,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// Live entries keyed by a unique id, since descriptor numbers are
+/// reused.
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    entries: BTreeMap<u64, Arc<Entry>>,
+}
+
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+fn kind(fd: RawFd) -> FdKind {
+    FdKind::of(&fd).unwrap_or(FdKind::Other)
+}
+
+/// Lists the files currently wrapped by a `File`, in order of creation.
+pub fn list() -> Vec<FdInfo> {
+    let registry = registry().lock().unwrap();
+    registry
+        .entries
+        .values()
+        .map(|entry| FdInfo {
+            fd: entry.fd,
+            kind: entry.kind,
+            interest: entry.interest,
+            bytes_read: entry.bytes_read.load(Ordering::Relaxed),
+            bytes_written: entry.bytes_written.load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+/// Spawns a task that prints [`list`](fn.list.html) to standard error
+/// every time the process receives `SIGUSR1`.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn dump_on_sigusr1() -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut signals = signal(SignalKind::user_defined1())?;
+    Ok(tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            let files = list();
+            eprintln!("tokio-file-unix: {} wrapped files", files.len());
+            for info in files {
+                eprintln!("  {}", info);
+            }
+        }
+    }))
+}
+
+#[derive(Debug)]
+pub(crate) struct Registration {
+    id: u64,
+    entry: Arc<Entry>,
+}
+
+impl Registration {
+    pub(crate) fn new(fd: RawFd, interest: Option<Interest>) -> Self {
+        let entry = Arc::new(Entry {
+            fd,
+            kind: kind(fd),
+            interest,
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+        });
+        let mut registry = registry().lock().unwrap();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.entries.insert(id, entry.clone());
+        Registration { id, entry }
+    }
+
+    pub(crate) fn read(&self, n: usize) {
+        self.entry.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn wrote(&self, n: usize) {
+        self.entry
+            .bytes_written
+            .fetch_add(n as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        registry().lock().unwrap().entries.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::File;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_list() -> std::io::Result<()> {
+        let (a, _b) = UnixStream::pair()?;
+        let fd = std::os::unix::io::AsRawFd::as_raw_fd(&a);
+        let mut a = File::new_nb(a)?;
+        a.write_all(b"counted").await?;
+        let info = list().into_iter().find(|info| info.fd() == fd).unwrap();
+        assert_eq!(info.kind(), FdKind::Socket);
+        assert_eq!(info.bytes_written(), 7);
+        drop(a);
+        assert!(list().iter().all(|info| info.fd() != fd));
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+//! Direct I/O (`O_DIRECT`), which bypasses the page cache.
+//!
+//! Reads and writes on a file opened this way must use buffers whose
+//! address and length, as well as the file offset, are multiples of the
+//! logical block size of the device, usually 512 or 4096 bytes.
+//! [`AlignedBuf`](struct.AlignedBuf.html) provides such buffers, and
+//! [`File::new_direct`](../struct.File.html#method.new_direct) makes reads
+//! and writes with misaligned buffers fail right away with `InvalidInput`
+//! instead of an opaque `EINVAL` from the kernel.  The file offset is not
+//! checked.
+//!
+//! Requires the `direct_io` feature.  Only available on Linux.
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use tokio::io::AsyncWriteExt;
+//! use tokio_file_unix::direct::{AlignedBuf, DEFAULT_ALIGNMENT};
+//!
+//! let file = std::fs::File::create("data.bin")?;
+//! let mut file = tokio_file_unix::File::new_direct(file, DEFAULT_ALIGNMENT)?;
+//! let mut buf = AlignedBuf::new(DEFAULT_ALIGNMENT, DEFAULT_ALIGNMENT);
+//! buf[..5].copy_from_slice(b"hello");
+//! file.write_all(&buf).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::File;
+use std::alloc::{self, Layout};
+use std::os::unix::io::AsRawFd;
+use std::ptr::NonNull;
+use std::{fmt, io, ops, slice};
+
+/// An alignment that satisfies the requirements of practically every
+/// device.
+pub const DEFAULT_ALIGNMENT: usize = 4096;
+
+/// A zero-initialized buffer whose address is a multiple of a given
+/// alignment, for use with direct I/O.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// the buffer is uniquely owned, like a `Box<[u8]>`
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    /// Allocates a buffer of `len` bytes aligned to `alignment`.
+    ///
+    /// Panics if `alignment` is not a power of two, or if `len` is 0.
+    pub fn new(len: usize, alignment: usize) -> Self {
+        assert!(len > 0, "zero length");
+        let layout = Layout::from_size_align(len, alignment).expect("invalid alignment");
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        AlignedBuf { ptr, layout }
+    }
+
+    /// The alignment of the buffer.
+    pub fn alignment(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+impl ops::Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl AsRef<[u8]> for AlignedBuf {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for AlignedBuf {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedBuf")
+            .field("len", &self.layout.size())
+            .field("alignment", &self.layout.align())
+            .finish()
+    }
+}
+
+/// The alignment required of the buffers used with a wrapped file, if it is
+/// in direct I/O mode.
+#[derive(Debug)]
+pub(crate) struct Alignment(Option<usize>);
+
+impl Alignment {
+    /// No alignment required, as for files not in direct I/O mode.
+    pub(crate) fn none() -> Self {
+        Alignment(None)
+    }
+
+    /// Fails with `InvalidInput` if the buffer at `ptr` of `len` bytes is
+    /// misaligned.
+    pub(crate) fn check(&self, ptr: *const u8, len: usize) -> io::Result<()> {
+        match self.0 {
+            Some(alignment) if ptr as usize % alignment != 0 || len % alignment != 0 => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "buffer of {} bytes at {:p} is not aligned to {} bytes for direct I/O",
+                        len, ptr, alignment
+                    ),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Wraps a regular file for direct I/O, turning on `O_DIRECT`, and
+    /// makes reads and writes fail with `InvalidInput` unless their buffers
+    /// are aligned to `alignment`, which must be a power of two.
+    ///
+    /// Fails with `EINVAL` if the filesystem does not support direct I/O,
+    /// as is the case for tmpfs.  The file is always read and written on the
+    /// calling thread, since the thread pool would copy the data into
+    /// buffers of its own.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new_direct(file: F, alignment: usize) -> io::Result<Self> {
+        assert!(alignment.is_power_of_two(), "invalid alignment");
+        let fd = file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if flags & libc::O_DIRECT == 0 {
+            if unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_DIRECT) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            audit!("File::new_direct", fd, "set O_DIRECT");
+        }
+        let mut file = File::raw_new(file)?;
+        file.direct = Alignment(Some(alignment));
+        // the pool and io_uring read and write through unaligned buffers
+        file.blocking = None;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_direct() -> io::Result<()> {
+        let raw = tempfile("direct")?;
+        let mut file = match File::new_direct(raw, DEFAULT_ALIGNMENT) {
+            // not supported by the filesystem
+            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => return Ok(()),
+            r => r?,
+        };
+
+        let mut buf = AlignedBuf::new(2 * DEFAULT_ALIGNMENT, DEFAULT_ALIGNMENT);
+        buf[..6].copy_from_slice(b"direct");
+        file.write_all(&buf).await?;
+        let err = file.write(&buf[1..]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        file.seek(io::SeekFrom::Start(0)).await?;
+        let mut read = AlignedBuf::new(DEFAULT_ALIGNMENT, DEFAULT_ALIGNMENT);
+        file.read_exact(&mut read).await?;
+        assert_eq!(&read[..6], b"direct");
+        let err = file.read(&mut [0; 100]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+}
@@ -0,0 +1,600 @@
+//! Treatment of directories.
+
+use crate::{FdKind, File};
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{FromRawFd, IntoRawFd, OwnedFd};
+use std::{error, fmt, io};
+
+/// What a [`File`](struct.File.html) does when asked to wrap a directory,
+/// set with
+/// [`FileBuilder::directory_policy`](struct.FileBuilder.html#method.directory_policy).
+///
+/// Directories cannot be polled, so they are otherwise treated like regular
+/// files, but reading or writing them always fails with `EISDIR`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DirectoryPolicy {
+    /// Wrap the directory anyway.  This is the traditional behavior, and
+    /// still useful for listing it with `File::read_dir` or syncing it.
+    #[default]
+    Allow,
+    /// Refuse to wrap the directory, failing with an
+    /// [`IsDirectory`](struct.IsDirectory.html) error.
+    Reject,
+}
+
+/// The payload of the `io::Error` returned when a directory is wrapped under
+/// `DirectoryPolicy::Reject`.  The error has kind `IsADirectory`.
+#[derive(Debug)]
+pub struct IsDirectory {
+    fd: RawFd,
+}
+
+impl IsDirectory {
+    /// The file descriptor of the directory.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl fmt::Display for IsDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file descriptor {} is a directory", self.fd)
+    }
+}
+
+impl error::Error for IsDirectory {}
+
+/// Applies `policy` to `fd`, which could not be polled.
+pub(crate) fn check(fd: RawFd, policy: DirectoryPolicy) -> io::Result<()> {
+    if policy == DirectoryPolicy::Reject && FdKind::of(&fd)? == FdKind::Directory {
+        return Err(io::Error::new(
+            io::ErrorKind::IsADirectory,
+            IsDirectory { fd },
+        ));
+    }
+    Ok(())
+}
+
+/// An entry of a directory listed by
+/// [`File::read_dir`](struct.File.html#method.read_dir).
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    name: std::ffi::OsString,
+    ino: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl DirEntry {
+    /// The name of the entry, without any leading path.
+    pub fn file_name(&self) -> &std::ffi::OsStr {
+        &self.name
+    }
+
+    /// The inode number of the entry.
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+}
+
+/// Lists the directory `fd` through a new open file description, so that
+/// the offset of `fd` is left alone.
+#[cfg(target_os = "linux")]
+fn list(fd: RawFd) -> io::Result<Vec<DirEntry>> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    let dir_fd = unsafe { libc::openat(fd, b".\0".as_ptr() as *const libc::c_char, flags) };
+    if dir_fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let dir = unsafe { libc::fdopendir(dir_fd) };
+    if dir.is_null() {
+        let e = io::Error::last_os_error();
+        unsafe { libc::close(dir_fd) };
+        return Err(e);
+    }
+    let mut entries = Vec::new();
+    let result = loop {
+        // readdir only reports errors through errno
+        unsafe { *libc::__errno_location() = 0 };
+        let entry = unsafe { libc::readdir64(dir) };
+        if entry.is_null() {
+            let e = io::Error::last_os_error();
+            break if e.raw_os_error() == Some(0) {
+                Ok(entries)
+            } else {
+                Err(e)
+            };
+        }
+        let entry = unsafe { &*entry };
+        let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) }.to_bytes();
+        if name != b"." && name != b".." {
+            entries.push(DirEntry {
+                name: OsStr::from_bytes(name).to_owned(),
+                ino: entry.d_ino,
+            });
+        }
+    };
+    unsafe { libc::closedir(dir) };
+    result
+}
+
+#[cfg(target_os = "linux")]
+impl<F: AsRawFd> File<F> {
+    /// Lists the entries of the directory, other than `.` and `..`, reading
+    /// them on the blocking thread pool.
+    ///
+    /// The directory is read afresh each time, through a new open file
+    /// description, so the offset of the file is not affected.  Fails with
+    /// `ENOTDIR` if the file is not a directory.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn read_dir(&self) -> io::Result<Vec<DirEntry>> {
+        read_dir(self.as_raw_fd()).await
+    }
+}
+
+/// Lists the directory `fd` on the blocking thread pool.
+#[cfg(target_os = "linux")]
+async fn read_dir(fd: RawFd) -> io::Result<Vec<DirEntry>> {
+    let dup = unsafe { crate::dupe_file_from_fd(fd)? };
+    tokio::task::spawn_blocking(move || list(dup.as_raw_fd()))
+        .await
+        .map_err(io::Error::other)?
+}
+
+#[cfg(target_os = "linux")]
+flags! {
+    /// Restrictions on how [`Dir::openat2`](struct.Dir.html#method.openat2)
+    /// resolves a path, combined with `|`.
+    pub struct ResolveFlags(u64);
+
+    /// Resolve the path as `openat` would.
+    const NONE = 0;
+    /// Fail with `EXDEV` if the path escapes the directory, whether through
+    /// `..`, an absolute path or a symbolic link (`RESOLVE_BENEATH`).
+    const BENEATH = libc::RESOLVE_BENEATH;
+    /// Resolve the path as if the directory were the root of the
+    /// filesystem, so that it cannot escape (`RESOLVE_IN_ROOT`).
+    const IN_ROOT = libc::RESOLVE_IN_ROOT;
+    /// Fail with `ELOOP` on any symbolic link in the path
+    /// (`RESOLVE_NO_SYMLINKS`).
+    const NO_SYMLINKS = libc::RESOLVE_NO_SYMLINKS;
+    /// Fail with `ELOOP` on magic links such as `/proc/self/fd/*`
+    /// (`RESOLVE_NO_MAGICLINKS`).
+    const NO_MAGICLINKS = libc::RESOLVE_NO_MAGICLINKS;
+    /// Fail with `EXDEV` if the path crosses into another mount
+    /// (`RESOLVE_NO_XDEV`).
+    const NO_XDEV = libc::RESOLVE_NO_XDEV;
+}
+
+/// An open directory, for listing it and opening files relative to it.
+///
+/// Opening files through a directory, rather than by absolute path, lets a
+/// service hand out access to a subtree as a capability, and with
+/// [`openat2`](#method.openat2) the kernel makes sure paths do not escape
+/// it.  Only available on Linux.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct Dir {
+    file: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl Dir {
+    /// Opens the directory at `path`.  Fails with `ENOTDIR` if it is not a
+    /// directory.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        Self::open_at(libc::AT_FDCWD, path.as_ref())
+    }
+
+    /// Opens the subdirectory at `path`, relative to this directory.
+    pub fn open_dir<P: AsRef<std::path::Path>>(&self, path: P) -> io::Result<Self> {
+        Self::open_at(self.as_raw_fd(), path.as_ref())
+    }
+
+    fn open_at(fd: RawFd, path: &std::path::Path) -> io::Result<Self> {
+        let path = c_path(path)?;
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+        let fd = unsafe { libc::openat(fd, path.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = unsafe { std::os::unix::io::FromRawFd::from_raw_fd(fd) };
+        Ok(Dir {
+            file: crate::move_fd_above(file, crate::STDIO_END)?,
+        })
+    }
+
+    /// Lists the entries of the directory, other than `.` and `..`, as
+    /// [`File::read_dir`](struct.File.html#method.read_dir) does.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn read_dir(&self) -> io::Result<Vec<DirEntry>> {
+        read_dir(self.as_raw_fd()).await
+    }
+
+    /// Opens `path` relative to this directory with the `open` flags
+    /// `flags`, such as `libc::O_RDWR | libc::O_CREAT`, and wraps it with
+    /// *nonblocking mode* enabled.  `mode` sets the permissions of a file
+    /// created by `O_CREAT` or `O_TMPFILE`.  The descriptor is always
+    /// close-on-exec.
+    ///
+    /// An absolute `path` ignores the directory, and `..` may leave it; use
+    /// [`openat2`](#method.openat2) to rule that out.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn openat<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        flags: libc::c_int,
+        mode: libc::mode_t,
+    ) -> io::Result<File<std::fs::File>> {
+        let path = c_path(path.as_ref())?;
+        let fd = unsafe {
+            libc::openat(
+                self.as_raw_fd(),
+                path.as_ptr(),
+                flags | libc::O_CLOEXEC,
+                mode as libc::c_uint,
+            )
+        };
+        wrap_opened(fd)
+    }
+
+    /// Like [`openat`](#method.openat), but restricts how `path` is
+    /// resolved with `resolve`, for example `ResolveFlags::BENEATH` to keep
+    /// it inside the directory (`openat2(2)`).  Fails with `ENOSYS` before
+    /// Linux 5.6.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn openat2<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        flags: libc::c_int,
+        mode: libc::mode_t,
+        resolve: ResolveFlags,
+    ) -> io::Result<File<std::fs::File>> {
+        let path = c_path(path.as_ref())?;
+        let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+        how.flags = (flags | libc::O_CLOEXEC) as u64;
+        how.mode = mode as u64;
+        how.resolve = resolve.0;
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_openat2,
+                self.as_raw_fd(),
+                path.as_ptr(),
+                &how as *const libc::open_how,
+                std::mem::size_of::<libc::open_how>(),
+            )
+        };
+        wrap_opened(fd as RawFd)
+    }
+
+    /// Gets a reference to the underlying directory.
+    pub fn get_ref(&self) -> &std::fs::File {
+        &self.file
+    }
+
+    /// Returns the underlying directory.
+    pub fn into_inner(self) -> std::fs::File {
+        self.file
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl AsRawFd for Dir {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::os::unix::io::AsFd for Dir {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+/// A descriptor that only names a place in the filesystem, for operations
+/// relative to it, such as one opened with `O_PATH` or a directory received
+/// from another process through [`recv_fd`](passfd/fn.recv_fd.html) and
+/// unwrapped with
+/// [`File::into_owned_fd`](struct.File.html#method.into_owned_fd).
+///
+/// Nothing is ever read from or written to the descriptor itself, so it is
+/// not registered with the reactor.  The operations go through the `*at`
+/// system calls, which may block on slow filesystems, on the blocking
+/// thread pool.  An empty path refers to the descriptor itself.  Clones
+/// share the descriptor.  Only available on Linux.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Debug)]
+pub struct Handle {
+    fd: std::sync::Arc<OwnedFd>,
+}
+
+#[cfg(target_os = "linux")]
+impl Handle {
+    /// Opens `path` with `O_PATH`, which neither requires nor grants
+    /// permission to read or write it.
+    pub fn open_path<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let path = c_path(path.as_ref())?;
+        let flags = libc::O_PATH | libc::O_CLOEXEC;
+        let fd = unsafe { libc::open(path.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(Handle::from_owned_fd(crate::move_fd_above(
+            fd,
+            crate::STDIO_END,
+        )?))
+    }
+
+    /// Wraps a descriptor, typically an `O_PATH` descriptor or a directory.
+    pub fn from_owned_fd(fd: OwnedFd) -> Self {
+        Handle {
+            fd: std::sync::Arc::new(fd),
+        }
+    }
+
+    /// Opens `path` relative to the handle, like
+    /// [`Dir::openat`](struct.Dir.html#method.openat), and wraps it with
+    /// *nonblocking mode* enabled.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn open_relative<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        flags: libc::c_int,
+        mode: libc::mode_t,
+    ) -> io::Result<File<std::fs::File>> {
+        let fd = self
+            .offload(path.as_ref(), move |dir, path| {
+                let fd = unsafe {
+                    libc::openat(
+                        dir,
+                        path.as_ptr(),
+                        flags | libc::O_CLOEXEC,
+                        mode as libc::c_uint,
+                    )
+                };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+            })
+            .await?;
+        wrap_opened(fd.into_raw_fd())
+    }
+
+    /// Gets the status of `path` relative to the handle, following a final
+    /// symbolic link only if `follow` is set.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    ///
+    /// Implementation detail: uses `fstatat`, with `AT_EMPTY_PATH` so that
+    /// an empty path works for `O_PATH` descriptors.
+    pub async fn stat_relative<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        follow: bool,
+    ) -> io::Result<libc::stat> {
+        self.offload(path.as_ref(), move |dir, path| {
+            let mut flags = libc::AT_EMPTY_PATH;
+            if !follow {
+                flags |= libc::AT_SYMLINK_NOFOLLOW;
+            }
+            let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+            if unsafe { libc::fstatat(dir, path.as_ptr(), &mut stat, flags) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(stat)
+        })
+        .await
+    }
+
+    /// Reads the target of the symbolic link at `path` relative to the
+    /// handle.  With an empty path, the handle must itself be an `O_PATH`
+    /// descriptor of the link, opened with `O_NOFOLLOW`.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn readlink_relative<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<std::path::PathBuf> {
+        use std::os::unix::ffi::OsStringExt;
+        self.offload(path.as_ref(), |dir, path| {
+            let mut buf = vec![0u8; 256];
+            loop {
+                let n = unsafe {
+                    libc::readlinkat(
+                        dir,
+                        path.as_ptr(),
+                        buf.as_mut_ptr() as *mut libc::c_char,
+                        buf.len(),
+                    )
+                };
+                if n < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                // the target may have been cut short
+                if (n as usize) < buf.len() {
+                    buf.truncate(n as usize);
+                    return Ok(std::ffi::OsString::from_vec(buf).into());
+                }
+                buf.resize(buf.len() * 2, 0);
+            }
+        })
+        .await
+    }
+
+    /// Runs `op` with the descriptor and `path` on the blocking thread pool.
+    async fn offload<T, Op>(&self, path: &std::path::Path, op: Op) -> io::Result<T>
+    where
+        T: Send + 'static,
+        Op: FnOnce(RawFd, &std::ffi::CStr) -> io::Result<T> + Send + 'static,
+    {
+        let path = c_path(path)?;
+        let fd = self.fd.clone();
+        tokio::task::spawn_blocking(move || op(fd.as_raw_fd(), &path))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl From<Dir> for Handle {
+    fn from(dir: Dir) -> Self {
+        Handle::from_owned_fd(OwnedFd::from(dir.file))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl AsRawFd for Handle {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::os::unix::io::AsFd for Handle {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn c_path(path: &std::path::Path) -> io::Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(std::ffi::CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Wraps the descriptor returned by `openat` or `openat2`.
+#[cfg(target_os = "linux")]
+fn wrap_opened(fd: RawFd) -> io::Result<File<std::fs::File>> {
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let file = unsafe { std::os::unix::io::FromRawFd::from_raw_fd(fd) };
+    File::new_nb(crate::move_fd_above(file, crate::STDIO_END)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use crate::FileBuilder;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_directory_policy() -> io::Result<()> {
+        let path = TempPath::new("dir");
+        fs::create_dir(&path)?;
+        fs::write(path.join("a"), b"")?;
+        fs::create_dir(path.join("b"))?;
+
+        let err = FileBuilder::new()
+            .directory_policy(DirectoryPolicy::Reject)
+            .build(fs::File::open(&path)?)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::IsADirectory);
+        assert!(err.get_ref().unwrap().is::<IsDirectory>());
+
+        let dir = File::new_nb(fs::File::open(&path)?)?;
+        #[cfg(target_os = "linux")]
+        {
+            let mut names: Vec<_> = dir
+                .read_dir()
+                .await?
+                .into_iter()
+                .map(|entry| entry.file_name().to_owned())
+                .collect();
+            names.sort();
+            assert_eq!(names, vec!["a", "b"]);
+        }
+        drop(dir);
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_dir() -> io::Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        let path = TempPath::new("openat");
+        fs::create_dir(&path)?;
+        fs::write(path.join("inside"), b"contents")?;
+        std::os::unix::fs::symlink("/etc/passwd", path.join("escape"))?;
+
+        let dir = Dir::open(&path)?;
+        let mut names: Vec<_> = dir
+            .read_dir()
+            .await?
+            .into_iter()
+            .map(|entry| entry.file_name().to_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["escape", "inside"]);
+
+        let mut file = dir.openat("inside", libc::O_RDONLY, 0)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+        assert_eq!(data, b"contents");
+
+        match dir.openat2("escape", libc::O_RDONLY, 0, ResolveFlags::BENEATH) {
+            // older kernels and some sandboxes lack openat2
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => {}
+            Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EXDEV)),
+            Ok(_) => panic!("escaped the directory"),
+        }
+        match dir.openat2("inside", libc::O_RDONLY, 0, ResolveFlags::NO_SYMLINKS) {
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => {}
+            r => drop(r?),
+        }
+        drop(dir);
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_handle() -> io::Result<()> {
+        use tokio::io::AsyncReadExt;
+
+        let path = TempPath::new("handle");
+        fs::create_dir(&path)?;
+        fs::write(path.join("inside"), b"contents")?;
+        std::os::unix::fs::symlink("inside", path.join("link"))?;
+
+        let handle = Handle::open_path(&path)?;
+        let stat = handle.stat_relative("", true).await?;
+        assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFDIR);
+        let stat = handle.stat_relative("link", false).await?;
+        assert_eq!(stat.st_mode & libc::S_IFMT, libc::S_IFLNK);
+        let stat = handle.stat_relative("link", true).await?;
+        assert_eq!(stat.st_size, 8);
+        assert_eq!(
+            handle.readlink_relative("link").await?,
+            std::path::Path::new("inside")
+        );
+
+        // as received from another process
+        let handle = Handle::from_owned_fd(OwnedFd::from(fs::File::open(&path)?));
+        let mut file = handle.open_relative("link", libc::O_RDONLY, 0).await?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+        assert_eq!(data, b"contents");
+        let e = handle
+            .open_relative("missing", libc::O_RDONLY, 0)
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+
+        drop(handle);
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Forwarding everything a file has to offer in one go.
+
+use crate::{BufferPool, File};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+impl<F: AsRawFd> File<F> {
+    /// Waits for data, then reads and writes it to `sink` until a read
+    /// would block, and returns the number of bytes forwarded, which is 0
+    /// only at EOF.
+    ///
+    /// This handles everything that one readiness event brought in a
+    /// single wakeup of the task, rather than one wakeup per buffer, which
+    /// suits forwarding logs from many pipes.  The sink is not flushed.
+    ///
+    /// Fails with `InvalidInput` for regular files offloaded to the blocking
+    /// thread pool, which have no readiness events to drain.
+    ///
+    /// Implementation detail: reads with
+    /// [`try_read`](#method.try_read) into a buffer from
+    /// [`BufferPool::global`](struct.BufferPool.html#method.global).
+    pub async fn drain_into<W>(&mut self, sink: &mut W) -> io::Result<u64>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        if self.is_offloaded() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot drain a file offloaded to the thread pool",
+            ));
+        }
+        let pool = BufferPool::global();
+        let mut buf = pool.get();
+        buf.resize(pool.buffer_size(), 0);
+        let r = self.drain_with(sink, &mut buf).await;
+        pool.put(buf);
+        r
+    }
+
+    async fn drain_with<W>(&self, sink: &mut W, buf: &mut [u8]) -> io::Result<u64>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let mut total = 0;
+        loop {
+            let mut guard = self.readable().await?;
+            loop {
+                match self.try_read(buf) {
+                    Ok(0) => return Ok(total),
+                    Ok(n) => {
+                        sink.write_all(&buf[..n]).await?;
+                        total += n as u64;
+                        if self.edge_triggered && n < buf.len() {
+                            guard.clear_ready();
+                            return Ok(total);
+                        }
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        guard.clear_ready();
+                        if total > 0 {
+                            return Ok(total);
+                        }
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Like [`drain_into`](#method.drain_into), but moves the data to
+    /// `dst` with `splice(2)`, without copying it through user space.  One
+    /// of the two files must be a pipe, as with
+    /// [`splice`](fn.splice.html).
+    ///
+    /// Only available on Linux and Android.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn drain_splice<W: AsRawFd>(&mut self, dst: &File<W>) -> io::Result<u64> {
+        /// The most bytes moved by each `splice`.
+        const SPLICE_LEN: usize = 64 * 1024;
+        let mut total = 0;
+        loop {
+            match crate::splice::splice(self, dst, SPLICE_LEN).await {
+                Ok(0) => return Ok(total),
+                Ok(n) => total += n as u64,
+                Err(e) => return crate::check_stale(self.as_raw_fd(), self.identity, Err(e)),
+            }
+            // stop once the data of this readiness event is used up
+            if !crate::poll_now(self.as_raw_fd(), libc::POLLIN)? {
+                return Ok(total);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_drain_into() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let mut reader = reader.into_inner();
+        writer.write_all(b"one\n").await?;
+        writer.write_all(b"two\n").await?;
+        let mut sink = Vec::new();
+        assert_eq!(reader.drain_into(&mut sink).await?, 8);
+        assert_eq!(sink, b"one\ntwo\n");
+        drop(writer);
+        assert_eq!(reader.drain_into(&mut sink).await?, 0);
+
+        let raw = crate::tempfile::tempfile("drain")?;
+        let mut file = File::new_nb_with(raw, crate::RegularFileMode::ThreadPool)?;
+        let err = file.drain_into(&mut sink).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn test_drain_splice() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let (splice_reader, splice_writer) = crate::pipe()?;
+        let mut reader = reader.into_inner();
+        writer.write_all(b"spliced\n").await?;
+        drop(writer);
+        let splice_writer = splice_writer.into_inner();
+        assert_eq!(reader.drain_splice(&splice_writer).await?, 8);
+        assert_eq!(reader.drain_splice(&splice_writer).await?, 0);
+        drop(splice_writer);
+        let mut spliced = Vec::new();
+        splice_reader.into_inner().read_to_end(&mut spliced).await?;
+        assert_eq!(spliced, b"spliced\n");
+        Ok(())
+    }
+}
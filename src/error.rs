@@ -0,0 +1,387 @@
+//! Classification of I/O errors raised by wrapped files.
+
+use crate::FdKind;
+use std::os::unix::io::RawFd;
+use std::{error, fmt, io};
+
+/// A coarse classification of errors returned from reading or writing a
+/// wrapped file, for protocol layers that need to react differently to "the
+/// peer went away" and "the descriptor was closed under us".
+///
+/// Obtain it from any `io::Error` through
+/// [`IoErrorExt::io_error_kind`](trait.IoErrorExt.html#tymethod.io_error_kind).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IoErrorKind {
+    /// The other end of the pipe, socket, or device is gone (`EPIPE`,
+    /// `ECONNRESET`, `ENXIO`).
+    PeerClosed,
+    /// The operation would block (`EAGAIN`/`EWOULDBLOCK`).
+    WouldBlock,
+    /// The descriptor itself is unusable (`EBADF`, `ESTALE`).
+    InvalidDescriptor,
+    /// The descriptor was closed or replaced by foreign code (see
+    /// [`StaleDescriptor`](struct.StaleDescriptor.html)).
+    StaleDescriptor,
+    /// Any other error.
+    Other,
+}
+
+/// Extension methods for `io::Error`.
+pub trait IoErrorExt {
+    /// Classifies the error.
+    fn io_error_kind(&self) -> IoErrorKind;
+
+    /// The errno of the error.  Unlike `raw_os_error`, this also finds the
+    /// errno of errors from wrapping a file, which carry an
+    /// [`Error`](enum.Error.html) instead.
+    fn errno(&self) -> Option<i32>;
+}
+
+impl IoErrorExt for io::Error {
+    fn errno(&self) -> Option<i32> {
+        match self.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(e) => e.io_error().errno(),
+            None => self.raw_os_error(),
+        }
+    }
+
+    fn io_error_kind(&self) -> IoErrorKind {
+        if let Some(e) = self.get_ref() {
+            if e.is::<StaleDescriptor>() {
+                return IoErrorKind::StaleDescriptor;
+            }
+            if let Some(e) = e.downcast_ref::<Error>() {
+                return e.io_error().io_error_kind();
+            }
+        }
+        match self.raw_os_error() {
+            Some(libc::EPIPE) | Some(libc::ECONNRESET) | Some(libc::ENXIO) => {
+                IoErrorKind::PeerClosed
+            }
+            Some(libc::EBADF) | Some(libc::ESTALE) => IoErrorKind::InvalidDescriptor,
+            Some(_) if self.kind() == io::ErrorKind::WouldBlock => IoErrorKind::WouldBlock,
+            Some(_) => IoErrorKind::Other,
+            None => match self.kind() {
+                io::ErrorKind::WouldBlock => IoErrorKind::WouldBlock,
+                io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset => {
+                    IoErrorKind::PeerClosed
+                }
+                _ => IoErrorKind::Other,
+            },
+        }
+    }
+}
+
+/// The payload of the `io::Error` returned when wrapping a file fails, saying
+/// which step failed and on what kind of file, as in "cannot register fd 7
+/// (regular file on nfs) with the reactor".  The error keeps the kind of the
+/// underlying error, which is also available from
+/// [`io_error`](#method.io_error), along with its errno.
+///
+/// An `io::Error` cannot carry both an errno and a payload, so
+/// `raw_os_error` returns `None` for these errors.  Use
+/// [`IoErrorExt::errno`](trait.IoErrorExt.html#tymethod.errno) to get the
+/// errno of any `io::Error`.
+///
+/// ```
+/// use tokio_file_unix::Error;
+///
+/// fn report(e: &std::io::Error) {
+///     match e.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+///         Some(&Error::Register { fd, kind, .. }) => {
+///             eprintln!("fd {} ({:?}) cannot be polled: {}", fd, kind, e)
+///         }
+///         _ => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The file could not be inspected with `fstat`, typically because the
+    /// descriptor is not open.
+    Inspect {
+        /// The file descriptor.
+        fd: RawFd,
+        /// The underlying error.
+        source: io::Error,
+    },
+    /// Nonblocking mode could not be enabled or disabled.
+    SetNonblocking {
+        /// The file descriptor.
+        fd: RawFd,
+        /// The kind of file.
+        kind: FdKind,
+        /// The underlying error.
+        source: io::Error,
+    },
+    /// The file could not be registered with the reactor.
+    Register {
+        /// The file descriptor.
+        fd: RawFd,
+        /// The kind of file.
+        kind: FdKind,
+        /// The type of filesystem the file lives on, such as `nfs`, if it
+        /// could be told, which is only on Linux.
+        filesystem: Option<&'static str>,
+        /// The underlying error.
+        source: io::Error,
+    },
+}
+
+impl Error {
+    /// The file descriptor that could not be wrapped.
+    pub fn fd(&self) -> RawFd {
+        match *self {
+            Error::Inspect { fd, .. }
+            | Error::SetNonblocking { fd, .. }
+            | Error::Register { fd, .. } => fd,
+        }
+    }
+
+    /// The kind of file, if it could be inspected.
+    pub fn kind(&self) -> Option<FdKind> {
+        match *self {
+            Error::Inspect { .. } => None,
+            Error::SetNonblocking { kind, .. } | Error::Register { kind, .. } => Some(kind),
+        }
+    }
+
+    /// The underlying error, with the errno if there is one.
+    pub fn io_error(&self) -> &io::Error {
+        match *self {
+            Error::Inspect { ref source, .. }
+            | Error::SetNonblocking { ref source, .. }
+            | Error::Register { ref source, .. } => source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Inspect { fd, ref source } => {
+                write!(f, "cannot inspect fd {}: {}", fd, source)
+            }
+            Error::SetNonblocking {
+                fd,
+                kind,
+                ref source,
+            } => write!(
+                f,
+                "cannot change nonblocking mode of fd {} ({}): {}",
+                fd,
+                describe(kind),
+                source
+            ),
+            Error::Register {
+                fd,
+                kind,
+                filesystem,
+                ref source,
+            } => {
+                write!(f, "cannot register fd {} ({}", fd, describe(kind))?;
+                if let Some(filesystem) = filesystem {
+                    write!(f, " on {}", filesystem)?;
+                }
+                write!(f, ") with the reactor: {}", source)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.io_error())
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        io::Error::new(e.io_error().kind(), e)
+    }
+}
+
+fn describe(kind: FdKind) -> &'static str {
+    match kind {
+        FdKind::Tty => "terminal",
+        FdKind::Pipe => "pipe",
+        FdKind::Socket => "socket",
+        FdKind::RegularFile => "regular file",
+        FdKind::CharDevice => "character device",
+        FdKind::Directory => "directory",
+        FdKind::Other => "file",
+    }
+}
+
+/// Wraps an error from `fstat` on `fd`.
+pub(crate) fn inspecting(fd: RawFd) -> impl FnOnce(io::Error) -> io::Error {
+    move |source| Error::Inspect { fd, source }.into()
+}
+
+/// Wraps an error from changing the nonblocking mode of `fd`.
+pub(crate) fn setting_nonblocking(fd: RawFd, kind: FdKind) -> impl FnOnce(io::Error) -> io::Error {
+    move |source| Error::SetNonblocking { fd, kind, source }.into()
+}
+
+/// Wraps an error from registering `fd` with the reactor.
+pub(crate) fn registering(fd: RawFd, kind: FdKind) -> impl FnOnce(io::Error) -> io::Error {
+    move |source| {
+        Error::Register {
+            fd,
+            kind,
+            filesystem: filesystem(fd),
+            source,
+        }
+        .into()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn filesystem(fd: RawFd) -> Option<&'static str> {
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstatfs(fd, &mut stat) } < 0 {
+        return None;
+    }
+    // the magic numbers of <linux/magic.h>, for the filesystems most likely
+    // to be behind a confusing failure
+    Some(match stat.f_type as u32 {
+        0x6969 => "nfs",
+        0xff53_4d42 => "cifs",
+        0xfe53_4d42 => "smb2",
+        0x6573_5546 => "fuse",
+        0x9fa0 => "proc",
+        0x6265_6572 => "sysfs",
+        0x0102_1994 => "tmpfs",
+        0x794c_7630 => "overlayfs",
+        0xef53 => "ext4",
+        0x5846_5342 => "xfs",
+        0x9123_683e => "btrfs",
+        _ => return None,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn filesystem(_: RawFd) -> Option<&'static str> {
+    None
+}
+
+/// The payload of the `io::Error` returned when a wrapped file descriptor is
+/// found to have been closed, or closed and reused for an unrelated file,
+/// behind the back of the [`File`](struct.File.html) wrapping it.
+#[derive(Debug)]
+pub struct StaleDescriptor {
+    fd: RawFd,
+}
+
+impl StaleDescriptor {
+    /// The file descriptor number that went stale.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl fmt::Display for StaleDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "file descriptor {} was closed or replaced by foreign code",
+            self.fd
+        )
+    }
+}
+
+impl error::Error for StaleDescriptor {}
+
+pub(crate) fn stale_descriptor(fd: RawFd) -> io::Error {
+    io::Error::other(StaleDescriptor { fd })
+}
+
+/// The payload of the `io::Error` returned when a file that cannot be
+/// polled is wrapped with the always-ready fallback disabled through
+/// [`FileBuilder::allow_fallback`](struct.FileBuilder.html#method.allow_fallback).
+/// The error has kind `Unsupported`.
+#[derive(Debug)]
+pub struct NotPollable {
+    fd: RawFd,
+}
+
+impl NotPollable {
+    /// The file descriptor that cannot be polled.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl fmt::Display for NotPollable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "file descriptor {} cannot be polled and would be treated as always ready",
+            self.fd
+        )
+    }
+}
+
+impl error::Error for NotPollable {}
+
+pub(crate) fn not_pollable(fd: RawFd) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, NotPollable { fd })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_kind() {
+        let kind = |errno| io::Error::from_raw_os_error(errno).io_error_kind();
+        assert_eq!(kind(libc::EPIPE), IoErrorKind::PeerClosed);
+        assert_eq!(kind(libc::ENXIO), IoErrorKind::PeerClosed);
+        assert_eq!(kind(libc::EAGAIN), IoErrorKind::WouldBlock);
+        assert_eq!(kind(libc::EBADF), IoErrorKind::InvalidDescriptor);
+        assert_eq!(kind(libc::ESTALE), IoErrorKind::InvalidDescriptor);
+        assert_eq!(kind(libc::EINVAL), IoErrorKind::Other);
+        let custom = io::Error::new(io::ErrorKind::BrokenPipe, "gone");
+        assert_eq!(custom.io_error_kind(), IoErrorKind::PeerClosed);
+        assert_eq!(
+            stale_descriptor(3).io_error_kind(),
+            IoErrorKind::StaleDescriptor
+        );
+    }
+
+    /// A descriptor number that is not open.
+    #[derive(Debug)]
+    struct Closed;
+
+    impl std::os::unix::io::AsRawFd for Closed {
+        fn as_raw_fd(&self) -> RawFd {
+            // above any reasonable descriptor limit
+            1 << 30
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_context() -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+        let file = std::fs::File::open("Cargo.toml")?;
+        let fd = file.as_raw_fd();
+        let e = registering(fd, FdKind::RegularFile)(io::Error::from_raw_os_error(libc::EPERM));
+        assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+        let message = e.to_string();
+        let prefix = format!("cannot register fd {} (regular file", fd);
+        assert!(message.starts_with(&prefix), "{}", message);
+        let inner = e.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+        assert_eq!(inner.kind(), Some(FdKind::RegularFile));
+        assert_eq!(inner.io_error().raw_os_error(), Some(libc::EPERM));
+        assert_eq!(e.raw_os_error(), None);
+        assert_eq!(e.errno(), Some(libc::EPERM));
+
+        let e = crate::File::new_nb(Closed).unwrap_err();
+        assert_eq!(e.io_error_kind(), IoErrorKind::InvalidDescriptor);
+        let inner = e.get_ref().unwrap().downcast_ref::<Error>().unwrap();
+        assert_eq!(inner.fd(), 1 << 30);
+        assert_eq!(inner.kind(), None);
+        Ok(())
+    }
+}
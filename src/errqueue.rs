@@ -0,0 +1,237 @@
+//! Reading the error queue of a socket (`MSG_ERRQUEUE`).
+//!
+//! With `IP_RECVERR` (or `IPV6_RECVERR`) enabled, the kernel queues errors
+//! such as ICMP unreachable messages on the socket instead of just failing
+//! the next call.  The same queue carries zero-copy completions and
+//! transmit timestamps.  Entries are signaled with `EPOLLERR`.
+
+use crate::File;
+use futures_core::Stream;
+use std::future::Future;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fmt, io, mem, ptr};
+use tokio::io::Interest;
+
+/// An entry of a socket's error queue (`struct sock_extended_err`).
+#[derive(Clone, Copy)]
+pub struct ExtendedError {
+    raw: libc::sock_extended_err,
+    offender: Option<SocketAddr>,
+}
+
+impl ExtendedError {
+    /// The error number, or zero for entries that are not errors, such as
+    /// zero-copy completions.
+    pub fn errno(&self) -> i32 {
+        self.raw.ee_errno as i32
+    }
+
+    /// Where the entry came from, one of the `libc::SO_EE_ORIGIN_*`
+    /// constants.
+    pub fn origin(&self) -> u8 {
+        self.raw.ee_origin
+    }
+
+    /// The ICMP type for ICMP errors.
+    pub fn kind(&self) -> u8 {
+        self.raw.ee_type
+    }
+
+    /// The ICMP code for ICMP errors, or flags specific to the origin.
+    pub fn code(&self) -> u8 {
+        self.raw.ee_code
+    }
+
+    /// Additional information specific to the origin, for example the MTU
+    /// for `EMSGSIZE`.
+    pub fn info(&self) -> u32 {
+        self.raw.ee_info
+    }
+
+    /// Additional data specific to the origin.
+    pub fn data(&self) -> u32 {
+        self.raw.ee_data
+    }
+
+    /// The address of the node that reported the error, if any.
+    pub fn offender(&self) -> Option<SocketAddr> {
+        self.offender
+    }
+
+    /// Converts the error number into an `io::Error`, if there is one.
+    pub fn to_io_error(&self) -> Option<io::Error> {
+        match self.errno() {
+            0 => None,
+            errno => Some(io::Error::from_raw_os_error(errno)),
+        }
+    }
+}
+
+impl fmt::Debug for ExtendedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedError")
+            .field("errno", &self.errno())
+            .field("origin", &self.origin())
+            .field("kind", &self.kind())
+            .field("code", &self.code())
+            .field("info", &self.info())
+            .field("data", &self.data())
+            .field("offender", &self.offender)
+            .finish()
+    }
+}
+
+/// Reads the offender address following the extended error in a control
+/// message with `len` bytes of data at `data`.
+unsafe fn read_offender(data: *const u8, len: usize) -> Option<SocketAddr> {
+    let start = mem::size_of::<libc::sock_extended_err>();
+    if len < start + mem::size_of::<libc::sa_family_t>() {
+        return None;
+    }
+    let addr = data.add(start);
+    let family = ptr::read_unaligned(addr as *const libc::sa_family_t);
+    match family as libc::c_int {
+        libc::AF_INET if len >= start + mem::size_of::<libc::sockaddr_in>() => {
+            let sin = ptr::read_unaligned(addr as *const libc::sockaddr_in);
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)),
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 if len >= start + mem::size_of::<libc::sockaddr_in6>() => {
+            let sin6 = ptr::read_unaligned(addr as *const libc::sockaddr_in6);
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Receives one message from the error queue of the socket `fd` without
+/// blocking.  Fails with `WouldBlock` if the queue is empty.
+pub(crate) fn recv_err(fd: RawFd) -> io::Result<ExtendedError> {
+    // large enough for a sock_extended_err plus the offending address
+    let mut control = [0u64; 16];
+    unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        if libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let level = (*cmsg).cmsg_level;
+            let ty = (*cmsg).cmsg_type;
+            if (level == libc::SOL_IP && ty == libc::IP_RECVERR)
+                || (level == libc::SOL_IPV6 && ty == libc::IPV6_RECVERR)
+            {
+                let data = libc::CMSG_DATA(cmsg) as *const u8;
+                let len = ((*cmsg).cmsg_len as usize).saturating_sub(libc::CMSG_LEN(0) as usize);
+                if len >= mem::size_of::<libc::sock_extended_err>() {
+                    return Ok(ExtendedError {
+                        raw: ptr::read_unaligned(data as *const libc::sock_extended_err),
+                        offender: read_offender(data, len),
+                    });
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "error queue message without an extended error",
+    ))
+}
+
+type RecvErr<'a> = Pin<Box<dyn Future<Output = io::Result<ExtendedError>> + Send + 'a>>;
+
+/// A stream of the entries of a socket's error queue.  Created by
+/// [`File::err_queue`](../struct.File.html#method.err_queue).
+pub struct ErrQueue<'a, F> {
+    file: &'a File<F>,
+    pending: Option<RecvErr<'a>>,
+}
+
+impl<F: fmt::Debug> fmt::Debug for ErrQueue<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrQueue").field("file", self.file).finish()
+    }
+}
+
+impl<'a, F: AsRawFd + Sync> Stream for ErrQueue<'a, F> {
+    type Item = io::Result<ExtendedError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let file = self.file;
+        let pending = self
+            .pending
+            .get_or_insert_with(|| Box::pin(file.recv_err()));
+        let result = ready!(pending.as_mut().poll(cx));
+        self.pending = None;
+        Poll::Ready(Some(result))
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Waits for and receives the next entry of the socket's error queue.
+    ///
+    /// Implementation detail: uses `recvmsg` with `MSG_ERRQUEUE`.
+    pub async fn recv_err(&self) -> io::Result<ExtendedError> {
+        loop {
+            let mut guard = self.ready(Interest::ERROR).await?;
+            match recv_err(self.as_raw_fd()) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                r => return r,
+            }
+        }
+    }
+
+    /// Returns a stream of the entries of the socket's error queue.  The
+    /// stream never ends on its own.
+    pub fn err_queue(&self) -> ErrQueue<'_, F> {
+        ErrQueue {
+            file: self,
+            pending: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_err_queue() -> io::Result<()> {
+        // nobody listens on the port of a socket that has been closed again
+        let closed = UdpSocket::bind("127.0.0.1:0")?.local_addr()?;
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        let on: libc::c_int = 1;
+        let r = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_IP,
+                libc::IP_RECVERR,
+                &on as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        assert_eq!(r, 0);
+        let socket = File::new_nb(socket)?;
+        socket.get_ref().send_to(b"anyone?", closed)?;
+        let ee = socket.err_queue().next().await.unwrap()?;
+        assert_eq!(ee.origin(), libc::SO_EE_ORIGIN_ICMP);
+        assert_eq!(ee.errno(), libc::ECONNREFUSED);
+        assert_eq!(ee.offender().map(|a| a.ip()), Some(closed.ip()));
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! Input devices (`/dev/input/event*`), read as streams of events.
+//!
+//! Only the event stream itself is covered; querying or grabbing the device
+//! is left to `ioctl`s on its descriptor.  Only available on Linux.
+
+use crate::File;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fs, io, mem, ptr};
+
+/// The size of an `input_event` record.
+const RECORD: usize = mem::size_of::<libc::input_event>();
+
+/// An input event (`struct input_event`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    time: Duration,
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+impl InputEvent {
+    /// When the event happened, measured from the epoch of the clock the
+    /// device reports in, which is the realtime clock unless changed with
+    /// `EVIOCSCLOCKID`.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// The type of event, such as `EV_KEY` (1) for keys and buttons, as
+    /// listed in `linux/input-event-codes.h`.
+    pub fn kind(&self) -> u16 {
+        self.kind
+    }
+
+    /// Which key, axis or the like the event is about, depending on its
+    /// type.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The new value, such as 1 for a key press and 0 for a release.
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+}
+
+/// Parses a single `input_event` record.
+fn parse_event(record: &[u8]) -> InputEvent {
+    debug_assert_eq!(record.len(), RECORD);
+    // the timestamp is two words, whose type depends on the architecture,
+    // so read the fields by offset rather than through libc's struct
+    let long = mem::size_of::<libc::c_long>();
+    let p = record.as_ptr();
+    unsafe {
+        let sec = ptr::read_unaligned(p as *const libc::c_long);
+        let usec = ptr::read_unaligned(p.add(long) as *const libc::c_long);
+        let rest = RECORD - 8;
+        InputEvent {
+            time: Duration::new(sec as u64, usec as u32 * 1000),
+            kind: ptr::read_unaligned(p.add(rest) as *const u16),
+            code: ptr::read_unaligned(p.add(rest + 2) as *const u16),
+            value: ptr::read_unaligned(p.add(rest + 4) as *const i32),
+        }
+    }
+}
+
+/// An input device, which is a `Stream` of its events.
+#[derive(Debug)]
+pub struct InputDevice {
+    file: File<fs::File>,
+    events: VecDeque<InputEvent>,
+    /// The start of a record cut off by the previous read.
+    partial: Vec<u8>,
+}
+
+impl InputDevice {
+    /// Opens the input device at `path`, such as `/dev/input/event0`.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+        Ok(Self::new(File::raw_new(file)?))
+    }
+
+    /// Reads events from a file that is already open, such as a device
+    /// passed in by a launcher, or a pipe carrying recorded events.
+    pub fn new(file: File<fs::File>) -> Self {
+        InputDevice {
+            file,
+            events: VecDeque::new(),
+            partial: Vec::with_capacity(RECORD),
+        }
+    }
+
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<fs::File> {
+        &self.file
+    }
+
+    /// Returns the file.  Any events already read but not yet taken from
+    /// the stream are lost.
+    pub fn into_inner(self) -> File<fs::File> {
+        self.file
+    }
+
+    /// Parses the records in `buf`, keeping any incomplete one at the end
+    /// for the next read.
+    fn parse(&mut self, mut buf: &[u8]) {
+        if !self.partial.is_empty() {
+            let n = (RECORD - self.partial.len()).min(buf.len());
+            self.partial.extend_from_slice(&buf[..n]);
+            buf = &buf[n..];
+            if self.partial.len() < RECORD {
+                return;
+            }
+            self.events.push_back(parse_event(&self.partial));
+            self.partial.clear();
+        }
+        let mut records = buf.chunks_exact(RECORD);
+        self.events.extend(records.by_ref().map(parse_event));
+        self.partial.extend_from_slice(records.remainder());
+    }
+}
+
+impl Stream for InputDevice {
+    type Item = io::Result<InputEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut buf = [0; 64 * RECORD];
+        while this.events.is_empty() {
+            let mut guard = ready!(this.file.poll_read_ready(cx))?;
+            match this.file.try_read(&mut buf) {
+                // a half-read record is cut off rather than finished
+                Ok(0) if !this.partial.is_empty() => {
+                    this.partial.clear();
+                    return Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())));
+                }
+                Ok(0) => return Poll::Ready(None),
+                Ok(n) => this.parse(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+        Poll::Ready(this.events.pop_front().map(Ok))
+    }
+}
+
+impl AsRawFd for InputDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for InputDevice {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    const EV_SYN: u16 = 0;
+    const EV_KEY: u16 = 1;
+    const KEY_A: u16 = 30;
+
+    fn record(sec: libc::c_long, kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut record = vec![0; RECORD];
+        record[..mem::size_of_val(&sec)].copy_from_slice(&sec.to_ne_bytes());
+        record[RECORD - 8..RECORD - 6].copy_from_slice(&kind.to_ne_bytes());
+        record[RECORD - 6..RECORD - 4].copy_from_slice(&code.to_ne_bytes());
+        record[RECORD - 4..].copy_from_slice(&value.to_ne_bytes());
+        record
+    }
+
+    #[tokio::test]
+    async fn test_input_device() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let mut device = InputDevice::new(reader.into_inner());
+        let press = record(7, EV_KEY, KEY_A, 1);
+        let sync = record(7, EV_SYN, 0, 0);
+
+        // one record split across two reads
+        writer.write_all(&press[..5]).await?;
+        let wait = Duration::from_millis(20);
+        assert!(tokio::time::timeout(wait, device.next()).await.is_err());
+        writer.write_all(&press[5..]).await?;
+        writer.write_all(&sync).await?;
+        let event = device.next().await.unwrap()?;
+        assert_eq!(event.time(), Duration::from_secs(7));
+        assert_eq!(event.kind(), EV_KEY);
+        assert_eq!(event.code(), KEY_A);
+        assert_eq!(event.value(), 1);
+        let event = device.next().await.unwrap()?;
+        assert_eq!(event.kind(), EV_SYN);
+
+        writer.write_all(&press[..3]).await?;
+        drop(writer);
+        let err = device.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(device.next().await.is_none());
+        Ok(())
+    }
+}
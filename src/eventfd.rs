@@ -0,0 +1,108 @@
+//! Event counters (`eventfd(2)`).
+
+use crate::File;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::{fs, io, mem};
+
+/// An event counter backed by `eventfd(2)`, for waking up a task from
+/// another thread or process.
+///
+/// Writing adds to the counter; reading waits until the counter is nonzero,
+/// then returns it and resets it to zero.  In semaphore mode, reading
+/// instead decrements the counter by one and returns 1.
+///
+/// Only available on Linux.
+#[derive(Debug)]
+pub struct EventFd {
+    file: File<fs::File>,
+}
+
+impl EventFd {
+    /// Creates an event counter with the given initial value.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new(initval: u32) -> io::Result<Self> {
+        EventFd::with_flags(initval, 0)
+    }
+
+    /// Creates an event counter in semaphore mode (`EFD_SEMAPHORE`).
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new_semaphore(initval: u32) -> io::Result<Self> {
+        EventFd::with_flags(initval, libc::EFD_SEMAPHORE)
+    }
+
+    fn with_flags(initval: u32, flags: libc::c_int) -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(initval, flags | libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+        Ok(EventFd {
+            file: File::raw_new(file)?,
+        })
+    }
+
+    /// Waits until the counter is nonzero and takes its value (or 1 in
+    /// semaphore mode).
+    pub async fn read(&self) -> io::Result<u64> {
+        let mut value = [0; mem::size_of::<u64>()];
+        loop {
+            let mut guard = self.file.readable().await?;
+            match self.file.try_read(&mut value) {
+                Ok(_) => return Ok(u64::from_ne_bytes(value)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Adds `value` to the counter, waiting if that would exceed the
+    /// maximum of `u64::MAX - 1`.
+    pub async fn write(&self, value: u64) -> io::Result<()> {
+        loop {
+            let mut guard = self.file.writable().await?;
+            match self.file.try_write(&value.to_ne_bytes()) {
+                Ok(_) => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for EventFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_eventfd() -> io::Result<()> {
+        let counter = Arc::new(EventFd::new(0)?);
+        let writer = counter.clone();
+        std::thread::spawn(move || {
+            let fd = writer.as_raw_fd();
+            let value = 3u64.to_ne_bytes();
+            unsafe { libc::write(fd, value.as_ptr() as *const _, value.len()) };
+        });
+        assert_eq!(counter.read().await?, 3);
+
+        let semaphore = EventFd::new_semaphore(0)?;
+        semaphore.write(2).await?;
+        assert_eq!(semaphore.read().await?, 1);
+        assert_eq!(semaphore.read().await?, 1);
+        Ok(())
+    }
+}
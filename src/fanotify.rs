@@ -0,0 +1,275 @@
+//! Filesystem access events, including ones that wait for permission
+//! (`fanotify(7)`).
+//!
+//! Unlike inotify, fanotify reports which process accessed a file and hands
+//! over a descriptor of the file, and with `FAN_CLASS_CONTENT` it can hold
+//! an open or read until the listener allows or denies it, as virus
+//! scanners and other gatekeepers do.  It needs `CAP_SYS_ADMIN`.  Only
+//! available on Linux.
+
+use crate::File;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fs, io, mem, ptr};
+
+/// The fixed part of each event (`struct fanotify_event_metadata`), which
+/// libc does not define for every C library.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Metadata {
+    event_len: u32,
+    vers: u8,
+    reserved: u8,
+    metadata_len: u16,
+    mask: u64,
+    fd: i32,
+    pid: i32,
+}
+
+/// A filesystem access event.
+#[derive(Debug)]
+pub struct Event {
+    mask: u64,
+    pid: i32,
+    file: Option<fs::File>,
+}
+
+impl Event {
+    /// What happened, as a combination of `libc::FAN_*` flags.
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
+
+    /// The process that accessed the file.
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// A read-only descriptor of the accessed file, whose accesses do not
+    /// cause further events.  `None` for events such as `FAN_Q_OVERFLOW`.
+    pub fn file(&self) -> Option<&fs::File> {
+        self.file.as_ref()
+    }
+
+    /// Takes the descriptor of the accessed file.  Respond to permission
+    /// events first, since the response refers to the descriptor.
+    pub fn into_file(self) -> Option<fs::File> {
+        self.file
+    }
+}
+
+/// Parses the `fanotify_event_metadata` records in `buf`, taking ownership
+/// of the descriptors they carry.
+fn parse_events(mut buf: &[u8], events: &mut VecDeque<Event>) -> io::Result<()> {
+    let header = mem::size_of::<Metadata>();
+    while buf.len() >= header {
+        let raw = unsafe { ptr::read_unaligned(buf.as_ptr() as *const Metadata) };
+        let file = if raw.fd >= 0 {
+            Some(unsafe { fs::File::from_raw_fd(raw.fd) })
+        } else {
+            None
+        };
+        if raw.vers != libc::FANOTIFY_METADATA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown fanotify metadata version {}", raw.vers),
+            ));
+        }
+        events.push_back(Event {
+            mask: raw.mask,
+            pid: raw.pid,
+            file,
+        });
+        let len = (raw.event_len as usize).max(header).min(buf.len());
+        buf = &buf[len..];
+    }
+    Ok(())
+}
+
+/// Whether to let an access that waits for permission go ahead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Response {
+    /// Let it go ahead (`FAN_ALLOW`).
+    Allow,
+    /// Make it fail with `EPERM` (`FAN_DENY`).
+    Deny,
+}
+
+/// A fanotify group, which is a `Stream` of the events of all its marks.
+#[derive(Debug)]
+pub struct Fanotify {
+    file: File<fs::File>,
+    events: VecDeque<Event>,
+}
+
+impl Fanotify {
+    /// Creates a fanotify group without any marks.  `class` is one of
+    /// `libc::FAN_CLASS_NOTIF` for plain notifications, or
+    /// `libc::FAN_CLASS_CONTENT` or `libc::FAN_CLASS_PRE_CONTENT` to also
+    /// receive permission events, possibly combined with other
+    /// `fanotify_init` flags such as `FAN_UNLIMITED_QUEUE`.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new(class: libc::c_uint) -> io::Result<Self> {
+        let flags = class | libc::FAN_NONBLOCK | libc::FAN_CLOEXEC;
+        let event_flags = libc::O_RDONLY | libc::O_CLOEXEC | libc::O_LARGEFILE;
+        let fd = unsafe { libc::fanotify_init(flags, event_flags as libc::c_uint) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+        Ok(Fanotify {
+            file: File::raw_new(file)?,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Marks `path` for the events in `mask`, a combination of
+    /// `libc::FAN_*` event flags.  `flags` are `fanotify_mark` flags such
+    /// as `libc::FAN_MARK_MOUNT` to mark the whole mount, or 0 to mark just
+    /// the file or directory.
+    pub fn add_mark<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flags: libc::c_uint,
+        mask: u64,
+    ) -> io::Result<()> {
+        self.mark(path.as_ref(), libc::FAN_MARK_ADD | flags, mask)
+    }
+
+    /// Removes the events in `mask` from the mark on `path`.
+    pub fn remove_mark<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flags: libc::c_uint,
+        mask: u64,
+    ) -> io::Result<()> {
+        self.mark(path.as_ref(), libc::FAN_MARK_REMOVE | flags, mask)
+    }
+
+    fn mark(&self, path: &Path, flags: libc::c_uint, mask: u64) -> io::Result<()> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let r = unsafe {
+            libc::fanotify_mark(self.as_raw_fd(), flags, mask, libc::AT_FDCWD, path.as_ptr())
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Allows or denies the access that the permission `event`, such as
+    /// `FAN_OPEN_PERM`, is holding up.
+    ///
+    /// Every permission event must be answered, or the process that caused
+    /// it waits until the group is closed.  Fails with `InvalidInput` for
+    /// events without a descriptor.
+    pub async fn respond(&self, event: &Event, response: Response) -> io::Result<()> {
+        let fd = match event.file {
+            Some(ref file) => file.as_raw_fd(),
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "event without a descriptor",
+                ))
+            }
+        };
+        let response = libc::fanotify_response {
+            fd,
+            response: match response {
+                Response::Allow => libc::FAN_ALLOW,
+                Response::Deny => libc::FAN_DENY,
+            },
+        };
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &response as *const libc::fanotify_response as *const u8,
+                mem::size_of_val(&response),
+            )
+        };
+        // the group takes responses right away, and is never reported as
+        // writable, so there is nothing to wait for
+        self.file.try_write(bytes)?;
+        Ok(())
+    }
+}
+
+impl Stream for Fanotify {
+    type Item = io::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut buf = [0; 4096];
+        while this.events.is_empty() {
+            let mut guard = ready!(this.file.poll_read_ready(cx))?;
+            match this.file.try_read(&mut buf) {
+                Ok(n) => {
+                    if let Err(e) = parse_events(&buf[..n], &mut this.events) {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+        Poll::Ready(this.events.pop_front().map(Ok))
+    }
+}
+
+impl AsRawFd for Fanotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for Fanotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_fanotify() -> io::Result<()> {
+        let fanotify = match Fanotify::new(libc::FAN_CLASS_CONTENT) {
+            Ok(fanotify) => fanotify,
+            // not privileged, or not supported by the kernel
+            Err(ref e)
+                if e.kind() == io::ErrorKind::PermissionDenied
+                    || e.raw_os_error() == Some(libc::ENOSYS) =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        };
+        let mut fanotify = fanotify;
+        let path = TempPath::new("fanotify");
+        fs::write(&path, b"scanned")?;
+        fanotify.add_mark(&path, 0, libc::FAN_OPEN_PERM)?;
+
+        let opener = {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || fs::read(path))
+        };
+        let event = fanotify.next().await.unwrap()?;
+        assert_eq!(event.mask(), libc::FAN_OPEN_PERM);
+        assert_eq!(event.pid(), std::process::id() as i32);
+        assert!(event.file().is_some());
+        fanotify.respond(&event, Response::Allow).await?;
+        assert_eq!(opener.await??, b"scanned");
+
+        fanotify.remove_mark(&path, 0, libc::FAN_OPEN_PERM)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+//! Named pipes (FIFOs).
+//!
+//! Opening a FIFO normally blocks until the other end is opened too.  The
+//! functions here open it in nonblocking mode instead and wait for the peer
+//! asynchronously.
+
+use crate::{File, RetryPolicy};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+use std::{fs, future, io};
+
+fn cstring(path: &Path) -> io::Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+fn open_nb(path: &Path, flags: libc::c_int) -> io::Result<fs::File> {
+    let path = cstring(path)?;
+    let fd = unsafe { libc::open(path.as_ptr(), flags | libc::O_NONBLOCK | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)
+}
+
+/// Creates a FIFO at `path` with the given permission bits (subject to the
+/// umask).
+pub fn mkfifo<P: AsRef<Path>>(path: P, mode: libc::mode_t) -> io::Result<()> {
+    let path = cstring(path.as_ref())?;
+    if unsafe { libc::mkfifo(path.as_ptr(), mode) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Opens the FIFO at `path` for reading, waiting until a writer has
+/// connected and either written something or hung up.
+///
+/// Without the wait, reading a FIFO that no writer has opened yet would
+/// report EOF right away.
+pub async fn open_read<P: AsRef<Path>>(path: P) -> io::Result<File<fs::File>> {
+    let file = File::raw_new(open_nb(path.as_ref(), libc::O_RDONLY)?)?;
+    // Linux does not report a hangup on a FIFO until a writer that connected
+    // after it was opened goes away, so this waits for the first writer
+    future::poll_fn(|cx| file.poll_read_ready(cx).map_ok(drop)).await?;
+    Ok(file)
+}
+
+/// Opens the FIFO at `path` for writing, waiting until a reader has
+/// connected.
+///
+/// The kernel offers no way to be notified of a reader, so this retries
+/// periodically with increasing delays of up to 100 ms.
+pub async fn open_write<P: AsRef<Path>>(path: P) -> io::Result<File<fs::File>> {
+    open_write_with(path, &RetryPolicy::default()).await
+}
+
+/// Like [`open_write`](fn.open_write.html), but retries according to
+/// `policy`.  Once it runs out of attempts, this fails with `ENXIO`.
+pub async fn open_write_with<P: AsRef<Path>>(
+    path: P,
+    policy: &RetryPolicy,
+) -> io::Result<File<fs::File>> {
+    let path = path.as_ref();
+    let file = policy
+        .retry(
+            || future::ready(open_nb(path, libc::O_WRONLY)),
+            |e| e.raw_os_error() == Some(libc::ENXIO),
+        )
+        .await?;
+    File::raw_new(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_fifo() -> io::Result<()> {
+        let path = TempPath::new("fifo");
+        mkfifo(&path, 0o600)?;
+        let writer = async {
+            let mut writer = open_write(&path).await?;
+            writer.write_all(b"through the fifo").await
+        };
+        let reader = async {
+            let mut reader = open_read(&path).await?;
+            let mut received = String::new();
+            reader.read_to_string(&mut received).await?;
+            Ok::<_, io::Error>(received)
+        };
+        let (written, received) = tokio::join!(writer, reader);
+        written?;
+        assert_eq!(received?, "through the fifo");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_write_gives_up() -> io::Result<()> {
+        let path = TempPath::new("fifo-unread");
+        mkfifo(&path, 0o600)?;
+        let policy = RetryPolicy::new().max_attempts(3);
+        let err = open_write_with(&path, &policy).await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+        Ok(())
+    }
+}
@@ -0,0 +1,284 @@
+//! Following a growing file, like `tail -F`.
+
+use crate::File;
+use bytes::{Bytes, BytesMut};
+use futures_core::Stream;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fs, future::Future};
+use tokio::time::{Instant, Sleep};
+
+/// How often the file is checked for changes when no notification arrives.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The largest chunk yielded at once.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A `Stream` of the data appended to a file, created by
+/// [`File::follow`](struct.File.html#method.follow).
+#[derive(Debug)]
+pub struct Follow {
+    path: PathBuf,
+    file: File<fs::File>,
+    /// The device and inode of `file`, to tell when the path is replaced.
+    identity: (u64, u64),
+    pos: u64,
+    /// Reused across polls, so that wakeups that find nothing new do not
+    /// allocate.  The chunks yielded are split off it.
+    buf: BytesMut,
+    wakeup: Wakeup,
+}
+
+/// Waits for something to happen to the file.  Notifications only cut the
+/// wait short; the timer keeps going in case they are missed.
+#[derive(Debug)]
+struct Wakeup {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    inotify: Option<crate::inotify::Inotify>,
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    watch: Option<crate::vnode::Watch>,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl Wakeup {
+    #[allow(unused_variables)]
+    fn new(path: &Path, file: &File<fs::File>) -> Self {
+        Wakeup {
+            // the directory, since the file may be replaced
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            inotify: crate::inotify::Inotify::new().ok().and_then(|inotify| {
+                let dir = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir,
+                    _ => Path::new("."),
+                };
+                let mask = libc::IN_MODIFY
+                    | libc::IN_ATTRIB
+                    | libc::IN_CREATE
+                    | libc::IN_MOVED_TO
+                    | libc::IN_MOVED_FROM
+                    | libc::IN_DELETE;
+                inotify.add_watch(dir, mask).ok()?;
+                Some(inotify)
+            }),
+            #[cfg(any(
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd",
+                target_os = "dragonfly",
+                target_os = "netbsd",
+                target_os = "openbsd"
+            ))]
+            watch: Wakeup::watch(file),
+            sleep: Box::pin(tokio::time::sleep(POLL_INTERVAL)),
+        }
+    }
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    fn watch(file: &File<fs::File>) -> Option<crate::vnode::Watch> {
+        use crate::vnode::Events;
+        let events = Events::WRITE | Events::EXTEND | Events::ATTRIB | Events::DELETE;
+        file.watch(events | Events::RENAME).ok()
+    }
+
+    /// Follows a file that replaced the previous one at the path.
+    #[allow(unused_variables)]
+    fn reopened(&mut self, file: &File<fs::File>) {
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        {
+            self.watch = Wakeup::watch(file);
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            if let Some(ref mut inotify) = self.inotify {
+                match Pin::new(inotify).poll_next(cx) {
+                    Poll::Ready(Some(Ok(_))) => return Poll::Ready(()),
+                    // fall back to the timer alone
+                    Poll::Ready(_) => self.inotify = None,
+                    Poll::Pending => {}
+                }
+            }
+        }
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        {
+            if let Some(ref mut watch) = self.watch {
+                match Pin::new(watch).poll_next(cx) {
+                    Poll::Ready(Some(Ok(_))) => return Poll::Ready(()),
+                    Poll::Ready(_) => self.watch = None,
+                    Poll::Pending => {}
+                }
+            }
+        }
+        ready!(self.sleep.as_mut().poll(cx));
+        self.sleep.as_mut().reset(Instant::now() + POLL_INTERVAL);
+        Poll::Ready(())
+    }
+}
+
+fn identity(metadata: &fs::Metadata) -> (u64, u64) {
+    (metadata.dev(), metadata.ino())
+}
+
+impl File<fs::File> {
+    /// Opens the file at `path` and follows it like `tail -F`: the stream
+    /// yields its contents from the start, then whatever is appended to it.
+    ///
+    /// Once the end is reached, the stream waits for the file to change,
+    /// through inotify on Linux and kqueue on macOS and the BSDs, and by
+    /// checking every second regardless.  If the file is truncated, it is
+    /// read again from the start.  If `path` comes to refer to a different
+    /// file, as when a log is rotated, the rest of the old file is read and
+    /// then the new one is followed from its start.  The stream never ends.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn follow<P: AsRef<Path>>(path: P) -> io::Result<Follow> {
+        let path = path.as_ref().to_owned();
+        let file = File::raw_new(fs::File::open(&path)?)?;
+        let identity = identity(&file.get_ref().metadata()?);
+        let wakeup = Wakeup::new(&path, &file);
+        Ok(Follow {
+            path,
+            file,
+            identity,
+            pos: 0,
+            buf: BytesMut::new(),
+            wakeup,
+        })
+    }
+}
+
+impl Follow {
+    /// The path being followed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Switches to a new file at the path, or rewinds the current one if it
+    /// was truncated, returning whether either happened.
+    fn check_replaced(&mut self) -> io::Result<bool> {
+        match fs::metadata(&self.path) {
+            Ok(ref metadata) if identity(metadata) != self.identity => {
+                let file = match fs::File::open(&self.path) {
+                    Ok(file) => file,
+                    // replaced again in the meantime
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+                    Err(e) => return Err(e),
+                };
+                self.identity = identity(&file.metadata()?);
+                self.file = File::raw_new(file)?;
+                self.pos = 0;
+                self.wakeup.reopened(&self.file);
+                return Ok(true);
+            }
+            // between the removal of the old file and the creation of the
+            // new one
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+        if self.file.get_ref().metadata()?.len() < self.pos {
+            self.file.get_ref().seek(SeekFrom::Start(0))?;
+            self.pos = 0;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl Stream for Follow {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        // reclaims the memory of earlier chunks if they were all dropped, and
+        // is free if the last poll read nothing
+        this.buf.resize(CHUNK_SIZE, 0);
+        loop {
+            match this.file.get_ref().read(&mut this.buf) {
+                Ok(0) => {}
+                Ok(n) => {
+                    this.pos += n as u64;
+                    this.buf.truncate(n);
+                    return Poll::Ready(Some(Ok(this.buf.split().freeze())));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+            match this.check_replaced() {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+            ready!(this.wakeup.poll(cx));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use futures::StreamExt;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_follow() -> io::Result<()> {
+        let path = TempPath::new("follow");
+        let rotated = TempPath::new("follow-rotated");
+        fs::write(&path, b"first\n")?;
+        let mut follow = File::follow(&path)?;
+        assert_eq!(follow.next().await.unwrap()?, &b"first\n"[..]);
+
+        let mut writer = fs::OpenOptions::new().append(true).open(&path)?;
+        writer.write_all(b"appended\n")?;
+        assert_eq!(follow.next().await.unwrap()?, &b"appended\n"[..]);
+
+        // the rest of the rotated file comes first
+        fs::rename(&path, &rotated)?;
+        writer.write_all(b"last\n")?;
+        fs::write(&path, b"rotated\n")?;
+        assert_eq!(follow.next().await.unwrap()?, &b"last\n"[..]);
+        assert_eq!(follow.next().await.unwrap()?, &b"rotated\n"[..]);
+
+        let writer = fs::OpenOptions::new().write(true).open(&path)?;
+        writer.set_len(0)?;
+        (&writer).write_all(b"new\n")?;
+        assert_eq!(follow.next().await.unwrap()?, &b"new\n"[..]);
+
+        Ok(())
+    }
+}
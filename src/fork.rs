@@ -0,0 +1,127 @@
+//! Taking files across `fork` and from one runtime to another.
+//!
+//! A registration with the reactor belongs to the runtime it was made in.
+//! A child process created with `fork` inherits the descriptors but not a
+//! working runtime, so the registrations it inherits silently stop
+//! delivering events.  Deactivate files before forking, or before the
+//! runtime shuts down, and reactivate them in the new runtime.
+
+use crate::blocking::{Blocking, RegularFileMode};
+use crate::{debug, File};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use tokio::io::unix::AsyncFd;
+
+/// A [`File`](struct.File.html) that is not registered with any runtime,
+/// created by [`File::deactivate`](struct.File.html#method.deactivate).
+///
+/// It keeps the settings of the file, such as the interest it was
+/// registered with and whether it is offloaded to the blocking thread pool,
+/// and is not tied to a runtime, so it can be kept across `fork` or moved
+/// to another runtime.
+#[derive(Debug)]
+pub struct Deactivated<F> {
+    file: File<F>,
+    /// How to offload the file, if it was not registered with the reactor.
+    mode: Option<RegularFileMode>,
+}
+
+impl<F> Deactivated<F> {
+    /// Gets a reference to the underlying file-like object.
+    pub fn get_ref(&self) -> &F {
+        &self.file.file
+    }
+}
+
+impl<F: AsRawFd> Deactivated<F> {
+    /// Returns the underlying file-like object, for handing the descriptor
+    /// to a program run with `exec`.  Nonblocking mode is left as is, see
+    /// [`set_nonblocking`](fn.set_nonblocking.html), and so is
+    /// close-on-exec, see [`set_cloexec`](fn.set_cloexec.html).
+    pub fn into_inner(self) -> F {
+        self.file.into_inner()
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for Deactivated<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Deregisters the file from the reactor, along with anything else tied
+    /// to the current runtime, such as the blocking thread pool and
+    /// [subscriptions](#method.subscribe), keeping the descriptor open.
+    ///
+    /// With `RegularFileMode::ThreadPool`, flush first, since writes still
+    /// in flight on the pool are not waited for.
+    pub fn deactivate(mut self) -> Deactivated<F> {
+        let mode = if self.evented.take().is_some() {
+            audit!(
+                "File::deactivate",
+                self.as_raw_fd(),
+                "deregistered from the reactor"
+            );
+            None
+        } else if self.blocking.take().is_some() {
+            Some(RegularFileMode::ThreadPool)
+        } else {
+            Some(RegularFileMode::Inline)
+        };
+        self.subscription.lock().unwrap().take();
+        Deactivated { file: self, mode }
+    }
+
+    /// Registers a file deactivated with
+    /// [`deactivate`](#method.deactivate) with the reactor of the current
+    /// runtime again, with the same settings.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn reactivate(deactivated: Deactivated<F>) -> io::Result<Self> {
+        let Deactivated { mut file, mode } = deactivated;
+        let fd = file.as_raw_fd();
+        match mode {
+            None => {
+                if let Some(interest) = file.interest {
+                    file.evented = Some(AsyncFd::with_interest(fd, interest)?);
+                    audit!("File::reactivate", fd, "registered with the reactor");
+                }
+            }
+            Some(mode) => file.blocking = Blocking::for_mode(&file.file, mode)?,
+        }
+        file.registration = debug::Registration::new(fd, file.interest);
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_reactivate() -> io::Result<()> {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let (reader, writer) = runtime.block_on(async {
+            let (reader, writer) = crate::pipe()?;
+            io::Result::Ok((reader.into_inner().deactivate(), writer.into_inner()))
+        })?;
+        let writer = writer.deactivate();
+        // as in a child process, whose runtime is not the parent's
+        drop(runtime);
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            let mut reader = File::reactivate(reader)?;
+            let mut writer = File::reactivate(writer)?;
+            assert!(crate::get_nonblocking(&reader)?);
+            writer.write_all(b"across runtimes").await?;
+            drop(writer);
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).await?;
+            assert_eq!(data, b"across runtimes");
+            Ok(())
+        })
+    }
+}
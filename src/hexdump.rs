@@ -0,0 +1,140 @@
+//! Hex dumps of traffic passing through a reader or writer.
+
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Writes `data` to `sink` in the style of `hexdump -C`, with offsets
+/// starting at `offset` and each line prefixed by `label`.
+fn dump<S: Write>(sink: &mut S, label: &str, offset: u64, data: &[u8]) -> io::Result<()> {
+    for (i, line) in data.chunks(16).enumerate() {
+        write!(sink, "{}{:08x} ", label, offset + 16 * i as u64)?;
+        for j in 0..16 {
+            if j % 8 == 0 {
+                write!(sink, " ")?;
+            }
+            match line.get(j) {
+                Some(byte) => write!(sink, "{:02x} ", byte)?,
+                None => write!(sink, "   ")?,
+            }
+        }
+        let ascii: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(sink, " |{}|", ascii)?;
+    }
+    Ok(())
+}
+
+/// Passes reads and writes through to `inner` unchanged while writing a hex
+/// dump of the data to a sink, for debugging binary protocols.
+///
+/// Each line of the dump shows the offset within the stream, sixteen bytes
+/// in hex, and the same bytes as ASCII.  Data read is prefixed with `< ` and
+/// data written with `> `, and the two directions count their offsets
+/// separately.  Errors writing to the sink are ignored.
+///
+/// ```
+/// # async fn run() -> std::io::Result<()> {
+/// let (reader, _writer) = tokio_file_unix::pipe()?;
+/// let reader = tokio_file_unix::HexDumpTap::new(reader, std::io::stderr());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HexDumpTap<T, S> {
+    inner: T,
+    sink: S,
+    read_offset: u64,
+    write_offset: u64,
+}
+
+impl<T, S: Write> HexDumpTap<T, S> {
+    /// Wraps `inner`, dumping its traffic to `sink`.
+    pub fn new(inner: T, sink: S) -> Self {
+        HexDumpTap {
+            inner,
+            sink,
+            read_offset: 0,
+            write_offset: 0,
+        }
+    }
+
+    /// Gets a reference to the wrapped reader or writer.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped reader or writer and the sink.
+    pub fn into_inner(self) -> (T, S) {
+        (self.inner, self.sink)
+    }
+}
+
+impl<T: AsyncRead + Unpin, S: Write + Unpin> AsyncRead for HexDumpTap<T, S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let data = &buf.filled()[filled..];
+        let _ = dump(&mut this.sink, "< ", this.read_offset, data);
+        this.read_offset += data.len() as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin, S: Write + Unpin> AsyncWrite for HexDumpTap<T, S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        let _ = dump(&mut this.sink, "> ", this.write_offset, &buf[..n]);
+        this.write_offset += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let _ = this.sink.flush();
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_hex_dump_tap() -> io::Result<()> {
+        let mut tap = HexDumpTap::new(Vec::new(), Vec::new());
+        tap.write_all(b"Hello, world!\n\x00\x01\xff").await?;
+        let (data, dump) = tap.into_inner();
+        assert_eq!(data, b"Hello, world!\n\x00\x01\xff");
+        assert_eq!(
+            String::from_utf8(dump).unwrap(),
+            "> 00000000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 01  |Hello, world!...|\n\
+             > 00000010  ff                                                |.|\n"
+        );
+        Ok(())
+    }
+}
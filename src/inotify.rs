@@ -0,0 +1,174 @@
+//! Filesystem events (`inotify(7)`).
+//!
+//! Only available on Linux.
+
+use crate::File;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::ffi::{CString, OsStr, OsString};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fs, io, mem, ptr};
+
+/// Identifies a watch added with
+/// [`Inotify::add_watch`](struct.Inotify.html#method.add_watch).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WatchDescriptor(libc::c_int);
+
+/// A filesystem event (`struct inotify_event`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    wd: WatchDescriptor,
+    mask: u32,
+    cookie: u32,
+    name: Option<OsString>,
+}
+
+impl Event {
+    /// The watch that produced the event.
+    pub fn wd(&self) -> WatchDescriptor {
+        self.wd
+    }
+
+    /// What happened, as a combination of `libc::IN_*` flags.
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Connects the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a rename.
+    pub fn cookie(&self) -> u32 {
+        self.cookie
+    }
+
+    /// The name of the affected file, for events on the contents of a
+    /// watched directory.
+    pub fn name(&self) -> Option<&OsStr> {
+        self.name.as_deref()
+    }
+}
+
+/// Parses the `inotify_event` records in `buf`.
+fn parse_events(mut buf: &[u8], events: &mut VecDeque<Event>) {
+    let header = mem::size_of::<libc::inotify_event>();
+    while buf.len() >= header {
+        let raw = unsafe { ptr::read_unaligned(buf.as_ptr() as *const libc::inotify_event) };
+        let end = (header + raw.len as usize).min(buf.len());
+        // the name is padded with NULs
+        let name = &buf[header..end];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+        events.push_back(Event {
+            wd: WatchDescriptor(raw.wd),
+            mask: raw.mask,
+            cookie: raw.cookie,
+            name: if name.is_empty() {
+                None
+            } else {
+                Some(OsStr::from_bytes(name).to_owned())
+            },
+        });
+        buf = &buf[end..];
+    }
+}
+
+/// An inotify instance, which is a `Stream` of the events of all its
+/// watches.
+#[derive(Debug)]
+pub struct Inotify {
+    file: File<fs::File>,
+    events: VecDeque<Event>,
+}
+
+impl Inotify {
+    /// Creates an inotify instance without any watches.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+        Ok(Inotify {
+            file: File::raw_new(file)?,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Watches `path` for the events in `mask`, a combination of
+    /// `libc::IN_*` flags.  Watching the same path again replaces the mask
+    /// and returns the same descriptor.
+    pub fn add_watch<P: AsRef<Path>>(&self, path: P, mask: u32) -> io::Result<WatchDescriptor> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+        let wd = unsafe { libc::inotify_add_watch(self.as_raw_fd(), path.as_ptr(), mask) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(WatchDescriptor(wd))
+    }
+
+    /// Removes a watch.  An `IN_IGNORED` event follows.
+    pub fn rm_watch(&self, wd: WatchDescriptor) -> io::Result<()> {
+        if unsafe { libc::inotify_rm_watch(self.as_raw_fd(), wd.0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Stream for Inotify {
+    type Item = io::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        // large enough for at least one event with the longest name
+        let mut buf = [0; 4096];
+        while this.events.is_empty() {
+            let mut guard = ready!(this.file.poll_read_ready(cx))?;
+            match this.file.try_read(&mut buf) {
+                Ok(n) => parse_events(&buf[..n], &mut this.events),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+        Poll::Ready(this.events.pop_front().map(Ok))
+    }
+}
+
+impl AsRawFd for Inotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for Inotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_inotify() -> io::Result<()> {
+        let dir = TempPath::new("inotify");
+        fs::create_dir(&dir)?;
+        let mut inotify = Inotify::new()?;
+        let wd = inotify.add_watch(&dir, libc::IN_CREATE | libc::IN_DELETE)?;
+        fs::write(dir.join("config"), b"")?;
+        fs::remove_file(dir.join("config"))?;
+        let created = inotify.next().await.unwrap()?;
+        let deleted = inotify.next().await.unwrap()?;
+        assert_eq!(created.wd(), wd);
+        assert_eq!(created.mask(), libc::IN_CREATE);
+        assert_eq!(created.name(), Some(OsStr::new("config")));
+        assert_eq!(deleted.mask(), libc::IN_DELETE);
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+//! Instrumentation through the `tracing` crate (the `tracing` feature).
+//!
+//! Every wrapped file gets a `file` span, with the descriptor number and its
+//! [`FdKind`](../enum.FdKind.html) as fields, under the `tokio_file_unix`
+//! target.  Registration with the reactor and the fallback to treating the
+//! file as always ready are recorded in it at debug level, and reads,
+//! writes, short writes, and operations that would block at trace level.
+
+use crate::{FdKind, RegularFileMode};
+use std::io;
+use std::os::unix::io::RawFd;
+use tokio::io::Interest;
+use tracing::{debug_span, event, Level};
+
+#[derive(Debug)]
+pub(crate) struct Span(tracing::Span);
+
+impl Span {
+    pub(crate) fn new(fd: RawFd, kind: FdKind) -> Self {
+        Span(debug_span!(target: "tokio_file_unix", "file", fd, kind = ?kind))
+    }
+
+    pub(crate) fn registered(&self, interest: Interest) {
+        event!(
+            target: "tokio_file_unix",
+            parent: &self.0,
+            Level::DEBUG,
+            interest = ?interest,
+            "registered with the reactor"
+        );
+    }
+
+    pub(crate) fn fallback(&self, mode: RegularFileMode) {
+        event!(
+            target: "tokio_file_unix",
+            parent: &self.0,
+            Level::DEBUG,
+            mode = ?mode,
+            "not pollable, treated as always ready"
+        );
+    }
+
+    pub(crate) fn would_block(&self, interest: Interest) {
+        event!(
+            target: "tokio_file_unix",
+            parent: &self.0,
+            Level::TRACE,
+            interest = ?interest,
+            "would block"
+        );
+    }
+
+    pub(crate) fn read<T>(&self, result: &io::Result<T>, n: usize) {
+        match *result {
+            Ok(_) => event!(
+                target: "tokio_file_unix",
+                parent: &self.0,
+                Level::TRACE,
+                bytes = n,
+                "read"
+            ),
+            Err(ref e) => self.failed("read", e),
+        }
+    }
+
+    pub(crate) fn wrote(&self, result: &io::Result<usize>, len: usize) {
+        match *result {
+            Ok(n) if n < len => event!(
+                target: "tokio_file_unix",
+                parent: &self.0,
+                Level::TRACE,
+                bytes = n,
+                requested = len,
+                "short write"
+            ),
+            Ok(n) => event!(
+                target: "tokio_file_unix",
+                parent: &self.0,
+                Level::TRACE,
+                bytes = n,
+                "wrote"
+            ),
+            Err(ref e) => self.failed("write", e),
+        }
+    }
+
+    fn failed(&self, op: &'static str, e: &io::Error) {
+        if e.kind() == io::ErrorKind::WouldBlock {
+            event!(
+                target: "tokio_file_unix",
+                parent: &self.0,
+                Level::TRACE,
+                op,
+                "would block"
+            );
+        } else {
+            event!(
+                target: "tokio_file_unix",
+                parent: &self.0,
+                Level::DEBUG,
+                op,
+                error = %e,
+                "failed"
+            );
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Device control requests that wait for readiness.
+
+use crate::{check_stale, File};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use tokio::io::Interest;
+
+impl<F: AsRawFd> File<F> {
+    /// Waits for any of the readiness states in `interest`, then issues the
+    /// `ioctl` `request` with a pointer to `arg`, waiting again for as long
+    /// as it fails with `EAGAIN`, and returns what it returned.
+    ///
+    /// This is the pattern for drivers whose requests fail with `EAGAIN` on
+    /// a nonblocking descriptor until the device is ready, such as dequeuing
+    /// a buffer from a V4L2 capture device once it is readable, or reading
+    /// the next event of a DRM or ALSA device, without calling `ioctl` on
+    /// the inner descriptor and handling readiness by hand.  Requests
+    /// interrupted by a signal are retried unless
+    /// [`FileBuilder::retry_interrupted`](struct.FileBuilder.html#method.retry_interrupted)
+    /// says otherwise.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be of the type that the driver expects for `request`,
+    /// since the kernel reads and writes through the pointer accordingly.
+    pub async unsafe fn ioctl_with_ready<T>(
+        &self,
+        request: libc::c_ulong,
+        arg: &mut T,
+        interest: Interest,
+    ) -> io::Result<libc::c_int> {
+        let fd = self.as_raw_fd();
+        loop {
+            let mut guard = self.ready(interest).await?;
+            let r = libc::ioctl(fd, request as _, arg as *mut T);
+            if r >= 0 {
+                return Ok(r);
+            }
+            let e = io::Error::last_os_error();
+            match e.kind() {
+                io::ErrorKind::WouldBlock => guard.clear_ready(),
+                io::ErrorKind::Interrupted if self.retry_interrupted => {}
+                _ => return check_stale(fd, self.identity, Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncWriteExt, Interest};
+
+    #[tokio::test]
+    async fn test_ioctl_with_ready() -> std::io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let reader = reader.into_inner();
+        writer.write_all(b"ready").await?;
+        let mut pending: libc::c_int = 0;
+        let r = unsafe {
+            reader
+                .ioctl_with_ready(
+                    libc::FIONREAD as libc::c_ulong,
+                    &mut pending,
+                    Interest::READABLE,
+                )
+                .await?
+        };
+        assert_eq!((r, pending), (0, 5));
+        Ok(())
+    }
+}
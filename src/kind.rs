@@ -0,0 +1,256 @@
+//! Telling what file is behind a descriptor.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+
+/// The kind of file behind a descriptor, as reported by `fstat` and
+/// `isatty`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FdKind {
+    /// A terminal or pseudoterminal.
+    Tty,
+    /// A pipe or FIFO.
+    Pipe,
+    /// A socket.
+    Socket,
+    /// A regular file.
+    RegularFile,
+    /// A character device other than a terminal, such as `/dev/null`.
+    CharDevice,
+    /// A directory.
+    Directory,
+    /// Anything else, such as a block device.
+    Other,
+}
+
+impl FdKind {
+    /// Inspects the file behind the descriptor of `file`.
+    pub fn of<F: AsRawFd>(file: &F) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        Ok(FdKind::from_stat(fd, &fstat(fd)?))
+    }
+
+    fn from_stat(fd: RawFd, stat: &libc::stat) -> Self {
+        match stat.st_mode & libc::S_IFMT {
+            libc::S_IFIFO => FdKind::Pipe,
+            libc::S_IFSOCK => FdKind::Socket,
+            libc::S_IFCHR if unsafe { libc::isatty(fd) } == 1 => FdKind::Tty,
+            libc::S_IFCHR => FdKind::CharDevice,
+            libc::S_IFREG => FdKind::RegularFile,
+            libc::S_IFDIR => FdKind::Directory,
+            _ => FdKind::Other,
+        }
+    }
+
+    /// Whether files of this kind may be registered with the reactor.
+    /// Regular files and directories are always ready as far as the kernel
+    /// is concerned, so epoll refuses them.
+    pub fn is_pollable(self) -> bool {
+        !matches!(self, FdKind::RegularFile | FdKind::Directory)
+    }
+}
+
+/// Whether the descriptor of `file` can be registered with epoll, which
+/// decides whether [`File`](struct.File.html) waits for readiness or treats
+/// the file as always ready.
+///
+/// Regular files and directories never can, with the exception of the
+/// sysfs and procfs files that signal changes through priority readiness.
+/// Some character devices, such as `/dev/null`, cannot either.
+///
+/// Implementation detail: pipes, sockets, and terminals are taken on trust
+/// from `fstat`.  Anything else is added to a temporary epoll instance on
+/// Linux, and judged by its kind alone on other platforms.
+pub fn supports_epoll<F: AsRawFd>(file: &F) -> io::Result<bool> {
+    let fd = file.as_raw_fd();
+    supports_epoll_as(fd, FdKind::of(&fd)?)
+}
+
+/// Like `supports_epoll`, for a descriptor already known to be of `kind`.
+pub(crate) fn supports_epoll_as(fd: RawFd, kind: FdKind) -> io::Result<bool> {
+    match kind {
+        FdKind::Pipe | FdKind::Socket | FdKind::Tty => Ok(true),
+        _ => probe_epoll(fd, kind),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn probe_epoll(fd: RawFd, _: FdKind) -> io::Result<bool> {
+    use std::os::unix::io::{FromRawFd, OwnedFd};
+    let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    if epoll < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let epoll = unsafe { OwnedFd::from_raw_fd(epoll) };
+    let mut event = libc::epoll_event { events: 0, u64: 0 };
+    if unsafe { libc::epoll_ctl(epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) } < 0 {
+        let e = io::Error::last_os_error();
+        // files without a poll operation are refused outright
+        return match e.raw_os_error() {
+            Some(libc::EPERM) => Ok(false),
+            _ => Err(e),
+        };
+    }
+    Ok(true)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn probe_epoll(_: RawFd, kind: FdKind) -> io::Result<bool> {
+    Ok(kind.is_pollable())
+}
+
+pub(crate) fn fstat(fd: RawFd) -> io::Result<libc::stat> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat)
+}
+
+/// What `fstat` says about the file behind a descriptor, returned by
+/// [`File::fd_stat`](struct.File.html#method.fd_stat).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FdStat {
+    dev: u64,
+    ino: u64,
+    kind: FdKind,
+}
+
+impl FdStat {
+    /// The device the file lives on.
+    pub fn dev(&self) -> u64 {
+        self.dev
+    }
+
+    /// The inode number, which together with the device identifies the
+    /// file, even a pipe or socket that has no path.
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// The kind of file.
+    pub fn kind(&self) -> FdKind {
+        self.kind
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Resolves the path of the file behind the descriptor, for logging and
+    /// error messages.
+    ///
+    /// The path is only a hint: the file may have been renamed or deleted
+    /// since it was opened.  On Linux, files without a path resolve to
+    /// names such as `pipe:[1234]` or `socket:[5678]`.  Fails with
+    /// `Unsupported` on platforms with neither of the mechanisms below.
+    ///
+    /// Implementation detail: reads the link `/proc/self/fd/N` on Linux and
+    /// uses `fcntl` with `F_GETPATH` on macOS.
+    pub fn fd_path(&self) -> io::Result<PathBuf> {
+        fd_path(self.as_raw_fd())
+    }
+
+    /// Gets the device, inode, and kind of the file behind the descriptor.
+    pub fn fd_stat(&self) -> io::Result<FdStat> {
+        let fd = self.as_raw_fd();
+        let stat = fstat(fd)?;
+        Ok(FdStat {
+            dev: stat.st_dev as u64,
+            ino: stat.st_ino as u64,
+            kind: FdKind::from_stat(fd, &stat),
+        })
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn fd_path(fd: RawFd) -> io::Result<PathBuf> {
+    std::fs::read_link(format!("/proc/self/fd/{}", fd))
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn fd_path(fd: RawFd) -> io::Result<PathBuf> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    let mut buf = [0u8; libc::PATH_MAX as usize];
+    if unsafe { libc::fcntl(fd, libc::F_GETPATH, buf.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(PathBuf::from(OsStr::from_bytes(&buf[..len])))
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+fn fd_path(_: RawFd) -> io::Result<PathBuf> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cannot resolve the path of a descriptor on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_fd_kind() -> io::Result<()> {
+        let (a, _b) = std::os::unix::net::UnixStream::pair()?;
+        assert_eq!(FdKind::of(&a)?, FdKind::Socket);
+        let (reader, _writer) = crate::pipe()?;
+        assert_eq!(FdKind::of(&reader)?, FdKind::Pipe);
+        assert_eq!(
+            FdKind::of(&fs::File::open("/dev/null")?)?,
+            FdKind::CharDevice
+        );
+        assert_eq!(FdKind::of(&fs::File::open("/")?)?, FdKind::Directory);
+        let kind = FdKind::of(&fs::File::open("Cargo.toml")?)?;
+        assert_eq!(kind, FdKind::RegularFile);
+        assert!(!kind.is_pollable());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_supports_epoll() -> io::Result<()> {
+        let (a, _b) = std::os::unix::net::UnixStream::pair()?;
+        assert!(supports_epoll(&a)?);
+        let (reader, _writer) = crate::pipe()?;
+        assert!(supports_epoll(&reader)?);
+        assert!(!supports_epoll(&fs::File::open("Cargo.toml")?)?);
+        assert!(!supports_epoll(&fs::File::open("/")?)?);
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let null = fs::File::open("/dev/null")?;
+            assert!(!supports_epoll(&null)?);
+            let file = File::new_nb(null)?;
+            assert!(file.is_fallback_active());
+            assert!(!crate::get_nonblocking(&file)?);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fd_path_and_stat() -> io::Result<()> {
+        use std::os::unix::fs::MetadataExt;
+        let path = std::env::current_dir()?.join("Cargo.toml");
+        let file = File::new_nb(fs::File::open(&path)?)?;
+        assert_eq!(file.fd_path()?, path);
+        let stat = file.fd_stat()?;
+        let metadata = fs::metadata(&path)?;
+        assert_eq!((stat.dev(), stat.ino()), (metadata.dev(), metadata.ino()));
+        assert_eq!(stat.kind(), FdKind::RegularFile);
+
+        let (reader, _writer) = crate::pipe()?;
+        let reader = reader.into_inner();
+        assert_eq!(reader.fd_stat()?.kind(), FdKind::Pipe);
+        let name = reader.fd_path()?;
+        assert!(name.to_string_lossy().starts_with("pipe:["), "{:?}", name);
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+//! A two-lane framing protocol in which urgent frames overtake bulk data.
+//!
+//! Each frame is sent as a one-byte lane tag (0 for urgent, 1 for bulk),
+//! the length of the payload as a little-endian `u32`, and the payload.
+
+use crate::middleware::{self, FrameMiddleware, Middleware};
+use crate::records::RecordTooLong;
+use crate::split::{ReadHalf, WriteHalf};
+use crate::File;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{future, io, mem};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const HEADER_LEN: usize = 5;
+
+/// A received frame and the lane it came in.
+type Frame = (Lane, Vec<u8>);
+
+/// Writes out the frames left in a dropped writer in the background.
+type SpawnFlush<W> = fn(LaneWriter<W>, Duration);
+
+/// The lane a frame travels in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lane {
+    /// Control messages, sent ahead of any queued bulk frames.
+    Urgent,
+    /// Everything else, sent in order once no urgent frame is queued.
+    Bulk,
+}
+
+impl Lane {
+    fn tag(self) -> u8 {
+        match self {
+            Lane::Urgent => 0,
+            Lane::Bulk => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> io::Result<Self> {
+        match tag {
+            0 => Ok(Lane::Urgent),
+            1 => Ok(Lane::Bulk),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown lane {}", tag),
+            )),
+        }
+    }
+}
+
+/// Splits a duplex file, such as a socket, into the two ends of a
+/// two-lane channel.  Frames longer than `max_len` bytes are rejected by
+/// the reader.
+///
+/// The peer should do the same with its end.
+pub fn lanes<F>(
+    file: File<F>,
+    max_len: usize,
+) -> (LaneReader<ReadHalf<F>>, LaneWriter<WriteHalf<F>>)
+where
+    F: AsRawFd,
+    for<'a> &'a F: io::Read + io::Write,
+{
+    let (read, write) = file.into_split();
+    (LaneReader::new(read, max_len), LaneWriter::new(write))
+}
+
+/// The sending end of a two-lane channel.
+///
+/// Frames are queued in the writer and written out by
+/// [`flush`](#method.flush) or [`send`](#method.send).  Whenever the next
+/// frame is chosen, queued urgent frames go first, so an urgent frame only
+/// ever waits for the one frame already being written, not for the bulk
+/// data queued before it.
+///
+/// Frames still queued when the writer is dropped are lost, unless
+/// [`flush_on_drop`](#method.flush_on_drop) is set.
+#[derive(Debug)]
+pub struct LaneWriter<W> {
+    /// Only `None` once taken by `into_inner` or on drop.
+    writer: Option<W>,
+    urgent: VecDeque<Vec<u8>>,
+    bulk: VecDeque<Vec<u8>>,
+    /// The frame being written and how much of it has been written.
+    current: Vec<u8>,
+    pos: usize,
+    flush_on_drop: Option<(Duration, SpawnFlush<W>)>,
+    middleware: Option<Middleware>,
+}
+
+impl<W: AsyncWrite + Unpin> LaneWriter<W> {
+    /// Sends frames over `writer`.
+    pub fn new(writer: W) -> Self {
+        LaneWriter {
+            writer: Some(writer),
+            urgent: VecDeque::new(),
+            bulk: VecDeque::new(),
+            current: Vec::new(),
+            pos: 0,
+            flush_on_drop: None,
+            middleware: None,
+        }
+    }
+
+    /// Passes every frame through `middleware` when it is queued.
+    pub fn middleware<M: FrameMiddleware>(mut self, middleware: M) -> Self {
+        self.middleware = Some(Middleware::new(middleware));
+        self
+    }
+
+    /// Queues a frame without writing anything.
+    ///
+    /// Fails with `InvalidInput` if the frame is longer than `u32::MAX`
+    /// bytes.
+    pub fn queue(&mut self, lane: Lane, frame: &[u8]) -> io::Result<()> {
+        let mut encoded = Vec::new();
+        let frame = if self.middleware.is_some() {
+            encoded.extend_from_slice(frame);
+            middleware::encode(&mut self.middleware, &mut encoded)?;
+            &encoded[..]
+        } else {
+            frame
+        };
+        let len = u32::try_from(frame.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+        let mut data = Vec::with_capacity(HEADER_LEN + frame.len());
+        data.push(lane.tag());
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(frame);
+        match lane {
+            Lane::Urgent => self.urgent.push_back(data),
+            Lane::Bulk => self.bulk.push_back(data),
+        }
+        Ok(())
+    }
+
+    /// The number of frames in `lane` that have not been written yet, not
+    /// counting one partially written.
+    pub fn queued(&self, lane: Lane) -> usize {
+        match lane {
+            Lane::Urgent => self.urgent.len(),
+            Lane::Bulk => self.bulk.len(),
+        }
+    }
+
+    /// Queues a frame and writes out everything queued.
+    pub async fn send(&mut self, lane: Lane, frame: &[u8]) -> io::Result<()> {
+        self.queue(lane, frame)?;
+        self.flush().await
+    }
+
+    /// Writes out every queued frame and flushes the writer.
+    ///
+    /// If this is cancelled, the remaining frames stay queued, and frames
+    /// queued in the meantime are sent in lane order by the next call.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        future::poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Polls to write out every queued frame and flush the writer.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let writer = self.writer.as_mut().unwrap();
+        loop {
+            if self.pos == self.current.len() {
+                let next = match self.urgent.pop_front() {
+                    Some(frame) => frame,
+                    None => match self.bulk.pop_front() {
+                        Some(frame) => frame,
+                        None => break,
+                    },
+                };
+                self.current = next;
+                self.pos = 0;
+            }
+            let n = ready!(Pin::new(&mut *writer).poll_write(cx, &self.current[self.pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pos += n;
+        }
+        Pin::new(writer).poll_flush(cx)
+    }
+
+    /// Returns the underlying writer.  Any frames still queued are lost,
+    /// even with [`flush_on_drop`](#method.flush_on_drop).
+    pub fn into_inner(mut self) -> W {
+        self.writer.take().unwrap()
+    }
+}
+
+impl<W: AsyncWrite + Send + Unpin + 'static> LaneWriter<W> {
+    /// If frames are still queued when the writer is dropped, such as when
+    /// the task sending them is cancelled, writes them out in a background
+    /// task rather than discard them, giving up after `deadline`.
+    ///
+    /// This is best effort: any error is ignored, and the frames are lost
+    /// anyway if the writer is dropped outside of a Tokio runtime.  The
+    /// runtime needs the time driver enabled.
+    pub fn flush_on_drop(mut self, deadline: Duration) -> Self {
+        self.flush_on_drop = Some((deadline, spawn_flush::<W>));
+        self
+    }
+}
+
+fn spawn_flush<W>(mut writer: LaneWriter<W>, deadline: Duration)
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        handle.spawn(async move {
+            let _ = tokio::time::timeout(deadline, writer.flush()).await;
+        });
+    }
+}
+
+impl<W> LaneWriter<W> {
+    fn has_pending(&self) -> bool {
+        self.pos < self.current.len() || !self.urgent.is_empty() || !self.bulk.is_empty()
+    }
+}
+
+impl<W> Drop for LaneWriter<W> {
+    fn drop(&mut self) {
+        let (deadline, spawn) = match self.flush_on_drop.take() {
+            Some(flush_on_drop) => flush_on_drop,
+            None => return,
+        };
+        if self.writer.is_none() || !self.has_pending() {
+            return;
+        }
+        spawn(
+            LaneWriter {
+                writer: self.writer.take(),
+                urgent: mem::take(&mut self.urgent),
+                bulk: mem::take(&mut self.bulk),
+                current: mem::take(&mut self.current),
+                pos: self.pos,
+                flush_on_drop: None,
+                middleware: None,
+            },
+            deadline,
+        );
+    }
+}
+
+/// The receiving end of a two-lane channel.
+#[derive(Debug)]
+pub struct LaneReader<R> {
+    reader: R,
+    max_len: usize,
+    header: [u8; HEADER_LEN],
+    /// How much of the header has been read.
+    header_len: usize,
+    /// The lane and payload of the frame being read, once its header is.
+    frame: Option<Frame>,
+    /// How much of the payload has been read.
+    payload_len: usize,
+    middleware: Option<Middleware>,
+}
+
+impl<R: AsyncRead + Unpin> LaneReader<R> {
+    /// Receives frames of at most `max_len` bytes from `reader`.
+    pub fn new(reader: R, max_len: usize) -> Self {
+        LaneReader {
+            reader,
+            max_len,
+            header: [0; HEADER_LEN],
+            header_len: 0,
+            frame: None,
+            payload_len: 0,
+            middleware: None,
+        }
+    }
+
+    /// Passes every frame through `middleware` when it is received.  The
+    /// maximum length applies to the frame before that.
+    pub fn middleware<M: FrameMiddleware>(mut self, middleware: M) -> Self {
+        self.middleware = Some(Middleware::new(middleware));
+        self
+    }
+
+    /// Receives the next frame, or `None` at EOF.
+    ///
+    /// A frame longer than the maximum length fails with a
+    /// [`RecordTooLong`](struct.RecordTooLong.html) error of kind
+    /// `InvalidData`, after which the channel is unusable.  This is
+    /// cancellation safe.
+    pub async fn recv(&mut self) -> io::Result<Option<Frame>> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Polls to receive the next frame, or `None` at EOF.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Frame>>> {
+        while self.frame.is_none() {
+            let mut buf = ReadBuf::new(&mut self.header[self.header_len..]);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                if self.header_len == 0 {
+                    return Poll::Ready(Ok(None));
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.header_len += n;
+            if self.header_len == HEADER_LEN {
+                let lane = Lane::from_tag(self.header[0])?;
+                let mut len = [0; 4];
+                len.copy_from_slice(&self.header[1..]);
+                let len = u32::from_le_bytes(len) as usize;
+                if len > self.max_len {
+                    return Poll::Ready(Err(RecordTooLong {
+                        max_len: self.max_len,
+                    }
+                    .into()));
+                }
+                self.frame = Some((lane, vec![0; len]));
+                self.payload_len = 0;
+            }
+        }
+        let (_, ref mut payload) = *self.frame.as_mut().unwrap();
+        while self.payload_len < payload.len() {
+            let mut buf = ReadBuf::new(&mut payload[self.payload_len..]);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.payload_len += n;
+        }
+        self.header_len = 0;
+        let (lane, mut payload) = self.frame.take().unwrap();
+        middleware::decode(&mut self.middleware, &mut payload)?;
+        Poll::Ready(Ok(Some((lane, payload))))
+    }
+
+    /// Returns the underlying reader.  Any partially received frame is
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_lanes() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let (_, mut writer) = lanes(File::new_nb(a)?, 64);
+        let (mut reader, _) = lanes(File::new_nb(b)?, 64);
+        writer.queue(Lane::Bulk, b"bulk 1")?;
+        writer.queue(Lane::Bulk, b"bulk 2")?;
+        writer.queue(Lane::Urgent, b"urgent")?;
+        assert_eq!(writer.queued(Lane::Bulk), 2);
+        writer.send(Lane::Bulk, b"").await?;
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(reader.recv().await?.unwrap());
+        }
+        assert_eq!(
+            received,
+            vec![
+                (Lane::Urgent, b"urgent".to_vec()),
+                (Lane::Bulk, b"bulk 1".to_vec()),
+                (Lane::Bulk, b"bulk 2".to_vec()),
+                (Lane::Bulk, Vec::new()),
+            ]
+        );
+        writer.send(Lane::Bulk, &[0; 65]).await?;
+        let err = reader.recv().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flush_on_drop() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let (_, writer) = lanes(File::new_nb(a)?, 64);
+        let (mut reader, _) = lanes(File::new_nb(b)?, 64);
+        let mut writer = writer.flush_on_drop(Duration::from_secs(10));
+        writer.queue(Lane::Bulk, b"last words")?;
+        drop(writer);
+        assert_eq!(
+            reader.recv().await?,
+            Some((Lane::Bulk, b"last words".to_vec()))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! File leases (`F_SETLEASE`).
+
+use crate::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+use std::{fmt, io};
+
+/// How often to check whether a lease is being broken.
+const BREAK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A kind of lease on a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lease {
+    /// Notifies the holder when another process opens the file for writing
+    /// or truncates it.  The file must be open read-only.
+    Read,
+    /// Notifies the holder when another process opens the file at all.  The
+    /// file must not be open anywhere else.
+    Write,
+}
+
+fn fcntl(fd: RawFd, cmd: libc::c_int, arg: libc::c_int) -> io::Result<libc::c_int> {
+    let r = unsafe { libc::fcntl(fd, cmd, arg) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(r)
+}
+
+/// A lease on a [`File`](struct.File.html), released when dropped.
+///
+/// Created by [`File::take_lease`](struct.File.html#method.take_lease).
+#[must_use = "the lease is released as soon as it is dropped"]
+pub struct FileLease<'a, F: AsRawFd> {
+    file: &'a File<F>,
+    lease: Lease,
+}
+
+impl<'a, F: AsRawFd> FileLease<'a, F> {
+    /// The lease held.
+    pub fn kind(&self) -> Lease {
+        self.lease
+    }
+
+    /// Waits until another process tries to open the file in a way that
+    /// conflicts with the lease, then returns the lease that it has to be
+    /// downgraded to: `None` to release it, or `Some(Lease::Read)`.
+    ///
+    /// The lease is checked every 50 ms, which is short of the seconds that
+    /// the kernel normally allows for a break.
+    pub async fn broken(&self) -> io::Result<Option<Lease>> {
+        loop {
+            let lease = self.file.lease()?;
+            if lease != Some(self.lease) {
+                return Ok(lease);
+            }
+            tokio::time::sleep(BREAK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Downgrades a write lease to a read lease.
+    pub fn downgrade(&mut self) -> io::Result<()> {
+        fcntl(self.file.as_raw_fd(), libc::F_SETLEASE, libc::F_RDLCK)?;
+        self.lease = Lease::Read;
+        Ok(())
+    }
+
+    /// Releases the lease, reporting any error, which dropping the lease
+    /// ignores.
+    pub fn release(self) -> io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        std::mem::forget(self);
+        fcntl(fd, libc::F_SETLEASE, libc::F_UNLCK).map(drop)
+    }
+}
+
+impl<'a, F: AsRawFd> Drop for FileLease<'a, F> {
+    fn drop(&mut self) {
+        let _ = fcntl(self.file.as_raw_fd(), libc::F_SETLEASE, libc::F_UNLCK);
+    }
+}
+
+impl<'a, F: AsRawFd> fmt::Debug for FileLease<'a, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLease")
+            .field("fd", &self.file.as_raw_fd())
+            .field("lease", &self.lease)
+            .finish()
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Takes a lease on the file, which must be a regular file owned by the
+    /// process, unless it has `CAP_LEASE`.
+    ///
+    /// When another process opens the file in a way that conflicts with the
+    /// lease, its `open` is held up until the lease is downgraded or
+    /// released, or until `/proc/sys/fs/lease-break-time` passes, so that the
+    /// holder can write back or invalidate whatever it has cached.  Wait for
+    /// that with [`FileLease::broken`](struct.FileLease.html#method.broken).
+    ///
+    /// The kernel would normally announce the break with `SIGIO`, which
+    /// terminates the process unless handled, so this directs the signals of
+    /// the open file description to no one (`F_SETOWN` with 0).
+    pub fn take_lease(&self, lease: Lease) -> io::Result<FileLease<'_, F>> {
+        let fd = self.as_raw_fd();
+        let raw = match lease {
+            Lease::Read => libc::F_RDLCK,
+            Lease::Write => libc::F_WRLCK,
+        };
+        fcntl(fd, libc::F_SETLEASE, raw)?;
+        let lease = FileLease { file: self, lease };
+        fcntl(fd, libc::F_SETOWN, 0)?;
+        Ok(lease)
+    }
+
+    /// Returns the lease on the file, if any.  While a lease is being broken,
+    /// this is the lease it must be downgraded to instead.
+    pub fn lease(&self) -> io::Result<Option<Lease>> {
+        let lease = match fcntl(self.as_raw_fd(), libc::F_GETLEASE, 0)? {
+            libc::F_RDLCK => Some(Lease::Read),
+            libc::F_WRLCK => Some(Lease::Write),
+            _ => None,
+        };
+        Ok(lease)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use std::fs;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    #[tokio::test]
+    async fn test_lease() -> io::Result<()> {
+        let path = TempPath::new("lease");
+        fs::write(&path, b"cached")?;
+        let file = File::new_nb(fs::File::open(&path)?)?;
+        assert_eq!(file.lease()?, None);
+        let lease = file.take_lease(Lease::Read)?;
+        assert_eq!(file.lease()?, Some(Lease::Read));
+
+        // a nonblocking open fails right away, but still starts the break
+        let err = fs::OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(lease.broken().await?, None);
+        lease.release()?;
+        assert_eq!(file.lease()?, None);
+        fs::OpenOptions::new().write(true).open(&path)?;
+        Ok(())
+    }
+}
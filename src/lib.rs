@@ -8,19 +8,425 @@
 //! See [`File`](struct.File.html) for an example of how a file can be made
 //! suitable for asynchronous I/O.
 
-use std::cell::RefCell;
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 use std::{fs, io};
-use tokio::io::PollEvented;
+use tokio::io::unix::{AsyncFd, AsyncFdReadyGuard};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, Interest, ReadBuf};
+
+macro_rules! ready {
+    ($e:expr) => {
+        match $e {
+            Poll::Ready(t) => t,
+            Poll::Pending => return Poll::Pending,
+        }
+    };
+}
+
+/// Logs a change to the state of a descriptor made by `$api`, if the `audit`
+/// feature is enabled.
+#[cfg(feature = "audit")]
+macro_rules! audit {
+    ($api:expr, $fd:expr, $($arg:tt)+) => {
+        log::debug!(
+            target: "tokio_file_unix::audit",
+            "fd {} ({}): {}",
+            $fd,
+            $api,
+            format_args!($($arg)+)
+        )
+    };
+}
+
+#[cfg(not(feature = "audit"))]
+macro_rules! audit {
+    ($api:expr, $fd:expr, $($arg:tt)+) => {{
+        let _ = (&$api, &$fd);
+    }};
+}
+
+/// Defines a set of flags for a system call, a newtype of the integer type
+/// of the flags argument with a constant for each flag, combined with `|`.
+macro_rules! flags {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident($ty:ty);
+        $(
+            $(#[$flag_attr:meta])*
+            const $flag:ident = $value:expr;
+        )*
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+        pub struct $name($ty);
+
+        impl $name {
+            $(
+                $(#[$flag_attr])*
+                pub const $flag: $name = $name($value);
+            )*
+
+            /// Whether all flags in `other` are set.
+            pub fn contains(self, other: $name) -> bool {
+                self.0 & other.0 == other.0
+            }
+        }
+
+        impl std::ops::BitOr for $name {
+            type Output = $name;
+
+            fn bitor(self, other: $name) -> $name {
+                $name(self.0 | other.0)
+            }
+        }
+
+        impl std::ops::BitOrAssign for $name {
+            fn bitor_assign(&mut self, other: $name) {
+                self.0 |= other.0;
+            }
+        }
+    };
+}
+
+mod adaptive;
+#[cfg(target_os = "linux")]
+mod allocate;
+mod audit;
+mod backpressure;
+mod block_on;
+mod blocking;
+mod borrowed;
+mod bridge;
+mod buffered;
+mod bufpool;
+mod bufread;
+mod builder;
+mod bytestream;
+mod child;
+#[cfg(feature = "codec")]
+pub mod codec;
+#[cfg(feature = "futures-io")]
+mod compat;
+mod copy;
+#[cfg(target_os = "linux")]
+mod copy_range;
+mod copy_to_file;
+#[cfg(feature = "registry")]
+pub mod debug;
+#[cfg(not(feature = "registry"))]
+mod debug {
+    use std::os::unix::io::RawFd;
+    use tokio::io::Interest;
+
+    /// Stands in for the entry in the debug registry when it is disabled.
+    #[derive(Debug)]
+    pub(crate) struct Registration;
+
+    impl Registration {
+        pub(crate) fn new(_fd: RawFd, _interest: Option<Interest>) -> Self {
+            Registration
+        }
+
+        pub(crate) fn read(&self, _n: usize) {}
+
+        pub(crate) fn wrote(&self, _n: usize) {}
+    }
+}
+mod deadline;
+#[cfg(all(feature = "direct_io", target_os = "linux"))]
+pub mod direct;
+#[cfg(not(all(feature = "direct_io", target_os = "linux")))]
+mod direct {
+    use std::io;
+
+    /// Stands in for the alignment of direct I/O when it is disabled.
+    #[derive(Debug)]
+    pub(crate) struct Alignment;
+
+    impl Alignment {
+        pub(crate) fn none() -> Self {
+            Alignment
+        }
+
+        pub(crate) fn check(&self, _ptr: *const u8, _len: usize) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+mod directory;
+mod drain;
+mod error;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod errqueue;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod evdev;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod eventfd;
+#[cfg(target_os = "linux")]
+pub mod fanotify;
+pub mod fifo;
+mod follow;
+mod fork;
+mod hexdump;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod inotify;
+#[cfg(feature = "tracing")]
+mod instrument;
+#[cfg(not(feature = "tracing"))]
+mod instrument {
+    use crate::{FdKind, RegularFileMode};
+    use std::io;
+    use std::os::unix::io::RawFd;
+    use tokio::io::Interest;
+
+    /// Stands in for the `tracing` span of a file when it is disabled.
+    #[derive(Debug)]
+    pub(crate) struct Span;
+
+    impl Span {
+        pub(crate) fn new(_fd: RawFd, _kind: FdKind) -> Self {
+            Span
+        }
+
+        pub(crate) fn registered(&self, _interest: Interest) {}
+
+        pub(crate) fn fallback(&self, _mode: RegularFileMode) {}
+
+        pub(crate) fn would_block(&self, _interest: Interest) {}
+
+        pub(crate) fn read<T>(&self, _result: &io::Result<T>, _n: usize) {}
+
+        pub(crate) fn wrote(&self, _result: &io::Result<usize>, _len: usize) {}
+    }
+}
+mod ioctl;
+mod kind;
+mod lanes;
+#[cfg(target_os = "linux")]
+mod lease;
+mod linewriter;
+mod lock;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod memfd;
+mod merge;
+mod message;
+mod metadata;
+mod middleware;
+mod observer;
+mod open;
+mod outcome;
+mod pager;
+pub mod passfd;
+mod peek;
+#[cfg(target_os = "linux")]
+mod pidfd;
+mod pipe;
+mod pollset;
+#[cfg(target_os = "linux")]
+mod positional;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod procwatch;
+pub mod pty;
+mod quota;
+pub mod reactor;
+mod readbuf;
+mod records;
+mod redirect;
+mod repoll;
+mod restore;
+mod resumable;
+mod retry;
+mod route;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sendfile;
+pub mod serial;
+mod shutdown;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod signalfd;
+mod sigpipe;
+mod smallbuf;
+mod socketpair;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod splice;
+mod split;
+mod stats;
+mod stdio;
+mod subscribe;
+mod sync;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod tee;
+#[cfg(test)]
+mod tempfile;
+pub mod terminal;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod throttle;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod timerfd;
+#[cfg(feature = "tools")]
+pub mod tools;
+pub mod trace;
+mod transaction;
+mod transfer;
+#[cfg(target_os = "linux")]
+pub mod tun;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+mod vmsplice;
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub mod vnode;
+mod zero_length;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod zerocopy;
+
+#[cfg(target_os = "linux")]
+pub use crate::allocate::{Advice, AllocateMode};
+pub use crate::backpressure::{BackpressureMonitor, BackpressureStats};
+pub use crate::block_on::{block_on_file, PolledFile};
+pub use crate::blocking::RegularFileMode;
+pub use crate::borrowed::BorrowedFile;
+pub use crate::bridge::{stdin_lines_channel, stdout_sink_channel};
+pub use crate::buffered::BufferedWriter;
+pub use crate::bufpool::{BufferPool, BufferPoolStats};
+pub use crate::bufread::BufFile;
+pub use crate::builder::FileBuilder;
+pub use crate::bytestream::{BytesSink, BytesStream};
+pub use crate::copy::{copy_bidirectional, copy_bidirectional_with, CopyOptions, OnEof};
+#[cfg(target_os = "linux")]
+pub use crate::copy_range::copy_file_range;
+pub use crate::copy_to_file::copy_to_file;
+pub use crate::deadline::TimeoutFile;
+#[cfg(target_os = "linux")]
+pub use crate::directory::{Dir, DirEntry, Handle, ResolveFlags};
+pub use crate::directory::{DirectoryPolicy, IsDirectory};
+pub use crate::error::{Error, IoErrorExt, IoErrorKind, NotPollable, StaleDescriptor};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::eventfd::EventFd;
+pub use crate::follow::Follow;
+pub use crate::fork::Deactivated;
+pub use crate::hexdump::HexDumpTap;
+pub use crate::kind::{supports_epoll, FdKind, FdStat};
+pub use crate::lanes::{lanes, Lane, LaneReader, LaneWriter};
+#[cfg(target_os = "linux")]
+pub use crate::lease::{FileLease, Lease};
+pub use crate::linewriter::LineWriter;
+pub use crate::lock::FileLock;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::memfd::{memfd_create, Seals};
+pub use crate::merge::{Fairness, Merger};
+pub use crate::message::Messages;
+pub use crate::middleware::FrameMiddleware;
+pub use crate::observer::IoObserver;
+pub use crate::open::open_with_deadline;
+pub use crate::outcome::ReadOutcome;
+pub use crate::pager::{page_output, Pager, PagerClosed};
+#[cfg(target_os = "linux")]
+pub use crate::pidfd::PidFd;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::pipe::PipeExt;
+pub use crate::pipe::{pipe, PipePool, PipeReader, PipeWriter, PooledPipe, ScratchPipe};
+pub use crate::pollset::PollSet;
+#[cfg(target_os = "linux")]
+pub use crate::positional::WriteFlags;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::procwatch::{ProcDiff, ProcWatcher};
+pub use crate::quota::{QuotaExceeded, QuotaPolicy, QuotaStats};
+pub use crate::records::{BoundedLines, Lines, Overlong, RecordTooLong};
+pub use crate::redirect::{redirect_stderr_to, redirect_stdout_to, Redirect};
+pub use crate::resumable::{Framing, ResumableFrames, ResumableLines};
+pub use crate::retry::RetryPolicy;
+pub use crate::route::Router;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::sendfile::sendfile;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::signalfd::{SignalFd, SignalInfo};
+pub use crate::sigpipe::ignore_sigpipe;
+pub use crate::smallbuf::SmallBuf;
+pub use crate::socketpair::socketpair;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::splice::splice;
+pub use crate::split::{ReadHalf, ReuniteError, WriteHalf};
+pub use crate::stats::FileStats;
+pub use crate::stdio::{Stderr, Stdin, Stdout};
+pub use crate::subscribe::Readiness;
+#[cfg(target_os = "linux")]
+pub use crate::sync::SyncRangeFlags;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::tee::{observe, tee, Observed};
+pub use crate::throttle::ThrottledFile;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::timerfd::{TimerClock, TimerFd};
+pub use crate::transaction::{Terminator, Transaction};
+pub use crate::transfer::{transfer, Checkpoint};
+pub use crate::zero_length::ZeroLengthMode;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::zerocopy::{ZeroCopyCompletion, ZeroCopySocket};
+
+/// The lowest descriptor number above the standard streams.
+const STDIO_END: RawFd = 3;
 
 unsafe fn dupe_file_from_fd(old_fd: RawFd) -> io::Result<fs::File> {
-    let fd = libc::fcntl(old_fd, libc::F_DUPFD_CLOEXEC, 0);
+    let fd = libc::fcntl(old_fd, libc::F_DUPFD_CLOEXEC, STDIO_END);
     if fd < 0 {
         return Err(io::Error::last_os_error());
     }
+    audit!("dup", fd, "duplicated from fd {}", old_fd);
     Ok(fs::File::from_raw_fd(fd))
 }
 
+/// Makes `fd` refer to what `target` does, keeping the close-on-exec flag
+/// of `fd`, which `dup2` would clear.
+fn replace_fd(fd: RawFd, target: RawFd) -> io::Result<()> {
+    let fd_flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if fd_flags < 0 || unsafe { libc::dup2(target, fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { libc::fcntl(fd, libc::F_SETFD, fd_flags) };
+    Ok(())
+}
+
+/// Renumbers the file descriptor of `file` so that it is at least `min_fd`,
+/// closing the original descriptor.  Files already at or above `min_fd` are
+/// returned unchanged.
+///
+/// If a process was started with some of its standard streams closed, newly
+/// opened files can land on descriptors 0 to 2, where a child process would
+/// mistake them for its standard streams.  Passing `min_fd = 3` rules this
+/// out.  The descriptors created by this crate are already kept above that
+/// range.
+///
+/// The new descriptor has close-on-exec set.
+///
+/// Implementation detail: uses `fcntl` with `F_DUPFD_CLOEXEC`.
+pub fn move_fd_above<F: FromRawFd + IntoRawFd>(file: F, min_fd: RawFd) -> io::Result<F> {
+    let fd = file.into_raw_fd();
+    if fd >= min_fd {
+        return Ok(unsafe { F::from_raw_fd(fd) });
+    }
+    unsafe {
+        let new_fd = libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, min_fd);
+        let e = io::Error::last_os_error();
+        libc::close(fd);
+        if new_fd < 0 {
+            audit!("move_fd_above", fd, "closed, failed to move: {}", e);
+            return Err(e);
+        }
+        audit!("move_fd_above", fd, "moved to fd {} and closed", new_fd);
+        Ok(F::from_raw_fd(new_fd))
+    }
+}
+
 /// Duplicate the standard input file.
 ///
 /// Unlike `std::io::Stdin`, this file is not buffered.
@@ -42,6 +448,74 @@ pub fn raw_stderr() -> io::Result<fs::File> {
     unsafe { dupe_file_from_fd(libc::STDERR_FILENO) }
 }
 
+/// Open the controlling terminal (`/dev/tty`), for reading and writing.
+///
+/// This reaches the user even when the standard streams are redirected, as
+/// is needed to prompt for a password.  If the process has no controlling
+/// terminal, the first of the standard streams that is a terminal is
+/// duplicated instead, and if there is none, the error from opening
+/// `/dev/tty` is returned, usually `ENXIO`.
+pub fn raw_tty() -> io::Result<fs::File> {
+    let e = match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open("/dev/tty")
+    {
+        Ok(file) => return move_fd_above(file, STDIO_END),
+        Err(e) => e,
+    };
+    for &fd in &[libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::isatty(fd) } == 1 {
+            return unsafe { dupe_file_from_fd(fd) };
+        }
+    }
+    Err(e)
+}
+
+/// Whether `interest` includes priority readiness.
+fn wants_priority(interest: Interest) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        interest.is_priority()
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = interest;
+        false
+    }
+}
+
+/// Identifies the open file behind a descriptor, so that a descriptor number
+/// that has been closed and reused can be told apart from the original.
+fn identity(fd: RawFd) -> io::Result<(libc::dev_t, libc::ino_t)> {
+    let stat = kind::fstat(fd)?;
+    Ok((stat.st_dev, stat.st_ino))
+}
+
+/// Returns a waker that does nothing, for polling outside of a task.
+fn noop_waker() -> Waker {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    unsafe fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    unsafe fn noop(_: *const ()) {}
+    unsafe { Waker::from_raw(clone(std::ptr::null())) }
+}
+
+/// Checks whether the given events are currently pending without blocking.
+pub(crate) fn poll_now(fd: RawFd, events: libc::c_short) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    if unsafe { libc::poll(&mut pollfd, 1, 0) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(pollfd.revents != 0)
+}
+
 /// Gets the nonblocking mode of the underlying file descriptor.
 ///
 /// Implementation detail: uses `fcntl` to retrieve `O_NONBLOCK`.
@@ -65,8 +539,16 @@ pub fn get_nonblocking<F: AsRawFd>(file: &F) -> io::Result<bool> {
 /// Implementation detail: uses `fcntl` to query the flags and set
 /// `O_NONBLOCK`.
 pub fn set_nonblocking<F: AsRawFd>(file: &mut F, nonblocking: bool) -> io::Result<()> {
+    set_nonblocking_for("set_nonblocking", file.as_raw_fd(), nonblocking)
+}
+
+/// Implements `set_nonblocking` on behalf of the public function `api`.
+pub(crate) fn set_nonblocking_for(
+    api: &'static str,
+    fd: RawFd,
+    nonblocking: bool,
+) -> io::Result<()> {
     unsafe {
-        let fd = file.as_raw_fd();
         // shamelessly copied from libstd/sys/unix/fd.rs
         let previous = libc::fcntl(fd, libc::F_GETFL);
         if previous < 0 {
@@ -77,13 +559,89 @@ pub fn set_nonblocking<F: AsRawFd>(file: &mut F, nonblocking: bool) -> io::Resul
         } else {
             previous & !libc::O_NONBLOCK
         };
-        if libc::fcntl(fd, libc::F_SETFL, new) < 0 {
+        if new != previous {
+            if libc::fcntl(fd, libc::F_SETFL, new) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            audit!(api, fd, "set O_NONBLOCK to {}", nonblocking);
+        }
+        Ok(())
+    }
+}
+
+/// Gets whether the underlying file descriptor is closed when the process
+/// executes another program.
+///
+/// Implementation detail: uses `fcntl` to retrieve `FD_CLOEXEC`.
+pub fn get_cloexec<F: AsRawFd>(file: &F) -> io::Result<bool> {
+    let flags = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFD) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flags & libc::FD_CLOEXEC != 0)
+}
+
+/// Sets whether the underlying file descriptor is closed when the process
+/// executes another program.  Clear it on descriptors meant to be inherited
+/// by a child process.  Descriptors created by this crate have it set.
+///
+/// Like `set_nonblocking`, this function is not atomic.
+///
+/// Implementation detail: uses `fcntl` to query the flags and set
+/// `FD_CLOEXEC`.
+pub fn set_cloexec<F: AsRawFd>(file: &mut F, cloexec: bool) -> io::Result<()> {
+    set_cloexec_for("set_cloexec", file.as_raw_fd(), cloexec)
+}
+
+/// Implements `set_cloexec` on behalf of the public function `api`.
+pub(crate) fn set_cloexec_for(api: &'static str, fd: RawFd, cloexec: bool) -> io::Result<()> {
+    unsafe {
+        let previous = libc::fcntl(fd, libc::F_GETFD);
+        if previous < 0 {
             return Err(io::Error::last_os_error());
         }
+        let new = if cloexec {
+            previous | libc::FD_CLOEXEC
+        } else {
+            previous & !libc::FD_CLOEXEC
+        };
+        if new != previous {
+            if libc::fcntl(fd, libc::F_SETFD, new) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            audit!(api, fd, "set FD_CLOEXEC to {}", cloexec);
+        }
         Ok(())
     }
 }
 
+/// Enables nonblocking mode on `fd` on behalf of the public function `api`,
+/// if it can be polled at all.  Errors carry an [`Error`](enum.Error.html)
+/// saying what went wrong.
+pub(crate) fn enable_nonblocking(api: &'static str, fd: RawFd) -> io::Result<()> {
+    let kind = FdKind::of(&fd).map_err(error::inspecting(fd))?;
+    if kind::supports_epoll_as(fd, kind).map_err(error::registering(fd, kind))? {
+        set_nonblocking_for(api, fd, true).map_err(error::setting_nonblocking(fd, kind))?;
+    }
+    Ok(())
+}
+
+/// Fails with `InvalidInput` if `fd` can be polled but is in blocking mode,
+/// on behalf of the public function `api` that was about to register it.
+pub(crate) fn check_nonblocking(api: &'static str, fd: RawFd) -> io::Result<()> {
+    if supports_epoll(&fd)? && !get_nonblocking(&fd)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{}: file descriptor {} is in blocking mode, which would stall \
+                 the reactor; use File::new_nb or enable nonblocking mode first",
+                api, fd
+            ),
+        ));
+    }
+    Ok(())
+}
+
 /// Wraps file-like objects for asynchronous I/O.
 ///
 /// Normally, you should use `File::new_nb` rather than `File::raw_new` unless
@@ -92,21 +650,28 @@ pub fn set_nonblocking<F: AsRawFd>(file: &mut F, nonblocking: bool) -> io::Resul
 /// I/O will lead to subtle and confusing bugs.
 ///
 /// Wrapping regular files has no effect because they do not support
-/// nonblocking mode.
+/// nonblocking mode.  Reads and writes on them block, by default on the
+/// calling thread.  See [`RegularFileMode`](enum.RegularFileMode.html) to
+/// move them onto the blocking thread pool instead.
 ///
 /// The most common instantiation of this type is `File<std::fs::File>`, which
-/// indirectly provides the following trait implementation:
+/// provides the following trait implementations:
 ///
 /// ```ignore
-/// impl AsyncRead + AsyncWrite for PollEvented<File<std::fs::File>>;
+/// impl AsyncRead + AsyncWrite for File<std::fs::File>;
+/// impl AsyncRead + AsyncWrite for &File<std::fs::File>;
 /// ```
 ///
+/// so it can be passed directly to `tokio::io::copy`, `FramedRead`, and the
+/// like.  As with `std::fs::File`, the implementations for `&File` allow
+/// reading and writing concurrently through shared references, and
+/// `File<F>` is `Send` and `Sync` whenever `F` is, so it can be shared
+/// between tasks in an `Arc`.
+///
 /// ## Example: read standard input line by line
 ///
 /// ```
-/// use tokio::stream::StreamExt;
-/// use tokio_util::codec::FramedRead;
-/// use tokio_util::codec::LinesCodec;
+/// use futures::StreamExt;
 ///
 /// #[tokio::main]
 /// async fn main() -> std::io::Result<()> {
@@ -115,9 +680,9 @@ pub fn set_nonblocking<F: AsRawFd>(file: &mut F, nonblocking: bool) -> io::Resul
 ///     let file = tokio_file_unix::raw_stdin()?;
 ///     let file = tokio_file_unix::File::new_nb(file)?;
 ///
-///     let mut framed = FramedRead::new(file, LinesCodec::new());
+///     let mut lines = file.lines(64 * 1024);
 ///
-///     while let Some(got) = framed.next().await {
+///     while let Some(got) = lines.next().await {
 ///         println!("Got this: {:?}", got);
 ///     }
 ///
@@ -145,18 +710,77 @@ pub fn set_nonblocking<F: AsRawFd>(file: &mut F, nonblocking: bool) -> io::Resul
 /// critical: it determines the ownership semantics of the file descriptor.
 /// For example, if you choose `F = std::fs::File`, the file descriptor will
 /// be closed when the `File` is dropped.
+///
+/// ## Errors
+///
+/// Reads and writes return plain `io::Error`s carrying the original `errno`.
+/// Use [`IoErrorExt::io_error_kind`](trait.IoErrorExt.html#tymethod.io_error_kind) to
+/// tell a departed peer apart from a descriptor that is no longer valid.
+/// A read or write that fails with `EBADF` is reported as a
+/// [`StaleDescriptor`](struct.StaleDescriptor.html) error only if the
+/// descriptor was closed or now refers to a different file; an `EBADF` from,
+/// say, writing to the reading end of a pipe is passed on as is.
+///
+/// Reads and writes that succeed never check the identity of the file, so a
+/// descriptor that was closed by someone else and reused for another file
+/// goes unnoticed until one fails.  Call
+/// [`validate`](#method.validate) to catch that.
+///
+/// Errors from wrapping a file, such as from `new_nb`, instead carry an
+/// [`Error`](enum.Error.html) saying which step failed, so their
+/// `raw_os_error` is `None`.  Use
+/// [`IoErrorExt::errno`](trait.IoErrorExt.html#tymethod.errno) to get their
+/// errno.
 #[derive(Debug)]
 pub struct File<F> {
+    // must be dropped before `file` so that the descriptor is deregistered
+    // before it gets closed
+    evented: Option<AsyncFd<RawFd>>,
+    blocking: Option<blocking::Blocking>,
+    observer: Option<observer::Observer>,
+    quota: Option<quota::Quota>,
+    restore: Option<restore::Restore>,
     file: F,
-    evented: RefCell<Option<mio::Registration>>,
+    identity: (libc::dev_t, libc::ino_t),
+    /// Whether the writing side was shut down by `poll_shutdown`.
+    write_shut: AtomicBool,
+    /// Whether flushing also runs `fdatasync` on files that are not
+    /// pollable.
+    pub(crate) sync_on_flush: bool,
+    /// Whether reads and writes interrupted by a signal are retried rather
+    /// than failing with `Interrupted`.
+    pub(crate) retry_interrupted: bool,
+    /// Whether writes go through `send` with `MSG_NOSIGNAL`.
+    pub(crate) nosignal: bool,
+    /// Whether a short read or write is taken to mean that the file has
+    /// been drained or filled, clearing its readiness.
+    pub(crate) edge_triggered: bool,
+    pub(crate) zero_length: ZeroLengthMode,
+    /// The interest the file is registered with, if it is.
+    interest: Option<Interest>,
+    direct: direct::Alignment,
+    subscription: Mutex<Option<subscribe::Subscription>>,
+    registration: debug::Registration,
+    span: instrument::Span,
+    /// Buffers the pipe may still refer to after `write_gift`.
+    gifts: vmsplice::Gifts,
+    /// Data peeked at, which reads hand out first.
+    pushback: peek::Pushback,
+    /// The timed re-polling for files whose readiness is unreliable.
+    pub(crate) repoll: Option<repoll::Repoll>,
+    pub(crate) stats: Option<stats::Counters>,
+    // must be dropped after `file`
+    close: audit::CloseLog,
 }
 
 impl<F: AsRawFd> File<F> {
     /// Wraps a file-like object into a pollable object that supports
     /// `tokio::io::AsyncRead` and `tokio::io::AsyncWrite`, and also *enables
     /// nonblocking mode* on the underlying file descriptor.
-    pub fn new_nb(mut file: F) -> io::Result<PollEvented<Self>> {
-        set_nonblocking(&mut file, true)?;
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new_nb(file: F) -> io::Result<Self> {
+        enable_nonblocking("File::new_nb", file.as_raw_fd())?;
         File::raw_new(file)
     }
 
@@ -164,12 +788,806 @@ impl<F: AsRawFd> File<F> {
     /// underlying file descriptor.  This constructor should only be used if
     /// you are certain that the underlying file descriptor is already in
     /// nonblocking mode.
-    pub fn raw_new(file: F) -> io::Result<PollEvented<Self>> {
-        PollEvented::new(File {
-            file: file,
-            evented: Default::default(),
+    ///
+    /// In debug builds, this fails with `InvalidInput` if the file can be
+    /// polled but is in blocking mode.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn raw_new(file: F) -> io::Result<Self> {
+        File::raw_new_with(file, RegularFileMode::Inline)
+    }
+
+    /// Like `File::new_nb`, but with the given treatment of regular files.
+    pub fn new_nb_with(file: F, mode: RegularFileMode) -> io::Result<Self> {
+        enable_nonblocking("File::new_nb_with", file.as_raw_fd())?;
+        File::raw_new_with(file, mode)
+    }
+
+    /// Like `File::raw_new`, but with the given treatment of regular files.
+    pub fn raw_new_with(file: F, mode: RegularFileMode) -> io::Result<Self> {
+        if cfg!(debug_assertions) {
+            check_nonblocking("File::raw_new", file.as_raw_fd())?;
+        }
+        File::register(
+            file,
+            mode,
+            DirectoryPolicy::Allow,
+            true,
+            Interest::READABLE | Interest::WRITABLE,
+        )
+    }
+
+    /// Registers the file with the reactor for `interest`, falling back to
+    /// `mode` if it is not pollable, or applying `directories` if it is a
+    /// directory.  Without `fallback`, files that are not pollable are
+    /// refused instead.
+    pub(crate) fn register(
+        file: F,
+        mode: RegularFileMode,
+        directories: DirectoryPolicy,
+        fallback: bool,
+        interest: Interest,
+    ) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        let identity = identity(fd).map_err(error::inspecting(fd))?;
+        let mut blocking = None;
+        // regular files and directories are not supported by epoll, unless
+        // the file may be one of the sysfs or procfs files that signal
+        // changes with priority readiness, so the rest are not even probed
+        let kind = FdKind::of(&fd).map_err(error::inspecting(fd))?;
+        let span = instrument::Span::new(fd, kind);
+        let pollable = (kind.is_pollable() || wants_priority(interest))
+            && kind::supports_epoll_as(fd, kind).map_err(error::registering(fd, kind))?;
+        let evented = if pollable {
+            let evented =
+                AsyncFd::with_interest(fd, interest).map_err(error::registering(fd, kind))?;
+            audit!("File::raw_new", fd, "registered with the reactor");
+            span.registered(interest);
+            Some(evented)
+        } else {
+            // files that are not pollable are put back into blocking mode
+            // and treated as always ready
+            directory::check(fd, directories)?;
+            if !fallback {
+                return Err(error::not_pollable(fd));
+            }
+            audit!("File::raw_new", fd, "not pollable, treated as always ready");
+            span.fallback(mode);
+            set_nonblocking_for("File::raw_new", fd, false)
+                .map_err(error::setting_nonblocking(fd, kind))?;
+            blocking = blocking::Blocking::for_mode(&file, mode)?;
+            None
+        };
+        let interest = evented.as_ref().map(|_| interest);
+        Ok(File {
+            evented,
+            blocking,
+            observer: None,
+            quota: None,
+            restore: None,
+            file,
+            identity,
+            write_shut: AtomicBool::new(false),
+            sync_on_flush: false,
+            retry_interrupted: true,
+            nosignal: false,
+            edge_triggered: false,
+            zero_length: ZeroLengthMode::Probe,
+            interest,
+            direct: direct::Alignment::none(),
+            subscription: Mutex::new(None),
+            registration: debug::Registration::new(fd, interest),
+            span,
+            gifts: vmsplice::Gifts::default(),
+            pushback: peek::Pushback::default(),
+            repoll: None,
+            stats: None,
+            close: audit::CloseLog::new(fd),
         })
     }
+
+    /// Checks that the underlying file descriptor still refers to the same
+    /// open file as when the `File` was created.
+    ///
+    /// If foreign code closes the descriptor, later operations fail with a
+    /// confusing `EBADF`, or worse, operate on an unrelated file that has
+    /// since been assigned the same number.  In either case this returns a
+    /// [`StaleDescriptor`](struct.StaleDescriptor.html) error.
+    ///
+    /// Implementation detail: compares the device and inode reported by
+    /// `fstat`.  This check is skipped once the writing end of a pipe has
+    /// been shut down, since that replaces the descriptor.
+    pub fn validate(&self) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        if self.write_shut.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        match identity(fd) {
+            Ok(id) if id == self.identity => Ok(()),
+            Ok(_) => Err(error::stale_descriptor(fd)),
+            Err(ref e) if e.raw_os_error() == Some(libc::EBADF) => Err(error::stale_descriptor(fd)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Deregisters the file from the reactor and returns the underlying
+    /// file-like object.  Nonblocking mode is left as is.
+    pub fn into_inner(mut self) -> F {
+        if self.evented.take().is_some() {
+            audit!(
+                "File::into_inner",
+                self.as_raw_fd(),
+                "deregistered from the reactor"
+            );
+        }
+        self.blocking.take();
+        self.restore.take();
+        self.subscription.lock().unwrap().take();
+        self.close.disarm();
+        self.file
+    }
+
+    /// Like [`into_inner`](#method.into_inner), but also turns nonblocking
+    /// mode off again, so that the file can go back to synchronous code that
+    /// expects reads and writes to wait.
+    pub fn into_blocking(self) -> io::Result<F> {
+        let file = self.into_inner();
+        set_nonblocking_for("File::into_blocking", file.as_raw_fd(), false)?;
+        Ok(file)
+    }
+
+    /// Re-registers the file with the reactor for `interest` only, such as
+    /// `Interest::READABLE` for stdin.
+    ///
+    /// By default, files are registered for both reading and writing.  Some
+    /// files report themselves as writable even though they are only ever
+    /// read from, which can cause spurious wakeups.  After narrowing the
+    /// interest, waiting for any other readiness never completes.  Files
+    /// that are not pollable are unaffected.  See also
+    /// [`set_interest`](#method.set_interest).
+    pub fn with_interest(mut self, interest: Interest) -> io::Result<Self> {
+        self.set_interest(interest)?;
+        Ok(self)
+    }
+
+    /// Re-registers the file with the reactor for `interest` in place, such
+    /// as to stop being woken for writability while there is nothing to
+    /// write, and to ask for it again once there is.
+    ///
+    /// Waiting for readiness outside of `interest` never completes until the
+    /// interest is widened again.  Readiness that was already observed is
+    /// forgotten, and reported anew by the fresh registration if it still
+    /// holds.  If the new registration fails, the previous interest is
+    /// restored.  Files that are not pollable are unaffected.
+    ///
+    /// Implementation detail: Tokio cannot modify a registration, so the
+    /// descriptor is deregistered and registered again.
+    pub fn set_interest(&mut self, interest: Interest) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        let previous = match (self.evented.is_some(), self.interest) {
+            (true, Some(previous)) if previous != interest => previous,
+            _ => return Ok(()),
+        };
+        // a descriptor can only be registered once, so the old registration
+        // has to go first
+        self.evented = None;
+        match AsyncFd::with_interest(fd, interest) {
+            Ok(evented) => self.evented = Some(evented),
+            Err(e) => {
+                self.evented = Some(AsyncFd::with_interest(fd, previous)?);
+                return Err(e);
+            }
+        }
+        audit!("File::set_interest", fd, "re-registered for {:?}", interest);
+        self.span.registered(interest);
+        self.registration = debug::Registration::new(fd, Some(interest));
+        self.interest = Some(interest);
+        Ok(())
+    }
+
+    /// Deregisters the file from the reactor and returns the underlying
+    /// descriptor.  Nonblocking mode is left as is.
+    pub fn into_owned_fd(self) -> OwnedFd
+    where
+        F: Into<OwnedFd>,
+    {
+        self.into_inner().into()
+    }
+
+    /// Flushes any pending writes, deregisters the file from the reactor,
+    /// and closes the descriptor, returning the first error encountered.
+    ///
+    /// Dropping a `File` does all of this too, but silently discards errors,
+    /// including those of writes still buffered by
+    /// `RegularFileMode::ThreadPool` and those that some filesystems, such
+    /// as NFS, only report on `close`.  The descriptor is closed even if the
+    /// flush fails.
+    pub async fn close(mut self) -> io::Result<()>
+    where
+        F: io::Write + Into<OwnedFd> + Unpin,
+    {
+        let flushed = std::future::poll_fn(|cx| Pin::new(&mut self).poll_flush(cx)).await;
+        let fd = self.into_owned_fd().into_raw_fd();
+        // on EINTR the descriptor is closed all the same and must not be
+        // closed again
+        let closed = match unsafe { libc::close(fd) } {
+            0 => Ok(()),
+            _ => match io::Error::last_os_error() {
+                ref e if e.raw_os_error() == Some(libc::EINTR) => Ok(()),
+                e => Err(e),
+            },
+        };
+        audit!("File::close", fd, "deregistered and closed");
+        flushed.and(closed)
+    }
+
+    /// Whether the file could not be registered with the reactor and is
+    /// instead treated as always ready, as regular files are.  Reads and
+    /// writes then never wait, but they may block, either the calling
+    /// thread or a thread of the blocking pool depending on the
+    /// [`RegularFileMode`](enum.RegularFileMode.html).
+    ///
+    /// Use [`FileBuilder::allow_fallback`](struct.FileBuilder.html#method.allow_fallback)
+    /// to refuse such files instead.
+    pub fn is_fallback_active(&self) -> bool {
+        self.evented.is_none()
+    }
+
+    /// Whether operations are offloaded to the blocking thread pool.
+    pub(crate) fn is_offloaded(&self) -> bool {
+        self.blocking.is_some()
+    }
+
+    /// Whether data can be spliced to or from the descriptor without
+    /// skipping the thread pool, data peeked at, quota, observer, or
+    /// statistics that reads and writes go through.
+    pub(crate) fn is_spliceable(&self) -> bool {
+        self.blocking.is_none()
+            && self.check_pushback().is_ok()
+            && self.quota.is_none()
+            && self.observer.is_none()
+            && self.stats.is_none()
+    }
+
+    /// Waits for any of the readiness states in `interest`, returning a guard
+    /// that must be cleared if the subsequent operation would block.
+    ///
+    /// This, along with [`readable`](#method.readable) and
+    /// [`writable`](#method.writable), is meant for performing your own
+    /// system calls on the descriptor, such as `recvmsg` or `ioctl`, instead
+    /// of going through `AsyncRead` or `AsyncWrite`.  Perform the operation
+    /// in nonblocking fashion and call
+    /// [`ReadyGuard::clear_ready`](struct.ReadyGuard.html#method.clear_ready)
+    /// if it fails with `WouldBlock` before waiting again.
+    /// [`ioctl_with_ready`](#method.ioctl_with_ready) does this for
+    /// `ioctl`.
+    pub async fn ready(&self, interest: Interest) -> io::Result<ReadyGuard<'_>> {
+        match self.evented {
+            None => Ok(ReadyGuard(None)),
+            Some(ref fd) => {
+                let guard = fd.ready(interest).await?;
+                self.count_wakeup();
+                Ok(ReadyGuard(Some(guard)))
+            }
+        }
+    }
+
+    /// Waits for the file to become readable.  See
+    /// [`ready`](#method.ready).
+    pub async fn readable(&self) -> io::Result<ReadyGuard<'_>> {
+        self.ready(Interest::READABLE).await
+    }
+
+    /// Waits for the file to become writable.  See
+    /// [`ready`](#method.ready).
+    pub async fn writable(&self) -> io::Result<ReadyGuard<'_>> {
+        self.ready(Interest::WRITABLE).await
+    }
+
+    /// Waits for priority readiness (`EPOLLPRI`), which some files use to
+    /// signal a change instead of becoming readable, such as the `value`
+    /// of a GPIO in sysfs with an edge configured, or `/proc/self/mounts`.
+    /// See [`ready`](#method.ready).
+    ///
+    /// The file must have been registered with `Interest::PRIORITY`, see
+    /// [`FileBuilder::interest`](struct.FileBuilder.html#method.interest).
+    /// Such a file is registered with the reactor even though it appears
+    /// to be a regular file, if the kernel allows it.  After a change, seek
+    /// back to the start and read the file again to see its new contents.
+    ///
+    /// Only available on Linux and Android.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn priority(&self) -> io::Result<ReadyGuard<'_>> {
+        self.ready(Interest::PRIORITY).await
+    }
+
+    /// Waits for the other end of the file to hang up or for an error
+    /// condition (`EPOLLHUP`, `EPOLLRDHUP`, or `EPOLLERR`), such as the
+    /// writing end of a pipe being closed while this is its reading end, or
+    /// vice versa.
+    ///
+    /// Unlike [`ready`](#method.ready), this does not consume any readiness
+    /// that reads and writes depend on, so it can be awaited concurrently
+    /// with them, for example to stop producing output as soon as the
+    /// consumer has gone away.  Data may still be left to read after a
+    /// hangup.  For files that cannot be polled, this never completes.
+    ///
+    /// Implementation detail: registers a duplicate of the descriptor with
+    /// the reactor for the duration of the call.
+    pub async fn hung_up(&self) -> io::Result<()> {
+        if self.evented.is_none() {
+            return std::future::pending().await;
+        }
+        let dup = unsafe { dupe_file_from_fd(self.as_raw_fd())? };
+        let fd = AsyncFd::with_interest(dup, Interest::READABLE | Interest::ERROR)?;
+        loop {
+            let mut guard = fd.ready(Interest::READABLE | Interest::ERROR).await?;
+            let ready = guard.ready();
+            if ready.is_read_closed() || ready.is_write_closed() || ready.is_error() {
+                return Ok(());
+            }
+            guard.clear_ready();
+        }
+    }
+
+    /// Polls for the file to become readable, returning a guard that must
+    /// be cleared if the subsequent operation would block.
+    pub fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<ReadyGuard<'_>>> {
+        match self.evented {
+            None => Poll::Ready(Ok(ReadyGuard(None))),
+            Some(ref fd) => {
+                let guard = ready!(fd.poll_read_ready(cx))?;
+                self.count_wakeup();
+                Poll::Ready(Ok(ReadyGuard(Some(guard))))
+            }
+        }
+    }
+
+    /// Polls for the file to become writable, returning a guard that must
+    /// be cleared if the subsequent operation would block.
+    pub fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<ReadyGuard<'_>>> {
+        match self.evented {
+            None => Poll::Ready(Ok(ReadyGuard(None))),
+            Some(ref fd) => {
+                let guard = ready!(fd.poll_write_ready(cx))?;
+                self.count_wakeup();
+                Poll::Ready(Ok(ReadyGuard(Some(guard))))
+            }
+        }
+    }
+
+    /// Forgets that the file is readable, so that the next read waits for
+    /// the reactor to report new data instead of first trying the read.
+    ///
+    /// This is for loops that drain the file in batches with
+    /// [`try_read`](#method.try_read) and know from a short read that it is
+    /// empty, saving the read that would fail with `WouldBlock`.  Data that
+    /// arrives after the readiness was reported is not lost, since the
+    /// reactor reports it again.  Call it only from the task that reads, as
+    /// it may take the place of that task's waker.  See also
+    /// [`FileBuilder::edge_triggered`](struct.FileBuilder.html#method.edge_triggered).
+    pub fn clear_read_ready(&self) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        if let Poll::Ready(Ok(mut guard)) = self.poll_read_ready(&mut cx) {
+            guard.clear_ready();
+        }
+    }
+
+    /// Forgets that the file is writable, the counterpart of
+    /// [`clear_read_ready`](#method.clear_read_ready) for writers that know
+    /// from a short write that the file is full.
+    pub fn clear_write_ready(&self) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        if let Poll::Ready(Ok(mut guard)) = self.poll_write_ready(&mut cx) {
+            guard.clear_ready();
+        }
+    }
+
+    /// Polls to read into `buf`, like `AsyncRead::poll_read` but through a
+    /// shared reference and without pinning, in the manner of
+    /// `tokio::net::UdpSocket::poll_recv`.  This is meant for implementing
+    /// `Future` or `Stream` by hand.
+    ///
+    /// Reads and writes may be polled at the same time from different
+    /// tasks, but only the last task to poll for each direction is woken.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>>
+    where
+        for<'a> &'a F: io::Read,
+    {
+        Pin::new(&mut &*self).poll_read(cx, buf)
+    }
+
+    /// Polls to write from `buf`, returning the number of bytes written.
+    /// See [`poll_read`](#method.poll_read).
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>
+    where
+        for<'a> &'a F: io::Write,
+    {
+        Pin::new(&mut &*self).poll_write(cx, buf)
+    }
+
+    /// Attempts to read into `buf` right away, without waiting for
+    /// readiness or registering a waker.  Fails with `WouldBlock` if no data
+    /// is available.
+    ///
+    /// This is meant for loops that wait on [`readable`](#method.readable)
+    /// themselves, for example inside `select!`.
+    ///
+    /// Implementation detail: uses `read` directly on the descriptor.
+    pub fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.read_allowance()?);
+        let buf = &mut buf[..len];
+        if let Some(n) = self.pushback.take(buf) {
+            self.report_read(&Ok(n), &buf[..n]);
+            return Ok(n);
+        }
+        let fd = self.as_raw_fd();
+        let r = retry_eintr(self.retry_interrupted, || {
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n < 0 {
+                check_stale(fd, self.identity, Err(io::Error::last_os_error()))
+            } else {
+                Ok(n as usize)
+            }
+        });
+        self.report_read(&r, &buf[..*r.as_ref().unwrap_or(&0)]);
+        r
+    }
+
+    /// Attempts to write `buf` right away, without waiting for readiness or
+    /// registering a waker.  Fails with `WouldBlock` if the file cannot
+    /// accept any data.
+    ///
+    /// This is meant for loops that wait on [`writable`](#method.writable)
+    /// themselves, for example inside `select!`.
+    ///
+    /// Implementation detail: uses `write` directly on the descriptor.
+    pub fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.check_write_shut()?;
+        let fd = self.as_raw_fd();
+        let r = retry_eintr(self.retry_interrupted, || {
+            if self.nosignal {
+                return check_stale(fd, self.identity, sigpipe::send(fd, buf));
+            }
+            let n = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+            if n < 0 {
+                check_stale(fd, self.identity, Err(io::Error::last_os_error()))
+            } else {
+                Ok(n as usize)
+            }
+        });
+        self.report_write(&r, buf);
+        r
+    }
+
+    /// Creates a new handle to the same open file, with a close-on-exec
+    /// duplicate of the descriptor and its own registration with the
+    /// reactor, so that two tasks can each own one.
+    ///
+    /// The two handles share the file offset and the nonblocking flag.  A
+    /// regular file offloaded to the blocking thread pool stays offloaded,
+    /// but observers, quotas and the like are not carried over.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn try_clone(&self) -> io::Result<File<fs::File>> {
+        let file = unsafe { dupe_file_from_fd(self.as_raw_fd())? };
+        let mode = if self.is_offloaded() {
+            RegularFileMode::ThreadPool
+        } else {
+            RegularFileMode::Inline
+        };
+        File::new_nb_with(file, mode)
+    }
+
+    /// Passes the result of a completed read, and the data read, on to the
+    /// `tracing` span, the observer, the debug registry, and the read quota.
+    fn report_read<T>(&self, result: &io::Result<T>, data: &[u8]) {
+        self.span.read(result, data.len());
+        if let Some(ref stats) = self.stats {
+            stats.read(result, data.len());
+        }
+        if let Some(ref observer) = self.observer {
+            observer.read(self.as_raw_fd(), result, data);
+        }
+        if result.is_ok() {
+            self.registration.read(data.len());
+            self.consume_read_quota(data.len());
+        }
+    }
+
+    /// Fails if the writing side was shut down, rather than writing to the
+    /// descriptor that replaced the end of a pipe.
+    fn check_write_shut(&self) -> io::Result<()> {
+        if self.write_shut.load(Ordering::Relaxed) {
+            return Err(shutdown::write_after_shutdown());
+        }
+        Ok(())
+    }
+
+    fn shutdown_write(&self, side: shutdown::WriteSide) -> io::Result<()> {
+        shutdown::shutdown_write(self.as_raw_fd(), side)?;
+        if side != shutdown::WriteSide::Other {
+            self.write_shut.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Passes the result of a completed write on to the `tracing` span, the
+    /// observer, and the debug registry.
+    fn report_write(&self, result: &io::Result<usize>, buf: &[u8]) {
+        self.span.wrote(result, buf.len());
+        if let Some(ref stats) = self.stats {
+            stats.wrote(result);
+        }
+        if let Some(ref observer) = self.observer {
+            observer.write(self.as_raw_fd(), result, buf);
+        }
+        if let Ok(n) = *result {
+            self.registration.wrote(n);
+        }
+    }
+
+    /// Like `report_write`, but for a vectored write.  The buffers are only
+    /// joined if there is an observer to see them.
+    fn report_write_vectored(&self, result: &io::Result<usize>, bufs: &[io::IoSlice<'_>]) {
+        self.span
+            .wrote(result, bufs.iter().map(|buf| buf.len()).sum());
+        if let Some(ref stats) = self.stats {
+            stats.wrote(result);
+        }
+        if let Some(ref observer) = self.observer {
+            let joined: Vec<u8> = bufs
+                .iter()
+                .flat_map(|buf| buf.iter().copied())
+                .take(*result.as_ref().unwrap_or(&0))
+                .collect();
+            observer.write(self.as_raw_fd(), result, &joined);
+        }
+        if let Ok(n) = *result {
+            self.registration.wrote(n);
+        }
+    }
+}
+
+impl File<fs::File> {
+    /// Wraps an owned descriptor and *enables nonblocking mode* on it, like
+    /// `File::new_nb`, without the need for `FromRawFd`.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn from_owned_fd(fd: OwnedFd) -> io::Result<Self> {
+        File::new_nb(fs::File::from(fd))
+    }
+
+    /// Wraps a private copy of `file` with *nonblocking mode* enabled,
+    /// leaving the flags of `file` itself untouched.
+    ///
+    /// Nonblocking mode is a property of the open file description, which is
+    /// shared by all descriptors obtained through `dup`, so `File::new_nb` on
+    /// a duplicate would still affect the original.  Instead, pipes,
+    /// terminals, and other character devices are reopened through
+    /// `/proc/self/fd`, creating an independent open file description with
+    /// the same access mode and the same `O_APPEND`, `O_SYNC`, and `O_DSYNC`
+    /// flags.  Since these files have no offset, nothing else is lost.  The
+    /// caller's descriptor keeps its flags and its lifetime is not tied to
+    /// the returned `File`.  This is the safest way to wrap standard streams
+    /// owned by a host application.
+    ///
+    /// Regular files are never put into nonblocking mode, so they are
+    /// duplicated with `dup` instead: a reopened copy would start at offset
+    /// zero, overwriting a log that stdout was appended to, for instance.
+    /// Sockets and other files that cannot be reopened fail with
+    /// `InvalidInput` rather than silently sharing flags.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new_nb_duped<G: AsRawFd>(file: &G) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        match FdKind::of(&fd)? {
+            FdKind::RegularFile => return File::raw_new(unsafe { dupe_file_from_fd(fd)? }),
+            FdKind::Pipe | FdKind::Tty | FdKind::CharDevice => {}
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("cannot wrap a private copy of fd {} ({:?})", fd, kind),
+                ))
+            }
+        }
+        let path = std::ffi::CString::new(format!("/proc/self/fd/{}", fd))?;
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let kept = libc::O_ACCMODE | libc::O_APPEND | libc::O_SYNC | libc::O_DSYNC;
+            let new_fd = libc::open(
+                path.as_ptr(),
+                (flags & kept) | libc::O_NONBLOCK | libc::O_CLOEXEC | libc::O_NOCTTY,
+            );
+            if new_fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let file = move_fd_above(fs::File::from_raw_fd(new_fd), STDIO_END)?;
+            File::raw_new(file)
+        }
+    }
+}
+
+impl<F> File<F> {
+    /// Gets a reference to the underlying file-like object.
+    pub fn get_ref(&self) -> &F {
+        &self.file
+    }
+
+    /// Gets a mutable reference to the underlying file-like object.
+    ///
+    /// Reading from or writing to it directly bypasses readiness tracking,
+    /// so a `WouldBlock` error obtained this way will not be noticed by the
+    /// reactor.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.file
+    }
+}
+
+/// Readiness of a wrapped file, as returned by
+/// [`File::ready`](struct.File.html#method.ready) and friends.  Regular
+/// files are always ready.
+///
+/// Dropping the guard without clearing it leaves the file marked as ready,
+/// so the next wait returns immediately.
+#[derive(Debug)]
+pub struct ReadyGuard<'a>(Option<AsyncFdReadyGuard<'a, RawFd>>);
+
+impl ReadyGuard<'_> {
+    /// Indicates that the file is no longer ready, so that the next poll
+    /// waits for a fresh readiness event.
+    pub fn clear_ready(&mut self) {
+        if let Some(ref mut guard) = self.0 {
+            guard.clear_ready();
+        }
+    }
+
+    /// The readiness states that were observed.  Regular files are always
+    /// readable and writable.
+    ///
+    /// A hangup shows up as `is_read_closed` or `is_write_closed`, and an
+    /// error condition as `is_error` if `Interest::ERROR` was waited for;
+    /// see also [`File::hung_up`](struct.File.html#method.hung_up).
+    pub fn ready(&self) -> tokio::io::Ready {
+        match self.0 {
+            None => tokio::io::Ready::READABLE | tokio::io::Ready::WRITABLE,
+            Some(ref guard) => guard.ready(),
+        }
+    }
+}
+
+/// Runs a nonblocking operation once the file is ready for `interest`,
+/// retrying whenever it would block, and also if it was interrupted by a
+/// signal and `retry_interrupted` is set.
+#[allow(clippy::too_many_arguments)]
+fn poll_io<T>(
+    evented: &Option<AsyncFd<RawFd>>,
+    span: &instrument::Span,
+    repoll: &Option<repoll::Repoll>,
+    stats: &Option<stats::Counters>,
+    retry_interrupted: bool,
+    cx: &mut Context<'_>,
+    interest: Interest,
+    f: impl FnMut() -> io::Result<T>,
+) -> Poll<io::Result<T>> {
+    poll_io_edge(
+        evented,
+        span,
+        repoll,
+        stats,
+        retry_interrupted,
+        cx,
+        interest,
+        f,
+        |_| false,
+    )
+}
+
+/// Like `poll_io`, but also clears the readiness if `drained` says that the
+/// result of the operation shows that another attempt would block.
+#[allow(clippy::too_many_arguments)]
+fn poll_io_edge<T>(
+    evented: &Option<AsyncFd<RawFd>>,
+    span: &instrument::Span,
+    repoll: &Option<repoll::Repoll>,
+    stats: &Option<stats::Counters>,
+    retry_interrupted: bool,
+    cx: &mut Context<'_>,
+    interest: Interest,
+    mut f: impl FnMut() -> io::Result<T>,
+    drained: impl Fn(&T) -> bool,
+) -> Poll<io::Result<T>> {
+    let mut f = || retry_eintr(retry_interrupted, &mut f);
+    let fd = match *evented {
+        None => return Poll::Ready(f()),
+        Some(ref fd) => fd,
+    };
+    loop {
+        let ready = if interest.is_writable() {
+            fd.poll_write_ready(cx)
+        } else {
+            fd.poll_read_ready(cx)
+        };
+        let mut guard = match ready {
+            Poll::Ready(guard) => guard?,
+            Poll::Pending => match *repoll {
+                // try anyway, in case the readiness was never reported
+                Some(ref repoll) if repoll.poll_due(cx, interest) => match f() {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        span.would_block(interest);
+                        if let Some(ref stats) = *stats {
+                            stats.would_block();
+                        }
+                        continue;
+                    }
+                    r => {
+                        repoll.done(interest);
+                        return Poll::Ready(r);
+                    }
+                },
+                _ => return Poll::Pending,
+            },
+        };
+        if let Some(ref stats) = *stats {
+            stats.wakeup();
+        }
+        if let Ok(r) = guard.try_io(|_| f()) {
+            if let Some(ref repoll) = *repoll {
+                repoll.done(interest);
+            }
+            if let Ok(ref value) = r {
+                if drained(value) {
+                    guard.clear_ready();
+                }
+            }
+            return Poll::Ready(r);
+        }
+        span.would_block(interest);
+        if let Some(ref repoll) = *repoll {
+            repoll.would_block(interest);
+        }
+        if let Some(ref stats) = *stats {
+            stats.would_block();
+        }
+    }
+}
+
+/// Runs `f` again for as long as it fails with `EINTR`, if `retry` is set.
+fn retry_eintr<T>(retry: bool, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match f() {
+            Err(ref e) if retry && e.kind() == io::ErrorKind::Interrupted => {}
+            r => return r,
+        }
+    }
+}
+
+/// Turns an `EBADF` from `fd` into a `StaleDescriptor` error if `fd` was
+/// closed or no longer refers to the open file with the given `identity`.
+pub(crate) fn check_stale<T>(
+    fd: RawFd,
+    identity: (libc::dev_t, libc::ino_t),
+    result: io::Result<T>,
+) -> io::Result<T> {
+    match result {
+        Err(ref e) if e.raw_os_error() == Some(libc::EBADF) && is_stale(fd, identity) => {
+            Err(error::stale_descriptor(fd))
+        }
+        r => r,
+    }
+}
+
+/// Whether `fd` is closed or refers to an open file other than `expected`,
+/// as opposed to being used in a way its access mode does not allow.
+fn is_stale(fd: RawFd, expected: (libc::dev_t, libc::ino_t)) -> bool {
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        return io::Error::last_os_error().raw_os_error() == Some(libc::EBADF);
+    }
+    identity(fd).is_ok_and(|id| id != expected)
 }
 
 impl<F: AsRawFd> AsRawFd for File<F> {
@@ -178,78 +1596,473 @@ impl<F: AsRawFd> AsRawFd for File<F> {
     }
 }
 
-impl<F: AsRawFd> mio::Evented for File<F> {
-    fn register(
-        &self,
-        poll: &mio::Poll,
-        token: mio::Token,
-        interest: mio::Ready,
-        opts: mio::PollOpt,
-    ) -> io::Result<()> {
-        match mio::unix::EventedFd(&self.as_raw_fd()).register(poll, token, interest, opts) {
-            // this is a workaround for regular files, which are not supported
-            // by epoll; they would instead cause EPERM upon registration
-            Err(ref e) if e.raw_os_error() == Some(libc::EPERM) => {
-                set_nonblocking(&mut self.as_raw_fd(), false)?;
-                // workaround: PollEvented/IoToken always starts off in the
-                // "not ready" state so we have to use a real Evented object
-                // to set its readiness state
-                let (r, s) = mio::Registration::new2();
-                r.register(poll, token, interest, opts)?;
-                s.set_readiness(mio::Ready::readable() | mio::Ready::writable())?;
-                *self.evented.borrow_mut() = Some(r);
+impl<F: AsFd> AsFd for File<F> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncRead for File<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(this.read_empty());
+        }
+        let unfilled = unsafe { buf.unfilled_mut() };
+        this.direct
+            .check(unfilled.as_ptr() as *const u8, unfilled.len())?;
+        let filled = buf.filled().len();
+        let limit = ready!(this.poll_read_allowance(cx))?;
+        let r = quota::read_limited(buf, limit, |buf| match this.blocking {
+            _ if this.pushback.take_buf(buf).is_some() => Poll::Ready(Ok(())),
+            Some(ref blocking) => blocking.poll_read(cx, buf),
+            None => {
+                let file = &mut this.file;
+                let identity = this.identity;
+                let edge = this.edge_triggered;
+                let len = buf.remaining();
+                poll_io_edge(
+                    &this.evented,
+                    &this.span,
+                    &this.repoll,
+                    &this.stats,
+                    this.retry_interrupted,
+                    cx,
+                    Interest::READABLE,
+                    || {
+                        let fd = file.as_raw_fd();
+                        check_stale(fd, identity, file.read(buf.initialize_unfilled()))
+                    },
+                    |&n| edge && n > 0 && n < len,
+                )
+                .map_ok(|n| buf.advance(n))
+            }
+        });
+        if let Poll::Ready(ref r) = r {
+            this.report_read(r, &buf.filled()[filled..]);
+        }
+        r
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> AsyncWrite for File<F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.check_write_shut()?;
+        if buf.is_empty() {
+            return Poll::Ready(this.write_empty());
+        }
+        this.direct.check(buf.as_ptr(), buf.len())?;
+        let r = match this.blocking {
+            Some(ref blocking) => blocking.poll_write(cx, buf),
+            None => {
+                let file = &mut this.file;
+                let identity = this.identity;
+                let nosignal = this.nosignal;
+                let edge = this.edge_triggered;
+                poll_io_edge(
+                    &this.evented,
+                    &this.span,
+                    &this.repoll,
+                    &this.stats,
+                    this.retry_interrupted,
+                    cx,
+                    Interest::WRITABLE,
+                    || {
+                        let fd = file.as_raw_fd();
+                        let r = if nosignal {
+                            sigpipe::send(fd, buf)
+                        } else {
+                            file.write(buf)
+                        };
+                        check_stale(fd, identity, r)
+                    },
+                    |&n| edge && n < buf.len(),
+                )
+            }
+        };
+        if let Poll::Ready(ref r) = r {
+            this.report_write(r, buf);
+        }
+        r
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.check_write_shut()?;
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(self.write_empty());
+        }
+        for buf in bufs {
+            self.direct.check(buf.as_ptr(), buf.len())?;
+        }
+        if self.blocking.is_some() {
+            // the thread pool writes one buffer at a time
+            let buf = bufs.iter().find(|buf| !buf.is_empty());
+            return self.poll_write(cx, buf.map_or(&[][..], |buf| &**buf));
+        }
+        let this = self.get_mut();
+        let file = &mut this.file;
+        let identity = this.identity;
+        let nosignal = this.nosignal;
+        let edge = this.edge_triggered;
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let r = poll_io_edge(
+            &this.evented,
+            &this.span,
+            &this.repoll,
+            &this.stats,
+            this.retry_interrupted,
+            cx,
+            Interest::WRITABLE,
+            || {
+                let fd = file.as_raw_fd();
+                let r = if nosignal {
+                    sigpipe::send_vectored(fd, bufs)
+                } else {
+                    file.write_vectored(bufs)
+                };
+                check_stale(fd, identity, r)
+            },
+            |&n| edge && n < len,
+        );
+        if let Poll::Ready(ref r) = r {
+            this.report_write_vectored(r, bufs);
+        }
+        r
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.blocking.is_none()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(ref blocking) = this.blocking {
+            return blocking.poll_flush(cx, this.sync_on_flush);
+        }
+        let sync = this.sync_on_flush && this.evented.is_none();
+        let file = &mut this.file;
+        let identity = this.identity;
+        poll_io(
+            &this.evented,
+            &this.span,
+            &this.repoll,
+            &this.stats,
+            this.retry_interrupted,
+            cx,
+            Interest::WRITABLE,
+            || {
+                let fd = file.as_raw_fd();
+                check_stale(fd, identity, file.flush())?;
+                if sync {
+                    check_stale(fd, identity, sync::sync_data_now(fd))?;
+                }
                 Ok(())
+            },
+        )
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.write_shut.load(Ordering::Relaxed) {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(self.as_mut().poll_flush(cx))?;
+        let this = self.get_mut();
+        let fd = this.as_raw_fd();
+        let side = shutdown::write_side(fd)?;
+        if side == shutdown::WriteSide::PipeWriter && this.evented.take().is_some() {
+            audit!("File::poll_shutdown", fd, "deregistered from the reactor");
+            this.interest = None;
+        }
+        Poll::Ready(this.shutdown_write(side))
+    }
+}
+
+impl<'a, F> AsyncRead for &'a File<F>
+where
+    F: AsRawFd,
+    &'a F: io::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this: &'a File<F> = *self;
+        if buf.remaining() == 0 {
+            return Poll::Ready(this.read_empty());
+        }
+        let unfilled = unsafe { buf.unfilled_mut() };
+        this.direct
+            .check(unfilled.as_ptr() as *const u8, unfilled.len())?;
+        let filled = buf.filled().len();
+        let limit = ready!(this.poll_read_allowance(cx))?;
+        let r = quota::read_limited(buf, limit, |buf| match this.blocking {
+            _ if this.pushback.take_buf(buf).is_some() => Poll::Ready(Ok(())),
+            Some(ref blocking) => blocking.poll_read(cx, buf),
+            None => {
+                let mut file = &this.file;
+                let edge = this.edge_triggered;
+                let len = buf.remaining();
+                poll_io_edge(
+                    &this.evented,
+                    &this.span,
+                    &this.repoll,
+                    &this.stats,
+                    this.retry_interrupted,
+                    cx,
+                    Interest::READABLE,
+                    || {
+                        check_stale(
+                            this.as_raw_fd(),
+                            this.identity,
+                            io::Read::read(&mut file, buf.initialize_unfilled()),
+                        )
+                    },
+                    |&n| edge && n > 0 && n < len,
+                )
+                .map_ok(|n| buf.advance(n))
             }
-            e => e,
+        });
+        if let Poll::Ready(ref r) = r {
+            this.report_read(r, &buf.filled()[filled..]);
         }
+        r
     }
+}
 
-    fn reregister(
-        &self,
-        poll: &mio::Poll,
-        token: mio::Token,
-        interest: mio::Ready,
-        opts: mio::PollOpt,
-    ) -> io::Result<()> {
-        match *self.evented.borrow() {
-            None => mio::unix::EventedFd(&self.as_raw_fd()).reregister(poll, token, interest, opts),
-            Some(ref r) => r.reregister(poll, token, interest, opts),
+impl<'a, F> AsyncWrite for &'a File<F>
+where
+    F: AsRawFd,
+    &'a F: io::Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this: &'a File<F> = *self;
+        this.check_write_shut()?;
+        if buf.is_empty() {
+            return Poll::Ready(this.write_empty());
+        }
+        this.direct.check(buf.as_ptr(), buf.len())?;
+        let r = match this.blocking {
+            Some(ref blocking) => blocking.poll_write(cx, buf),
+            None => {
+                let mut file = &this.file;
+                poll_io_edge(
+                    &this.evented,
+                    &this.span,
+                    &this.repoll,
+                    &this.stats,
+                    this.retry_interrupted,
+                    cx,
+                    Interest::WRITABLE,
+                    || {
+                        let fd = this.as_raw_fd();
+                        let r = if this.nosignal {
+                            sigpipe::send(fd, buf)
+                        } else {
+                            io::Write::write(&mut file, buf)
+                        };
+                        check_stale(fd, this.identity, r)
+                    },
+                    |&n| this.edge_triggered && n < buf.len(),
+                )
+            }
+        };
+        if let Poll::Ready(ref r) = r {
+            this.report_write(r, buf);
         }
+        r
     }
 
-    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
-        match *self.evented.borrow() {
-            None => mio::unix::EventedFd(&self.as_raw_fd()).deregister(poll),
-            Some(ref r) => mio::Evented::deregister(r, poll),
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this: &'a File<F> = *self;
+        this.check_write_shut()?;
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(this.write_empty());
         }
+        for buf in bufs {
+            this.direct.check(buf.as_ptr(), buf.len())?;
+        }
+        if this.blocking.is_some() {
+            // the thread pool writes one buffer at a time
+            let buf = bufs.iter().find(|buf| !buf.is_empty());
+            return self.poll_write(cx, buf.map_or(&[][..], |buf| &**buf));
+        }
+        let mut file = &this.file;
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let r = poll_io_edge(
+            &this.evented,
+            &this.span,
+            &this.repoll,
+            &this.stats,
+            this.retry_interrupted,
+            cx,
+            Interest::WRITABLE,
+            || {
+                let fd = this.as_raw_fd();
+                let r = if this.nosignal {
+                    sigpipe::send_vectored(fd, bufs)
+                } else {
+                    io::Write::write_vectored(&mut file, bufs)
+                };
+                check_stale(fd, this.identity, r)
+            },
+            |&n| this.edge_triggered && n < len,
+        );
+        if let Poll::Ready(ref r) = r {
+            this.report_write_vectored(r, bufs);
+        }
+        r
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.blocking.is_none()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this: &'a File<F> = *self;
+        if let Some(ref blocking) = this.blocking {
+            return blocking.poll_flush(cx, this.sync_on_flush);
+        }
+        let sync = this.sync_on_flush && this.evented.is_none();
+        let mut file = &this.file;
+        poll_io(
+            &this.evented,
+            &this.span,
+            &this.repoll,
+            &this.stats,
+            this.retry_interrupted,
+            cx,
+            Interest::WRITABLE,
+            || {
+                let fd = this.as_raw_fd();
+                check_stale(fd, this.identity, io::Write::flush(&mut file))?;
+                if sync {
+                    check_stale(fd, this.identity, sync::sync_data_now(fd))?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this: &'a File<F> = *self;
+        if this.write_shut.load(Ordering::Relaxed) {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(self.as_mut().poll_flush(cx))?;
+        // the end of a pipe stays registered with the reactor, which keeps
+        // its registration until the runtime shuts down as the descriptor
+        // no longer refers to what was registered
+        Poll::Ready(
+            shutdown::write_side(this.as_raw_fd()).and_then(|side| this.shutdown_write(side)),
+        )
     }
 }
 
-impl<F: io::Read> io::Read for File<F> {
+impl<F: AsRawFd + io::Read> io::Read for File<F> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.file.read(buf)
+        let len = buf.len().min(self.read_allowance()?);
+        let buf = &mut buf[..len];
+        if let Some(n) = self.pushback.take(buf) {
+            self.report_read(&Ok(n), &buf[..n]);
+            return Ok(n);
+        }
+        let r = check_stale(self.as_raw_fd(), self.identity, self.file.read(buf));
+        self.report_read(&r, &buf[..*r.as_ref().unwrap_or(&0)]);
+        r
     }
 }
 
-impl<F: io::Write> io::Write for File<F> {
+impl<F: AsRawFd + io::Write> io::Write for File<F> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.file.write(buf)
+        self.check_write_shut()?;
+        let fd = self.as_raw_fd();
+        let r = if self.nosignal {
+            sigpipe::send(fd, buf)
+        } else {
+            self.file.write(buf)
+        };
+        let r = check_stale(fd, self.identity, r);
+        self.report_write(&r, buf);
+        r
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.check_write_shut()?;
+        let fd = self.as_raw_fd();
+        let r = if self.nosignal {
+            sigpipe::send_vectored(fd, bufs)
+        } else {
+            self.file.write_vectored(bufs)
+        };
+        let r = check_stale(fd, self.identity, r);
+        self.report_write_vectored(&r, bufs);
+        r
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.file.flush()
+        let r = self.file.flush();
+        check_stale(self.as_raw_fd(), self.identity, r)
     }
 }
 
 impl<F: io::Seek> io::Seek for File<F> {
     fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
-        self.file.seek(pos)
+        let file = &mut self.file;
+        self.pushback.seek(pos, |pos| file.seek(pos))
+    }
+}
+
+/// Seeking regular files.  With `RegularFileMode::ThreadPool`, the seek
+/// runs on the blocking thread pool after any pending write, and data read
+/// ahead is discarded.  Otherwise, it is performed right away, like reads
+/// and writes, and data peeked at is discarded.
+impl<F: AsRawFd + io::Seek + Unpin> AsyncSeek for File<F> {
+    fn start_seek(self: Pin<&mut Self>, pos: io::SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        match this.blocking {
+            Some(ref blocking) => blocking.start_seek(pos),
+            None => {
+                let file = &mut this.file;
+                this.pushback.seek(pos, |pos| file.seek(pos)).map(drop)
+            }
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        match this.blocking {
+            Some(ref blocking) => blocking.poll_complete_seek(cx),
+            None => Poll::Ready(this.file.stream_position()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tempfile::{tempfile, TempPath};
     use std::os::unix::net::UnixStream;
 
     #[test]
@@ -264,4 +2077,376 @@ mod tests {
         assert!(!get_nonblocking(&fd)?);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_thread_pool_mode() -> io::Result<()> {
+        use std::io::{Seek, SeekFrom};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let raw = tempfile("thread-pool")?;
+        let mut file = File::new_nb_with(raw, RegularFileMode::ThreadPool)?;
+        assert!(file.is_offloaded());
+        file.write_all(b"offloaded").await?;
+        file.flush().await?;
+        file.get_mut().seek(SeekFrom::Start(0))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).await?;
+        assert_eq!(contents, "offloaded");
+        let mut raw = file.into_inner();
+        raw.seek(SeekFrom::Start(3))?;
+        contents.clear();
+        io::Read::read_to_string(&mut raw, &mut contents)?;
+        assert_eq!(contents, "loaded");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_thread_pool_shared() -> io::Result<()> {
+        use std::sync::Arc;
+        use tokio::io::AsyncWriteExt;
+        let raw = tempfile("shared")?;
+        let file = Arc::new(File::new_nb_with(raw, RegularFileMode::ThreadPool)?);
+        // every task waits on operations started by the others
+        let tasks: Vec<_> = (0..4u8)
+            .map(|i| {
+                let file = file.clone();
+                tokio::spawn(async move {
+                    let mut file = &*file;
+                    for _ in 0..50 {
+                        file.write_all(&[i; 4096]).await?;
+                        file.flush().await?;
+                    }
+                    Ok::<_, io::Error>(())
+                })
+            })
+            .collect();
+        for task in tasks {
+            tokio::time::timeout(std::time::Duration::from_secs(10), task)
+                .await
+                .expect("a wakeup was lost")??;
+        }
+        assert_eq!(file.get_ref().metadata()?.len(), 4 * 50 * 4096);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_seek() -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+        for &mode in &[RegularFileMode::Inline, RegularFileMode::ThreadPool] {
+            let raw = tempfile("seek")?;
+            let mut file = File::new_nb_with(raw, mode)?;
+            file.write_all(b"hello world").await?;
+            assert_eq!(file.seek(io::SeekFrom::Start(0)).await?, 0);
+            let mut buf = [0; 2];
+            file.read_exact(&mut buf).await?;
+            assert_eq!(&buf, b"he");
+            // relative to what was consumed, not what was read ahead
+            assert_eq!(file.seek(io::SeekFrom::Current(4)).await?, 6);
+            let mut rest = String::new();
+            file.read_to_string(&mut rest).await?;
+            assert_eq!(rest, "world");
+            assert_eq!(file.stream_position().await?, 11);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cloexec() -> io::Result<()> {
+        let mut file = fs::File::open("/dev/null")?;
+        assert!(get_cloexec(&file)?);
+        set_cloexec(&mut file, false)?;
+        assert!(!get_cloexec(&file)?);
+        set_cloexec(&mut file, true)?;
+        assert!(get_cloexec(&file)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_fd_above() -> io::Result<()> {
+        let file = fs::File::open("/dev/null")?;
+        let file = move_fd_above(file, 100)?;
+        assert!(file.as_raw_fd() >= 100);
+        let fd = file.as_raw_fd();
+        let file = move_fd_above(file, 3)?;
+        assert_eq!(file.as_raw_fd(), fd);
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_tty() {
+        match raw_tty() {
+            Ok(tty) => assert_eq!(unsafe { libc::isatty(tty.as_raw_fd()) }, 1),
+            // not run from a terminal
+            Err(e) => assert!(e.raw_os_error().is_some(), "{}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_read_write() -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (a, b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        let b = File::new_nb(b)?;
+        let (mut writer, mut reader) = (&a, &b);
+        let mut received = [0; 5];
+        let (written, read) =
+            tokio::join!(writer.write_all(b"hello"), reader.read_exact(&mut received));
+        written?;
+        read?;
+        assert_eq!(&received, b"hello");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_new_nb_duped() -> io::Result<()> {
+        let mut fds = [0; 2];
+        assert!(unsafe { libc::pipe(fds.as_mut_ptr()) } >= 0);
+        let (reader, _writer) =
+            unsafe { (fs::File::from_raw_fd(fds[0]), fs::File::from_raw_fd(fds[1])) };
+        let file = File::new_nb_duped(&reader)?;
+        assert!(get_nonblocking(file.get_ref())?);
+        assert!(!get_nonblocking(&reader)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_new_nb_duped_append() -> io::Result<()> {
+        use std::io::Write;
+        let path = TempPath::new("duped-append");
+        fs::write(&path, b"earlier\n")?;
+        let log = fs::OpenOptions::new().append(true).open(&path)?;
+        let mut file = File::new_nb_duped(&log)?;
+        file.write_all(b"later\n")?;
+        drop(file);
+        assert_eq!(fs::read(&path)?, b"earlier\nlater\n");
+        assert!(!get_nonblocking(&log)?);
+
+        let (sock, _peer) = UnixStream::pair()?;
+        let e = File::new_nb_duped(&sock).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_validate() -> io::Result<()> {
+        let (sock, _peer) = UnixStream::pair()?;
+        let fd = sock.as_raw_fd();
+        let file = File::new_nb(sock)?;
+        file.validate()?;
+        // replace the descriptor with an unrelated file behind its back
+        let other = fs::File::open("/dev/null")?;
+        assert!(unsafe { libc::dup2(other.as_raw_fd(), fd) } >= 0);
+        let e = file.validate().unwrap_err();
+        assert_eq!(e.io_error_kind(), IoErrorKind::StaleDescriptor);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_ebadf_not_stale() -> io::Result<()> {
+        let (reader, _writer) = crate::pipe()?;
+        let reader = reader.into_inner();
+        // the read end of a pipe is open, just not for writing
+        let e = reader.try_write(b"wrong way").unwrap_err();
+        assert_eq!(e.raw_os_error(), Some(libc::EBADF));
+        assert_ne!(e.io_error_kind(), IoErrorKind::StaleDescriptor);
+        let other = fs::File::open("/dev/null")?;
+        assert!(unsafe { libc::dup2(other.as_raw_fd(), reader.as_raw_fd()) } >= 0);
+        let e = reader.try_write(b"wrong file").unwrap_err();
+        assert_eq!(e.io_error_kind(), IoErrorKind::StaleDescriptor);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_try_read_write() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        let b = File::new_nb(b)?;
+        let mut buf = [0; 8];
+        let e = a.try_read(&mut buf).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(b.try_write(b"now")?, 3);
+        a.readable().await?;
+        assert_eq!(a.try_read(&mut buf)?, 3);
+        assert_eq!(&buf[..3], b"now");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_into_blocking() -> io::Result<()> {
+        let (sock, _peer) = UnixStream::pair()?;
+        let file = File::new_nb(sock)?;
+        assert!(get_nonblocking(file.get_ref())?);
+        let sock = file.into_blocking()?;
+        assert!(!get_nonblocking(&sock)?);
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn test_priority() -> io::Result<()> {
+        use std::time::Duration;
+        use tokio::io::AsyncReadExt;
+        let mounts = fs::File::open("/proc/self/mounts")?;
+        let mut file = FileBuilder::new()
+            .interest(Interest::READABLE | Interest::PRIORITY)
+            .build(mounts)?;
+        assert!(file.evented.is_some());
+        let mut buf = [0; 64];
+        assert!(file.read(&mut buf).await? > 0);
+        // nothing is mounted or unmounted in the meantime
+        let changed = tokio::time::timeout(Duration::from_millis(50), file.priority()).await;
+        assert!(changed.is_err());
+
+        // without priority interest, it is treated as a regular file
+        let file = File::new_nb(fs::File::open("/proc/self/mounts")?)?;
+        assert!(file.evented.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<File<fs::File>>();
+        assert_send_sync::<File<UnixStream>>();
+        assert_send_sync::<std::sync::Arc<File<fs::File>>>();
+    }
+
+    #[tokio::test]
+    async fn test_try_clone() -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (a, b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        let mut clone = a.try_clone()?;
+        assert_ne!(clone.as_raw_fd(), a.as_raw_fd());
+        let flags = unsafe { libc::fcntl(clone.as_raw_fd(), libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+        // both handles can wait at the same time
+        let mut buf = [0; 5];
+        let (read, written) = tokio::join!(clone.read_exact(&mut buf), async {
+            a.writable().await?;
+            b.write_all(b"clone").await
+        });
+        read?;
+        written?;
+        assert_eq!(&buf, b"clone");
+        drop(a);
+        clone.write_all(b"still open").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_poll_read_write() -> io::Result<()> {
+        let (reader, writer) = pipe()?;
+        let (reader, writer) = (reader.into_inner(), writer.into_inner());
+        let n = std::future::poll_fn(|cx| writer.poll_write(cx, b"by hand")).await?;
+        assert_eq!(n, 7);
+        let mut buf = [0; 16];
+        let mut buf = ReadBuf::new(&mut buf);
+        std::future::poll_fn(|cx| reader.poll_read(cx, &mut buf)).await?;
+        assert_eq!(buf.filled(), b"by hand");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_interest() -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?.with_interest(Interest::READABLE)?;
+        let mut b = File::new_nb(b)?.with_interest(Interest::WRITABLE)?;
+        b.write_all(b"one way").await?;
+        let mut buf = [0; 7];
+        a.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"one way");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_interest() -> io::Result<()> {
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        a.set_interest(Interest::READABLE)?;
+        let writable = tokio::time::timeout(Duration::from_millis(50), a.writable()).await;
+        assert!(writable.is_err());
+        b.write_all(b"still read").await?;
+        let mut buf = [0; 10];
+        a.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"still read");
+
+        a.set_interest(Interest::READABLE | Interest::WRITABLE)?;
+        a.writable().await?;
+        a.write_all(b"back").await?;
+        let mut buf = [0; 4];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"back");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_owned_fd() -> io::Result<()> {
+        let (reader, _writer) = pipe::raw_pipe(libc::O_CLOEXEC)?;
+        let file = File::from_owned_fd(OwnedFd::from(reader))?;
+        assert!(get_nonblocking(&file.as_fd())?);
+        let fd = file.into_owned_fd();
+        assert!(get_nonblocking(&fd)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_readable() -> io::Result<()> {
+        let (sock, peer) = UnixStream::pair()?;
+        let file = File::new_nb(sock)?;
+        let mut buf = [0; 8];
+        let task = tokio::spawn(async move { io::Write::write_all(&mut &peer, b"custom") });
+        let n = loop {
+            let mut guard = file.readable().await?;
+            let n =
+                unsafe { libc::recv(file.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+            if n >= 0 {
+                break n as usize;
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e);
+            }
+            guard.clear_ready();
+        };
+        task.await.unwrap()?;
+        assert_eq!(&buf[..n], b"custom");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_vectored() -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        assert!(a.is_write_vectored());
+        let bufs = [io::IoSlice::new(b"scatter"), io::IoSlice::new(b"/gather")];
+        assert_eq!(a.write_vectored(&bufs).await?, 14);
+        let mut buf = [0; 14];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"scatter/gather");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close() -> io::Result<()> {
+        use std::io::Read;
+        use tokio::io::AsyncWriteExt;
+        let path = TempPath::new("close");
+        let raw = fs::File::create(&path)?;
+        let mut file = File::new_nb_with(raw, RegularFileMode::ThreadPool)?;
+        file.write_all(b"flushed on close").await?;
+        file.close().await?;
+        assert_eq!(fs::read(&path)?, b"flushed on close");
+
+        let (a, mut b) = UnixStream::pair()?;
+        File::new_nb(a)?.close().await?;
+        assert_eq!(b.read(&mut [0; 1])?, 0);
+        Ok(())
+    }
 }
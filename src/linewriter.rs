@@ -0,0 +1,174 @@
+//! Line-buffered writing, as stdout is in C.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+impl<F> File<F> {
+    /// Wraps the file in a writer that buffers up to `capacity` bytes and
+    /// writes them out whenever a line is complete, like `std::io::LineWriter`
+    /// and the standard output of a C program connected to a terminal.
+    ///
+    /// This saves a system call per `write` when output is produced in small
+    /// pieces, without holding back complete lines.
+    pub fn into_line_writer(self, capacity: usize) -> LineWriter<F> {
+        LineWriter {
+            inner: self,
+            buf: Vec::with_capacity(capacity),
+            capacity,
+            written: 0,
+        }
+    }
+}
+
+/// A [`File`](struct.File.html) whose writes are buffered until a newline,
+/// created by [`File::into_line_writer`](struct.File.html#method.into_line_writer).
+///
+/// Buffered data is not written out when the writer is dropped, so call
+/// `flush` or `shutdown` first.  Reads go straight to the file.
+#[derive(Debug)]
+pub struct LineWriter<F> {
+    inner: File<F>,
+    buf: Vec<u8>,
+    capacity: usize,
+    /// The part of `buf` that has been written out already.
+    written: usize,
+}
+
+impl<F> LineWriter<F> {
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the file.
+    ///
+    /// Writing to it directly goes ahead of the data in the buffer.
+    pub fn get_mut(&mut self) -> &mut File<F> {
+        &mut self.inner
+    }
+
+    /// The data written but not passed on to the file yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.written..]
+    }
+
+    /// Returns the file.  Any data in the buffer is lost, so flush first.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> LineWriter<F> {
+    /// Writes out the whole buffer.
+    fn poll_flush_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.buf.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += n;
+        }
+        self.buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for LineWriter<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncRead for LineWriter<F> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> AsyncWrite for LineWriter<F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        src: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        match src.iter().rposition(|&b| b == b'\n') {
+            Some(i) => {
+                // earlier data goes first, then the complete lines directly
+                ready!(this.poll_flush_buf(cx))?;
+                let lines = &src[..=i];
+                let n = ready!(Pin::new(&mut this.inner).poll_write(cx, lines))?;
+                if n < lines.len() {
+                    return Poll::Ready(Ok(n));
+                }
+                let rest = &src[n..];
+                let m = rest.len().min(this.capacity);
+                this.buf.extend_from_slice(&rest[..m]);
+                Poll::Ready(Ok(n + m))
+            }
+            None => {
+                if this.buf.len() + src.len() > this.capacity {
+                    ready!(this.poll_flush_buf(cx))?;
+                }
+                if src.len() >= this.capacity {
+                    return Pin::new(&mut this.inner).poll_write(cx, src);
+                }
+                this.buf.extend_from_slice(src);
+                Poll::Ready(Ok(src.len()))
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_flush_buf(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_flush_buf(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_line_writer() -> io::Result<()> {
+        let (reader, writer) = crate::pipe()?;
+        let mut reader = reader.into_inner();
+        let mut writer = writer.into_inner().into_line_writer(16);
+        let mut data = [0; 64];
+
+        writer.write_all(b"partial").await?;
+        assert_eq!(writer.buffer(), b"partial");
+        let err = reader.try_read(&mut data).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        writer.write_all(b" line\nrest").await?;
+        let n = reader.read(&mut data).await?;
+        assert_eq!(&data[..n], b"partial line\n");
+        assert_eq!(writer.buffer(), b"rest");
+
+        // too long for the buffer
+        writer.write_all(b" of a longer line").await?;
+        assert_eq!(writer.buffer(), b"");
+        writer.flush().await?;
+        let n = reader.read(&mut data).await?;
+        assert_eq!(&data[..n], b"rest of a longer line");
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Advisory file locks.
+
+use crate::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+use std::{fmt, io};
+
+/// How long to wait before trying again to take a contended lock, at first
+/// and at most.
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+fn flock(fd: RawFd, operation: libc::c_int) -> io::Result<()> {
+    loop {
+        if unsafe { libc::flock(fd, operation) } == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+/// An advisory lock on a [`File`](struct.File.html), released when dropped.
+///
+/// Created by [`File::lock_exclusive`](struct.File.html#method.lock_exclusive)
+/// and the like.
+#[must_use = "the lock is released as soon as it is dropped"]
+pub struct FileLock<'a, F: AsRawFd> {
+    file: &'a File<F>,
+}
+
+impl<'a, F: AsRawFd> FileLock<'a, F> {
+    /// Releases the lock, reporting any error, which dropping the lock
+    /// ignores.
+    pub fn unlock(self) -> io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        std::mem::forget(self);
+        flock(fd, libc::LOCK_UN)
+    }
+}
+
+impl<'a, F: AsRawFd> Drop for FileLock<'a, F> {
+    fn drop(&mut self) {
+        let _ = flock(self.file.as_raw_fd(), libc::LOCK_UN);
+    }
+}
+
+impl<'a, F: AsRawFd> fmt::Debug for FileLock<'a, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileLock")
+            .field("fd", &self.file.as_raw_fd())
+            .finish()
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Waits until the file can be locked exclusively, then locks it.
+    ///
+    /// Uses `flock(2)`, so the lock belongs to the open file description:
+    /// it conflicts with locks taken through other `open` calls, by this or
+    /// other processes, but not with those taken through duplicates of this
+    /// descriptor.  The lock is advisory and only excludes others that lock
+    /// the file too.
+    ///
+    /// While the lock is held by someone else, this tries again after a
+    /// delay that grows up to 100 ms, so it does not take up a thread, and
+    /// dropping the future stops waiting right away.  (Blocking in `flock`
+    /// on the thread pool instead could take the lock after the future is
+    /// dropped, and releasing it then could release a lock taken since
+    /// through the same descriptor.)
+    pub async fn lock_exclusive(&self) -> io::Result<FileLock<'_, F>> {
+        self.lock(libc::LOCK_EX).await
+    }
+
+    /// Waits until the file can be locked in shared mode, then locks it.
+    /// Any number of shared locks can be held at once, but not alongside an
+    /// exclusive lock.
+    ///
+    /// See [`lock_exclusive`](#method.lock_exclusive) for details.
+    pub async fn lock_shared(&self) -> io::Result<FileLock<'_, F>> {
+        self.lock(libc::LOCK_SH).await
+    }
+
+    /// Locks the file exclusively if that can be done without waiting, or
+    /// returns `None`.
+    pub fn try_lock(&self) -> io::Result<Option<FileLock<'_, F>>> {
+        self.try_lock_with(libc::LOCK_EX)
+    }
+
+    /// Locks the file in shared mode if that can be done without waiting,
+    /// or returns `None`.
+    pub fn try_lock_shared(&self) -> io::Result<Option<FileLock<'_, F>>> {
+        self.try_lock_with(libc::LOCK_SH)
+    }
+
+    fn try_lock_with(&self, operation: libc::c_int) -> io::Result<Option<FileLock<'_, F>>> {
+        match flock(self.as_raw_fd(), operation | libc::LOCK_NB) {
+            Ok(()) => Ok(Some(FileLock { file: self })),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn lock(&self, operation: libc::c_int) -> io::Result<FileLock<'_, F>> {
+        let mut delay = MIN_RETRY_DELAY;
+        loop {
+            if let Some(lock) = self.try_lock_with(operation)? {
+                return Ok(lock);
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use std::fs;
+
+    #[tokio::test]
+    async fn test_lock() -> io::Result<()> {
+        let path = TempPath::new("lock");
+        let a = File::new_nb(fs::File::create(&path)?)?;
+        let b = File::new_nb(fs::File::open(&path)?)?;
+        fs::remove_file(&path)?;
+
+        let lock = a.lock_exclusive().await?;
+        assert!(b.try_lock_shared()?.is_none());
+        let waiting = tokio::time::timeout(Duration::from_millis(20), b.lock_shared()).await;
+        assert!(waiting.is_err());
+        let unlocker = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(lock);
+        };
+        let (shared, ()) = tokio::join!(b.lock_shared(), unlocker);
+        let shared = shared?;
+        a.try_lock_shared()?.unwrap().unlock()?;
+        assert!(a.try_lock()?.is_none());
+        shared.unlock()?;
+        assert!(a.try_lock()?.is_some());
+        Ok(())
+    }
+}
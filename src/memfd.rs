@@ -0,0 +1,110 @@
+//! Anonymous shared memory files and seals (`memfd_create(2)`).
+
+use crate::File;
+use std::ffi::CString;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::{fs, io};
+
+/// Creates an anonymous file in memory and wraps it with *nonblocking mode*
+/// enabled.  `name` only shows up in `/proc/self/fd`, for debugging.
+///
+/// Unless `allow_sealing` is set, the file can never be sealed.  A sealed
+/// file is a safe way to share a buffer with another process, for example
+/// over [`passfd`](passfd/index.html): once the sender has sealed it
+/// against writing and shrinking, the receiver can map and read it without
+/// the contents changing underneath.
+///
+/// Only available on Linux.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn memfd_create(name: &str, allow_sealing: bool) -> io::Result<File<fs::File>> {
+    let name = CString::new(name)?;
+    let mut flags = libc::MFD_CLOEXEC;
+    if allow_sealing {
+        flags |= libc::MFD_ALLOW_SEALING;
+    }
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+    File::new_nb(file)
+}
+
+flags! {
+    /// Seals that restrict changes to a file created by
+    /// [`memfd_create`](fn.memfd_create.html), combined with `|`.
+    pub struct Seals(libc::c_int);
+
+    /// No seals.
+    const NONE = 0;
+    /// Prevent further seals from being added (`F_SEAL_SEAL`).
+    const SEAL = libc::F_SEAL_SEAL;
+    /// Prevent the file from shrinking (`F_SEAL_SHRINK`).
+    const SHRINK = libc::F_SEAL_SHRINK;
+    /// Prevent the file from growing (`F_SEAL_GROW`).
+    const GROW = libc::F_SEAL_GROW;
+    /// Prevent writes to the contents; fails with `EBUSY` while the file
+    /// is mapped writable (`F_SEAL_WRITE`).
+    const WRITE = libc::F_SEAL_WRITE;
+    /// Prevent new writes and writable mappings, while letting existing
+    /// writable mappings be (`F_SEAL_FUTURE_WRITE`, Linux 5.1).
+    const FUTURE_WRITE = libc::F_SEAL_FUTURE_WRITE;
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Adds `seals` to the file (`F_ADD_SEALS`).  Seals cannot be removed.
+    ///
+    /// Fails with `EPERM` if the file does not allow sealing or is sealed
+    /// with `Seals::SEAL`, and with `EINVAL` if it does not support seals
+    /// at all.
+    pub fn add_seals(&self, seals: Seals) -> io::Result<()> {
+        if unsafe { libc::fcntl(self.as_raw_fd(), libc::F_ADD_SEALS, seals.0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Gets the seals of the file (`F_GET_SEALS`).
+    pub fn get_seals(&self) -> io::Result<Seals> {
+        let seals = unsafe { libc::fcntl(self.as_raw_fd(), libc::F_GET_SEALS) };
+        if seals < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Seals(seals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_memfd_seals() -> io::Result<()> {
+        let mut file = memfd_create("tokio-file-unix", true)?;
+        file.write_all(b"sealed").await?;
+        file.flush().await?;
+        file.add_seals(Seals::SHRINK | Seals::GROW | Seals::WRITE)?;
+        let seals = file.get_seals()?;
+        assert!(seals.contains(Seals::WRITE | Seals::SHRINK));
+        assert!(!seals.contains(Seals::SEAL));
+
+        // with write-behind, the error only shows up when flushing
+        let r = async {
+            file.write_all(b"more").await?;
+            file.flush().await
+        };
+        let err = r.await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+        file.seek(io::SeekFrom::Start(0)).await?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+        assert_eq!(data, b"sealed");
+
+        let unsealable = memfd_create("tokio-file-unix", false)?;
+        let err = unsealable.add_seals(Seals::WRITE).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+//! Merging the records of several inputs into one output.
+
+use crate::backpressure::{self, BackpressureMonitor, Side};
+use crate::records::BoundedLines;
+use crate::route::Outbox;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fmt, future, io};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The default number of bytes that may be queued for the output before the
+/// inputs wait for it.
+const DEFAULT_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// How a [`Merger`](struct.Merger.html) chooses among inputs that all have
+/// records ready.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fairness {
+    /// Take one record from each ready input in turn, so that a busy input
+    /// cannot starve the others.
+    #[default]
+    RoundRobin,
+    /// Always take the next record from the first ready input in the order
+    /// they were added, so that later inputs only get through when earlier
+    /// ones have nothing to say.
+    Priority,
+}
+
+/// Interleaves the records of several inputs into a single writer, such as
+/// to aggregate many producer FIFOs into one downstream pipe; the inverse
+/// of a [`Router`](struct.Router.html).
+///
+/// Each input is split into records with a
+/// [`BoundedLines`](struct.BoundedLines.html), and each record is written
+/// whole, followed by the delimiter of its input, so records from different
+/// inputs never get mixed up.  Once more than the
+/// [buffer limit](#method.buffer_limit) is waiting for the writer, the
+/// inputs wait for it.
+///
+/// ```
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio_file_unix::{BoundedLines, Fairness, Merger};
+///
+/// let output = tokio_file_unix::fifo::open_write("/run/all.fifo").await?;
+/// let mut merger = Merger::new(output).fairness(Fairness::RoundRobin);
+/// for path in &["/run/a.fifo", "/run/b.fifo"] {
+///     let input = tokio_file_unix::fifo::open_read(path).await?;
+///     merger = merger.input(BoundedLines::new(input, 4096));
+/// }
+/// merger.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Merger<R, W> {
+    /// The inputs that have not reached EOF yet.
+    inputs: Vec<Option<BoundedLines<R>>>,
+    output: Outbox<W>,
+    fairness: Fairness,
+    buffer_limit: usize,
+    /// The input to poll first next time, for round-robin.
+    next: usize,
+    monitor: Option<BackpressureMonitor>,
+}
+
+impl<R, W> Merger<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    /// Merges records into `output`.  Inputs are added with
+    /// [`input`](#method.input).
+    pub fn new(output: W) -> Self {
+        Merger {
+            inputs: Vec::new(),
+            output: Outbox::new(output),
+            fairness: Fairness::default(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+            next: 0,
+            monitor: None,
+        }
+    }
+
+    /// Adds an input, after all those added so far.
+    pub fn input(mut self, input: BoundedLines<R>) -> Self {
+        self.inputs.push(Some(input));
+        self
+    }
+
+    /// Sets how inputs are chosen.  Defaults to `Fairness::RoundRobin`.
+    pub fn fairness(mut self, fairness: Fairness) -> Self {
+        self.fairness = fairness;
+        self
+    }
+
+    /// Sets how many bytes may be queued for the output before the inputs
+    /// wait for it.  Defaults to 64 KiB.
+    pub fn buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+
+    /// Records in `monitor` how long the merger waits for the inputs, when
+    /// none of them has a record ready, and for the output.
+    pub fn monitor(mut self, monitor: BackpressureMonitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Merges records until every input reaches EOF, then writes out and
+    /// flushes the output.  The output is dropped once done.
+    ///
+    /// Fails with the first error of any input or of the output.
+    pub async fn run(mut self) -> io::Result<()> {
+        future::poll_fn(|cx| self.poll_run(cx)).await
+    }
+
+    fn poll_run(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if let Poll::Ready(Err(e)) = self.output.poll_drain(cx) {
+                return Poll::Ready(Err(e));
+            }
+            if self.inputs.iter().all(Option::is_none) {
+                if self.output.poll_finish(cx)?.is_pending() {
+                    backpressure::note(&self.monitor, Some(Side::Output));
+                    return Poll::Pending;
+                }
+                backpressure::note(&self.monitor, None);
+                return Poll::Ready(Ok(()));
+            }
+            if self.output.queued() >= self.buffer_limit {
+                backpressure::note(&self.monitor, Some(Side::Output));
+                return Poll::Pending;
+            }
+            let moved = ready!(self.poll_record(cx))?;
+            backpressure::note(&self.monitor, (!moved).then_some(Side::Input));
+            if !moved {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Moves one record from an input to the output, returning whether any
+    /// input made progress, including by reaching EOF.
+    fn poll_record(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        let count = self.inputs.len();
+        let start = match self.fairness {
+            Fairness::RoundRobin => self.next,
+            Fairness::Priority => 0,
+        };
+        for i in (start..count).chain(0..start) {
+            let input = match self.inputs[i] {
+                Some(ref mut input) => input,
+                None => continue,
+            };
+            match Pin::new(&mut *input).poll_next(cx) {
+                Poll::Pending => {}
+                Poll::Ready(None) => {
+                    self.inputs[i] = None;
+                    return Poll::Ready(Ok(true));
+                }
+                Poll::Ready(Some(record)) => {
+                    let delimiter = input.delimiter_byte();
+                    self.output.push(&record?, delimiter);
+                    self.next = (i + 1) % count;
+                    return Poll::Ready(Ok(true));
+                }
+            }
+        }
+        Poll::Ready(Ok(false))
+    }
+}
+
+impl<R: fmt::Debug, W> fmt::Debug for Merger<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Merger")
+            .field("inputs", &self.inputs)
+            .field("output", &self.output)
+            .field("fairness", &self.fairness)
+            .field("buffer_limit", &self.buffer_limit)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    async fn merge(fairness: Fairness) -> io::Result<String> {
+        let (output, mut received) = tokio::io::duplex(1024);
+        Merger::new(output)
+            .input(BoundedLines::new(&b"a1\na2\na3\n"[..], 16))
+            .input(BoundedLines::new(&b"b1\nb2"[..], 16))
+            .input(BoundedLines::new(&b"c1\0"[..], 16).delimiter(0))
+            .fairness(fairness)
+            .buffer_limit(4)
+            .run()
+            .await?;
+        let mut merged = String::new();
+        received.read_to_string(&mut merged).await?;
+        Ok(merged)
+    }
+
+    #[tokio::test]
+    async fn test_merger() -> io::Result<()> {
+        assert_eq!(
+            merge(Fairness::RoundRobin).await?,
+            "a1\nb1\nc1\0a2\nb2\na3\n"
+        );
+        assert_eq!(merge(Fairness::Priority).await?, "a1\na2\na3\nb1\nb2\nc1\0");
+        Ok(())
+    }
+}
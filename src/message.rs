@@ -0,0 +1,276 @@
+//! Sending and receiving whole messages on `SOCK_SEQPACKET` and
+//! `SOCK_DGRAM` sockets.
+//!
+//! `AsyncRead` treats every file as a byte stream, so a read may return
+//! part of a message, and messages that are read with `read_exact` or a
+//! codec run into one another.  The methods here deal in one message per
+//! call instead.
+
+use crate::{File, RecordTooLong};
+use bytes::Bytes;
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{future, io, mem};
+
+/// Gets the type of the socket `fd`, such as `SOCK_DGRAM`, failing with
+/// `ENOTSOCK` if it is not one.
+fn socket_type(fd: RawFd) -> io::Result<libc::c_int> {
+    let mut ty: libc::c_int = 0;
+    let mut len = mem::size_of_val(&ty) as libc::socklen_t;
+    let r = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut ty as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ty)
+}
+
+/// Receives a message into `buf` without waiting, returning its length and
+/// whether it was truncated.
+fn recv_nb(fd: RawFd, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    let n = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_DONTWAIT) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((n as usize, msg.msg_flags & libc::MSG_TRUNC != 0))
+}
+
+/// Sends `buf` as one message without waiting.
+fn send_nb(fd: RawFd, buf: &[u8], nosignal: bool) -> io::Result<usize> {
+    if nosignal {
+        return crate::sigpipe::send(fd, buf);
+    }
+    let n = unsafe {
+        libc::send(
+            fd,
+            buf.as_ptr() as *const libc::c_void,
+            buf.len(),
+            libc::MSG_DONTWAIT,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Receives one message into `buf`, waiting for one if need be, and
+    /// returns its length and whether it was truncated to fit, in which
+    /// case the rest of it is lost.
+    ///
+    /// On a `SOCK_SEQPACKET` socket, a length of 0 means that the peer has
+    /// hung up, unless it sent an empty message.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        future::poll_fn(|cx| self.poll_recv(cx, buf)).await
+    }
+
+    /// Polls to receive one message into `buf`.  See
+    /// [`recv`](#method.recv).
+    pub fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        loop {
+            let mut guard = ready!(self.poll_read_ready(cx))?;
+            match recv_nb(self.as_raw_fd(), buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted && self.retry_interrupted => {}
+                r => return Poll::Ready(r),
+            }
+        }
+    }
+
+    /// Sends `buf` as one message, waiting for room if need be.  Messages
+    /// are sent whole or not at all, so this fails with `EMSGSIZE` rather
+    /// than sending part of a message that is too large for the socket.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        future::poll_fn(|cx| self.poll_send(cx, buf)).await
+    }
+
+    /// Polls to send `buf` as one message.  See [`send`](#method.send).
+    pub fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.check_write_shut()?;
+        loop {
+            let mut guard = ready!(self.poll_write_ready(cx))?;
+            match send_nb(self.as_raw_fd(), buf, self.nosignal) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted && self.retry_interrupted => {}
+                r => return Poll::Ready(r),
+            }
+        }
+    }
+
+    /// Turns the socket into a stream of the messages received on it and a
+    /// sink of messages to send, like a `Framed` whose frames are the
+    /// messages themselves.
+    ///
+    /// Messages longer than `max_len` bytes are yielded as a
+    /// [`RecordTooLong`](struct.RecordTooLong.html) error, after which the
+    /// stream carries on with the next message.  On a `SOCK_SEQPACKET`
+    /// socket, the stream ends when the peer hangs up.
+    ///
+    /// Fails with `InvalidInput` unless the file is a `SOCK_SEQPACKET` or
+    /// `SOCK_DGRAM` socket, since a stream socket has no message
+    /// boundaries to preserve.
+    pub fn into_messages(self, max_len: usize) -> io::Result<Messages<F>> {
+        let ty = match socket_type(self.as_raw_fd()) {
+            Ok(ty) => ty,
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOTSOCK) => -1,
+            Err(e) => return Err(e),
+        };
+        if ty != libc::SOCK_SEQPACKET && ty != libc::SOCK_DGRAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a SOCK_SEQPACKET or SOCK_DGRAM socket",
+            ));
+        }
+        Ok(Messages {
+            inner: self,
+            // one more byte, so that a message of exactly `max_len` bytes is
+            // not mistaken for a truncated one on systems without MSG_TRUNC
+            buf: vec![0; max_len + 1],
+            max_len,
+            seqpacket: ty == libc::SOCK_SEQPACKET,
+            pending: None,
+            eof: false,
+        })
+    }
+}
+
+/// A stream and sink of the messages of a socket, created by
+/// [`File::into_messages`](struct.File.html#method.into_messages).
+#[derive(Debug)]
+pub struct Messages<F> {
+    inner: File<F>,
+    buf: Vec<u8>,
+    max_len: usize,
+    seqpacket: bool,
+    /// A message passed to `start_send` but not sent yet.
+    pending: Option<Bytes>,
+    eof: bool,
+}
+
+impl<F> Messages<F> {
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Returns the file.  A message not sent yet is lost, so flush first.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd> Messages<F> {
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(ref message) = self.pending {
+            ready!(self.inner.poll_send(cx, message))?;
+            self.pending = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for Messages<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + Unpin> Stream for Messages<F> {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.eof {
+            return Poll::Ready(None);
+        }
+        let (n, truncated) = ready!(this.inner.poll_recv(cx, &mut this.buf))?;
+        if n == 0 && this.seqpacket {
+            this.eof = true;
+            return Poll::Ready(None);
+        }
+        if truncated || n > this.max_len {
+            let err = RecordTooLong {
+                max_len: this.max_len,
+            };
+            return Poll::Ready(Some(Err(err.into())));
+        }
+        Poll::Ready(Some(Ok(Bytes::copy_from_slice(&this.buf[..n]))))
+    }
+}
+
+impl<F: AsRawFd + Unpin> Sink<Bytes> for Messages<F> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Bytes) -> io::Result<()> {
+        self.get_mut().pending = Some(message);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send_pending(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+
+    #[tokio::test]
+    async fn test_messages() -> io::Result<()> {
+        let (a, b) = crate::socketpair(libc::SOCK_SEQPACKET)?;
+        a.send(b"first").await?;
+        a.send(b"second message").await?;
+        let mut buf = [0; 8];
+        assert_eq!(b.recv(&mut buf).await?, (5, false));
+        assert_eq!(&buf[..5], b"first");
+        assert_eq!(b.recv(&mut buf).await?, (8, true));
+
+        let mut a = a.into_messages(8)?;
+        let mut b = b.into_messages(8)?;
+        a.send(Bytes::from_static(b"one")).await?;
+        a.send(Bytes::from_static(b"too long for b")).await?;
+        a.send(Bytes::from_static(b"two")).await?;
+        drop(a);
+        assert_eq!(b.next().await.unwrap()?, &b"one"[..]);
+        let err = b.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(b.next().await.unwrap()?, &b"two"[..]);
+        assert!(b.next().await.is_none());
+
+        let (c, _d) = crate::socketpair(libc::SOCK_STREAM)?;
+        let err = c.into_messages(8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+//! Querying and changing the size and attributes of wrapped files.
+
+use crate::File;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::{fs, io};
+
+impl<F: AsRawFd> File<F> {
+    /// Queries metadata about the file, like `std::fs::File::metadata`.
+    ///
+    /// For files that cannot be polled, such as regular files, the `fstat`
+    /// runs on the blocking thread pool after any pending writes, since it
+    /// can stall on a slow or network filesystem.  Pipes, sockets, and the
+    /// like are queried right away.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn metadata(&self) -> io::Result<fs::Metadata> {
+        if self.evented.is_some() {
+            // borrows the descriptor for the portable std implementation
+            let file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(self.as_raw_fd()) });
+            return file.metadata();
+        }
+        self.offload(|file| file.metadata()).await
+    }
+
+    /// Truncates or extends the file to `size` bytes, like
+    /// `std::fs::File::set_len`, on the blocking thread pool after any
+    /// pending writes.  The file offset is left alone.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn set_len(&self, size: u64) -> io::Result<()> {
+        self.offload(move |file| file.set_len(size)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+    use crate::RegularFileMode;
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_metadata_set_len() -> io::Result<()> {
+        let raw = tempfile("metadata")?;
+        let mut file = File::new_nb_with(raw, RegularFileMode::ThreadPool)?;
+        file.write_all(b"hello world").await?;
+        // the write is still pending on the thread pool
+        assert_eq!(file.metadata().await?.len(), 11);
+        file.set_len(5).await?;
+        assert_eq!(file.metadata().await?.len(), 5);
+
+        let (reader, _writer) = crate::pipe()?;
+        assert!(reader.into_inner().metadata().await?.file_type().is_fifo());
+        Ok(())
+    }
+}
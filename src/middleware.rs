@@ -0,0 +1,114 @@
+//! Hooks that see every frame passing through a codec.
+
+use std::{fmt, io};
+
+/// Inspects or rewrites the frames handled by the crate's codecs, for
+/// example to collect size histograms, redact fields, or compress payloads,
+/// without reimplementing the codec.
+///
+/// Accepted by [`BoundedLines::middleware`](struct.BoundedLines.html#method.middleware),
+/// [`LaneReader::middleware`](struct.LaneReader.html#method.middleware), and
+/// [`LaneWriter::middleware`](struct.LaneWriter.html#method.middleware).
+/// A frame is the payload alone, without delimiters or headers.
+///
+/// An error returned from a hook is returned in place of the frame, and the
+/// frame is dropped.  The hooks run inline on the task using the codec, so
+/// they should be cheap.
+pub trait FrameMiddleware: Send + 'static {
+    /// Called with each frame after it is decoded and before it is returned.
+    fn on_decode(&mut self, frame: &mut Vec<u8>) -> io::Result<()> {
+        let _ = frame;
+        Ok(())
+    }
+
+    /// Called with each frame before it is encoded.
+    fn on_encode(&mut self, frame: &mut Vec<u8>) -> io::Result<()> {
+        let _ = frame;
+        Ok(())
+    }
+}
+
+pub(crate) struct Middleware(Box<dyn FrameMiddleware>);
+
+impl fmt::Debug for Middleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Middleware")
+    }
+}
+
+impl Middleware {
+    pub(crate) fn new<M: FrameMiddleware>(middleware: M) -> Self {
+        Middleware(Box::new(middleware))
+    }
+}
+
+/// Passes a decoded frame through `middleware`, if there is one.
+pub(crate) fn decode(middleware: &mut Option<Middleware>, frame: &mut Vec<u8>) -> io::Result<()> {
+    match *middleware {
+        Some(ref mut middleware) => middleware.0.on_decode(frame),
+        None => Ok(()),
+    }
+}
+
+/// Passes a frame about to be encoded through `middleware`, if there is one.
+pub(crate) fn encode(middleware: &mut Option<Middleware>, frame: &mut Vec<u8>) -> io::Result<()> {
+    match *middleware {
+        Some(ref mut middleware) => middleware.0.on_encode(frame),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lanes, BoundedLines, File, Lane};
+    use futures::StreamExt;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+
+    /// Reverses frames on the wire and records the sizes it sees.
+    #[derive(Clone, Default)]
+    struct Reverse(Arc<Mutex<Vec<usize>>>);
+
+    impl FrameMiddleware for Reverse {
+        fn on_decode(&mut self, frame: &mut Vec<u8>) -> io::Result<()> {
+            if frame.starts_with(b"terces") {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "redacted"));
+            }
+            self.0.lock().unwrap().push(frame.len());
+            frame.reverse();
+            Ok(())
+        }
+
+        fn on_encode(&mut self, frame: &mut Vec<u8>) -> io::Result<()> {
+            frame.reverse();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware() -> io::Result<()> {
+        let sizes = Reverse::default();
+        let lines = BoundedLines::new(&b"olleh\n\nterces\n!"[..], 16).middleware(sizes.clone());
+        let lines: Vec<_> = lines.map(|r| r.map_err(|e| e.kind())).collect().await;
+        assert_eq!(
+            lines,
+            vec![
+                Ok(b"hello".to_vec()),
+                Ok(Vec::new()),
+                Err(io::ErrorKind::InvalidData),
+                Ok(b"!".to_vec()),
+            ]
+        );
+        assert_eq!(*sizes.0.lock().unwrap(), vec![5, 0, 1]);
+
+        let (a, b) = UnixStream::pair()?;
+        let (_, writer) = lanes(File::new_nb(a)?, 64);
+        let (reader, _) = lanes(File::new_nb(b)?, 64);
+        let mut writer = writer.middleware(Reverse::default());
+        let mut reader = reader.middleware(Reverse::default());
+        writer.send(Lane::Bulk, b"abc").await?;
+        assert_eq!(reader.recv().await?, Some((Lane::Bulk, b"abc".to_vec())));
+        Ok(())
+    }
+}
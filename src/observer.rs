@@ -0,0 +1,109 @@
+//! Instrumentation hooks for wrapped files.
+
+use crate::File;
+use std::os::unix::io::RawFd;
+use std::{fmt, io};
+
+/// Receives the results of the reads and writes performed on a
+/// [`File`](struct.File.html), for example to collect metrics, audit, or
+/// record traffic for replay.  Registered with
+/// [`File::set_observer`](struct.File.html#method.set_observer).
+///
+/// Each completed operation is reported once, with the bytes that were
+/// transferred or the error it failed with.  Asynchronous operations that
+/// are still pending are not reported.  The callbacks run inline on the task
+/// doing the I/O, so they should be cheap.
+pub trait IoObserver: Send + Sync + 'static {
+    /// Called after a read on `fd` completes.  An empty slice means EOF.
+    fn on_read(&self, fd: RawFd, result: Result<&[u8], &io::Error>) {
+        let _ = (fd, result);
+    }
+
+    /// Called after a write on `fd` completes, with the part of the buffer
+    /// that was written.
+    fn on_write(&self, fd: RawFd, result: Result<&[u8], &io::Error>) {
+        let _ = (fd, result);
+    }
+}
+
+pub(crate) struct Observer(Box<dyn IoObserver>);
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+impl Observer {
+    pub(crate) fn read<T>(&self, fd: RawFd, result: &io::Result<T>, data: &[u8]) {
+        match *result {
+            Ok(_) => self.0.on_read(fd, Ok(data)),
+            Err(ref e) => self.0.on_read(fd, Err(e)),
+        }
+    }
+
+    pub(crate) fn write(&self, fd: RawFd, result: &io::Result<usize>, buf: &[u8]) {
+        match *result {
+            Ok(n) => self.0.on_write(fd, Ok(&buf[..n])),
+            Err(ref e) => self.0.on_write(fd, Err(e)),
+        }
+    }
+}
+
+impl<F> File<F> {
+    /// Registers an observer to be notified of every read and write on this
+    /// file, replacing any previous one.
+    pub fn set_observer<O: IoObserver>(&mut self, observer: O) {
+        self.observer = Some(Observer(Box::new(observer)));
+    }
+
+    /// Removes the observer, if any.
+    pub fn clear_observer(&mut self) {
+        self.observer = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(bool, Vec<u8>)>>);
+
+    impl IoObserver for Arc<Recorder> {
+        fn on_read(&self, _fd: RawFd, result: Result<&[u8], &io::Error>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((false, result.unwrap().to_vec()));
+        }
+
+        fn on_write(&self, _fd: RawFd, result: Result<&[u8], &io::Error>) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((true, result.unwrap().to_vec()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        let recorder = Arc::new(Recorder::default());
+        a.set_observer(recorder.clone());
+        a.write_all(b"hello").await?;
+        b.write_all(b"hi").await?;
+        let mut buf = [0; 2];
+        a.read_exact(&mut buf).await?;
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![(true, b"hello".to_vec()), (false, b"hi".to_vec())]
+        );
+        Ok(())
+    }
+}
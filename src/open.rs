@@ -0,0 +1,66 @@
+//! Opening files that may hang.
+
+use crate::File;
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, io, thread};
+use tokio::sync::oneshot;
+
+/// Opens `path` with `options` and wraps it with *nonblocking mode*
+/// enabled, giving up with a `TimedOut` error if the open does not finish
+/// within `timeout`.
+///
+/// Opening some files can block indefinitely regardless of `O_NONBLOCK`,
+/// such as a modem waiting for carrier or a path on an unresponsive NFS
+/// server.  The open therefore runs on a dedicated thread rather than the
+/// runtime, so neither the runtime nor its shutdown can get stuck on it.  If
+/// the time runs out, the thread is abandoned, and should the open succeed
+/// after all, the file is closed right away.
+///
+/// This must be called within the context of a Tokio runtime.
+pub async fn open_with_deadline<P: AsRef<Path>>(
+    path: P,
+    options: &fs::OpenOptions,
+    timeout: Duration,
+) -> io::Result<File<fs::File>> {
+    let path = path.as_ref().to_owned();
+    let options = options.clone();
+    let (tx, rx) = oneshot::channel();
+    thread::Builder::new()
+        .name("tokio-file-unix-open".to_owned())
+        .spawn(move || {
+            // if nobody is waiting anymore, the file is dropped here
+            let _ = tx.send(options.open(&path));
+        })?;
+    match tokio::time::timeout(timeout, rx).await {
+        Ok(Ok(file)) => File::new_nb(crate::move_fd_above(file?, crate::STDIO_END)?),
+        Ok(Err(_)) => Err(io::Error::other("open thread panicked")),
+        Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "open timed out")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use std::os::unix::io::AsRawFd;
+
+    #[tokio::test]
+    async fn test_open_with_deadline() -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.read(true);
+        let file = open_with_deadline("/dev/null", &options, Duration::from_secs(5)).await?;
+        assert!(file.as_raw_fd() >= crate::STDIO_END);
+
+        // opening a FIFO for reading blocks until there is a writer
+        let path = TempPath::new("deadline");
+        crate::fifo::mkfifo(&path, 0o600)?;
+        let err = open_with_deadline(&path, &options, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        // release the abandoned thread
+        let _writer = crate::fifo::open_write(&path).await?;
+        Ok(())
+    }
+}
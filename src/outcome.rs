@@ -0,0 +1,101 @@
+//! Telling the end of input apart from the other end hanging up.
+
+use crate::{FdKind, File};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// The outcome of [`File::read_outcome`](struct.File.html#method.read_outcome).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReadOutcome {
+    /// This many bytes were read, which is zero only for an empty buffer.
+    Data(usize),
+    /// The end of input was reached while the file stays usable, as when
+    /// Ctrl-D is typed on a terminal or a regular file has been read to the
+    /// end.  Reading again may yield more data.
+    Eof,
+    /// The other end is gone, as when every writer of a pipe or the peer of
+    /// a socket has closed it, or the master of a pseudoterminal has been
+    /// closed.  Reading again will not yield more data.
+    HangUp,
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Waits for the file to become readable and reads into `buf`, like
+    /// `AsyncReadExt::read`, but reports a read of nothing as either
+    /// `ReadOutcome::Eof` or `ReadOutcome::HangUp`.  Other errors are
+    /// returned as they are.
+    ///
+    /// This lets a program on a terminal tell Ctrl-D, after which it may
+    /// keep reading, apart from the terminal going away.
+    ///
+    /// Implementation detail: a read of nothing is a hangup for pipes and
+    /// sockets, and for terminals and other devices if `poll` then reports
+    /// `POLLHUP`.  A read from a device that fails with `EIO`, as on Linux
+    /// once the master of a pseudoterminal is closed, is also a hangup.
+    /// Terminals are not told apart from other devices, since a terminal
+    /// that has been hung up may no longer pass for one.
+    pub async fn read_outcome(&self, buf: &mut [u8]) -> io::Result<ReadOutcome> {
+        if buf.is_empty() {
+            return Ok(ReadOutcome::Data(0));
+        }
+        loop {
+            let mut guard = self.readable().await?;
+            match self.try_read(buf) {
+                Ok(0) => {
+                    return Ok(match FdKind::of(self)? {
+                        FdKind::Pipe | FdKind::Socket => ReadOutcome::HangUp,
+                        FdKind::RegularFile | FdKind::Directory => ReadOutcome::Eof,
+                        _ if crate::poll_now(self.as_raw_fd(), libc::POLLHUP)? => {
+                            ReadOutcome::HangUp
+                        }
+                        _ => ReadOutcome::Eof,
+                    })
+                }
+                Ok(n) => return Ok(ReadOutcome::Data(n)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(ref e) if e.raw_os_error() == Some(libc::EIO) && is_device(self)? => {
+                    return Ok(ReadOutcome::HangUp)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn is_device<F: AsRawFd>(file: &F) -> io::Result<bool> {
+    Ok(matches!(
+        FdKind::of(file)?,
+        FdKind::Tty | FdKind::CharDevice
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_read_outcome() -> io::Result<()> {
+        let mut buf = [0; 16];
+        let (reader, mut writer) = crate::pipe()?;
+        let reader = reader.into_inner();
+        writer.write_all(b"x").await?;
+        assert_eq!(reader.read_outcome(&mut buf).await?, ReadOutcome::Data(1));
+        drop(writer);
+        assert_eq!(reader.read_outcome(&mut buf).await?, ReadOutcome::HangUp);
+
+        let file = File::new_nb(std::fs::File::open("/dev/null")?)?;
+        assert_eq!(file.read_outcome(&mut buf).await?, ReadOutcome::Eof);
+
+        let (mut master, slave) = crate::pty::open()?;
+        let slave = slave.into_inner();
+        // Ctrl-D on an empty line, in canonical mode
+        master.write_all(b"\x04").await?;
+        assert_eq!(slave.read_outcome(&mut buf).await?, ReadOutcome::Eof);
+        master.write_all(b"more\n").await?;
+        assert_eq!(slave.read_outcome(&mut buf).await?, ReadOutcome::Data(5));
+        drop(master);
+        assert_eq!(slave.read_outcome(&mut buf).await?, ReadOutcome::HangUp);
+        Ok(())
+    }
+}
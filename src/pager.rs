@@ -0,0 +1,116 @@
+//! Piping output through the user's pager.
+
+use crate::File;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use std::task::{Context, Poll};
+use std::{env, error, fmt, fs, io};
+use tokio::io::AsyncWrite;
+use tokio::process::{Child, Command};
+
+/// The payload of the `io::Error` returned by writes to a
+/// [`Pager`](struct.Pager.html) after the user has quit it.  The error kind
+/// is `BrokenPipe`.
+#[derive(Debug)]
+pub struct PagerClosed;
+
+impl fmt::Display for PagerClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the pager has exited")
+    }
+}
+
+impl error::Error for PagerClosed {}
+
+/// Starts the pager named by the `PAGER` environment variable, or `less` if
+/// it is unset, and returns a writer feeding its input.
+///
+/// The pager inherits the standard output and error of the process.  The
+/// command is run by `sh`, so `PAGER` may contain arguments.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn page_output() -> io::Result<Pager> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_owned());
+    Pager::spawn(&pager)
+}
+
+/// A writer feeding a running pager.  Created by
+/// [`page_output`](fn.page_output.html).
+///
+/// Once the user quits the pager, writes fail with a
+/// [`PagerClosed`](struct.PagerClosed.html) error, which tells the program
+/// that it can stop producing output.
+#[derive(Debug)]
+pub struct Pager {
+    input: File<fs::File>,
+    child: Child,
+}
+
+impl Pager {
+    fn spawn(command: &str) -> io::Result<Self> {
+        let (reader, writer) = crate::pipe::raw_pipe(libc::O_CLOEXEC)?;
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::from(reader))
+            .spawn()?;
+        Ok(Pager {
+            input: File::new_nb(crate::move_fd_above(writer, crate::STDIO_END)?)?,
+            child,
+        })
+    }
+
+    /// Closes the input of the pager and waits for the user to quit it.
+    pub async fn wait(self) -> io::Result<ExitStatus> {
+        let Pager { input, mut child } = self;
+        drop(input);
+        child.wait().await
+    }
+}
+
+fn closed(r: Poll<io::Result<usize>>) -> Poll<io::Result<usize>> {
+    match r {
+        Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::BrokenPipe => {
+            Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, PagerClosed)))
+        }
+        r => r,
+    }
+}
+
+impl AsyncWrite for Pager {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        closed(Pin::new(&mut self.input).poll_write(cx, buf))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.input).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.input).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_pager_closed() -> io::Result<()> {
+        // a pager that the user quits right away
+        let mut pager = Pager::spawn("head -c 1 >/dev/null")?;
+        let e = loop {
+            if let Err(e) = pager.write_all(&[b'x'; 4096]).await {
+                break e;
+            }
+        };
+        assert!(e.get_ref().unwrap().is::<PagerClosed>());
+        assert!(pager.wait().await?.success());
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Passing file descriptors over Unix domain sockets (`SCM_RIGHTS`).
+//!
+//! Each descriptor travels with a single byte of ordinary data, since some
+//! systems drop control messages sent without any.  On a stream socket that
+//! byte is interleaved with whatever else is sent, so it is simplest to use
+//! a socket just for passing descriptors, or to agree on when they come.
+
+use crate::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::{fs, io, mem, ptr};
+
+/// Room for the control message carrying a single descriptor.
+type Control = [u64; 4];
+
+/// Sends the descriptor of `fd` over the Unix domain socket `socket`,
+/// waiting for it to become writable if need be.  The descriptor stays open
+/// on this side too.
+///
+/// Implementation detail: uses `sendmsg` with `SCM_RIGHTS`.
+pub async fn send_fd<S: AsRawFd, T: AsRawFd>(socket: &File<S>, fd: &T) -> io::Result<()> {
+    loop {
+        let mut guard = socket.writable().await?;
+        match send_fd_nb(socket.as_raw_fd(), fd.as_raw_fd()) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+            r => return r,
+        }
+    }
+}
+
+fn send_fd_nb(socket: RawFd, fd: RawFd) -> io::Result<()> {
+    let mut data = [0u8; 1];
+    let mut control: Control = [0; 4];
+    unsafe {
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+        if libc::sendmsg(socket, &msg, libc::MSG_DONTWAIT) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Receives a descriptor sent with [`send_fd`](fn.send_fd.html) over the
+/// Unix domain socket `socket`, waiting for one to arrive if need be.
+///
+/// The descriptor is close-on-exec and, like with
+/// [`File::new_nb`](../struct.File.html#method.new_nb), in nonblocking mode
+/// and registered with the reactor if it can be polled.  Fails with
+/// `UnexpectedEof` if the peer has hung up, and with `InvalidData` if a
+/// byte arrives without a descriptor.
+///
+/// This must be called within the context of a Tokio runtime.
+pub async fn recv_fd<S: AsRawFd>(socket: &File<S>) -> io::Result<File<fs::File>> {
+    let file = loop {
+        let mut guard = socket.readable().await?;
+        match recv_fd_nb(socket.as_raw_fd()) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+            r => break r?,
+        }
+    };
+    File::new_nb(crate::move_fd_above(file, crate::STDIO_END)?)
+}
+
+fn recv_fd_nb(socket: RawFd) -> io::Result<fs::File> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let flags = libc::MSG_DONTWAIT | libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let flags = libc::MSG_DONTWAIT;
+    let mut data = [0u8; 1];
+    let mut control: Control = [0; 4];
+    let mut received = None;
+    unsafe {
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        let mut msg: libc::msghdr = mem::zeroed();
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+        let n = libc::recvmsg(socket, &mut msg, flags);
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let len = ((*cmsg).cmsg_len as usize).saturating_sub(libc::CMSG_LEN(0) as usize);
+                for i in 0..len / mem::size_of::<RawFd>() {
+                    // keep the first and close any extras
+                    let file = fs::File::from_raw_fd(ptr::read_unaligned(data.add(i)));
+                    received.get_or_insert(file);
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        if n == 0 && received.is_none() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    let file = received.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without a descriptor")
+    })?;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_pass_fd() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        let b = File::new_nb(b)?;
+        let (mut reader, writer) = crate::pipe()?;
+
+        send_fd(&a, &writer).await?;
+        drop(writer);
+        let mut writer = recv_fd(&b).await?;
+        let flags = unsafe { libc::fcntl(writer.as_raw_fd(), libc::F_GETFD) };
+        assert_ne!(flags & libc::FD_CLOEXEC, 0);
+        writer.write_all(b"passed").await?;
+        drop(writer);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        assert_eq!(data, b"passed");
+
+        assert_eq!(a.try_write(b"x")?, 1);
+        let err = recv_fd(&b).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        drop(a);
+        let err = recv_fd(&b).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+//! Looking at incoming data without consuming it.
+
+use crate::{check_stale, retry_eintr, FdKind, File};
+use bytes::{Buf, BytesMut};
+use std::io::{self, SeekFrom};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tokio::io::ReadBuf;
+
+/// The data read by [`File::peek`](struct.File.html#method.peek) from files
+/// other than sockets, which reads hand out before reading the descriptor.
+#[derive(Debug, Default)]
+pub(crate) struct Pushback {
+    /// Whether `data` may be nonempty, to keep reads off the lock.
+    pending: AtomicBool,
+    data: Mutex<BytesMut>,
+}
+
+impl Pushback {
+    /// Moves data that was peeked at into `buf`, returning how much, or
+    /// `None` if there is none.
+    pub(crate) fn take(&self, buf: &mut [u8]) -> Option<usize> {
+        self.take_with(buf.len(), |data| buf[..data.len()].copy_from_slice(data))
+    }
+
+    /// Like [`take`](#method.take), but into the unfilled part of `buf`.
+    pub(crate) fn take_buf(&self, buf: &mut ReadBuf<'_>) -> Option<usize> {
+        self.take_with(buf.remaining(), |data| buf.put_slice(data))
+    }
+
+    fn take_with(&self, len: usize, copy: impl FnOnce(&[u8])) -> Option<usize> {
+        if !self.pending.load(Ordering::Acquire) {
+            return None;
+        }
+        let mut data = self.data.lock().unwrap();
+        if data.is_empty() {
+            return None;
+        }
+        let n = len.min(data.len());
+        copy(&data[..n]);
+        data.advance(n);
+        if data.is_empty() {
+            self.pending.store(false, Ordering::Release);
+        }
+        Some(n)
+    }
+
+    /// Runs `seek` to `pos` and discards the data peeked at if it succeeds.
+    /// A seek relative to the current position is moved back by the data
+    /// held, since the descriptor is that far ahead.
+    pub(crate) fn seek(
+        &self,
+        pos: SeekFrom,
+        seek: impl FnOnce(SeekFrom) -> io::Result<u64>,
+    ) -> io::Result<u64> {
+        if !self.pending.load(Ordering::Acquire) {
+            return seek(pos);
+        }
+        let mut data = self.data.lock().unwrap();
+        let pos = match pos {
+            SeekFrom::Current(n) => SeekFrom::Current(n - data.len() as i64),
+            pos => pos,
+        };
+        let r = seek(pos)?;
+        data.clear();
+        self.pending.store(false, Ordering::Release);
+        Ok(r)
+    }
+}
+
+fn recv_peek(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    let n = unsafe {
+        libc::recv(
+            fd,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            libc::MSG_PEEK,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Waits for data and copies as much of it as fits into `buf` without
+    /// consuming it, returning the number of bytes copied, which is 0 at
+    /// EOF.  The next read returns the same data.  This lets a server sniff
+    /// the protocol on an inherited descriptor, such as TLS versus
+    /// plaintext, before handing it on.
+    ///
+    /// Like `recv` with `MSG_PEEK`, this returns whatever is available, so
+    /// peeking again may return more.  If data has already been peeked at,
+    /// this does not wait, but picks up whatever more has arrived since.
+    ///
+    /// Implementation detail: sockets are peeked at with `recv` and
+    /// `MSG_PEEK`.  Other files, such as pipes and terminals, are read
+    /// directly, and the data is kept in a buffer that reads through
+    /// `AsyncRead`, `io::Read`, [`try_read`](#method.try_read), and
+    /// [`read_buf`](#method.read_buf) take from before reading the
+    /// descriptor.  Seeking discards it, and
+    /// [`copy_bidirectional`](fn.copy_bidirectional.html) copies it first,
+    /// but operations that move data on the descriptor itself, such as
+    /// [`splice`](fn.splice.html), [`tee`](fn.tee.html), and
+    /// [`copy_to_file`](fn.copy_to_file.html), fail with `InvalidInput`
+    /// until it has been read.  It is lost by
+    /// [`into_inner`](#method.into_inner).
+    ///
+    /// Fails with `InvalidInput` for regular files offloaded to the blocking
+    /// thread pool, which reads ahead on its own.
+    pub async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.is_offloaded() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot peek at a file offloaded to the thread pool",
+            ));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let fd = self.as_raw_fd();
+        if FdKind::of(&fd)? == FdKind::Socket {
+            loop {
+                let mut guard = self.readable().await?;
+                match check_stale(fd, self.identity, recv_peek(fd, buf)) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                    r => return r,
+                }
+            }
+        }
+        let retry_interrupted = self.retry_interrupted;
+        let identity = self.identity;
+        let read = |buf: &mut [u8]| {
+            retry_eintr(retry_interrupted, || {
+                let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+                if n < 0 {
+                    check_stale(fd, identity, Err(io::Error::last_os_error()))
+                } else {
+                    Ok(n as usize)
+                }
+            })
+        };
+        {
+            let mut data = self.pushback.data.lock().unwrap();
+            if !data.is_empty() {
+                let have = data.len();
+                if have < buf.len() {
+                    // anything that goes wrong shows up on the next read
+                    if let Ok(n) = read(&mut buf[have..]) {
+                        data.extend_from_slice(&buf[have..have + n]);
+                    }
+                }
+                let n = buf.len().min(data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                return Ok(n);
+            }
+        }
+        loop {
+            let mut guard = self.readable().await?;
+            match read(buf) {
+                Ok(n) => {
+                    if n > 0 {
+                        self.pushback
+                            .data
+                            .lock()
+                            .unwrap()
+                            .extend_from_slice(&buf[..n]);
+                        self.pushback.pending.store(true, Ordering::Release);
+                    }
+                    return Ok(n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<F> File<F> {
+    /// Fails with `InvalidInput` if data was peeked at but not read yet, for
+    /// operations on the descriptor that would skip over it.
+    pub(crate) fn check_pushback(&self) -> io::Result<()> {
+        if self.pushback.pending.load(Ordering::Acquire) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "data was peeked at but not read yet",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_peek() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let mut reader = reader.into_inner();
+        writer.write_all(b"GET").await?;
+        let mut buf = [0; 8];
+        assert_eq!(reader.peek(&mut buf[..2]).await?, 2);
+        assert_eq!(&buf[..2], b"GE");
+        // picks up what arrived since
+        writer.write_all(b" /").await?;
+        assert_eq!(reader.peek(&mut buf).await?, 5);
+        assert_eq!(&buf[..5], b"GET /");
+        let mut head = [0; 4];
+        assert_eq!(reader.try_read(&mut head)?, 4);
+        assert_eq!(&head, b"GET ");
+        writer.write_all(b"index").await?;
+        drop(writer);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await?;
+        assert_eq!(rest, b"/index");
+        assert_eq!(reader.peek(&mut buf).await?, 0);
+
+        let (a, mut b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        io::Write::write_all(&mut b, b"\x16\x03\x01")?;
+        assert_eq!(a.peek(&mut buf[..1]).await?, 1);
+        assert_eq!(buf[0], 0x16);
+        let mut record = [0; 3];
+        a.read_exact(&mut record).await?;
+        assert_eq!(&record, b"\x16\x03\x01");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seek_after_peek() -> io::Result<()> {
+        use tokio::io::AsyncSeekExt;
+        let mut raw = crate::tempfile::tempfile("peek")?;
+        io::Write::write_all(&mut raw, b"abcdef")?;
+        io::Seek::rewind(&mut raw)?;
+        let mut file = File::new_nb(raw)?;
+        let mut buf = [0; 3];
+        assert_eq!(file.peek(&mut buf).await?, 3);
+        assert_eq!(file.stream_position().await?, 0);
+        assert_eq!(file.seek(SeekFrom::Current(1)).await?, 1);
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).await?;
+        assert_eq!(rest, b"bcdef");
+
+        let raw = crate::tempfile::tempfile("peek-offloaded")?;
+        let mut file = File::new_nb_with(raw, crate::RegularFileMode::ThreadPool)?;
+        let err = file.peek(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn test_splice_after_peek() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let (a, mut b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        let mut reader = reader.into_inner();
+        writer.write_all(b"peeked").await?;
+        drop(writer);
+        let mut buf = [0; 4];
+        assert_eq!(reader.peek(&mut buf).await?, 4);
+        let err = crate::splice(&reader, &a, 64).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        // once the data peeked at is read, splicing picks up after it
+        reader.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"peek");
+        assert_eq!(crate::splice(&reader, &a, 64).await?, 2);
+        drop(a);
+        let mut rest = Vec::new();
+        io::Read::read_to_end(&mut b, &mut rest)?;
+        assert_eq!(rest, b"ed");
+        Ok(())
+    }
+}
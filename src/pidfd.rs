@@ -0,0 +1,111 @@
+//! Process descriptors (`pidfd_open(2)`).
+
+use crate::{DirectoryPolicy, File, RegularFileMode};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::{fs, io, process, ptr};
+use tokio::io::Interest;
+
+/// A descriptor referring to a process, obtained with `pidfd_open(2)`,
+/// which becomes readable once the process exits.
+///
+/// Unlike a process ID, it keeps referring to the same process even after
+/// the ID has been reused, so signals cannot hit the wrong process.
+///
+/// Only available on Linux 5.3 and later.
+#[derive(Debug)]
+pub struct PidFd {
+    file: File<fs::File>,
+}
+
+impl PidFd {
+    /// Opens a descriptor for the process with the given ID.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn open(pid: libc::pid_t) -> io::Result<Self> {
+        // PIDFD_NONBLOCK is not needed since the descriptor is only polled
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = unsafe { fs::File::from_raw_fd(fd as RawFd) };
+        // pidfds are always close-on-exec
+        let file = crate::move_fd_above(file, crate::STDIO_END)?;
+        // registered directly, since `raw_new` rejects blocking descriptors
+        let file = File::register(
+            file,
+            RegularFileMode::Inline,
+            DirectoryPolicy::Allow,
+            true,
+            Interest::READABLE,
+        )?;
+        Ok(PidFd { file })
+    }
+
+    /// Opens a descriptor for a child process.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn from_child(child: &process::Child) -> io::Result<Self> {
+        PidFd::open(child.id() as libc::pid_t)
+    }
+
+    /// Waits for the process to exit.
+    ///
+    /// This does not reap a child process; call `wait` on the
+    /// `std::process::Child` afterwards, which then returns right away.
+    pub async fn wait(&self) -> io::Result<()> {
+        self.file.readable().await.map(drop)
+    }
+
+    /// Sends `signal` to the process.
+    pub fn send_signal(&self, signal: libc::c_int) -> io::Result<()> {
+        let r = unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.as_raw_fd(),
+                signal,
+                ptr::null::<libc::siginfo_t>(),
+                0,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for PidFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[tokio::test]
+    async fn test_pidfd() -> io::Result<()> {
+        let mut child = process::Command::new("sleep").arg("10").spawn()?;
+        let pidfd = match PidFd::open(child.id() as libc::pid_t) {
+            Ok(pidfd) => pidfd,
+            // kernel too old
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                child.kill()?;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        pidfd.send_signal(libc::SIGTERM)?;
+        pidfd.wait().await?;
+        assert_eq!(child.wait()?.signal(), Some(libc::SIGTERM));
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+//! Pipes.
+
+use crate::File;
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Creates a pipe with the given flags (`O_NONBLOCK` and/or `O_CLOEXEC`),
+/// returning the reading and writing ends.
+pub(crate) fn raw_pipe(flags: libc::c_int) -> io::Result<(fs::File, fs::File)> {
+    let mut fds = [0; 2];
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let result = unsafe { libc::pipe2(fds.as_mut_ptr(), flags) };
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let result = unsafe { libc::pipe(fds.as_mut_ptr()) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // owned right away, so that both ends are closed if anything below fails
+    let ends = unsafe { (fs::File::from_raw_fd(fds[0]), fs::File::from_raw_fd(fds[1])) };
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    for end in &[&ends.0, &ends.1] {
+        let fd = end.as_raw_fd();
+        if flags & libc::O_CLOEXEC != 0 && unsafe { libc::ioctl(fd, libc::FIOCLEX) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if flags & libc::O_NONBLOCK != 0 {
+            crate::set_nonblocking_for("pipe", fd, true)?;
+        }
+    }
+    Ok(ends)
+}
+
+/// Creates a pipe, returning its reading and writing ends wrapped for use
+/// with Tokio.
+///
+/// Both ends are nonblocking and close-on-exec, and like all descriptors
+/// created by this crate they are placed above the standard streams.
+///
+/// Implementation detail: uses `pipe2` where available.
+pub fn pipe() -> io::Result<(PipeReader, PipeWriter)> {
+    let (reader, writer) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+    let reader = crate::move_fd_above(reader, crate::STDIO_END)?;
+    let writer = crate::move_fd_above(writer, crate::STDIO_END)?;
+    Ok((
+        PipeReader(File::raw_new(reader)?),
+        PipeWriter(File::raw_new(writer)?),
+    ))
+}
+
+/// The reading end of a pipe, created by [`pipe`](fn.pipe.html).
+#[derive(Debug)]
+pub struct PipeReader(File<fs::File>);
+
+/// The writing end of a pipe, created by [`pipe`](fn.pipe.html).
+#[derive(Debug)]
+pub struct PipeWriter(File<fs::File>);
+
+impl PipeReader {
+    /// Returns the wrapped file.
+    pub fn into_inner(self) -> File<fs::File> {
+        self.0
+    }
+}
+
+impl PipeWriter {
+    /// Returns the wrapped file.
+    pub fn into_inner(self) -> File<fs::File> {
+        self.0
+    }
+}
+
+impl AsRef<File<fs::File>> for PipeReader {
+    fn as_ref(&self) -> &File<fs::File> {
+        &self.0
+    }
+}
+
+impl AsRef<File<fs::File>> for PipeWriter {
+    fn as_ref(&self) -> &File<fs::File> {
+        &self.0
+    }
+}
+
+impl AsRawFd for PipeReader {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsRawFd for PipeWriter {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.0.poll_read_uninit(cx, buf)
+    }
+}
+
+impl AsyncWrite for PipeWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+/// Extension methods for controlling the kernel buffer of a pipe.
+///
+/// A larger buffer lets a fast producer write more before it has to wait for
+/// the consumer, so both sides are woken up less often.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub trait PipeExt: AsRawFd {
+    /// The size of the pipe's kernel buffer in bytes (`F_GETPIPE_SZ`).
+    fn pipe_capacity(&self) -> io::Result<usize> {
+        let r = unsafe { libc::fcntl(self.as_raw_fd(), libc::F_GETPIPE_SZ) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(r as usize)
+    }
+
+    /// Resizes the pipe's kernel buffer to at least `bytes` bytes
+    /// (`F_SETPIPE_SZ`), returning the size actually set, which the kernel
+    /// rounds up to a power of two pages.
+    ///
+    /// Unprivileged processes cannot exceed `/proc/sys/fs/pipe-max-size`
+    /// (`EPERM`), and shrinking below the amount of buffered data fails with
+    /// `EBUSY`.
+    fn set_pipe_capacity(&self, bytes: usize) -> io::Result<usize> {
+        let bytes: libc::c_int = std::convert::TryFrom::try_from(bytes)
+            .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))?;
+        let r = unsafe { libc::fcntl(self.as_raw_fd(), libc::F_SETPIPE_SZ, bytes) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(r as usize)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl<F: AsRawFd> PipeExt for File<F> {}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl PipeExt for PipeReader {}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl PipeExt for PipeWriter {}
+
+/// A pair of nonblocking, close-on-exec pipe ends used as a scratch buffer,
+/// for example as the intermediate pipe of `splice` or `tee`.
+#[derive(Debug)]
+pub struct ScratchPipe {
+    reader: fs::File,
+    writer: fs::File,
+}
+
+impl ScratchPipe {
+    fn new() -> io::Result<Self> {
+        let (reader, writer) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        Ok(ScratchPipe { reader, writer })
+    }
+
+    /// The reading end of the pipe.
+    pub fn reader(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+
+    /// The writing end of the pipe.
+    pub fn writer(&self) -> RawFd {
+        self.writer.as_raw_fd()
+    }
+
+    /// Whether no data is buffered in the pipe.
+    fn is_empty(&self) -> bool {
+        let mut n: libc::c_int = 0;
+        let r = unsafe { libc::ioctl(self.reader.as_raw_fd(), libc::FIONREAD, &mut n) };
+        r == 0 && n == 0
+    }
+}
+
+/// A pool of recycled [`ScratchPipe`](struct.ScratchPipe.html)s.
+///
+/// Creating and closing a pipe for every short-lived transfer costs three
+/// system calls; a pool amortizes that away.  The `splice` fast path of
+/// [`copy_bidirectional`](fn.copy_bidirectional.html) draws from
+/// [`PipePool::global`](#method.global).
+///
+/// A pipe is only returned to the pool if it is empty once released, so
+/// leftover data never leaks from one user to the next.
+#[derive(Debug)]
+pub struct PipePool {
+    idle: Mutex<Vec<ScratchPipe>>,
+    max_idle: usize,
+}
+
+impl PipePool {
+    /// Creates an empty pool that keeps at most `max_idle` unused pipes.
+    pub fn new(max_idle: usize) -> Self {
+        PipePool {
+            idle: Mutex::new(Vec::new()),
+            max_idle,
+        }
+    }
+
+    /// Creates a pool with `count` pipes already allocated.
+    pub fn with_preallocated(count: usize, max_idle: usize) -> io::Result<Self> {
+        let pool = PipePool::new(max_idle);
+        {
+            let mut idle = pool.idle.lock().unwrap();
+            for _ in 0..count.min(max_idle) {
+                idle.push(ScratchPipe::new()?);
+            }
+        }
+        Ok(pool)
+    }
+
+    /// The pool shared by the whole process, which keeps up to 16 idle
+    /// pipes.
+    pub fn global() -> &'static PipePool {
+        static GLOBAL: OnceLock<PipePool> = OnceLock::new();
+        GLOBAL.get_or_init(|| PipePool::new(16))
+    }
+
+    /// Takes a pipe from the pool, creating one if none is idle.  The pipe
+    /// goes back to the pool when the returned handle is dropped.
+    pub fn get(&self) -> io::Result<PooledPipe<'_>> {
+        let pipe = self.idle.lock().unwrap().pop();
+        let pipe = match pipe {
+            Some(pipe) => pipe,
+            None => ScratchPipe::new()?,
+        };
+        Ok(PooledPipe {
+            pipe: Some(pipe),
+            pool: self,
+        })
+    }
+
+    /// The number of idle pipes currently held by the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+}
+
+/// A pipe borrowed from a [`PipePool`](struct.PipePool.html).
+#[derive(Debug)]
+pub struct PooledPipe<'a> {
+    pipe: Option<ScratchPipe>,
+    pool: &'a PipePool,
+}
+
+impl Deref for PooledPipe<'_> {
+    type Target = ScratchPipe;
+
+    fn deref(&self) -> &ScratchPipe {
+        self.pipe.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledPipe<'_> {
+    fn drop(&mut self) {
+        if let Some(pipe) = self.pipe.take() {
+            if pipe.is_empty() {
+                let mut idle = self.pool.idle.lock().unwrap();
+                if idle.len() < self.pool.max_idle {
+                    idle.push(pipe);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_pipe() -> io::Result<()> {
+        let (mut reader, mut writer) = pipe()?;
+        assert!(reader.as_raw_fd() >= crate::STDIO_END);
+        writer.write_all(b"piped").await?;
+        drop(writer);
+        let mut received = String::new();
+        reader.read_to_string(&mut received).await?;
+        assert_eq!(received, "piped");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hung_up() -> io::Result<()> {
+        use std::time::Duration;
+        let (reader, mut writer) = pipe()?;
+        let wait = Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, reader.as_ref().hung_up())
+            .await
+            .is_err());
+        // pending data must not be consumed by waiting for a hangup
+        writer.write_all(b"x").await?;
+        assert!(tokio::time::timeout(wait, reader.as_ref().hung_up())
+            .await
+            .is_err());
+        drop(writer);
+        reader.as_ref().hung_up().await?;
+
+        let (reader, writer) = pipe()?;
+        let hung_up = tokio::spawn(async move { writer.as_ref().hung_up().await });
+        drop(reader);
+        hung_up.await.unwrap()
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn test_pipe_capacity() -> io::Result<()> {
+        let (reader, writer) = pipe()?;
+        let capacity = writer.set_pipe_capacity(reader.pipe_capacity()? * 2)?;
+        assert_eq!(reader.pipe_capacity()?, capacity);
+        assert!(capacity >= 2 * 4096);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipe_pool() -> io::Result<()> {
+        let pool = PipePool::with_preallocated(1, 2)?;
+        assert_eq!(pool.idle(), 1);
+        let a = pool.get()?;
+        let b = pool.get()?;
+        assert_eq!(pool.idle(), 0);
+        drop(a);
+        assert_eq!(pool.idle(), 1);
+        // a pipe with leftover data is discarded
+        (&b.writer).write_all(b"leftover")?;
+        drop(b);
+        assert_eq!(pool.idle(), 1);
+        Ok(())
+    }
+}
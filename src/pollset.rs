@@ -0,0 +1,134 @@
+//! Waiting on several files at once, as with `poll(2)`.
+
+use crate::{File, ReadyGuard};
+use std::future;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+/// A set of files to wait on together, for porting `poll(2)` loops over
+/// many serial ports or FIFOs without spawning a task per file.
+///
+/// ```ignore
+/// let mut set = PollSet::new();
+/// for port in &ports {
+///     set.add(port, Interest::READABLE);
+/// }
+/// loop {
+///     let (i, mut guard) = set.ready().await?;
+///     match ports[i].try_read(&mut buf) {
+///         Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+///         r => handle(i, r?),
+///     }
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct PollSet<'a> {
+    entries: Vec<(Option<&'a AsyncFd<RawFd>>, Interest)>,
+    /// Where the next search starts, so that a busy file cannot starve the
+    /// ones after it.
+    next: usize,
+}
+
+impl<'a> PollSet<'a> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `file` to the set, to be waited on for `interest`, returning
+    /// the index that identifies it in the results of
+    /// [`ready`](#method.ready).  Files that cannot be polled, such as
+    /// regular files, are always ready.
+    pub fn add<F>(&mut self, file: &'a File<F>, interest: Interest) -> usize {
+        self.entries.push((file.evented.as_ref(), interest));
+        self.entries.len() - 1
+    }
+
+    /// The number of files in the set.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Waits until one of the files is ready, returning its index and a
+    /// guard as [`File::ready`](struct.File.html#method.ready) does.  Clear
+    /// the guard if the operation that follows would block, or the same
+    /// file is reported again right away.
+    ///
+    /// When several files are ready, they take turns.  An empty set never
+    /// becomes ready.
+    pub async fn ready(&mut self) -> io::Result<(usize, ReadyGuard<'a>)> {
+        future::poll_fn(|cx| self.poll_ready(cx)).await
+    }
+
+    /// Polls for one of the files to become ready.  See
+    /// [`ready`](#method.ready).
+    pub fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<(usize, ReadyGuard<'a>)>> {
+        let len = self.entries.len();
+        for k in 0..len {
+            let i = (self.next + k) % len;
+            let (fd, interest) = self.entries[i];
+            let fd = match fd {
+                None => {
+                    self.next = i + 1;
+                    return Poll::Ready(Ok((i, ReadyGuard(None))));
+                }
+                Some(fd) => fd,
+            };
+            // polling registers the waker even if the file is not ready
+            if interest.is_readable() {
+                if let Poll::Ready(r) = fd.poll_read_ready(cx) {
+                    self.next = i + 1;
+                    return Poll::Ready(r.map(|guard| (i, ReadyGuard(Some(guard)))));
+                }
+            }
+            if interest.is_writable() {
+                if let Poll::Ready(r) = fd.poll_write_ready(cx) {
+                    self.next = i + 1;
+                    return Poll::Ready(r.map(|guard| (i, ReadyGuard(Some(guard)))));
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_poll_set() -> io::Result<()> {
+        let (reader0, _writer0) = crate::pipe()?;
+        let (reader1, mut writer1) = crate::pipe()?;
+        let (reader0, reader1) = (reader0.into_inner(), reader1.into_inner());
+        let mut set = PollSet::new();
+        assert_eq!(set.add(&reader0, Interest::READABLE), 0);
+        assert_eq!(set.add(&reader1, Interest::READABLE), 1);
+
+        writer1.write_all(b"one").await?;
+        let (i, mut guard) = set.ready().await?;
+        assert_eq!(i, 1);
+        assert!(guard.ready().is_readable());
+        let mut buf = [0; 8];
+        assert_eq!(reader1.try_read(&mut buf)?, 3);
+        let err = reader1.try_read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        guard.clear_ready();
+
+        let wait = std::time::Duration::from_millis(20);
+        assert!(tokio::time::timeout(wait, set.ready()).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Positional reads and writes (`pread(2)` and `pwritev2(2)`).
+
+use crate::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::task::Poll;
+use std::{future, io};
+
+flags! {
+    /// Flags for a single [`File::write_at`](struct.File.html#method.write_at),
+    /// combined with `|`.
+    pub struct WriteFlags(libc::c_int);
+
+    /// No flags.
+    const NONE = 0;
+    /// Make the data durable before returning, as if the file were opened
+    /// with `O_DSYNC` (`RWF_DSYNC`).
+    const DSYNC = libc::RWF_DSYNC;
+    /// Make the data and all metadata durable before returning, as if the
+    /// file were opened with `O_SYNC` (`RWF_SYNC`).
+    const SYNC = libc::RWF_SYNC;
+    /// Poll for completion instead of waiting for an interrupt, which lowers
+    /// latency on fast devices opened with `O_DIRECT` (`RWF_HIPRI`).
+    const HIPRI = libc::RWF_HIPRI;
+}
+
+fn pwritev2(fd: RawFd, buf: &[u8], offset: u64, flags: WriteFlags) -> io::Result<usize> {
+    let iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let n = unsafe { libc::pwritev2(fd, &iov, 1, offset as libc::off_t, flags.0) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+fn pread(fd: RawFd, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let n = unsafe {
+        libc::pread(
+            fd,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            offset as libc::off_t,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Reads into `buf` from `offset` without moving the file offset,
+    /// returning the number of bytes read, which is 0 at EOF.
+    ///
+    /// Since there is no shared cursor, any number of these can run
+    /// concurrently at different offsets.  With
+    /// `RegularFileMode::ThreadPool`, the read runs on the blocking thread
+    /// pool.  Like other reads, it counts toward the read quota, if any.
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let limit = future::poll_fn(|cx| self.poll_read_allowance(cx)).await?;
+        let len = buf.len().min(limit);
+        let buf = &mut buf[..len];
+        let r = if self.blocking.is_some() {
+            // own the descriptor and buffer, since the read outlives the
+            // future if it is cancelled
+            let file = unsafe { crate::dupe_file_from_fd(self.as_raw_fd())? };
+            let mut data = vec![0; buf.len()];
+            let (r, data) = tokio::task::spawn_blocking(move || {
+                let r = pread(file.as_raw_fd(), &mut data, offset);
+                (r, data)
+            })
+            .await
+            .map_err(io::Error::other)?;
+            if let Ok(n) = r {
+                buf[..n].copy_from_slice(&data[..n]);
+            }
+            r
+        } else {
+            future::poll_fn(|cx| loop {
+                let mut ready = ready!(self.poll_read_ready(cx))?;
+                match pread(self.as_raw_fd(), buf, offset) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => ready.clear_ready(),
+                    r => return Poll::Ready(r),
+                }
+            })
+            .await
+        };
+        self.report_read(&r, &buf[..*r.as_ref().unwrap_or(&0)]);
+        r
+    }
+
+    /// Writes `buf` at `offset` without moving the file offset, returning
+    /// the number of bytes written.  Like `write`, this may write only part
+    /// of the data.
+    ///
+    /// `flags` apply to this write only, so a database can make its critical
+    /// writes durable with `WriteFlags::DSYNC` without opening the whole file
+    /// with `O_DSYNC`.
+    ///
+    /// With `RegularFileMode::ThreadPool`, pending writes are flushed first
+    /// and the write itself runs on the blocking thread pool.
+    ///
+    /// Only available on Linux 4.7 and later (4.13 for `RWF_DSYNC` and
+    /// `RWF_SYNC`).
+    pub async fn write_at(&self, buf: &[u8], offset: u64, flags: WriteFlags) -> io::Result<usize> {
+        let r = if let Some(ref blocking) = self.blocking {
+            future::poll_fn(|cx| blocking.poll_flush(cx, false)).await?;
+            // own the descriptor and data, since the write outlives the
+            // future if it is cancelled
+            let file = unsafe { crate::dupe_file_from_fd(self.as_raw_fd())? };
+            let data = buf.to_vec();
+            tokio::task::spawn_blocking(move || pwritev2(file.as_raw_fd(), &data, offset, flags))
+                .await
+                .map_err(io::Error::other)?
+        } else {
+            future::poll_fn(|cx| loop {
+                let mut ready = ready!(self.poll_write_ready(cx))?;
+                match pwritev2(self.as_raw_fd(), buf, offset, flags) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => ready.clear_ready(),
+                    r => return Poll::Ready(r),
+                }
+            })
+            .await
+        };
+        self.report_write(&r, buf);
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+    use crate::RegularFileMode;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[tokio::test]
+    async fn test_read_write_at() -> io::Result<()> {
+        for &mode in &[RegularFileMode::Inline, RegularFileMode::ThreadPool] {
+            let raw = tempfile("write-at")?;
+            let file = File::new_nb_with(raw, mode)?;
+            assert_eq!(file.write_at(b"world", 6, WriteFlags::DSYNC).await?, 5);
+            assert_eq!(file.write_at(b"hello ", 0, WriteFlags::NONE).await?, 6);
+            let mut raw = file.into_inner();
+            assert_eq!(raw.stream_position()?, 0);
+            raw.seek(SeekFrom::Start(0))?;
+            let mut contents = String::new();
+            raw.read_to_string(&mut contents)?;
+            assert_eq!(contents, "hello world");
+            let file = File::new_nb_with(raw, mode)?;
+            let mut buf = [0; 5];
+            let (a, b) = tokio::join!(file.read_at(&mut buf, 6), async {
+                let mut buf = [0; 16];
+                let n = file.read_at(&mut buf, 0).await?;
+                Ok::<_, io::Error>(buf[..n].to_vec())
+            });
+            assert_eq!(a?, 5);
+            assert_eq!(&buf, b"world");
+            assert_eq!(b?, b"hello world");
+            assert_eq!(file.read_at(&mut buf, 11).await?, 0);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! Watching files in procfs and sysfs for changes.
+
+use crate::{File, FileBuilder};
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::future::Future;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{fmt, fs, io};
+use tokio::io::Interest;
+
+type Changed = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
+/// The lines that changed between two readings of a watched file, yielded by
+/// [`ProcWatcher`](struct.ProcWatcher.html).
+///
+/// Lines are compared as a multiset, so a line that merely moved is not
+/// reported, which suits files such as `/proc/self/mounts`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProcDiff {
+    /// The lines that are new, in the order they now appear.
+    pub added: Vec<String>,
+    /// The lines that are gone, in the order they appeared.
+    pub removed: Vec<String>,
+}
+
+impl ProcDiff {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A `Stream` of the changes to a file in procfs or sysfs that signals them
+/// through exceptional readiness, such as `/proc/self/mounts`,
+/// `/proc/self/mountinfo`, `/proc/swaps`, or a sysfs attribute that the
+/// driver notifies, like the `value` of a GPIO with an edge configured.
+///
+/// Such files cannot be watched with inotify, since their contents are
+/// generated on each read rather than written.  Instead, `poll` reports
+/// `POLLPRI` and `POLLERR` on them after a change, and the file has to be
+/// read again from the start to clear it.
+///
+/// Only available on Linux and Android.
+pub struct ProcWatcher {
+    path: PathBuf,
+    file: Arc<File<fs::File>>,
+    contents: String,
+    changed: Option<Changed>,
+}
+
+impl ProcWatcher {
+    /// Opens the file at `path`, reads its contents, and registers it with
+    /// the reactor for priority and error readiness.
+    ///
+    /// Fails with `Unsupported` if the kernel does not allow the file to be
+    /// polled, as for ordinary regular files, which never signal changes.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = FileBuilder::new()
+            .interest(Interest::READABLE | Interest::PRIORITY | Interest::ERROR)
+            .allow_fallback(false)
+            .build(fs::File::open(&path)?)?;
+        let contents = read_contents(file.get_ref())?;
+        Ok(ProcWatcher {
+            path,
+            file: Arc::new(file),
+            contents,
+            changed: None,
+        })
+    }
+
+    /// The path being watched.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The contents of the file as of the last change yielded, or as opened.
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+}
+
+impl fmt::Debug for ProcWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcWatcher")
+            .field("path", &self.path)
+            .field("file", &self.file)
+            .finish()
+    }
+}
+
+/// Waits for the file to signal a change, consuming the readiness.
+async fn changed(file: Arc<File<fs::File>>) -> io::Result<()> {
+    let mut guard = file.ready(Interest::PRIORITY | Interest::ERROR).await?;
+    // cleared before reading, so that a change during the read is not lost
+    guard.clear_ready();
+    Ok(())
+}
+
+/// Reads the whole file from the start, which also acknowledges the change.
+fn read_contents(file: &fs::File) -> io::Result<String> {
+    let mut contents = Vec::new();
+    let mut chunk = vec![0; 4096];
+    loop {
+        match file.read_at(&mut chunk, contents.len() as u64) {
+            Ok(0) => break,
+            Ok(n) => contents.extend_from_slice(&chunk[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    String::from_utf8(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The lines of `new` that are not in `old` and vice versa, counting
+/// repeated lines.
+fn diff(old: &str, new: &str) -> ProcDiff {
+    fn missing(from: &str, other: &str) -> Vec<String> {
+        let mut counts = HashMap::new();
+        for line in other.lines() {
+            *counts.entry(line).or_insert(0usize) += 1;
+        }
+        from.lines()
+            .filter(|line| match counts.get_mut(line) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            })
+            .map(str::to_owned)
+            .collect()
+    }
+    ProcDiff {
+        added: missing(new, old),
+        removed: missing(old, new),
+    }
+}
+
+impl Stream for ProcWatcher {
+    type Item = io::Result<ProcDiff>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let file = &this.file;
+            let changed = this
+                .changed
+                .get_or_insert_with(|| Box::pin(changed(file.clone())));
+            let result = ready!(changed.as_mut().poll(cx));
+            this.changed = None;
+            if let Err(e) = result {
+                return Poll::Ready(Some(Err(e)));
+            }
+            let contents = match read_contents(this.file.get_ref()) {
+                Ok(contents) => contents,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            };
+            let diff = diff(&this.contents, &contents);
+            this.contents = contents;
+            // a change may have been undone before it was read
+            if !diff.is_empty() {
+                return Poll::Ready(Some(Ok(diff)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_proc_watcher() -> io::Result<()> {
+        let mut watcher = ProcWatcher::open("/proc/self/mounts")?;
+        assert!(watcher.contents().contains(" / "));
+        // nothing is mounted or unmounted in the meantime
+        let next = tokio::time::timeout(Duration::from_millis(50), watcher.next()).await;
+        assert!(next.is_err());
+
+        let path = TempPath::new("procwatch");
+        fs::write(&path, b"ordinary\n")?;
+        let e = ProcWatcher::open(&path).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff() {
+        let diff = diff("a\nb\nb\nc\n", "c\nb\nd\na\n");
+        assert_eq!(diff.added, ["d"]);
+        assert_eq!(diff.removed, ["b"]);
+        assert!(super::diff("a\nb\n", "b\na\n").is_empty());
+    }
+}
@@ -0,0 +1,237 @@
+//! Pseudoterminals.
+//!
+//! A pseudoterminal is a pair of connected devices: the slave behaves like
+//! an ordinary terminal and is handed to an interactive program, while the
+//! master is driven by this process to see the output of the program and
+//! type into it.
+
+use crate::File;
+use std::ffi::{CStr, CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fs, io};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn ptsname(fd: RawFd) -> io::Result<PathBuf> {
+    let mut buf = [0; 128];
+    let r = unsafe { libc::ptsname_r(fd, buf.as_mut_ptr(), buf.len()) };
+    if r != 0 {
+        return Err(io::Error::from_raw_os_error(r));
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Ok(PathBuf::from(OsStr::from_bytes(name.to_bytes())))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn ptsname(fd: RawFd) -> io::Result<PathBuf> {
+    // not thread-safe, but ptsname_r is not available everywhere
+    let name = unsafe { libc::ptsname(fd) };
+    if name.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let name = unsafe { CStr::from_ptr(name) };
+    Ok(PathBuf::from(OsStr::from_bytes(name.to_bytes())))
+}
+
+fn open_slave(path: &Path, flags: libc::c_int) -> io::Result<fs::File> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let fd = unsafe { libc::open(path.as_ptr(), flags | libc::O_NOCTTY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)
+}
+
+/// Allocates a pseudoterminal, returning its master and slave wrapped for
+/// use with Tokio.
+///
+/// Both ends are nonblocking and close-on-exec, and neither becomes the
+/// controlling terminal of this process.  To run a program on the slave,
+/// give it the descriptors from
+/// [`PtySlave::open_blocking`](struct.PtySlave.html#method.open_blocking).
+///
+/// This must be called within the context of a Tokio runtime.
+///
+/// Implementation detail: uses `posix_openpt`, `grantpt`, and `unlockpt`.
+pub fn open() -> io::Result<(PtyMaster, PtySlave)> {
+    let master = unsafe {
+        libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC)
+    };
+    if master < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { fs::File::from_raw_fd(master) };
+    if unsafe { libc::grantpt(master.as_raw_fd()) } < 0
+        || unsafe { libc::unlockpt(master.as_raw_fd()) } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    let master = crate::move_fd_above(master, crate::STDIO_END)?;
+    let path = ptsname(master.as_raw_fd())?;
+    let slave = open_slave(&path, libc::O_RDWR | libc::O_NONBLOCK)?;
+    Ok((
+        PtyMaster {
+            file: File::raw_new(master)?,
+            slave_path: path.clone(),
+        },
+        PtySlave {
+            file: File::raw_new(slave)?,
+            path,
+        },
+    ))
+}
+
+/// The master end of a pseudoterminal, created by [`open`](fn.open.html).
+///
+/// Reading yields the output of the program on the slave and writing types
+/// into it.  Once every descriptor of the slave has been closed, reads fail
+/// with `EIO` on Linux rather than reporting EOF.
+#[derive(Debug)]
+pub struct PtyMaster {
+    file: File<fs::File>,
+    slave_path: PathBuf,
+}
+
+/// The slave end of a pseudoterminal, created by [`open`](fn.open.html).
+#[derive(Debug)]
+pub struct PtySlave {
+    file: File<fs::File>,
+    path: PathBuf,
+}
+
+impl PtyMaster {
+    /// The path of the slave device, such as `/dev/pts/3`.
+    pub fn slave_path(&self) -> &Path {
+        &self.slave_path
+    }
+
+    /// Returns the wrapped file.
+    pub fn into_inner(self) -> File<fs::File> {
+        self.file
+    }
+}
+
+impl PtySlave {
+    /// The path of the device, such as `/dev/pts/3`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the slave again in blocking mode, for use as the standard
+    /// streams of a child process.
+    ///
+    /// The nonblocking flag is shared by all duplicates of a descriptor, so
+    /// the slave itself must not be handed to a child, which would not
+    /// expect it to be nonblocking.
+    pub fn open_blocking(&self) -> io::Result<fs::File> {
+        open_slave(&self.path, libc::O_RDWR)
+    }
+
+    /// Returns the wrapped file.
+    pub fn into_inner(self) -> File<fs::File> {
+        self.file
+    }
+}
+
+macro_rules! impl_pty_io {
+    ($ty:ty) => {
+        impl AsRef<File<fs::File>> for $ty {
+            fn as_ref(&self) -> &File<fs::File> {
+                &self.file
+            }
+        }
+
+        impl AsRawFd for $ty {
+            fn as_raw_fd(&self) -> RawFd {
+                self.file.as_raw_fd()
+            }
+        }
+
+        impl AsFd for $ty {
+            fn as_fd(&self) -> BorrowedFd<'_> {
+                self.file.as_fd()
+            }
+        }
+
+        impl AsyncRead for $ty {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.file).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for $ty {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.file).poll_write(cx, buf)
+            }
+
+            fn poll_write_vectored(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[io::IoSlice<'_>],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.file).poll_write_vectored(cx, bufs)
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.file.is_write_vectored()
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.file).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.file).poll_shutdown(cx)
+            }
+        }
+    };
+}
+
+impl_pty_io!(PtyMaster);
+impl_pty_io!(PtySlave);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_pty() -> io::Result<()> {
+        let (mut master, mut slave) = open()?;
+        assert_eq!(master.slave_path(), slave.path());
+        let _raw = crate::terminal::set_raw_mode(&slave)?;
+        master.write_all(b"typed").await?;
+        let mut buf = [0; 5];
+        slave.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"typed");
+
+        let tty = slave.open_blocking()?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg("printf hello")
+            .stdin(Stdio::null())
+            .stdout(tty)
+            .spawn()?;
+        let mut buf = [0; 5];
+        master.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello");
+        child.wait()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+//! Limiting how much may be read from a wrapped file.
+
+use crate::File;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{error, fmt, io};
+use tokio::io::ReadBuf;
+use tokio::time::{Instant, Sleep};
+
+/// What happens to reads once the quota set with
+/// [`File::set_read_quota`](struct.File.html#method.set_read_quota) is used
+/// up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaPolicy {
+    /// Asynchronous reads wait until the next window begins.  Synchronous
+    /// reads fail with `WouldBlock` in the meantime.
+    Pause,
+    /// Reads fail with a [`QuotaExceeded`](struct.QuotaExceeded.html) error
+    /// until the next window begins.
+    Error,
+}
+
+/// The payload of the `io::Error` returned by reads when the read quota is
+/// used up under `QuotaPolicy::Error`.  Its kind is `Other`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    /// The number of bytes allowed per window.
+    pub limit: u64,
+    /// The length of the window.
+    pub window: Duration,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "read quota of {} bytes per {:?} exceeded",
+            self.limit, self.window
+        )
+    }
+}
+
+impl error::Error for QuotaExceeded {}
+
+/// How much has been read from a file with a read quota, as returned by
+/// [`File::read_quota_stats`](struct.File.html#method.read_quota_stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuotaStats {
+    /// Bytes read since the quota was set.
+    pub total: u64,
+    /// Bytes read in the current window.
+    pub window_used: u64,
+    /// The number of windows in which the quota was used up.
+    pub windows_exceeded: u64,
+}
+
+#[derive(Debug)]
+struct State {
+    window_start: Instant,
+    stats: QuotaStats,
+    /// Whether the quota has been used up in the current window.
+    exceeded: bool,
+    /// Wakes a paused reader at the end of the window.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl State {
+    fn start_window(&mut self) {
+        self.stats.window_used = 0;
+        self.exceeded = false;
+        self.sleep = None;
+    }
+}
+
+/// A limit on the bytes read per window of time, counted in fixed windows
+/// that start when the quota is set.
+#[derive(Debug)]
+pub(crate) struct Quota {
+    limit: u64,
+    window: Duration,
+    policy: QuotaPolicy,
+    state: Mutex<State>,
+}
+
+impl Quota {
+    /// Returns how many bytes may be read right now.  If none, either fails
+    /// or, given a context, waits for the next window.
+    fn poll_allowance(&self, cx: Option<&mut Context<'_>>) -> Poll<io::Result<usize>> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if now >= state.window_start + self.window {
+            state.window_start = now;
+            state.start_window();
+        }
+        let remaining = self.limit.saturating_sub(state.stats.window_used);
+        if remaining > 0 {
+            return Poll::Ready(Ok(remaining.min(usize::MAX as u64) as usize));
+        }
+        if !state.exceeded {
+            state.exceeded = true;
+            state.stats.windows_exceeded += 1;
+        }
+        let cx = match (self.policy, cx) {
+            (QuotaPolicy::Error, _) => {
+                return Poll::Ready(Err(io::Error::other(QuotaExceeded {
+                    limit: self.limit,
+                    window: self.window,
+                })))
+            }
+            (QuotaPolicy::Pause, None) => {
+                return Poll::Ready(Err(io::ErrorKind::WouldBlock.into()))
+            }
+            (QuotaPolicy::Pause, Some(cx)) => cx,
+        };
+        let end = state.window_start + self.window;
+        let sleep = state
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(end)));
+        ready!(sleep.as_mut().poll(cx));
+        state.window_start = end;
+        state.start_window();
+        Poll::Ready(Ok(self.limit.min(usize::MAX as u64) as usize))
+    }
+
+    fn consume(&self, n: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.stats.total += n as u64;
+        state.stats.window_used += n as u64;
+    }
+
+    fn stats(&self) -> QuotaStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+/// Reads into at most `limit` bytes of `buf`.
+pub(crate) fn read_limited(
+    buf: &mut ReadBuf<'_>,
+    limit: usize,
+    read: impl FnOnce(&mut ReadBuf<'_>) -> Poll<io::Result<()>>,
+) -> Poll<io::Result<()>> {
+    if limit >= buf.remaining() {
+        return read(buf);
+    }
+    let mut limited = buf.take(limit);
+    ready!(read(&mut limited))?;
+    let n = limited.filled().len();
+    unsafe { buf.assume_init(n) };
+    buf.advance(n);
+    Poll::Ready(Ok(()))
+}
+
+impl<F> File<F> {
+    /// Limits reads from this file to `limit` bytes per `window`, replacing
+    /// any previous quota, for example to keep one client of a multi-tenant
+    /// daemon from hogging it.
+    ///
+    /// Windows are consecutive and fixed, starting now.  No single read
+    /// returns more than what is left of the quota in the current window;
+    /// once nothing is left, `policy` decides what happens until the next
+    /// window begins.
+    pub fn set_read_quota(&mut self, limit: u64, window: Duration, policy: QuotaPolicy) {
+        self.quota = Some(Quota {
+            limit,
+            window,
+            policy,
+            state: Mutex::new(State {
+                window_start: Instant::now(),
+                stats: QuotaStats::default(),
+                exceeded: false,
+                sleep: None,
+            }),
+        });
+    }
+
+    /// Removes the read quota, if any.
+    pub fn clear_read_quota(&mut self) {
+        self.quota = None;
+    }
+
+    /// How much has been read under the read quota, or `None` if there is
+    /// none.
+    pub fn read_quota_stats(&self) -> Option<QuotaStats> {
+        self.quota.as_ref().map(Quota::stats)
+    }
+
+    /// The number of bytes that the quota allows reading now, waiting for
+    /// the next window if needed and allowed by the policy.
+    pub(crate) fn poll_read_allowance(&self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        match self.quota {
+            None => Poll::Ready(Ok(usize::MAX)),
+            Some(ref quota) => quota.poll_allowance(Some(cx)),
+        }
+    }
+
+    /// Like `poll_read_allowance`, but fails with `WouldBlock` instead of
+    /// waiting.
+    pub(crate) fn read_allowance(&self) -> io::Result<usize> {
+        match self.quota {
+            None => Ok(usize::MAX),
+            Some(ref quota) => match quota.poll_allowance(None) {
+                Poll::Ready(r) => r,
+                Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+            },
+        }
+    }
+
+    /// Counts `n` bytes read against the quota.
+    pub(crate) fn consume_read_quota(&self, n: usize) {
+        if let Some(ref quota) = self.quota {
+            quota.consume(n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_read_quota() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        b.write_all(b"0123456789").await?;
+
+        a.set_read_quota(4, Duration::from_secs(1), QuotaPolicy::Error);
+        let mut buf = [0; 10];
+        assert_eq!(a.read(&mut buf).await?, 4);
+        let err = a.read(&mut buf).await.unwrap_err();
+        assert!(err.get_ref().unwrap().is::<QuotaExceeded>());
+        assert_eq!(
+            a.read_quota_stats(),
+            Some(QuotaStats {
+                total: 4,
+                window_used: 4,
+                windows_exceeded: 1,
+            })
+        );
+
+        let start = Instant::now();
+        a.set_read_quota(3, Duration::from_millis(100), QuotaPolicy::Pause);
+        let mut buf = [0; 6];
+        a.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"456789");
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(a.read_quota_stats().unwrap().total, 6);
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+//! Pluggable reactors, for using the crate outside of Tokio.
+//!
+//! [`File`](../struct.File.html) is registered with the Tokio reactor, but
+//! much of what the crate does to a descriptor needs no reactor at all:
+//! [`set_nonblocking`](../fn.set_nonblocking.html),
+//! [`move_fd_above`](../fn.move_fd_above.html),
+//! [`FdKind`](../enum.FdKind.html), and the
+//! [`terminal`](../terminal/index.html) module work on plain descriptors.
+//! This module connects that layer to a runtime through the small
+//! [`Reactor`](trait.Reactor.html) trait.  [`Evented`](struct.Evented.html)
+//! wraps a file like `File::new_nb` does, but waits for readiness through
+//! the reactor chosen by its type parameter:
+//!
+//!   - [`Tokio`](struct.Tokio.html), which is always available, and
+//!   - [`AsyncIo`](struct.AsyncIo.html), with the `async-io` feature, for
+//!     `smol` and other runtimes built on `async-io`.
+//!
+//! `Evented` implements Tokio's `AsyncRead` and `AsyncWrite`, as well as
+//! those of `futures-io` with the `futures-io` feature.
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A runtime that can wait for descriptors to become ready.
+pub trait Reactor {
+    /// A descriptor registered with the reactor, which is deregistered when
+    /// dropped.
+    type Source: Source + Unpin;
+
+    /// Registers `fd`, which must already be in nonblocking mode, for both
+    /// reading and writing.
+    fn register(fd: RawFd) -> io::Result<Self::Source>;
+}
+
+/// A descriptor registered with a [`Reactor`](trait.Reactor.html).
+pub trait Source {
+    /// Runs `op` until it no longer fails with `WouldBlock`, waiting for the
+    /// descriptor to become readable in between.
+    fn poll_read_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>>;
+
+    /// Like `poll_read_with`, but waits for the descriptor to become
+    /// writable.
+    fn poll_write_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>>;
+}
+
+/// The Tokio reactor.
+///
+/// Registration must happen within the context of a Tokio runtime.
+#[derive(Clone, Copy, Debug)]
+pub struct Tokio;
+
+impl Reactor for Tokio {
+    type Source = TokioSource;
+
+    fn register(fd: RawFd) -> io::Result<TokioSource> {
+        Ok(TokioSource(AsyncFd::new(fd)?))
+    }
+}
+
+/// A descriptor registered with the Tokio reactor.
+#[derive(Debug)]
+pub struct TokioSource(AsyncFd<RawFd>);
+
+impl Source for TokioSource {
+    fn poll_read_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready(cx))?;
+            if let Ok(r) = guard.try_io(|_| op()) {
+                return Poll::Ready(r);
+            }
+        }
+    }
+
+    fn poll_write_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready(cx))?;
+            if let Ok(r) = guard.try_io(|_| op()) {
+                return Poll::Ready(r);
+            }
+        }
+    }
+}
+
+/// The reactor of `async-io`, which drives `smol` among others.
+///
+/// Registration starts the `async-io` driver thread if no runtime is
+/// driving it already.
+#[cfg(feature = "async-io")]
+#[derive(Clone, Copy, Debug)]
+pub struct AsyncIo;
+
+#[cfg(feature = "async-io")]
+impl Reactor for AsyncIo {
+    type Source = AsyncIoSource;
+
+    fn register(fd: RawFd) -> io::Result<AsyncIoSource> {
+        Ok(AsyncIoSource(async_io::Async::new_nonblocking(Fd(fd))?))
+    }
+}
+
+/// A descriptor registered with the `async-io` reactor.
+#[cfg(feature = "async-io")]
+#[derive(Debug)]
+pub struct AsyncIoSource(async_io::Async<Fd>);
+
+/// Lends a descriptor to `async-io` without handing over ownership.
+#[cfg(feature = "async-io")]
+#[derive(Debug)]
+struct Fd(RawFd);
+
+#[cfg(feature = "async-io")]
+impl std::os::unix::io::AsFd for Fd {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        // the descriptor outlives the registration, see `Evented`
+        unsafe { std::os::unix::io::BorrowedFd::borrow_raw(self.0) }
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl Source for AsyncIoSource {
+    fn poll_read_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match op() {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                r => return Poll::Ready(r),
+            }
+            ready!(self.0.poll_readable(cx))?;
+        }
+    }
+
+    fn poll_write_with<T>(
+        &self,
+        cx: &mut Context<'_>,
+        mut op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match op() {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                r => return Poll::Ready(r),
+            }
+            ready!(self.0.poll_writable(cx))?;
+        }
+    }
+}
+
+/// A file registered with the reactor `R`.
+///
+/// This is a leaner counterpart of [`File`](../struct.File.html) that works
+/// with any [`Reactor`](trait.Reactor.html).  Only files that can be polled
+/// are supported: regular files and directories fail to register with
+/// `EPERM`.
+#[derive(Debug)]
+pub struct Evented<F, R: Reactor = Tokio> {
+    // must be dropped before `file` so that the descriptor is deregistered
+    // before it gets closed
+    source: R::Source,
+    file: F,
+}
+
+impl<F: AsRawFd, R: Reactor> Evented<F, R> {
+    /// Enables nonblocking mode on the file and registers it with `R`.
+    pub fn new_nb(file: F) -> io::Result<Self> {
+        crate::set_nonblocking_for("Evented::new_nb", file.as_raw_fd(), true)?;
+        Evented::raw_new(file)
+    }
+
+    /// Registers a file that is already in nonblocking mode with `R`.
+    pub fn raw_new(file: F) -> io::Result<Self> {
+        let source = R::register(file.as_raw_fd())?;
+        Ok(Evented { source, file })
+    }
+
+    /// Returns the registration of the file, for performing custom system
+    /// calls once it is ready.
+    pub fn source(&self) -> &R::Source {
+        &self.source
+    }
+
+    /// Gets a reference to the underlying file-like object.
+    pub fn get_ref(&self) -> &F {
+        &self.file
+    }
+
+    /// Deregisters the file from the reactor and returns the underlying
+    /// file-like object.  Nonblocking mode is left as is.
+    pub fn into_inner(self) -> F {
+        let Evented { source, file } = self;
+        drop(source);
+        file
+    }
+}
+
+impl<F: AsRawFd, R: Reactor> AsRawFd for Evented<F, R> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl<F: io::Read + Unpin, R: Reactor> AsyncRead for Evented<F, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let Evented { source, file } = self.get_mut();
+        let n = ready!(source.poll_read_with(cx, || file.read(buf.initialize_unfilled())))?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: io::Write + Unpin, R: Reactor> AsyncWrite for Evented<F, R> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let Evented { source, file } = self.get_mut();
+        source.poll_write_with(cx, || file.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Evented { source, file } = self.get_mut();
+        source.poll_write_with(cx, || file.flush())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Evented, Tokio};
+    use std::io;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_tokio_reactor() -> io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (a, b) = UnixStream::pair()?;
+        let mut a = Evented::<_, Tokio>::new_nb(a)?;
+        let mut b = Evented::<_, Tokio>::new_nb(b)?;
+        let reader = async {
+            let mut buf = [0; 5];
+            b.read_exact(&mut buf).await?;
+            Ok::<_, io::Error>(buf)
+        };
+        let (received, written) = tokio::join!(reader, a.write_all(b"tokio"));
+        written?;
+        assert_eq!(&received?, b"tokio");
+        Ok(())
+    }
+
+    #[cfg(feature = "async-io")]
+    #[test]
+    fn test_async_io_reactor() -> io::Result<()> {
+        use super::AsyncIo;
+        use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+        async_io::block_on(async {
+            let (a, b) = UnixStream::pair()?;
+            let mut a = Evented::<_, AsyncIo>::new_nb(a)?;
+            let mut b = Evented::<_, AsyncIo>::new_nb(b)?;
+            a.write_all(b"smol").await?;
+            a.close().await?;
+            drop(a);
+            let mut received = Vec::new();
+            b.read_to_end(&mut received).await?;
+            assert_eq!(received, b"smol");
+            Ok(())
+        })
+    }
+}
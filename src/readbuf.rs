@@ -0,0 +1,136 @@
+//! Reading into uninitialized memory.
+//!
+//! `AsyncRead` for a generic `File` has to zero the unfilled part of the
+//! buffer first, since it reads through `io::Read`, which takes `&mut [u8]`.
+//! Reading the descriptor directly avoids that, which adds up for large
+//! transfers into freshly allocated buffers.
+
+use crate::{check_stale, retry_eintr, File};
+use bytes::BufMut;
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fs, io};
+use tokio::io::{AsyncRead, ReadBuf};
+
+impl<F: AsRawFd> File<F> {
+    /// Attempts to read into the unfilled part of `buf` right away, like
+    /// [`try_read`](#method.try_read), returning the number of bytes read.
+    /// The memory is not zeroed first, so `buf` can start out
+    /// uninitialized.
+    ///
+    /// Implementation detail: uses `read` directly on the descriptor.
+    pub fn try_read_buf(&self, buf: &mut ReadBuf<'_>) -> io::Result<usize> {
+        let len = buf.remaining().min(self.read_allowance()?);
+        let filled = buf.filled().len();
+        if let Some(n) = self.pushback.take_buf(&mut buf.take(len)) {
+            // the data was copied into the first `n` bytes
+            unsafe { buf.assume_init(n) };
+            buf.advance(n);
+            self.report_read(&Ok(n), &buf.filled()[filled..]);
+            return Ok(n);
+        }
+        let fd = self.as_raw_fd();
+        let r = {
+            let dst = unsafe { &mut buf.unfilled_mut()[..len] };
+            retry_eintr(self.retry_interrupted, || {
+                let n = unsafe { libc::read(fd, dst.as_mut_ptr() as *mut libc::c_void, len) };
+                if n < 0 {
+                    check_stale(fd, self.identity, Err(io::Error::last_os_error()))
+                } else {
+                    Ok(n as usize)
+                }
+            })
+        };
+        if let Ok(n) = r {
+            // the kernel initialized the first `n` bytes
+            unsafe { buf.assume_init(n) };
+            buf.advance(n);
+        }
+        self.report_read(&r, &buf.filled()[filled..]);
+        r
+    }
+
+    /// Reads into the spare capacity of `buf`, such as a `BytesMut` or a
+    /// `Vec<u8>`, waiting for data if need be, and returns the number of
+    /// bytes read.  Reserve room first, as 0 means either EOF or no spare
+    /// capacity.
+    ///
+    /// Unlike `AsyncReadExt::read_buf`, this does not zero the spare
+    /// capacity before reading into it.  Like
+    /// [`try_read`](#method.try_read), it reads the descriptor directly,
+    /// even if the file is offloaded to the blocking thread pool.
+    pub async fn read_buf<B: BufMut>(&self, buf: &mut B) -> io::Result<usize> {
+        if !buf.has_remaining_mut() {
+            return Ok(0);
+        }
+        loop {
+            let mut guard = self.readable().await?;
+            let dst = buf.chunk_mut();
+            let dst = unsafe { dst.as_uninit_slice_mut() };
+            let mut read_buf = ReadBuf::uninit(dst);
+            match self.try_read_buf(&mut read_buf) {
+                Ok(n) => {
+                    // the first `n` bytes were initialized by the read
+                    unsafe { buf.advance_mut(n) };
+                    return Ok(n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl File<fs::File> {
+    /// Polls to read into `buf` without zeroing it first, for files whose
+    /// `io::Read` is known to be a plain `read` of the descriptor.
+    pub(crate) fn poll_read_uninit(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.evented.is_none() || buf.remaining() == 0 {
+            return Pin::new(&mut &*self).poll_read(cx, buf);
+        }
+        ready!(self.poll_read_allowance(cx))?;
+        let len = buf.remaining();
+        loop {
+            let mut guard = ready!(self.poll_read_ready(cx))?;
+            match self.try_read_buf(buf) {
+                Ok(n) => {
+                    if self.edge_triggered && n > 0 && n < len {
+                        guard.clear_ready();
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use std::io;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_read_buf() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let reader = reader.into_inner();
+        writer.write_all(b"uninitialized").await?;
+        let mut buf = BytesMut::with_capacity(64);
+        assert_eq!(reader.read_buf(&mut buf).await?, 13);
+        assert_eq!(&buf[..], b"uninitialized");
+        drop(writer);
+        assert_eq!(reader.read_buf(&mut buf).await?, 0);
+
+        // full buffers read nothing rather than waiting
+        let mut full = &mut [0u8; 0][..];
+        assert_eq!(reader.read_buf(&mut full).await?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,551 @@
+//! Splitting untrusted input into delimited records with bounded memory.
+
+use crate::adaptive::AdaptiveBuf;
+use crate::middleware::{self, FrameMiddleware, Middleware};
+use crate::smallbuf::SmallBuf;
+use crate::File;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{error, fmt, future, io, mem};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// What [`BoundedLines`](struct.BoundedLines.html) does with a record longer
+/// than the maximum length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overlong {
+    /// Yield the first `max_len` bytes of the record and discard the rest.
+    Truncate,
+    /// Yield a [`RecordTooLong`](struct.RecordTooLong.html) error as soon as
+    /// the record exceeds the limit, then discard the rest of it.
+    Error,
+    /// Discard the record silently.
+    Skip,
+}
+
+/// The error yielded for an overlong record under `Overlong::Error`, wrapped
+/// in an `io::Error` of kind `InvalidData`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordTooLong {
+    /// The maximum record length that was exceeded.
+    pub max_len: usize,
+}
+
+impl fmt::Display for RecordTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record longer than {} bytes", self.max_len)
+    }
+}
+
+impl error::Error for RecordTooLong {}
+
+impl From<RecordTooLong> for io::Error {
+    fn from(err: RecordTooLong) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Takes a completed record out of the splitter.
+fn take_vec(record: &mut Vec<u8>) -> Vec<u8> {
+    mem::take(record)
+}
+
+/// Copies a completed record out of the splitter, which then reuses its
+/// allocation for the next record, unless the record does not fit inline.
+fn take_small<const N: usize>(record: &mut Vec<u8>) -> SmallBuf<N> {
+    if record.len() > N {
+        return SmallBuf::from(mem::take(record));
+    }
+    let small = SmallBuf::from(&record[..]);
+    record.clear();
+    small
+}
+
+/// The state machine that splits input into records, independent of where
+/// the input comes from.
+#[derive(Debug)]
+struct Splitter {
+    delimiter: u8,
+    max_len: usize,
+    overlong: Overlong,
+    record: Vec<u8>,
+    /// Whether the current record has exceeded `max_len`.
+    exceeded: bool,
+}
+
+impl Splitter {
+    /// Consumes a prefix of `input`, returning its length and the record or
+    /// error it completed, if any, with the record taken out by
+    /// `take_record`.  Consumes at least one byte of nonempty input.
+    fn split<T>(
+        &mut self,
+        input: &[u8],
+        take_record: impl FnOnce(&mut Vec<u8>) -> T,
+    ) -> (usize, Option<Result<T, RecordTooLong>>) {
+        let end = input.iter().position(|&b| b == self.delimiter);
+        let take = end.unwrap_or(input.len());
+        let consumed = end.map_or(take, |i| i + 1);
+        let mut result = None;
+        if !self.exceeded {
+            let room = self.max_len - self.record.len();
+            if take <= room {
+                self.record.extend_from_slice(&input[..take]);
+            } else {
+                self.exceeded = true;
+                match self.overlong {
+                    Overlong::Truncate => self.record.extend_from_slice(&input[..room]),
+                    Overlong::Error => {
+                        self.record.clear();
+                        result = Some(Err(RecordTooLong {
+                            max_len: self.max_len,
+                        }));
+                    }
+                    Overlong::Skip => self.record.clear(),
+                }
+            }
+        }
+        if end.is_some() {
+            let exceeded = mem::replace(&mut self.exceeded, false);
+            if !exceeded || self.overlong == Overlong::Truncate {
+                result = Some(Ok(take_record(&mut self.record)));
+            }
+        }
+        (consumed, result)
+    }
+
+    /// Returns the unterminated record at EOF, if any.
+    fn finish<T>(&mut self, take: impl FnOnce(&mut Vec<u8>) -> T) -> Option<T> {
+        let exceeded = mem::replace(&mut self.exceeded, false);
+        if exceeded && self.overlong != Overlong::Truncate {
+            return None;
+        }
+        if self.record.is_empty() && !exceeded {
+            return None;
+        }
+        Some(take(&mut self.record))
+    }
+}
+
+/// A stream of the records in `reader` separated by a delimiter byte, which
+/// never buffers more than a fixed amount of memory no matter what the input
+/// looks like.
+///
+/// Records are yielded without the delimiter.  A final record that is not
+/// followed by a delimiter is yielded at EOF.  No record longer than
+/// `max_len` bytes is ever held in memory; what happens to longer ones is
+/// chosen with [`overlong`](#method.overlong).  After an overlong record, the
+/// stream resynchronizes at the next delimiter, so it can keep being polled
+/// even after yielding a `RecordTooLong` error.
+///
+/// This is meant for reading untrusted data, such as from a world-writable
+/// FIFO, where `AsyncBufReadExt::lines` could be made to buffer without
+/// bound.
+///
+/// ```
+/// # async fn run() -> std::io::Result<()> {
+/// use futures::StreamExt;
+/// use tokio_file_unix::{BoundedLines, Overlong};
+///
+/// let reader = tokio_file_unix::fifo::open_read("/run/myservice.fifo").await?;
+/// let mut lines = BoundedLines::new(reader, 4096).overlong(Overlong::Skip);
+/// while let Some(line) = lines.next().await {
+///     println!("{}", String::from_utf8_lossy(&line?));
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct BoundedLines<R> {
+    reader: R,
+    splitter: Splitter,
+    chunk: AdaptiveBuf,
+    pos: usize,
+    end: usize,
+    eof: bool,
+    middleware: Option<Middleware>,
+}
+
+impl<R> BoundedLines<R> {
+    /// Splits `reader` into lines of at most `max_len` bytes, excluding the
+    /// newline.  Overlong lines are reported as errors by default.
+    pub fn new(reader: R, max_len: usize) -> Self {
+        BoundedLines {
+            reader,
+            splitter: Splitter {
+                delimiter: b'\n',
+                max_len,
+                overlong: Overlong::Error,
+                record: Vec::new(),
+                exceeded: false,
+            },
+            chunk: AdaptiveBuf::new(512, 8 * 1024),
+            pos: 0,
+            end: 0,
+            eof: false,
+            middleware: None,
+        }
+    }
+
+    /// Sets the byte that ends each record.  Defaults to `b'\n'`.
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.splitter.delimiter = delimiter;
+        self
+    }
+
+    /// Sets what to do with records longer than the maximum length.
+    /// Defaults to `Overlong::Error`.
+    pub fn overlong(mut self, overlong: Overlong) -> Self {
+        self.splitter.overlong = overlong;
+        self
+    }
+
+    /// Passes every record through `middleware` before yielding it.
+    /// Overlong records are handled before that, so the middleware never
+    /// sees more than `max_len` bytes.
+    pub fn middleware<M: FrameMiddleware>(mut self, middleware: M) -> Self {
+        self.middleware = Some(Middleware::new(middleware));
+        self
+    }
+
+    /// The byte that ends each record.
+    pub(crate) fn delimiter_byte(&self) -> u8 {
+        self.splitter.delimiter
+    }
+
+    /// Returns the underlying reader.  Any input read but not yet split is
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin> BoundedLines<R> {
+    /// Receives the next record, like `StreamExt::next`, but in a
+    /// [`SmallBuf`](struct.SmallBuf.html) that holds records of up to `N`
+    /// bytes inline.
+    ///
+    /// Short records then cost no allocation, since the buffer the record
+    /// is assembled in is kept for the next one.
+    ///
+    /// ```
+    /// # async fn run() -> std::io::Result<()> {
+    /// use tokio_file_unix::BoundedLines;
+    ///
+    /// let reader = tokio_file_unix::fifo::open_read("/run/myservice.fifo").await?;
+    /// let mut lines = BoundedLines::new(reader, 4096);
+    /// while let Some(line) = lines.next_small::<64>().await {
+    ///     println!("{}", String::from_utf8_lossy(&line?));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn next_small<const N: usize>(&mut self) -> Option<io::Result<SmallBuf<N>>> {
+        future::poll_fn(|cx| self.poll_next_small(cx)).await
+    }
+
+    /// Polls for the next record in a [`SmallBuf`](struct.SmallBuf.html).
+    pub fn poll_next_small<const N: usize>(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<SmallBuf<N>>>> {
+        self.poll_record(cx, take_small::<N>)
+    }
+
+    fn poll_record<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        take: fn(&mut Vec<u8>) -> T,
+    ) -> Poll<Option<io::Result<T>>> {
+        let middleware = &mut self.middleware;
+        let mut decode = |record: &mut Vec<u8>| match middleware::decode(middleware, record) {
+            Ok(()) => Ok(take(record)),
+            Err(e) => {
+                record.clear();
+                Err(e)
+            }
+        };
+        loop {
+            while self.pos < self.end {
+                let (n, result) = self
+                    .splitter
+                    .split(&self.chunk[self.pos..self.end], &mut decode);
+                self.pos += n;
+                if let Some(result) = result {
+                    return Poll::Ready(Some(result.map_err(io::Error::from).and_then(|r| r)));
+                }
+            }
+            if self.eof {
+                return Poll::Ready(None);
+            }
+            self.chunk.prepare(None);
+            let mut buf = ReadBuf::new(&mut self.chunk);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
+            self.pos = 0;
+            self.end = buf.filled().len();
+            self.chunk.record(self.end);
+            if self.end == 0 {
+                self.eof = true;
+                return Poll::Ready(self.splitter.finish(decode));
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for BoundedLines<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_record(cx, take_vec)
+    }
+}
+
+impl<F> File<F> {
+    /// Returns a stream of the lines of the file as strings, so that reading
+    /// standard input line by line needs no codec.
+    ///
+    /// Lines are split as by [`BoundedLines`](struct.BoundedLines.html), with
+    /// overlong lines reported as errors by default, and a trailing carriage
+    /// return is removed.  A line that is not valid UTF-8 fails with
+    /// `InvalidData`; see [`lines_lossy`](#method.lines_lossy) to replace the
+    /// invalid parts instead.
+    ///
+    /// ```
+    /// # async fn run() -> std::io::Result<()> {
+    /// use futures::StreamExt;
+    ///
+    /// let stdin = tokio_file_unix::File::new_nb(tokio_file_unix::raw_stdin()?)?;
+    /// let mut lines = stdin.lines(64 * 1024);
+    /// while let Some(line) = lines.next().await {
+    ///     println!("Got: {}", line?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lines(self, max_len: usize) -> Lines<Self> {
+        Lines {
+            inner: BoundedLines::new(self, max_len),
+            lossy: false,
+        }
+    }
+
+    /// Like [`lines`](#method.lines), but replaces invalid UTF-8 with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    pub fn lines_lossy(self, max_len: usize) -> Lines<Self> {
+        Lines {
+            inner: BoundedLines::new(self, max_len),
+            lossy: true,
+        }
+    }
+}
+
+/// A stream of lines as strings, created by
+/// [`File::lines`](struct.File.html#method.lines) or
+/// [`File::lines_lossy`](struct.File.html#method.lines_lossy).
+#[derive(Debug)]
+pub struct Lines<R> {
+    inner: BoundedLines<R>,
+    lossy: bool,
+}
+
+impl<R> Lines<R> {
+    /// Sets what to do with lines longer than the maximum length.  Defaults
+    /// to `Overlong::Error`.
+    pub fn overlong(mut self, overlong: Overlong) -> Self {
+        self.inner = self.inner.overlong(overlong);
+        self
+    }
+
+    /// Passes every line through `middleware` before it is decoded as
+    /// UTF-8.
+    pub fn middleware<M: FrameMiddleware>(mut self, middleware: M) -> Self {
+        self.inner = self.inner.middleware(middleware);
+        self
+    }
+
+    /// Returns the underlying reader.  Any input read but not yet split is
+    /// lost.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for Lines<R> {
+    type Item = io::Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut line = match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+            Some(line) => line?,
+            None => return Poll::Ready(None),
+        };
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Poll::Ready(Some(if self.lossy {
+            Ok(String::from_utf8_lossy(&line).into_owned())
+        } else {
+            String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn splitter(max_len: usize, overlong: Overlong) -> Splitter {
+        Splitter {
+            delimiter: b'\n',
+            max_len,
+            overlong,
+            record: Vec::new(),
+            exceeded: false,
+        }
+    }
+
+    /// Feeds `input` to the splitter in pieces of the given sizes.
+    fn split_in_pieces(
+        splitter: &mut Splitter,
+        input: &[u8],
+        sizes: &mut dyn Iterator<Item = usize>,
+    ) -> Vec<Result<Vec<u8>, RecordTooLong>> {
+        let mut results = Vec::new();
+        let mut rest = input;
+        while !rest.is_empty() {
+            let mut piece = &rest[..sizes.next().unwrap().min(rest.len()).max(1)];
+            rest = &rest[piece.len()..];
+            while !piece.is_empty() {
+                let (n, result) = splitter.split(piece, take_vec);
+                assert!(n > 0);
+                assert!(splitter.record.len() <= splitter.max_len);
+                piece = &piece[n..];
+                results.extend(result);
+            }
+        }
+        results.extend(splitter.finish(take_vec).map(Ok));
+        results
+    }
+
+    /// The straightforward, unbounded equivalent of the splitter.
+    fn reference(
+        input: &[u8],
+        max_len: usize,
+        overlong: Overlong,
+    ) -> Vec<Result<Vec<u8>, RecordTooLong>> {
+        let mut records: Vec<&[u8]> = input.split(|&b| b == b'\n').collect();
+        if records.last() == Some(&&b""[..]) {
+            records.pop();
+        }
+        records
+            .into_iter()
+            .filter_map(|record| {
+                if record.len() <= max_len {
+                    return Some(Ok(record.to_vec()));
+                }
+                match overlong {
+                    Overlong::Truncate => Some(Ok(record[..max_len].to_vec())),
+                    Overlong::Error => Some(Err(RecordTooLong { max_len })),
+                    Overlong::Skip => None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_splitter_random() {
+        // xorshift, so that failures are reproducible
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        for _ in 0..2000 {
+            let len = random() % 64;
+            let input: Vec<u8> = (0..len)
+                .map(|_| if random() % 4 == 0 { b'\n' } else { b'x' })
+                .collect();
+            let max_len = random() % 8;
+            for &overlong in &[Overlong::Truncate, Overlong::Error, Overlong::Skip] {
+                let mut sizes = std::iter::repeat_with(&mut random).map(|n| n % 10);
+                let mut splitter = splitter(max_len, overlong);
+                assert_eq!(
+                    split_in_pieces(&mut splitter, &input, &mut sizes),
+                    reference(&input, max_len, overlong),
+                    "input {:?}, max_len {}, {:?}",
+                    String::from_utf8_lossy(&input),
+                    max_len,
+                    overlong
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bounded_lines() -> io::Result<()> {
+        let input = &b"short\nmuch too long\n\nend"[..];
+        let mut lines = BoundedLines::new(input, 8);
+        assert_eq!(lines.next().await.unwrap()?, b"short");
+        let err = lines.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(lines.next().await.unwrap()?, b"");
+        assert_eq!(lines.next().await.unwrap()?, b"end");
+        assert!(lines.next().await.is_none());
+
+        let lines = BoundedLines::new(input, 8).overlong(Overlong::Truncate);
+        let lines: Vec<_> = lines.map(Result::unwrap).collect().await;
+        assert_eq!(lines, vec![&b"short"[..], b"much too", b"", b"end"]);
+
+        let lines = BoundedLines::new(&b"a\0bc\0"[..], 1)
+            .delimiter(0)
+            .overlong(Overlong::Skip);
+        let lines: Vec<_> = lines.map(Result::unwrap).collect().await;
+        assert_eq!(lines, vec![b"a"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_next_small() -> io::Result<()> {
+        let mut lines = BoundedLines::new(
+            &b"short
+much longer
+"[..],
+            16,
+        );
+        let line = lines.next_small::<8>().await.unwrap()?;
+        assert!(line.is_inline());
+        assert_eq!(&*line, b"short");
+        let line = lines.next_small::<8>().await.unwrap()?;
+        assert!(!line.is_inline());
+        assert_eq!(&*line, b"much longer");
+        assert!(lines.next_small::<8>().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_file_lines() -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let input = b"one\r\nbad \xff\ntoo long\nlast";
+        let (reader, mut writer) = crate::pipe()?;
+        writer.write_all(input).await?;
+        drop(writer);
+        let mut lines = reader.into_inner().lines(7);
+        assert_eq!(lines.next().await.unwrap()?, "one");
+        let err = lines.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = lines.next().await.unwrap().unwrap_err();
+        assert!(err.get_ref().unwrap().is::<RecordTooLong>());
+        assert_eq!(lines.next().await.unwrap()?, "last");
+        assert!(lines.next().await.is_none());
+
+        let (reader, mut writer) = crate::pipe()?;
+        writer.write_all(input).await?;
+        drop(writer);
+        let lines = reader.into_inner().lines_lossy(7).overlong(Overlong::Skip);
+        let lines: Vec<_> = lines.map(Result::unwrap).collect().await;
+        assert_eq!(lines, vec!["one", "bad \u{fffd}", "last"]);
+        Ok(())
+    }
+}
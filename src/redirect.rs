@@ -0,0 +1,110 @@
+//! Redirecting the standard output and error of the process.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Makes the standard output of the process (descriptor 1) refer to
+/// `file`, such as the writing end of a [`pipe`](fn.pipe.html), until the
+/// returned guard is dropped, so that an application can capture its own
+/// output, including that of `println!` and of C libraries.
+///
+/// The descriptor is replaced atomically with `dup2`, after flushing the
+/// buffer of `std::io::stdout`, and put back the same way.  Child processes
+/// spawned in the meantime inherit the redirected stream.
+///
+/// Nonblocking mode belongs to the open file rather than the descriptor, so
+/// if `file` is a nonblocking `File`, `println!` panics with `WouldBlock`
+/// once the pipe is full.  Pass a file in blocking mode, for example one
+/// unwrapped with [`File::into_blocking`](struct.File.html#method.into_blocking),
+/// to make such writes wait for the reader instead.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use futures::StreamExt;
+///
+/// let (reader, writer) = tokio_file_unix::pipe()?;
+/// let writer = writer.into_inner().into_blocking()?;
+/// let guard = tokio_file_unix::redirect_stdout_to(&writer)?;
+/// let mut lines = reader.into_inner().lines(4096);
+/// println!("captured");
+/// assert_eq!(lines.next().await.unwrap()?, "captured");
+/// drop(guard);
+/// # Ok(())
+/// # }
+/// ```
+pub fn redirect_stdout_to<F: AsRawFd + ?Sized>(file: &F) -> io::Result<Redirect> {
+    let _ = io::stdout().flush();
+    Redirect::new("redirect_stdout_to", libc::STDOUT_FILENO, file.as_raw_fd())
+}
+
+/// Makes the standard error of the process (descriptor 2) refer to `file`
+/// until the returned guard is dropped.  See
+/// [`redirect_stdout_to`](fn.redirect_stdout_to.html).
+pub fn redirect_stderr_to<F: AsRawFd + ?Sized>(file: &F) -> io::Result<Redirect> {
+    Redirect::new("redirect_stderr_to", libc::STDERR_FILENO, file.as_raw_fd())
+}
+
+/// A redirected standard stream, put back when dropped, created by
+/// [`redirect_stdout_to`](fn.redirect_stdout_to.html) or
+/// [`redirect_stderr_to`](fn.redirect_stderr_to.html).
+///
+/// Guards for the same stream must be dropped in the reverse order of their
+/// creation, as each puts back what it replaced.
+#[derive(Debug)]
+#[must_use = "the stream is put back as soon as the guard is dropped"]
+pub struct Redirect {
+    fd: RawFd,
+    /// A duplicate of what `fd` referred to before.
+    saved: fs::File,
+}
+
+impl Redirect {
+    fn new(name: &str, fd: RawFd, target: RawFd) -> io::Result<Self> {
+        let saved = unsafe { crate::dupe_file_from_fd(fd)? };
+        crate::replace_fd(fd, target)?;
+        audit!(name, fd, "redirected to fd {}", target);
+        Ok(Redirect { fd, saved })
+    }
+}
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        if self.fd == libc::STDOUT_FILENO {
+            let _ = io::stdout().flush();
+        }
+        if crate::replace_fd(self.fd, self.saved.as_raw_fd()).is_ok() {
+            audit!("Redirect::drop", self.fd, "put back");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_redirect() -> io::Result<()> {
+        // redirect a descriptor of our own rather than the real stderr,
+        // which the test harness and other tests write to concurrently
+        let stream = fs::File::open("/dev/null")?;
+        let fd = stream.as_raw_fd();
+        let before = crate::identity(fd)?;
+        let (reader, writer) = crate::pipe()?;
+        let guard = Redirect::new("test_redirect", fd, writer.as_raw_fd())?;
+        assert_eq!(crate::identity(fd)?, crate::identity(writer.as_raw_fd())?);
+        let fd_flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_ne!(fd_flags & libc::FD_CLOEXEC, 0);
+        let message = b"to the pipe\n";
+        let n = unsafe { libc::write(fd, message.as_ptr() as *const libc::c_void, message.len()) };
+        assert_eq!(n, message.len() as isize);
+        drop(guard);
+        drop(writer);
+        assert_eq!(crate::identity(fd)?, before);
+        let mut captured = Vec::new();
+        reader.into_inner().read_to_end(&mut captured).await?;
+        assert_eq!(captured, message);
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Timed re-polling for descriptors whose readiness cannot be trusted.
+
+use crate::RetryPolicy;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard};
+use std::task::Context;
+use tokio::io::Interest;
+use tokio::time::Sleep;
+
+/// The fallback set by
+/// [`FileBuilder::repoll_after`](struct.FileBuilder.html#method.repoll_after),
+/// kept separately for each direction.
+#[derive(Debug)]
+pub(crate) struct Repoll {
+    after: u32,
+    policy: RetryPolicy,
+    read: Mutex<State>,
+    write: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The number of times in a row that readiness was reported but the
+    /// operation would block anyway.
+    false_wakeups: u32,
+    /// The number of timed attempts so far, which sets the next delay.
+    attempt: u32,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Repoll {
+    pub(crate) fn new(after: u32, policy: RetryPolicy) -> Self {
+        Repoll {
+            after,
+            policy,
+            read: Mutex::default(),
+            write: Mutex::default(),
+        }
+    }
+
+    fn state(&self, interest: Interest) -> MutexGuard<'_, State> {
+        if interest.is_writable() {
+            self.write.lock().unwrap()
+        } else {
+            self.read.lock().unwrap()
+        }
+    }
+
+    /// Records that an operation would block although the file was
+    /// reported ready.
+    pub(crate) fn would_block(&self, interest: Interest) {
+        let mut state = self.state(interest);
+        state.false_wakeups = state.false_wakeups.saturating_add(1);
+    }
+
+    /// Records that an operation went through, which ends the re-polling.
+    pub(crate) fn done(&self, interest: Interest) {
+        *self.state(interest) = State::default();
+    }
+
+    /// Whether the operation is due to be tried again without waiting for
+    /// readiness.  If not, the task is woken once it is, provided that the
+    /// file has given enough false wakeups to be re-polled at all.
+    pub(crate) fn poll_due(&self, cx: &mut Context<'_>, interest: Interest) -> bool {
+        let mut state = self.state(interest);
+        if state.false_wakeups < self.after {
+            return false;
+        }
+        if state.sleep.is_none() {
+            state.attempt = state.attempt.saturating_add(1);
+            let delay = self.policy.backoff(state.attempt);
+            state.sleep = Some(Box::pin(tokio::time::sleep(delay)));
+        }
+        let sleep = state.sleep.as_mut().unwrap();
+        if sleep.as_mut().poll(cx).is_pending() {
+            return false;
+        }
+        state.sleep = None;
+        true
+    }
+}
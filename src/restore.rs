@@ -0,0 +1,70 @@
+//! Restoring the original state of a descriptor once it is no longer
+//! wrapped.
+
+use crate::{terminal, File};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// The file status and terminal settings of a descriptor at the time it was
+/// wrapped, put back when dropped.
+#[derive(Debug)]
+pub(crate) struct Restore {
+    fd: RawFd,
+    nonblocking: bool,
+    termios: Option<libc::termios>,
+}
+
+impl Restore {
+    fn save(fd: RawFd) -> io::Result<Self> {
+        Ok(Restore {
+            fd,
+            nonblocking: crate::get_nonblocking(&fd)?,
+            // not a terminal
+            termios: terminal::get_attr(fd).ok(),
+        })
+    }
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        if let Some(ref termios) = self.termios {
+            let _ = terminal::set_attr(self.fd, termios);
+        }
+        let _ = crate::set_nonblocking_for("File::new_nb_restoring", self.fd, self.nonblocking);
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Like `File::new_nb`, but restores the original nonblocking mode and,
+    /// for terminals, the original terminal settings once the `File` is
+    /// dropped or unwrapped with `into_inner`.  This also happens when the
+    /// `File` is dropped while unwinding from a panic.
+    ///
+    /// Nonblocking mode is shared with every other descriptor for the same
+    /// open file, including those of the parent process, so leaving a
+    /// standard stream in nonblocking mode can break the shell a program was
+    /// started from.
+    pub fn new_nb_restoring(file: F) -> io::Result<Self> {
+        let restore = Restore::save(file.as_raw_fd())?;
+        let mut file = File::new_nb(file)?;
+        file.restore = Some(restore);
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_new_nb_restoring() -> io::Result<()> {
+        let (sock, _peer) = UnixStream::pair()?;
+        let dup = sock.try_clone()?;
+        let file = File::new_nb_restoring(sock)?;
+        assert!(crate::get_nonblocking(&dup)?);
+        drop(file);
+        assert!(!crate::get_nonblocking(&dup)?);
+        Ok(())
+    }
+}
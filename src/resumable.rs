@@ -0,0 +1,355 @@
+//! Framed reading that picks up where a cancelled read left off.
+
+use crate::RecordTooLong;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_core::Stream;
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{future, io};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// The most bytes read at once.
+const READ_SIZE: usize = 8 * 1024;
+
+/// The length of the prefix of a `Framing::LengthPrefixed` frame.
+const PREFIX_LEN: usize = 4;
+
+/// How [`ResumableFrames`](struct.ResumableFrames.html) finds the end of
+/// each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Framing {
+    /// Frames are separated by this byte, which is not included in them.
+    /// A final frame that is not followed by it is yielded at EOF.
+    Delimiter(u8),
+    /// Each frame is preceded by its length, as a 32-bit big-endian
+    /// integer that is not included in it.
+    LengthPrefixed,
+}
+
+/// A stream of the frames in `reader` whose partial input is kept in the
+/// reader rather than in the future that reads it, so that
+/// [`next_frame`](#method.next_frame) can be cancelled at any point, as in a
+/// branch of `select!` that loses, without losing data.
+///
+/// Frames longer than `max_len` bytes are yielded as a
+/// [`RecordTooLong`](struct.RecordTooLong.html) error, after which the rest
+/// of the frame is skipped and the stream carries on with the next one.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use std::time::Duration;
+/// use tokio_file_unix::{Framing, ResumableFrames};
+///
+/// let stdin = tokio_file_unix::File::new_nb(tokio_file_unix::raw_stdin()?)?;
+/// let mut frames = ResumableFrames::new(stdin, Framing::Delimiter(0), 4096);
+/// loop {
+///     tokio::select! {
+///         frame = frames.next_frame() => match frame {
+///             Some(frame) => println!("{:?}", frame?),
+///             None => break,
+///         },
+///         _ = tokio::time::sleep(Duration::from_secs(1)) => println!("tick"),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ResumableFrames<R> {
+    reader: R,
+    framing: Framing,
+    max_len: usize,
+    buf: BytesMut,
+    /// How far `buf` has been searched for the delimiter.
+    searched: usize,
+    /// How much of an overlong frame remains to be skipped, or `usize::MAX`
+    /// to skip up to the next delimiter.
+    skip: usize,
+    eof: bool,
+}
+
+impl<R> ResumableFrames<R> {
+    /// Splits `reader` into frames of at most `max_len` bytes.
+    pub fn new(reader: R, framing: Framing, max_len: usize) -> Self {
+        ResumableFrames {
+            reader,
+            framing,
+            max_len,
+            buf: BytesMut::new(),
+            searched: 0,
+            skip: 0,
+            eof: false,
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    ///
+    /// Reading from it directly skips over the data in the buffer.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// The data read but not yet yielded as part of a frame.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Returns the underlying reader.  Any data in the buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Takes the next complete frame out of the buffer, if there is one.
+    fn decode(&mut self) -> Option<Result<Bytes, RecordTooLong>> {
+        let too_long = RecordTooLong {
+            max_len: self.max_len,
+        };
+        if self.skip > 0 {
+            let end = match self.framing {
+                Framing::Delimiter(delimiter) => {
+                    match self.buf.iter().position(|&b| b == delimiter) {
+                        Some(i) => {
+                            self.skip = 0;
+                            i + 1
+                        }
+                        None => self.buf.len(),
+                    }
+                }
+                Framing::LengthPrefixed => {
+                    let n = self.skip.min(self.buf.len());
+                    self.skip -= n;
+                    n
+                }
+            };
+            self.buf.advance(end);
+            if self.skip > 0 {
+                return None;
+            }
+        }
+        match self.framing {
+            Framing::Delimiter(delimiter) => {
+                let found = self.buf[self.searched..]
+                    .iter()
+                    .position(|&b| b == delimiter);
+                let i = match found {
+                    Some(i) => self.searched + i,
+                    None => {
+                        self.searched = self.buf.len();
+                        if self.buf.len() > self.max_len {
+                            self.buf.clear();
+                            self.searched = 0;
+                            self.skip = usize::MAX;
+                            return Some(Err(too_long));
+                        }
+                        return None;
+                    }
+                };
+                self.searched = 0;
+                if i > self.max_len {
+                    self.buf.advance(i + 1);
+                    return Some(Err(too_long));
+                }
+                let frame = self.buf.split_to(i).freeze();
+                self.buf.advance(1);
+                Some(Ok(frame))
+            }
+            Framing::LengthPrefixed => {
+                if self.buf.len() < PREFIX_LEN {
+                    return None;
+                }
+                let len = (&self.buf[..PREFIX_LEN]).get_u32() as usize;
+                if len > self.max_len {
+                    self.buf.advance(PREFIX_LEN);
+                    self.skip = len;
+                    return Some(Err(too_long));
+                }
+                if self.buf.len() < PREFIX_LEN + len {
+                    self.buf.reserve(PREFIX_LEN + len - self.buf.len());
+                    return None;
+                }
+                self.buf.advance(PREFIX_LEN);
+                Some(Ok(self.buf.split_to(len).freeze()))
+            }
+        }
+    }
+
+    /// Takes what is left in the buffer at EOF.
+    fn finish(&mut self) -> Option<io::Result<Bytes>> {
+        if self.skip > 0 {
+            self.skip = 0;
+            self.buf.clear();
+            return None;
+        }
+        if self.buf.is_empty() {
+            return None;
+        }
+        self.searched = 0;
+        match self.framing {
+            Framing::Delimiter(_) => Some(Ok(self.buf.split().freeze())),
+            Framing::LengthPrefixed => {
+                self.buf.clear();
+                Some(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "EOF in the middle of a frame",
+                )))
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> ResumableFrames<R> {
+    /// Receives the next frame, like `StreamExt::next`, or `None` at EOF.
+    ///
+    /// This is cancellation safe: if the future is dropped before it
+    /// completes, no data is lost, and the next call carries on with the
+    /// frame that was being read.
+    pub async fn next_frame(&mut self) -> Option<io::Result<Bytes>> {
+        future::poll_fn(|cx| self.poll_next_frame(cx)).await
+    }
+
+    /// Polls for the next frame.  See [`next_frame`](#method.next_frame).
+    pub fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        loop {
+            if let Some(frame) = self.decode() {
+                return Poll::Ready(Some(frame.map_err(io::Error::from)));
+            }
+            if self.eof {
+                return Poll::Ready(self.finish());
+            }
+            self.buf.reserve(READ_SIZE);
+            let dst = self.buf.chunk_mut();
+            let dst = unsafe { &mut *(dst as *mut _ as *mut [MaybeUninit<u8>]) };
+            let mut buf = ReadBuf::uninit(dst);
+            ready!(Pin::new(&mut self.reader).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                self.eof = true;
+            }
+            // the first `n` bytes were initialized by the read
+            unsafe { self.buf.advance_mut(n) };
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for ResumableFrames<R> {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_frame(cx)
+    }
+}
+
+/// A stream of lines as strings, like
+/// [`File::lines`](struct.File.html#method.lines), that can be cancelled in
+/// the middle of a line without losing it, like
+/// [`ResumableFrames`](struct.ResumableFrames.html).
+///
+/// A trailing carriage return is removed from each line, and a line that
+/// is not valid UTF-8 fails with `InvalidData`.
+#[derive(Debug)]
+pub struct ResumableLines<R> {
+    inner: ResumableFrames<R>,
+}
+
+impl<R> ResumableLines<R> {
+    /// Splits `reader` into lines of at most `max_len` bytes, excluding the
+    /// newline.
+    pub fn new(reader: R, max_len: usize) -> Self {
+        ResumableLines {
+            inner: ResumableFrames::new(reader, Framing::Delimiter(b'\n'), max_len),
+        }
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// The data read but not yet yielded as part of a line.
+    pub fn buffer(&self) -> &[u8] {
+        self.inner.buffer()
+    }
+
+    /// Returns the underlying reader.  Any data in the buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+}
+
+impl<R: AsyncRead + Unpin> ResumableLines<R> {
+    /// Receives the next line, or `None` at EOF.  This is cancellation
+    /// safe, like [`ResumableFrames::next_frame`](struct.ResumableFrames.html#method.next_frame).
+    pub async fn next_line(&mut self) -> Option<io::Result<String>> {
+        future::poll_fn(|cx| self.poll_next_line(cx)).await
+    }
+
+    /// Polls for the next line.  See [`next_line`](#method.next_line).
+    pub fn poll_next_line(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<String>>> {
+        let mut line = match ready!(self.inner.poll_next_frame(cx)) {
+            Some(line) => Vec::from(line?),
+            None => return Poll::Ready(None),
+        };
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        Poll::Ready(Some(
+            String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        ))
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for ResumableLines<R> {
+    type Item = io::Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_line(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_resumable_lines() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let mut lines = ResumableLines::new(reader, 16);
+        writer.write_all(b"half a ").await?;
+        tokio::select! {
+            _ = lines.next_line() => panic!("line before its end"),
+            _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+        }
+        assert_eq!(lines.buffer(), b"half a ");
+        writer
+            .write_all(b"line\r\nthis one is far too long\nok")
+            .await?;
+        drop(writer);
+        assert_eq!(lines.next_line().await.unwrap()?, "half a line");
+        let e = lines.next_line().await.unwrap().unwrap_err();
+        assert!(e.get_ref().unwrap().is::<RecordTooLong>());
+        assert_eq!(lines.next_line().await.unwrap()?, "ok");
+        assert!(lines.next_line().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_length_prefixed() -> io::Result<()> {
+        let input: &[u8] = b"\0\0\0\x03abc\0\0\0\x09overlong!\0\0\0\0\0\0\0\x02x";
+        let mut frames = ResumableFrames::new(input, Framing::LengthPrefixed, 4);
+        assert_eq!(&frames.next_frame().await.unwrap()?[..], b"abc");
+        assert!(frames.next_frame().await.unwrap().is_err());
+        assert_eq!(&frames.next_frame().await.unwrap()?[..], b"");
+        let e = frames.next_frame().await.unwrap().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert!(frames.next_frame().await.is_none());
+        Ok(())
+    }
+}
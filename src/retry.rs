@@ -0,0 +1,142 @@
+//! Retrying operations that fail transiently.
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+/// How often and how patiently to retry an operation that fails
+/// transiently, such as opening a FIFO for writing before any reader has
+/// connected.  Accepted by
+/// [`fifo::open_write_with`](fifo/fn.open_write_with.html).
+///
+/// The delay starts at `initial_delay` and doubles after every attempt, up
+/// to `max_delay`.  With jitter, each delay is shortened by a random
+/// fraction of up to `jitter`, so that many processes retrying at once do
+/// not do so in lockstep.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    max_attempts: Option<u32>,
+    initial_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: None,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(100),
+            jitter: 0.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Equivalent to `RetryPolicy::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of attempts after which to give up and return the
+    /// last error.  Defaults to retrying forever.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts.max(1));
+        self
+    }
+
+    /// Sets the delay after the first failed attempt.  Defaults to 1 ms.
+    pub fn initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+
+    /// Sets the longest delay between attempts.  Defaults to 100 ms.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Sets the largest fraction, between 0 and 1, by which each delay may
+    /// be randomly shortened.  Defaults to 0.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The delay after the failure of attempt number `attempt`, counting
+    /// from 1, or `None` if that was the last attempt allowed.
+    fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+        Some(self.backoff(attempt))
+    }
+
+    /// The delay after the failure of attempt number `attempt`, regardless
+    /// of the maximum number of attempts.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self
+            .initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        // a fresh RandomState is seeded differently each time
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        delay.mul_f64(1.0 - self.jitter * random)
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that is not
+    /// `transient`, or runs out of attempts.
+    pub(crate) async fn retry<T, F, Fut>(
+        &self,
+        mut op: F,
+        transient: impl Fn(&io::Error) -> bool,
+    ) -> io::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = io::Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let e = match op().await {
+                Err(e) if transient(&e) => e,
+                r => return r,
+            };
+            match self.delay(attempt) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new()
+            .initial_delay(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(50))
+            .max_attempts(5);
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.delay(attempt)).collect();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(delays, vec![ms(10), ms(20), ms(40), ms(50), None]);
+
+        let policy = policy.jitter(0.5);
+        for attempt in 1..5 {
+            let delay = policy.delay(attempt).unwrap();
+            assert!(delay <= Duration::from_millis(50));
+            assert!(delay >= Duration::from_millis(5));
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Routing the records of one input to several outputs.
+
+use crate::backpressure::{self, BackpressureMonitor, Side};
+use crate::records::BoundedLines;
+use futures_core::Stream;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fmt, future, io};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// The default number of bytes a route may have queued before the input
+/// waits for it.
+const DEFAULT_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// Whole records waiting to be written to a writer.
+pub(crate) struct Outbox<W> {
+    writer: W,
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl<W: AsyncWrite + Unpin> Outbox<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Outbox {
+            writer,
+            data: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// The number of bytes not yet written.
+    pub(crate) fn queued(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Whether a record of `len` bytes fits within `limit`.  A record always
+    /// fits in an empty outbox, however long it is.
+    pub(crate) fn has_room(&self, len: usize, limit: usize) -> bool {
+        self.queued() == 0 || self.queued() + len <= limit
+    }
+
+    /// Queues `record` followed by `delimiter`.
+    pub(crate) fn push(&mut self, record: &[u8], delimiter: u8) {
+        if self.pos == self.data.len() {
+            self.data.clear();
+            self.pos = 0;
+        }
+        self.data.extend_from_slice(record);
+        self.data.push(delimiter);
+    }
+
+    /// Writes as much of the queue as possible, returning `Ready` once it is
+    /// empty.
+    pub(crate) fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pos < self.data.len() {
+            let n = ready!(Pin::new(&mut self.writer).poll_write(cx, &self.data[self.pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pos += n;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Writes out the whole queue and flushes the writer.
+    pub(crate) fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_drain(cx))?;
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+}
+
+impl<W> fmt::Debug for Outbox<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outbox")
+            .field("queued", &(self.data.len() - self.pos))
+            .finish()
+    }
+}
+
+/// Distributes the records of one input among several writers according to
+/// a key computed from each record, as a fan-out daemon would.
+///
+/// The input is split into records with a
+/// [`BoundedLines`](struct.BoundedLines.html), which determines the
+/// delimiter, the maximum record length, and what happens to overlong
+/// records.  Each record is written whole, followed by the delimiter, to the
+/// writer registered for its key.  Records whose key has no route are
+/// discarded.
+///
+/// Every route has its own queue, so a slow writer does not hold up the
+/// others until it falls behind by more than the
+/// [buffer limit](#method.buffer_limit).  Then the input waits for it
+/// rather than buffering without bound.
+///
+/// ```
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio_file_unix::{BoundedLines, Router};
+///
+/// let input = tokio_file_unix::fifo::open_read("/run/events.fifo").await?;
+/// let errors = tokio_file_unix::fifo::open_write("/run/errors.fifo").await?;
+/// let other = tokio_file_unix::fifo::open_write("/run/other.fifo").await?;
+/// Router::new(BoundedLines::new(input, 4096), |line: &[u8]| {
+///     line.starts_with(b"ERROR")
+/// })
+/// .route(true, errors)
+/// .route(false, other)
+/// .run()
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Router<R, W, K, F> {
+    input: BoundedLines<R>,
+    key: F,
+    routes: HashMap<K, Outbox<W>>,
+    buffer_limit: usize,
+    /// A record waiting for room in the queue of its route.
+    stalled: Option<(K, Vec<u8>)>,
+    eof: bool,
+    monitor: Option<BackpressureMonitor>,
+}
+
+impl<R, W, K, F> Router<R, W, K, F>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    K: Hash + Eq,
+    F: FnMut(&[u8]) -> K,
+{
+    /// Routes the records of `input` by the key that `key` returns for each
+    /// one.
+    pub fn new(input: BoundedLines<R>, key: F) -> Self {
+        Router {
+            input,
+            key,
+            routes: HashMap::new(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+            stalled: None,
+            eof: false,
+            monitor: None,
+        }
+    }
+
+    /// Sends records with the given key to `writer`, replacing any writer
+    /// previously registered for it.
+    pub fn route(mut self, key: K, writer: W) -> Self {
+        self.routes.insert(key, Outbox::new(writer));
+        self
+    }
+
+    /// Sets how many bytes may be queued for a single route before the
+    /// input waits for it.  Defaults to 64 KiB.
+    pub fn buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+
+    /// Records in `monitor` how long the router waits for the input and for
+    /// routes that have fallen behind.
+    pub fn monitor(mut self, monitor: BackpressureMonitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Routes records until the input reaches EOF, then writes out and
+    /// flushes every route.  The writers are dropped once done.
+    ///
+    /// Fails with the first error of the input or of any writer.
+    pub async fn run(mut self) -> io::Result<()> {
+        future::poll_fn(|cx| self.poll_run(cx)).await
+    }
+
+    fn poll_run(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let delimiter = self.input.delimiter_byte();
+        loop {
+            // the writers register for wakeups even when a route is not
+            // what the input is waiting for
+            for outbox in self.routes.values_mut() {
+                if let Poll::Ready(Err(e)) = outbox.poll_drain(cx) {
+                    return Poll::Ready(Err(e));
+                }
+            }
+            if let Some((key, record)) = self.stalled.take() {
+                let outbox = self.routes.get_mut(&key).unwrap();
+                if !outbox.has_room(record.len() + 1, self.buffer_limit) {
+                    self.stalled = Some((key, record));
+                    backpressure::note(&self.monitor, Some(Side::Output));
+                    return Poll::Pending;
+                }
+                outbox.push(&record, delimiter);
+                continue;
+            }
+            if self.eof {
+                for outbox in self.routes.values_mut() {
+                    if outbox.poll_finish(cx)?.is_pending() {
+                        backpressure::note(&self.monitor, Some(Side::Output));
+                        return Poll::Pending;
+                    }
+                }
+                backpressure::note(&self.monitor, None);
+                return Poll::Ready(Ok(()));
+            }
+            let next = Pin::new(&mut self.input).poll_next(cx);
+            backpressure::note(&self.monitor, next.is_pending().then_some(Side::Input));
+            match ready!(next) {
+                None => self.eof = true,
+                Some(record) => {
+                    let record = record?;
+                    let key = (self.key)(&record);
+                    if self.routes.contains_key(&key) {
+                        self.stalled = Some((key, record));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R, W, K, F> fmt::Debug for Router<R, W, K, F>
+where
+    R: fmt::Debug,
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("input", &self.input)
+            .field("routes", &self.routes)
+            .field("buffer_limit", &self.buffer_limit)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_router() -> io::Result<()> {
+        let input = &b"a1\nb1\na2\nc1\na3\nb2"[..];
+        // a tiny buffer on route a makes the input wait for its reader
+        let (a, mut a_out) = tokio::io::duplex(2);
+        let (b, mut b_out) = tokio::io::duplex(1024);
+        let router = Router::new(BoundedLines::new(input, 16), |record: &[u8]| record[0])
+            .route(b'a', a)
+            .route(b'b', b)
+            .buffer_limit(4);
+        let reader = tokio::spawn(async move {
+            let mut received = String::new();
+            a_out.read_to_string(&mut received).await.map(|_| received)
+        });
+        router.run().await?;
+        assert_eq!(reader.await.unwrap()?, "a1\na2\na3\n");
+        let mut received = String::new();
+        b_out.read_to_string(&mut received).await?;
+        assert_eq!(received, "b1\nb2\n");
+        Ok(())
+    }
+}
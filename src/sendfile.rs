@@ -0,0 +1,106 @@
+//! Sending a file to a socket with `sendfile(2)`.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// The most bytes sent by each `sendfile`, which Linux caps at a little
+/// under 2 GiB anyway.
+const MAX_CHUNK: usize = 0x7fff_f000;
+
+/// Sends up to `count` bytes of `file`, starting at `offset`, to `socket`
+/// inside the kernel, without copying them through user space, and returns
+/// the number of bytes sent, which is less than `count` only if `file`
+/// reached EOF or an error occurred after some data was sent.
+///
+/// `offset` is advanced past the data sent, while the offset of `file`
+/// itself is left alone, so several transfers from one file can run at
+/// once.  This waits for `socket` to be writable and sends again after any
+/// short transfer until `count` bytes are sent.
+///
+/// `file` must support `mmap`, as regular files do.  Reading it may still
+/// block the thread if its pages are not in the cache.
+///
+/// Only available on Linux and Android.
+///
+/// ```no_run
+/// # async fn run(socket: std::os::unix::net::UnixStream) -> std::io::Result<()> {
+/// let file = std::fs::File::open("index.html")?;
+/// let len = file.metadata()?.len() as usize;
+/// let socket = tokio_file_unix::File::new_nb(socket)?;
+/// let mut offset = 0;
+/// tokio_file_unix::sendfile(&file, &socket, &mut offset, len).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn sendfile<F, W>(
+    file: &F,
+    socket: &File<W>,
+    offset: &mut u64,
+    count: usize,
+) -> io::Result<usize>
+where
+    F: AsRawFd + ?Sized,
+    W: AsRawFd,
+{
+    let (in_fd, out_fd) = (file.as_raw_fd(), socket.as_raw_fd());
+    let mut sent = 0;
+    while sent < count {
+        let mut guard = socket.writable().await?;
+        let mut off = *offset as libc::off_t;
+        let len = (count - sent).min(MAX_CHUNK);
+        let n = unsafe { libc::sendfile(out_fd, in_fd, &mut off, len) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            match e.kind() {
+                io::ErrorKind::WouldBlock => guard.clear_ready(),
+                io::ErrorKind::Interrupted => {}
+                // report what was sent before the failure
+                _ if sent > 0 => break,
+                _ => return Err(e),
+            }
+            continue;
+        }
+        if n == 0 {
+            break;
+        }
+        *offset = off as u64;
+        sent += n as usize;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_sendfile() -> io::Result<()> {
+        let path = TempPath::new("sendfile");
+        let mut file = fs::File::create(&path)?;
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        file.write_all(&data)?;
+        let file = fs::File::open(&path)?;
+
+        let (a, mut b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        // more than a socket buffer holds, so some sends must wait
+        let receiving = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            b.read_to_end(&mut received).map(|_| received)
+        });
+        let mut offset = 100;
+        assert_eq!(sendfile(&file, &a, &mut offset, 150_000).await?, 150_000);
+        assert_eq!(offset, 150_100);
+        assert_eq!(sendfile(&file, &a, &mut offset, 100_000).await?, 49_900);
+        assert_eq!(offset, 200_000);
+        drop(a);
+        let received = receiving.join().unwrap()?;
+        assert_eq!(received, &data[100..]);
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Serial port settings, for devices such as `/dev/ttyUSB0`.
+//!
+//! Open the device with `O_NOCTTY` (through `OpenOptionsExt::custom_flags`),
+//! wrap it with [`File::new_nb`](../struct.File.html#method.new_nb), then
+//! configure it with
+//! [`File::configure_serial`](../struct.File.html#method.configure_serial).
+
+use crate::terminal::{get_attr, set_attr};
+use crate::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// The number of data bits in each character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+/// The parity bit sent after the data bits of each character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// Makes the number of set bits odd.
+    Odd,
+    /// Makes the number of set bits even.
+    Even,
+}
+
+/// The number of stop bits after each character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// The line settings applied by
+/// [`File::configure_serial`](../struct.File.html#method.configure_serial).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SerialSettings {
+    baud_rate: u32,
+    data_bits: DataBits,
+    parity: Parity,
+    stop_bits: StopBits,
+    rts_cts: bool,
+}
+
+impl SerialSettings {
+    /// Settings for `baud_rate` bits per second, 8N1 without flow control.
+    pub fn new(baud_rate: u32) -> Self {
+        SerialSettings {
+            baud_rate,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            rts_cts: false,
+        }
+    }
+
+    /// Sets the number of data bits.  Defaults to `DataBits::Eight`.
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.data_bits = data_bits;
+        self
+    }
+
+    /// Sets the parity.  Defaults to `Parity::None`.
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Sets the number of stop bits.  Defaults to `StopBits::One`.
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Whether to use hardware flow control with the RTS and CTS lines
+    /// (`CRTSCTS`).  Defaults to `false`.
+    pub fn rts_cts(mut self, rts_cts: bool) -> Self {
+        self.rts_cts = rts_cts;
+        self
+    }
+
+    /// Applies the settings to `termios`.
+    fn apply(&self, termios: &mut libc::termios) -> io::Result<()> {
+        let speed = speed(self.baud_rate)?;
+        unsafe {
+            libc::cfmakeraw(termios);
+            if libc::cfsetispeed(termios, speed) < 0 || libc::cfsetospeed(termios, speed) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        termios.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB);
+        termios.c_cflag &= !libc::CRTSCTS;
+        termios.c_cflag |= libc::CREAD | libc::CLOCAL;
+        termios.c_cflag |= match self.data_bits {
+            DataBits::Five => libc::CS5,
+            DataBits::Six => libc::CS6,
+            DataBits::Seven => libc::CS7,
+            DataBits::Eight => libc::CS8,
+        };
+        termios.c_cflag |= match self.parity {
+            Parity::None => 0,
+            Parity::Odd => libc::PARENB | libc::PARODD,
+            Parity::Even => libc::PARENB,
+        };
+        if self.stop_bits == StopBits::Two {
+            termios.c_cflag |= libc::CSTOPB;
+        }
+        if self.rts_cts {
+            termios.c_cflag |= libc::CRTSCTS;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a baud rate to the `B*` constant that stands for it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn speed(baud_rate: u32) -> io::Result<libc::speed_t> {
+    Ok(match baud_rate {
+        50 => libc::B50,
+        75 => libc::B75,
+        110 => libc::B110,
+        134 => libc::B134,
+        150 => libc::B150,
+        200 => libc::B200,
+        300 => libc::B300,
+        600 => libc::B600,
+        1200 => libc::B1200,
+        1800 => libc::B1800,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        500000 => libc::B500000,
+        576000 => libc::B576000,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        1152000 => libc::B1152000,
+        1500000 => libc::B1500000,
+        2000000 => libc::B2000000,
+        2500000 => libc::B2500000,
+        3000000 => libc::B3000000,
+        3500000 => libc::B3500000,
+        4000000 => libc::B4000000,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate {}", baud_rate),
+            ))
+        }
+    })
+}
+
+/// Converts a baud rate to a `speed_t`, which is the rate itself on the BSDs.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn speed(baud_rate: u32) -> io::Result<libc::speed_t> {
+    Ok(baud_rate as libc::speed_t)
+}
+
+flags! {
+    /// Modem control lines, combined with `|`.
+    pub struct ModemLines(libc::c_int);
+
+    /// No lines.
+    const NONE = 0;
+    /// Data Terminal Ready, an output (`TIOCM_DTR`).
+    const DTR = libc::TIOCM_DTR;
+    /// Request To Send, an output (`TIOCM_RTS`).
+    const RTS = libc::TIOCM_RTS;
+    /// Clear To Send, an input (`TIOCM_CTS`).
+    const CTS = libc::TIOCM_CTS;
+    /// Data Set Ready, an input (`TIOCM_DSR`).
+    const DSR = libc::TIOCM_DSR;
+    /// Carrier Detect, an input (`TIOCM_CD`).
+    const CD = libc::TIOCM_CD;
+    /// Ring Indicator, an input (`TIOCM_RI`).
+    const RI = libc::TIOCM_RI;
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Configures the serial port behind the file with `settings`.
+    ///
+    /// The port is also put into raw mode, so that bytes pass through
+    /// unchanged, and set to ignore the carrier detect line (`CLOCAL`), as
+    /// most devices that are not modems do not drive it.  Fails with
+    /// `InvalidInput` if the baud rate is not supported.
+    ///
+    /// Implementation detail: uses `tcgetattr`, `cfsetispeed`,
+    /// `cfsetospeed`, and `tcsetattr`.
+    pub fn configure_serial(&self, settings: &SerialSettings) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        let mut termios = get_attr(fd)?;
+        settings.apply(&mut termios)?;
+        set_attr(fd, &termios)
+    }
+
+    /// Gets the state of the modem control lines (`TIOCMGET`).
+    pub fn modem_lines(&self) -> io::Result<ModemLines> {
+        let mut lines: libc::c_int = 0;
+        if unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCMGET, &mut lines) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ModemLines(lines))
+    }
+
+    /// Sets the modem control lines (`TIOCMSET`).  Only the outputs, DTR
+    /// and RTS, can be changed; the others are ignored.
+    pub fn set_modem_lines(&self, lines: ModemLines) -> io::Result<()> {
+        if unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCMSET, &lines.0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::tests::open_pty;
+
+    #[tokio::test]
+    async fn test_configure_serial() -> io::Result<()> {
+        let (_master, slave) = open_pty()?;
+        let file = File::new_nb(slave)?;
+        // pseudoterminals force 8 bits without parity, so only the speed
+        // and stop bits can be checked
+        let settings = SerialSettings::new(115200).stop_bits(StopBits::Two);
+        file.configure_serial(&settings)?;
+        let termios = get_attr(file.as_raw_fd())?;
+        assert_eq!(unsafe { libc::cfgetospeed(&termios) }, libc::B115200);
+        assert_ne!(termios.c_cflag & libc::CSTOPB, 0);
+        assert_eq!(termios.c_lflag & libc::ICANON, 0);
+
+        let err = file
+            .configure_serial(&SerialSettings::new(12345))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+}
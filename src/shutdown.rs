@@ -0,0 +1,113 @@
+//! Shutting down the writing side of a descriptor.
+
+use crate::FdKind;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// How the writing side of a descriptor can be shut down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum WriteSide {
+    /// A socket, shut down with `shutdown(SHUT_WR)`.
+    Socket,
+    /// The write-only end of a pipe or FIFO, which can only be shut down by
+    /// closing it.
+    PipeWriter,
+    /// Anything else, such as a terminal or regular file, which has nothing
+    /// to shut down.
+    Other,
+}
+
+/// Determines how the writing side of `fd` can be shut down.
+pub(crate) fn write_side(fd: RawFd) -> io::Result<WriteSide> {
+    Ok(match FdKind::of(&fd)? {
+        FdKind::Socket => WriteSide::Socket,
+        FdKind::Pipe => {
+            let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+            if flags < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if flags & libc::O_ACCMODE == libc::O_WRONLY {
+                WriteSide::PipeWriter
+            } else {
+                WriteSide::Other
+            }
+        }
+        _ => WriteSide::Other,
+    })
+}
+
+/// Shuts down the writing side of `fd` so that the peer sees EOF.
+///
+/// Since the descriptor itself belongs to someone else, the end of a pipe is
+/// closed by atomically replacing it with the writing end of a pipe that has
+/// no reader.  The peer sees EOF once no other copies of the original
+/// descriptor remain, for example in child processes.
+pub(crate) fn shutdown_write(fd: RawFd, side: WriteSide) -> io::Result<()> {
+    match side {
+        WriteSide::Socket => {
+            if unsafe { libc::shutdown(fd, libc::SHUT_WR) } < 0 {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() != Some(libc::ENOTCONN) {
+                    return Err(e);
+                }
+            }
+        }
+        WriteSide::PipeWriter => {
+            let (_, widowed) = crate::pipe::raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+            crate::replace_fd(fd, widowed.as_raw_fd())?;
+            audit!("File::poll_shutdown", fd, "replaced to close the pipe");
+        }
+        WriteSide::Other => {}
+    }
+    Ok(())
+}
+
+/// The error for writing after the writing side was shut down.
+pub(crate) fn write_after_shutdown() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "write after shutdown")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::File;
+    use std::io;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_shutdown_socket() -> io::Result<()> {
+        let (a, mut b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        a.write_all(b"done").await?;
+        a.shutdown().await?;
+        let mut received = Vec::new();
+        io::Read::read_to_end(&mut b, &mut received)?;
+        assert_eq!(received, b"done");
+        let err = a.write_all(b"more").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        // the reading side is still open
+        io::Write::write_all(&mut b, b"reply")?;
+        let mut reply = [0; 5];
+        a.read_exact(&mut reply).await?;
+        assert_eq!(&reply, b"reply");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_pipe() -> io::Result<()> {
+        let (mut reader, writer) = crate::pipe()?;
+        let (_read_half, mut write_half) = writer.into_inner().into_split();
+        write_half.write_all(b"done").await?;
+        write_half.shutdown().await?;
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received).await?;
+        assert_eq!(received, b"done");
+        let err = write_half.write_all(b"more").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        let (mut reader, mut writer) = crate::pipe()?;
+        writer.shutdown().await?;
+        assert_eq!(reader.read(&mut [0; 1]).await?, 0);
+        Ok(())
+    }
+}
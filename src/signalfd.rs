@@ -0,0 +1,154 @@
+//! Receiving signals through a descriptor (`signalfd(2)`).
+
+use crate::File;
+use futures_core::Stream;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fmt, fs, io, mem, ptr};
+
+/// A signal received by a [`SignalFd`](struct.SignalFd.html)
+/// (`struct signalfd_siginfo`).
+#[derive(Clone, Copy)]
+pub struct SignalInfo(libc::signalfd_siginfo);
+
+impl SignalInfo {
+    /// The signal number.
+    pub fn signo(&self) -> libc::c_int {
+        self.0.ssi_signo as libc::c_int
+    }
+
+    /// The signal code (`si_code`), such as `SI_USER`.
+    pub fn code(&self) -> i32 {
+        self.0.ssi_code
+    }
+
+    /// The process ID of the sender.
+    pub fn pid(&self) -> libc::pid_t {
+        self.0.ssi_pid as libc::pid_t
+    }
+
+    /// The real user ID of the sender.
+    pub fn uid(&self) -> libc::uid_t {
+        self.0.ssi_uid
+    }
+
+    /// The exit status or signal of the child, for `SIGCHLD`.
+    pub fn status(&self) -> i32 {
+        self.0.ssi_status
+    }
+}
+
+impl fmt::Debug for SignalInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignalInfo")
+            .field("signo", &self.signo())
+            .field("code", &self.code())
+            .field("pid", &self.pid())
+            .field("uid", &self.uid())
+            .field("status", &self.status())
+            .finish()
+    }
+}
+
+/// A stream of signals received through `signalfd(2)`, as a lightweight
+/// alternative to `tokio::signal`.
+///
+/// Signals are only delivered to the descriptor if they are blocked, so
+/// creating a `SignalFd` blocks the given signals in the calling thread.
+/// Threads inherit the signal mask of the thread that spawns them, so this
+/// should happen before any other threads are started, including those of a
+/// multi-threaded Tokio runtime; otherwise the signals may still be
+/// delivered to one of them the ordinary way.  The signals stay blocked
+/// after the `SignalFd` is dropped.
+///
+/// Only available on Linux.
+#[derive(Debug)]
+pub struct SignalFd {
+    file: File<fs::File>,
+}
+
+impl SignalFd {
+    /// Blocks the `signals` in the calling thread and creates a descriptor
+    /// that receives them.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new(signals: &[libc::c_int]) -> io::Result<Self> {
+        unsafe {
+            let mut mask: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut mask);
+            for &signal in signals {
+                if libc::sigaddset(&mut mask, signal) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            let r = libc::pthread_sigmask(libc::SIG_BLOCK, &mask, ptr::null_mut());
+            if r != 0 {
+                return Err(io::Error::from_raw_os_error(r));
+            }
+            let fd = libc::signalfd(-1, &mask, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let file = crate::move_fd_above(fs::File::from_raw_fd(fd), crate::STDIO_END)?;
+            Ok(SignalFd {
+                file: File::raw_new(file)?,
+            })
+        }
+    }
+
+    /// Polls for the next signal.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<SignalInfo>> {
+        let mut info = [0; mem::size_of::<libc::signalfd_siginfo>()];
+        loop {
+            let mut guard = ready!(self.file.poll_read_ready(cx))?;
+            match self.file.try_read(&mut info) {
+                Ok(_) => {
+                    let info = unsafe {
+                        ptr::read_unaligned(info.as_ptr() as *const libc::signalfd_siginfo)
+                    };
+                    return Poll::Ready(Ok(SignalInfo(info)));
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl Stream for SignalFd {
+    type Item = io::Result<SignalInfo>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_recv(cx).map(Some)
+    }
+}
+
+impl AsRawFd for SignalFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for SignalFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_signalfd() -> io::Result<()> {
+        let mut signals = SignalFd::new(&[libc::SIGUSR2])?;
+        // direct the signal at this thread, which has it blocked
+        unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR2) };
+        let info = signals.next().await.unwrap()?;
+        assert_eq!(info.signo(), libc::SIGUSR2);
+        assert_eq!(info.pid(), unsafe { libc::getpid() });
+        Ok(())
+    }
+}
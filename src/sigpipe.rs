@@ -0,0 +1,177 @@
+//! Keeping `SIGPIPE` from killing the process.
+//!
+//! Writing to a pipe or socket whose other end is closed raises `SIGPIPE`,
+//! which terminates the process unless it is ignored or handled.  Rust
+//! programs ignore it from the start, but a library loaded into a program
+//! written in another language cannot count on that.
+
+use std::os::unix::io::RawFd;
+use std::{io, mem, ptr};
+
+/// Ignores `SIGPIPE` for the whole process, so that writes to a closed pipe
+/// or socket fail with `BrokenPipe` instead.  A handler that is already
+/// installed is left in place.
+///
+/// This is the only way to protect writes to pipes, since unlike sockets
+/// they have no per-descriptor or per-call equivalent of `MSG_NOSIGNAL`.
+/// Note that ignored signals stay ignored in child processes after `exec`.
+pub fn ignore_sigpipe() -> io::Result<()> {
+    unsafe {
+        let mut previous: libc::sigaction = mem::zeroed();
+        if libc::sigaction(libc::SIGPIPE, ptr::null(), &mut previous) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if previous.sa_sigaction != libc::SIG_DFL {
+            return Ok(());
+        }
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = libc::SIG_IGN;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGPIPE, &action, ptr::null_mut()) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Arranges for writes to the socket `fd` not to raise `SIGPIPE`, returning
+/// whether they must go through `send` for that.
+pub(crate) fn suppress(fd: RawFd) -> io::Result<bool> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let _ = fd;
+        Ok(true)
+    }
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd"
+    ))]
+    {
+        let on: libc::c_int = 1;
+        let r = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_NOSIGPIPE,
+                &on as *const libc::c_int as *const libc::c_void,
+                mem::size_of_val(&on) as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(false)
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "netbsd"
+    )))]
+    {
+        let _ = fd;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SIGPIPE cannot be suppressed for a single socket on this platform",
+        ))
+    }
+}
+
+/// Writes `buf` to the socket `fd` with `MSG_NOSIGNAL`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn send(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let n = unsafe {
+        libc::send(
+            fd,
+            buf.as_ptr() as *const libc::c_void,
+            buf.len(),
+            libc::MSG_NOSIGNAL,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Writes `bufs` to the socket `fd` with `MSG_NOSIGNAL`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn send_vectored(fd: RawFd, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+    let n = unsafe {
+        let mut msg: libc::msghdr = mem::zeroed();
+        // IoSlice is guaranteed to be ABI compatible with iovec
+        msg.msg_iov = bufs.as_ptr() as *mut libc::iovec;
+        msg.msg_iovlen = bufs.len() as _;
+        libc::sendmsg(fd, &msg, libc::MSG_NOSIGNAL)
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn send(_: RawFd, _: &[u8]) -> io::Result<usize> {
+    unreachable!("only used where suppress returns true")
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn send_vectored(_: RawFd, _: &[io::IoSlice<'_>]) -> io::Result<usize> {
+    unreachable!("only used where suppress returns true")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FileBuilder;
+    use std::os::unix::io::OwnedFd;
+    use std::os::unix::net::UnixStream;
+    use std::{fs, io, mem, ptr};
+    use tokio::io::AsyncWriteExt;
+
+    /// Writes to a socket whose peer is gone with `SIGPIPE` blocked in this
+    /// thread, returning the error and whether the signal was raised.
+    async fn write_to_closed(suppress: bool) -> io::Result<(io::Error, bool)> {
+        let (a, b) = UnixStream::pair()?;
+        drop(b);
+        // std sends with MSG_NOSIGNAL itself, unlike a plain write
+        let a = fs::File::from(OwnedFd::from(a));
+        let mut file = FileBuilder::new().suppress_sigpipe(suppress).build(a)?;
+        unsafe {
+            let mut set: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGPIPE);
+            let mut old: libc::sigset_t = mem::zeroed();
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+            let err = file.write_all(b"nobody listening").await.unwrap_err();
+            let mut pending: libc::sigset_t = mem::zeroed();
+            libc::sigpending(&mut pending);
+            let raised = libc::sigismember(&pending, libc::SIGPIPE) == 1;
+            if raised {
+                let zero = libc::timespec {
+                    tv_sec: 0,
+                    tv_nsec: 0,
+                };
+                libc::sigtimedwait(&set, ptr::null_mut(), &zero);
+            }
+            libc::pthread_sigmask(libc::SIG_SETMASK, &old, ptr::null_mut());
+            Ok((err, raised))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_suppress_sigpipe() -> io::Result<()> {
+        let (err, raised) = write_to_closed(false).await?;
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(raised);
+        let (err, raised) = write_to_closed(true).await?;
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(!raised);
+        Ok(())
+    }
+}
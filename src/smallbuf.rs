@@ -0,0 +1,123 @@
+//! Byte buffers that keep short contents inline.
+
+use std::{fmt, ops};
+
+/// A byte buffer that holds up to `N` bytes inline, without allocating, and
+/// only moves to the heap for longer contents.
+///
+/// Yielded by [`BoundedLines::next_small`](struct.BoundedLines.html#method.next_small)
+/// so that reading mostly short records, such as interactive input, does
+/// not allocate for each one.
+#[derive(Clone)]
+pub struct SmallBuf<const N: usize> {
+    repr: Repr<N>,
+}
+
+#[derive(Clone)]
+enum Repr<const N: usize> {
+    Inline([u8; N], usize),
+    Heap(Vec<u8>),
+}
+
+impl<const N: usize> SmallBuf<N> {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        SmallBuf {
+            repr: Repr::Inline([0; N], 0),
+        }
+    }
+
+    /// Whether the contents are stored inline.
+    pub fn is_inline(&self) -> bool {
+        match self.repr {
+            Repr::Inline(..) => true,
+            Repr::Heap(_) => false,
+        }
+    }
+
+    /// Converts the buffer into a `Vec`, which allocates if the contents
+    /// are inline.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.repr {
+            Repr::Inline(data, len) => data[..len].to_vec(),
+            Repr::Heap(vec) => vec,
+        }
+    }
+}
+
+impl<const N: usize> Default for SmallBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> From<&[u8]> for SmallBuf<N> {
+    fn from(data: &[u8]) -> Self {
+        if data.len() > N {
+            return SmallBuf {
+                repr: Repr::Heap(data.to_vec()),
+            };
+        }
+        let mut inline = [0; N];
+        inline[..data.len()].copy_from_slice(data);
+        SmallBuf {
+            repr: Repr::Inline(inline, data.len()),
+        }
+    }
+}
+
+/// Takes over the allocation of the `Vec`, even if the contents would fit
+/// inline.
+impl<const N: usize> From<Vec<u8>> for SmallBuf<N> {
+    fn from(vec: Vec<u8>) -> Self {
+        SmallBuf {
+            repr: Repr::Heap(vec),
+        }
+    }
+}
+
+impl<const N: usize> ops::Deref for SmallBuf<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.repr {
+            Repr::Inline(ref data, len) => &data[..len],
+            Repr::Heap(ref vec) => vec,
+        }
+    }
+}
+
+impl<const N: usize> ops::DerefMut for SmallBuf<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self.repr {
+            Repr::Inline(ref mut data, len) => &mut data[..len],
+            Repr::Heap(ref mut vec) => vec,
+        }
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for SmallBuf<N> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> fmt::Debug for SmallBuf<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<const N: usize, const M: usize> PartialEq<SmallBuf<M>> for SmallBuf<N> {
+    fn eq(&self, other: &SmallBuf<M>) -> bool {
+        **self == **other
+    }
+}
+
+impl<const N: usize> Eq for SmallBuf<N> {}
+
+impl<const N: usize> PartialEq<[u8]> for SmallBuf<N> {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
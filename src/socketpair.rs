@@ -0,0 +1,63 @@
+//! Connected pairs of Unix domain sockets.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixStream;
+
+/// Creates a pair of connected Unix domain sockets of the given type,
+/// `libc::SOCK_STREAM` or `libc::SOCK_SEQPACKET`, wrapped for use with
+/// Tokio.
+///
+/// Both ends are nonblocking and close-on-exec, and placed above the
+/// standard streams.  A `SOCK_SEQPACKET` pair keeps the boundaries between
+/// writes, so each read returns at most one of them.  Either end can be
+/// handed to a child process, after clearing close-on-exec, to talk to it
+/// without going through Tokio's own socket types.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn socketpair(ty: libc::c_int) -> io::Result<(File<UnixStream>, File<UnixStream>)> {
+    let mut fds = [0; 2];
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let ty = ty | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+    if unsafe { libc::socketpair(libc::AF_UNIX, ty, 0, fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let a = unsafe { UnixStream::from_raw_fd(fds[0]) };
+    let b = unsafe { UnixStream::from_raw_fd(fds[1]) };
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        for &fd in &fds {
+            unsafe { libc::ioctl(fd, libc::FIOCLEX) };
+            crate::set_nonblocking_for("socketpair", fd, true)?;
+        }
+    }
+    let a = crate::move_fd_above(a, crate::STDIO_END)?;
+    let b = crate::move_fd_above(b, crate::STDIO_END)?;
+    Ok((File::raw_new(a)?, File::raw_new(b)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_socketpair() -> io::Result<()> {
+        let (mut a, mut b) = socketpair(libc::SOCK_STREAM)?;
+        a.write_all(b"ping").await?;
+        let mut data = [0; 4];
+        b.read_exact(&mut data).await?;
+        assert_eq!(&data, b"ping");
+
+        let (mut a, mut b) = socketpair(libc::SOCK_SEQPACKET)?;
+        a.write_all(b"one").await?;
+        a.write_all(b"two").await?;
+        let mut data = [0; 16];
+        let n = b.read(&mut data).await?;
+        assert_eq!(&data[..n], b"one");
+        let n = b.read(&mut data).await?;
+        assert_eq!(&data[..n], b"two");
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+//! Moving data between two wrapped files with `splice(2)`.
+
+use crate::File;
+use std::os::unix::io::AsRawFd;
+use std::task::Poll;
+use std::{future, io};
+
+/// Moves up to `len` bytes from `src` to `dst` inside the kernel, without
+/// copying them through user space, and returns the number of bytes moved.
+/// Like `read`, this may move less than `len`, and returns 0 once `src`
+/// reaches EOF.
+///
+/// At least one of the two files must be a pipe, which makes this suitable
+/// for relaying between a pipe and a socket in either direction.  Otherwise,
+/// the kernel rejects the call with `EINVAL`; use
+/// [`copy_bidirectional`](fn.copy_bidirectional.html), which splices through
+/// a scratch pipe, in that case.
+///
+/// Only available on Linux.
+pub async fn splice<R, W>(src: &File<R>, dst: &File<W>, len: usize) -> io::Result<usize>
+where
+    R: AsRawFd,
+    W: AsRawFd,
+{
+    src.check_pushback()?;
+    future::poll_fn(|cx| loop {
+        let mut src_ready = ready!(src.poll_read_ready(cx))?;
+        let mut dst_ready = ready!(dst.poll_write_ready(cx))?;
+        match crate::copy::splice(src.as_raw_fd(), dst.as_raw_fd(), len) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                let blocked = crate::copy::clear_splice_blocked(
+                    (src.as_raw_fd(), &mut src_ready),
+                    (dst.as_raw_fd(), &mut dst_ready),
+                )?;
+                if blocked == (false, false) {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            }
+            r => return Poll::Ready(r),
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_splice() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let (a, b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        writer.write_all(b"relayed").await?;
+        drop(writer);
+        let reader = reader.into_inner();
+        assert_eq!(splice(&reader, &a, 64).await?, 7);
+        assert_eq!(splice(&reader, &a, 64).await?, 0);
+        let mut buf = [0; 7];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"relayed");
+        Ok(())
+    }
+}
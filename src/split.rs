@@ -0,0 +1,167 @@
+//! Splitting a wrapped file into independently owned halves.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{error, fmt};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The reading half of a [`File`](struct.File.html), created by
+/// [`File::into_split`](struct.File.html#method.into_split).
+#[derive(Debug)]
+pub struct ReadHalf<F> {
+    inner: Arc<File<F>>,
+}
+
+/// The writing half of a [`File`](struct.File.html), created by
+/// [`File::into_split`](struct.File.html#method.into_split).
+#[derive(Debug)]
+pub struct WriteHalf<F> {
+    inner: Arc<File<F>>,
+}
+
+/// Error returned by
+/// [`ReadHalf::reunite`](struct.ReadHalf.html#method.reunite) when the two
+/// halves did not originate from the same `File`.  The halves are handed
+/// back unchanged.
+#[derive(Debug)]
+pub struct ReuniteError<F>(pub ReadHalf<F>, pub WriteHalf<F>);
+
+impl<F> fmt::Display for ReuniteError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tried to reunite halves that are not from the same file")
+    }
+}
+
+impl<F: fmt::Debug> error::Error for ReuniteError<F> {}
+
+impl<F> File<F> {
+    /// Splits the file into a reading half and a writing half that can be
+    /// moved into different tasks, for example to read from and write to a
+    /// pty or a socket pair concurrently.
+    ///
+    /// The halves are `Send` whenever `F` is `Send + Sync`, as is the case
+    /// for `std::fs::File` and `std::os::unix::net::UnixStream`.  Use
+    /// [`ReadHalf::reunite`](struct.ReadHalf.html#method.reunite) to put
+    /// them back together.
+    pub fn into_split(self) -> (ReadHalf<F>, WriteHalf<F>) {
+        let inner = Arc::new(self);
+        (
+            ReadHalf {
+                inner: inner.clone(),
+            },
+            WriteHalf { inner },
+        )
+    }
+}
+
+impl<F> ReadHalf<F> {
+    /// Reunites with a `WriteHalf` split from the same `File`.
+    pub fn reunite(self, other: WriteHalf<F>) -> Result<File<F>, ReuniteError<F>> {
+        if !Arc::ptr_eq(&self.inner, &other.inner) {
+            return Err(ReuniteError(self, other));
+        }
+        drop(other);
+        match Arc::try_unwrap(self.inner) {
+            Ok(file) => Ok(file),
+            Err(_) => unreachable!("both halves were given"),
+        }
+    }
+}
+
+impl<F> WriteHalf<F> {
+    /// Reunites with a `ReadHalf` split from the same `File`.
+    pub fn reunite(self, other: ReadHalf<F>) -> Result<File<F>, ReuniteError<F>> {
+        other.reunite(self)
+    }
+}
+
+impl<F> AsRef<File<F>> for ReadHalf<F> {
+    fn as_ref(&self) -> &File<F> {
+        &self.inner
+    }
+}
+
+impl<F> AsRef<File<F>> for WriteHalf<F> {
+    fn as_ref(&self) -> &File<F> {
+        &self.inner
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for ReadHalf<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for WriteHalf<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F> AsyncRead for ReadHalf<F>
+where
+    F: AsRawFd,
+    for<'a> &'a F: io::Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<F> AsyncWrite for WriteHalf<F>
+where
+    F: AsRawFd,
+    for<'a> &'a F: io::Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut &*self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut &*self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_into_split() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let (mut a_read, mut a_write) = File::new_nb(a)?.into_split();
+        let mut b = File::new_nb(b)?;
+        let task = tokio::spawn(async move {
+            a_write.write_all(b"ping").await?;
+            Ok::<_, io::Error>(a_write)
+        });
+        let mut received = [0; 4];
+        b.read_exact(&mut received).await?;
+        assert_eq!(&received, b"ping");
+        b.write_all(b"pong").await?;
+        a_read.read_exact(&mut received).await?;
+        assert_eq!(&received, b"pong");
+        let a_write = task.await.unwrap()?;
+        a_read.reunite(a_write).unwrap();
+        Ok(())
+    }
+}
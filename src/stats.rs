@@ -0,0 +1,128 @@
+//! Counting the work done on behalf of one wrapped file.
+
+use crate::File;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of a [`File`](struct.File.html) since it was created, returned
+/// by [`File::stats`](struct.File.html#method.stats) if enabled with
+/// [`FileBuilder::collect_stats`](struct.FileBuilder.html#method.collect_stats).
+///
+/// Many more wakeups or `WouldBlock` results than reads and writes that
+/// moved data point to a file that is woken for nothing, such as one
+/// registered for writability that it never uses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// The number of bytes read.
+    pub bytes_read: u64,
+    /// The number of bytes written.
+    pub bytes_written: u64,
+    /// The number of reads and writes issued, including those that failed.
+    pub syscalls: u64,
+    /// The number of reads and writes that failed with `WouldBlock`.
+    pub would_block: u64,
+    /// The number of times the reactor was found to report the file ready,
+    /// whether or not it turned out to be.
+    pub wakeups: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    syscalls: AtomicU64,
+    would_block: AtomicU64,
+    wakeups: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn read<T>(&self, result: &io::Result<T>, n: usize) {
+        self.complete(result);
+        if result.is_ok() {
+            self.bytes_read.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn wrote(&self, result: &io::Result<usize>) {
+        self.complete(result);
+        if let Ok(n) = *result {
+            self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn complete<T>(&self, result: &io::Result<T>) {
+        match *result {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => self.would_block(),
+            _ => {
+                self.syscalls.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Records a read or write that would block.
+    pub(crate) fn would_block(&self) {
+        self.syscalls.fetch_add(1, Ordering::Relaxed);
+        self.would_block.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that the file was reported ready.
+    pub(crate) fn wakeup(&self) {
+        self.wakeups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> FileStats {
+        FileStats {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            syscalls: self.syscalls.load(Ordering::Relaxed),
+            would_block: self.would_block.load(Ordering::Relaxed),
+            wakeups: self.wakeups.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<F> File<F> {
+    /// The counters of the file since it was created, or `None` unless
+    /// enabled with [`FileBuilder::collect_stats`](struct.FileBuilder.html#method.collect_stats).
+    ///
+    /// Reads and writes are counted whichever way they are made, except
+    /// through the inner file, and wakeups whenever the reactor reports the
+    /// file ready, including to [`ready`](#method.ready).
+    pub fn stats(&self) -> Option<FileStats> {
+        self.stats.as_ref().map(Counters::snapshot)
+    }
+
+    pub(crate) fn count_wakeup(&self) {
+        if let Some(ref stats) = self.stats {
+            stats.wakeup();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FileBuilder;
+    use std::io;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_stats() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let mut a = FileBuilder::new().collect_stats(true).build(a)?;
+        let mut b = FileBuilder::new().build(b)?;
+        assert!(b.stats().is_none());
+        a.write_all(b"hello").await?;
+        let mut buf = [0; 5];
+        b.read_exact(&mut buf).await?;
+        b.write_all(b"hi").await?;
+        let mut buf = [0; 2];
+        a.read_exact(&mut buf).await?;
+        let stats = a.stats().unwrap();
+        assert_eq!((stats.bytes_read, stats.bytes_written), (2, 5));
+        assert!(stats.syscalls >= 2);
+        assert!(stats.wakeups >= 2);
+        assert!(stats.would_block <= stats.syscalls);
+        Ok(())
+    }
+}
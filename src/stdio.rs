@@ -0,0 +1,225 @@
+//! Ready-made wrappers of the standard streams.
+
+use crate::File;
+use std::fs;
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Whether each of the standard streams is wrapped at the moment.
+static CLAIMED: [AtomicBool; 3] = [
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+    AtomicBool::new(false),
+];
+
+/// The claim of a wrapper on one of the standard streams, given up when
+/// dropped.
+#[derive(Debug)]
+struct Claim(usize);
+
+impl Claim {
+    fn take(stream: usize, name: &str) -> io::Result<Self> {
+        if CLAIMED[stream].swap(true, Ordering::AcqRel) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is already wrapped by another {}", name, wrapper(stream)),
+            ));
+        }
+        Ok(Claim(stream))
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        CLAIMED[self.0].store(false, Ordering::Release);
+    }
+}
+
+fn wrapper(stream: usize) -> &'static str {
+    ["Stdin", "Stdout", "Stderr"][stream]
+}
+
+/// Wraps a duplicate of the standard stream `stream`, restoring its
+/// nonblocking mode once dropped.
+fn open(stream: usize, name: &str, dup: fn() -> io::Result<fs::File>) -> io::Result<Stdio> {
+    let claim = Claim::take(stream, name)?;
+    Ok(Stdio {
+        file: File::new_nb_restoring(dup()?)?,
+        _claim: claim,
+    })
+}
+
+#[derive(Debug)]
+struct Stdio {
+    file: File<fs::File>,
+    // must be dropped after `file`, which restores the nonblocking mode
+    _claim: Claim,
+}
+
+/// The standard input of the process, for reading asynchronously.
+///
+/// Only one `Stdin` can exist at a time, since two registrations of the
+/// same stream would compete for its readiness and its nonblocking mode.
+///
+/// Nonblocking mode belongs to the open file rather than the descriptor, so
+/// while a `Stdin`, `Stdout` or `Stderr` exists, the stream is nonblocking
+/// for everyone sharing it: the rest of the process, such as `println!`,
+/// which panics with `WouldBlock` once a pipe is full, and also the parent
+/// process and any other process that inherited the same terminal or pipe.
+/// The mode is only restored once the wrapper is dropped, and not at all if
+/// the process is killed in the meantime.  On Linux,
+/// [`File::new_nb_duped`](struct.File.html#method.new_nb_duped) wraps a
+/// private copy of a pipe or terminal instead.
+#[derive(Debug)]
+pub struct Stdin(Stdio);
+
+/// The standard output of the process, for writing asynchronously.
+///
+/// Only one `Stdout` can exist at a time, and the stream is nonblocking for
+/// every other user of it meanwhile; see [`Stdin`](struct.Stdin.html).
+/// Nothing is buffered, and `print!` and the like write to the same stream
+/// without coordination.
+#[derive(Debug)]
+pub struct Stdout(Stdio);
+
+/// The standard error of the process, for writing asynchronously.
+///
+/// Only one `Stderr` can exist at a time, and the stream is nonblocking for
+/// every other user of it meanwhile, so that `eprintln!` can panic; see
+/// [`Stdin`](struct.Stdin.html).
+#[derive(Debug)]
+pub struct Stderr(Stdio);
+
+impl Stdin {
+    /// Wraps a duplicate of the standard input, like
+    /// [`File::new_nb_restoring`](struct.File.html#method.new_nb_restoring)
+    /// on [`raw_stdin`](fn.raw_stdin.html), so that its nonblocking mode is
+    /// restored once dropped.
+    ///
+    /// Fails with `AlreadyExists` while another `Stdin` exists.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        open(0, "standard input", crate::raw_stdin).map(Stdin)
+    }
+}
+
+impl Stdout {
+    /// Wraps a duplicate of the standard output.  See
+    /// [`Stdin::new`](struct.Stdin.html#method.new).
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        open(1, "standard output", crate::raw_stdout).map(Stdout)
+    }
+}
+
+impl Stderr {
+    /// Wraps a duplicate of the standard error.  See
+    /// [`Stdin::new`](struct.Stdin.html#method.new).
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        open(2, "standard error", crate::raw_stderr).map(Stderr)
+    }
+}
+
+macro_rules! impl_stdio {
+    ($ty:ty) => {
+        impl AsRef<File<fs::File>> for $ty {
+            fn as_ref(&self) -> &File<fs::File> {
+                &self.0.file
+            }
+        }
+
+        impl AsRawFd for $ty {
+            fn as_raw_fd(&self) -> RawFd {
+                self.0.file.as_raw_fd()
+            }
+        }
+
+        impl AsFd for $ty {
+            fn as_fd(&self) -> BorrowedFd<'_> {
+                self.0.file.as_fd()
+            }
+        }
+    };
+}
+
+macro_rules! impl_stdio_write {
+    ($ty:ty) => {
+        impl AsyncWrite for $ty {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.0.file).poll_write(cx, buf)
+            }
+
+            fn poll_write_vectored(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                bufs: &[io::IoSlice<'_>],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.0.file).poll_write_vectored(cx, bufs)
+            }
+
+            fn is_write_vectored(&self) -> bool {
+                self.0.file.is_write_vectored()
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0.file).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.0.file).poll_shutdown(cx)
+            }
+        }
+    };
+}
+
+impl_stdio!(Stdin);
+impl_stdio!(Stdout);
+impl_stdio!(Stderr);
+impl_stdio_write!(Stdout);
+impl_stdio_write!(Stderr);
+
+impl AsyncRead for Stdin {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0.file).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens a description of its own, unlike a duplicate of the real
+    /// standard input, whose flags the test must not change.
+    fn dev_null() -> io::Result<fs::File> {
+        fs::File::open("/dev/null")
+    }
+
+    #[tokio::test]
+    async fn test_one_stdin() -> io::Result<()> {
+        let stdin = open(0, "standard input", dev_null)?;
+        let e = open(0, "standard input", dev_null).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+        drop(stdin);
+        open(0, "standard input", dev_null)?;
+        Ok(())
+    }
+}
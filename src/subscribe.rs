@@ -0,0 +1,140 @@
+//! Watching the readiness of a file from elsewhere.
+
+use crate::{dupe_file_from_fd, File};
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::{fmt, io};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// The readiness of a file, as reported by the latest readiness event, sent
+/// to the receivers returned by
+/// [`File::subscribe`](struct.File.html#method.subscribe).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Readiness {
+    /// The file can be read from.
+    pub readable: bool,
+    /// The file can be written to.
+    pub writable: bool,
+    /// The peer has hung up, in either direction.
+    pub hung_up: bool,
+    /// An error is pending on the file.
+    pub error: bool,
+}
+
+/// The task that watches a file on behalf of its subscribers, stopped when
+/// the file is dropped.
+pub(crate) struct Subscription {
+    sender: Arc<watch::Sender<Readiness>>,
+    task: JoinHandle<()>,
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("receivers", &self.sender.receiver_count())
+            .finish()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn watch_readiness(fd: AsyncFd<std::fs::File>, sender: Arc<watch::Sender<Readiness>>) {
+    let interest = Interest::READABLE | Interest::WRITABLE | Interest::ERROR;
+    loop {
+        let guard = tokio::select! {
+            guard = fd.ready(interest) => guard,
+            _ = sender.closed() => return,
+        };
+        let mut guard = match guard {
+            Ok(guard) => guard,
+            Err(_) => return,
+        };
+        let ready = guard.ready();
+        let readiness = Readiness {
+            readable: ready.is_readable(),
+            writable: ready.is_writable(),
+            hung_up: ready.is_read_closed() || ready.is_write_closed(),
+            error: ready.is_error(),
+        };
+        sender.send_replace(readiness);
+        // a hangup is never cleared, so the file would be ready forever
+        if readiness.hung_up {
+            return;
+        }
+        guard.clear_ready();
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Returns a receiver of the readiness events of the file, so that
+    /// observers such as metrics or watchdogs can follow it without access
+    /// to the file itself.
+    ///
+    /// The value changes only when a new event arrives, not when readiness
+    /// is used up by reading or writing, so a file may no longer be readable
+    /// by the time a receiver sees `readable`, and it stops changing once
+    /// `hung_up` is reported.  All receivers share a single task, which holds
+    /// a duplicate of the descriptor and runs until then, or until the file
+    /// or every receiver is dropped.  For files that are not
+    /// pollable, such as regular files, the receiver reports the file as
+    /// always readable and writable and never changes.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn subscribe(&self) -> io::Result<watch::Receiver<Readiness>> {
+        if self.evented.is_none() {
+            let always = Readiness {
+                readable: true,
+                writable: true,
+                ..Readiness::default()
+            };
+            return Ok(watch::channel(always).1);
+        }
+        let mut subscription = self.subscription.lock().unwrap();
+        if let Some(ref subscription) = *subscription {
+            if !subscription.task.is_finished() {
+                return Ok(subscription.sender.subscribe());
+            }
+        }
+        let dup = unsafe { dupe_file_from_fd(self.as_raw_fd())? };
+        let fd = AsyncFd::with_interest(dup, Interest::READABLE | Interest::WRITABLE)?;
+        let (sender, receiver) = watch::channel(Readiness::default());
+        let sender = Arc::new(sender);
+        let task = tokio::spawn(watch_readiness(fd, sender.clone()));
+        *subscription = Some(Subscription { sender, task });
+        Ok(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_subscribe() -> io::Result<()> {
+        let (reader, mut writer) = crate::pipe()?;
+        let reader = reader.into_inner();
+        let mut first = reader.subscribe()?;
+        let mut second = reader.subscribe()?;
+        writer.write_all(b"x").await?;
+        first.changed().await.unwrap();
+        assert!(first.borrow_and_update().readable);
+        drop(writer);
+        second
+            .wait_for(|readiness| readiness.hung_up)
+            .await
+            .unwrap();
+
+        assert!(first.borrow_and_update().hung_up);
+        drop(reader);
+        assert!(first.changed().await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Making written data durable (`fsync(2)` and friends).
+
+use crate::File;
+use std::mem::ManuallyDrop;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::{fs, future, io};
+
+/// Runs `fdatasync` on `fd` right away.
+pub(crate) fn sync_data_now(fd: RawFd) -> io::Result<()> {
+    // borrows the descriptor for the portable std implementation
+    let file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) });
+    file.sync_data()
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Makes everything written to the file, data and metadata, durable,
+    /// like `std::fs::File::sync_all`, on the blocking thread pool.
+    ///
+    /// With `RegularFileMode::ThreadPool`, pending writes are flushed first.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub async fn sync_all(&self) -> io::Result<()> {
+        self.offload(|file| file.sync_all()).await
+    }
+
+    /// Like [`sync_all`](#method.sync_all), but leaves out metadata that is
+    /// not needed to read the data back, such as the modification time.
+    pub async fn sync_data(&self) -> io::Result<()> {
+        self.offload(|file| file.sync_data()).await
+    }
+
+    /// Writes back the dirty pages of `len` bytes starting at `offset`, or
+    /// up to the end of the file if `len` is 0, with `sync_file_range(2)`,
+    /// on the blocking thread pool.
+    ///
+    /// This neither writes metadata nor flushes the disk cache, so it
+    /// guarantees nothing on its own, but it lets a writer start writeback
+    /// early and wait for it when convenient.
+    ///
+    /// Only available on Linux.
+    #[cfg(target_os = "linux")]
+    pub async fn sync_range(&self, offset: u64, len: u64, flags: SyncRangeFlags) -> io::Result<()> {
+        self.offload(move |file| {
+            let r = unsafe {
+                libc::sync_file_range(
+                    file.as_raw_fd(),
+                    offset as libc::off64_t,
+                    len as libc::off64_t,
+                    flags.0,
+                )
+            };
+            if r < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Runs `op` on a duplicate of the descriptor on the blocking thread
+    /// pool, after any pending writes.
+    pub(crate) async fn offload<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&fs::File) -> io::Result<T> + Send + 'static,
+    ) -> io::Result<T> {
+        if let Some(ref blocking) = self.blocking {
+            future::poll_fn(|cx| blocking.poll_flush(cx, false)).await?;
+        }
+        // own the descriptor, since the operation outlives the future if it
+        // is cancelled
+        let file = unsafe { crate::dupe_file_from_fd(self.as_raw_fd())? };
+        tokio::task::spawn_blocking(move || op(&file))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(target_os = "linux")]
+flags! {
+    /// Flags for [`File::sync_range`](struct.File.html#method.sync_range),
+    /// combined with `|`.
+    pub struct SyncRangeFlags(libc::c_uint);
+
+    /// Wait for writeback of the range that is already under way before
+    /// starting (`SYNC_FILE_RANGE_WAIT_BEFORE`).
+    const WAIT_BEFORE = libc::SYNC_FILE_RANGE_WAIT_BEFORE;
+    /// Start writeback of the dirty pages in the range
+    /// (`SYNC_FILE_RANGE_WRITE`).
+    const WRITE = libc::SYNC_FILE_RANGE_WRITE;
+    /// Wait for the writeback to finish (`SYNC_FILE_RANGE_WAIT_AFTER`).
+    const WAIT_AFTER = libc::SYNC_FILE_RANGE_WAIT_AFTER;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use crate::{FileBuilder, RegularFileMode};
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_sync() -> io::Result<()> {
+        let path = TempPath::new("sync");
+        for &mode in &[RegularFileMode::Inline, RegularFileMode::ThreadPool] {
+            let mut file = FileBuilder::new()
+                .regular_file_mode(mode)
+                .sync_on_flush(true)
+                .build(fs::File::create(&path)?)?;
+            file.write_all(b"durable").await?;
+            file.flush().await?;
+            file.write_all(b" data").await?;
+            file.sync_data().await?;
+            #[cfg(target_os = "linux")]
+            file.sync_range(0, 0, SyncRangeFlags::WRITE | SyncRangeFlags::WAIT_AFTER)
+                .await?;
+            file.sync_all().await?;
+            assert_eq!(fs::read(&path)?, b"durable data");
+        }
+
+        // pipes cannot be synced
+        let (reader, _writer) = crate::pipe()?;
+        let err = reader.into_inner().sync_all().await.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        Ok(())
+    }
+}
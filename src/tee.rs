@@ -0,0 +1,168 @@
+//! Observing pipe contents with `tee(2)`.
+
+use crate::{poll_now, File};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{future, io};
+use tokio::io::{AsyncRead, ReadBuf};
+
+fn raw_tee(src: RawFd, dst: RawFd, len: usize) -> io::Result<usize> {
+    let n = unsafe { libc::tee(src, dst, len, libc::SPLICE_F_NONBLOCK) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+fn poll_tee<R, S>(
+    cx: &mut Context<'_>,
+    src: &File<R>,
+    dst: &File<S>,
+    len: usize,
+) -> Poll<io::Result<usize>>
+where
+    R: AsRawFd,
+    S: AsRawFd,
+{
+    src.check_pushback()?;
+    loop {
+        let mut src_ready = ready!(src.poll_read_ready(cx))?;
+        let mut dst_ready = ready!(dst.poll_write_ready(cx))?;
+        match raw_tee(src.as_raw_fd(), dst.as_raw_fd(), len) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                // tee does not tell us which side blocked
+                if !poll_now(src.as_raw_fd(), libc::POLLIN)? {
+                    src_ready.clear_ready();
+                }
+                if !poll_now(dst.as_raw_fd(), libc::POLLOUT)? {
+                    dst_ready.clear_ready();
+                }
+            }
+            r => return Poll::Ready(r),
+        }
+    }
+}
+
+/// Duplicates up to `len` bytes from the pipe `src` into the pipe `dst`
+/// without consuming them, returning the number of bytes duplicated, or 0
+/// once `src` has reached EOF.
+///
+/// The data stays in `src` for its reader, so a pipeline can mirror it into
+/// a logging pipe in passing.  Calling this again without first reading
+/// from `src` duplicates the same data again.  Both files must be pipes.
+///
+/// Only available on Linux.
+pub async fn tee<R, S>(src: &File<R>, dst: &File<S>, len: usize) -> io::Result<usize>
+where
+    R: AsRawFd,
+    S: AsRawFd,
+{
+    future::poll_fn(|cx| poll_tee(cx, src, dst, len)).await
+}
+
+/// Wraps the reading end of a pipe so that everything read from it is also
+/// duplicated into `sink`, which must be the writing end of another pipe.
+///
+/// The duplication happens inside the kernel via `tee(2)`, so observing a
+/// stream for logging or metrics costs no copy through user space.  The
+/// consumer reading from the returned [`Observed`](struct.Observed.html)
+/// still receives all of the data.
+///
+/// Observation is lossless: if `sink` is full, reads wait until it drains.
+///
+/// Only available on Linux.
+pub fn observe<R, S>(reader: File<R>, sink: File<S>) -> Observed<R, S> {
+    Observed {
+        reader,
+        sink,
+        teed: 0,
+    }
+}
+
+/// A pipe reader whose data is duplicated into a sink pipe, created by
+/// [`observe`](fn.observe.html).
+#[derive(Debug)]
+pub struct Observed<R, S> {
+    reader: File<R>,
+    sink: File<S>,
+    /// Bytes that have been duplicated but not yet consumed.
+    teed: usize,
+}
+
+impl<R, S> Observed<R, S> {
+    /// Returns the pipe reader and the sink.
+    pub fn into_inner(self) -> (File<R>, File<S>) {
+        (self.reader, self.sink)
+    }
+}
+
+impl<R, S> AsyncRead for Observed<R, S>
+where
+    R: AsRawFd + io::Read + Unpin,
+    S: AsRawFd + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.teed == 0 {
+            match ready!(poll_tee(cx, &this.reader, &this.sink, buf.remaining()))? {
+                0 => return Poll::Ready(Ok(())),
+                n => this.teed = n,
+            }
+        }
+        let mut limited = buf.take(this.teed);
+        ready!(Pin::new(&mut this.reader).poll_read(cx, &mut limited))?;
+        let n = limited.filled().len();
+        unsafe { buf.assume_init(n) };
+        buf.advance(n);
+        this.teed -= n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::raw_pipe;
+    use std::io::{Read, Write};
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_observe() -> io::Result<()> {
+        let (reader, mut writer) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        let (mut sink_reader, sink) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        writer.write_all(b"observed")?;
+        drop(writer);
+        let mut observed = observe(File::raw_new(reader)?, File::raw_new(sink)?);
+        let mut received = Vec::new();
+        observed.read_to_end(&mut received).await?;
+        assert_eq!(received, b"observed");
+        drop(observed);
+        let mut copy = Vec::new();
+        sink_reader.read_to_end(&mut copy)?;
+        assert_eq!(copy, b"observed");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tee() -> io::Result<()> {
+        let (mut reader, mut writer) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        let (mut log_reader, log) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        writer.write_all(b"mirrored")?;
+        let src = File::raw_new(reader.try_clone()?)?;
+        let log = File::raw_new(log)?;
+        assert_eq!(tee(&src, &log, 64).await?, 8);
+        drop(log);
+        let mut copy = Vec::new();
+        log_reader.read_to_end(&mut copy)?;
+        assert_eq!(copy, b"mirrored");
+        let mut original = [0; 8];
+        reader.read_exact(&mut original)?;
+        assert_eq!(&original, b"mirrored");
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Temporary files for the tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// A path in the temporary directory that is removed, along with whatever
+/// was created there, when dropped, so that a failing test leaves nothing
+/// behind.
+#[derive(Debug)]
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    /// Returns a path unique to the process, named after `name`, which must
+    /// be unique among the tests.
+    pub(crate) fn new(name: &str) -> Self {
+        TempPath(std::env::temp_dir().join(format!(
+            "tokio-file-unix-{}-{}",
+            name,
+            std::process::id()
+        )))
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // the test may have removed or renamed it already
+        let _ = match fs::symlink_metadata(&self.0) {
+            Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(&self.0),
+            Ok(_) => fs::remove_file(&self.0),
+            Err(_) => Ok(()),
+        };
+    }
+}
+
+/// Creates an empty regular file open for reading and writing, which is
+/// already unlinked.
+pub(crate) fn tempfile(name: &str) -> io::Result<fs::File> {
+    tempfile_with(
+        name,
+        fs::OpenOptions::new().read(true).write(true).truncate(true),
+    )
+}
+
+/// Like `tempfile`, but opens the file with `options`.
+pub(crate) fn tempfile_with(name: &str, options: &fs::OpenOptions) -> io::Result<fs::File> {
+    let path = TempPath::new(name);
+    options.clone().create(true).open(&path)
+}
@@ -0,0 +1,156 @@
+//! Terminal settings.
+
+use crate::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::{fmt, fs, io};
+
+mod caps;
+mod clipboard;
+pub mod input;
+mod password;
+mod prompt;
+#[cfg(feature = "readline")]
+pub mod readline;
+mod repl;
+mod resize;
+
+pub use self::caps::TermCaps;
+pub use self::clipboard::{copy_to_clipboard, write_clipboard, MAX_CLIPBOARD_LEN};
+pub use self::password::read_password;
+pub use self::prompt::prompt;
+pub use self::repl::{Repl, ReplPrinter};
+pub use self::resize::{resize_events, size, ResizeEvents};
+
+pub(crate) fn get_attr(fd: RawFd) -> io::Result<libc::termios> {
+    unsafe {
+        let mut termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(termios)
+    }
+}
+
+pub(crate) fn set_attr(fd: RawFd, termios: &libc::termios) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Switches the terminal behind `file` into raw mode, returning a guard that
+/// restores the original settings when dropped.
+///
+/// In raw mode, input is available byte by byte as soon as it is typed
+/// rather than line by line, nothing is echoed, special characters such as
+/// Ctrl-C are delivered as ordinary input instead of raising signals, and
+/// output is not post-processed (so `\n` no longer implies a carriage
+/// return).
+///
+/// Implementation detail: uses `tcgetattr`, `cfmakeraw`, and `tcsetattr`.
+pub fn set_raw_mode<F: AsRawFd>(file: &F) -> io::Result<RawModeGuard> {
+    set_mode(file, |termios| unsafe { libc::cfmakeraw(termios) })
+}
+
+/// Changes the settings of the terminal behind `file` with `change`,
+/// returning a guard that restores the original settings when dropped.
+pub(crate) fn set_mode<F: AsRawFd>(
+    file: &F,
+    change: impl FnOnce(&mut libc::termios),
+) -> io::Result<RawModeGuard> {
+    let fd = file.as_raw_fd();
+    let original = get_attr(fd)?;
+    let mut new = original;
+    change(&mut new);
+    // keep our own descriptor so that the settings can be restored even if
+    // the original is closed first
+    let tty = unsafe { crate::dupe_file_from_fd(fd)? };
+    set_attr(fd, &new)?;
+    Ok(RawModeGuard {
+        tty,
+        original: Some(original),
+    })
+}
+
+/// Restores the original terminal settings when dropped.  Created by
+/// [`set_raw_mode`](fn.set_raw_mode.html).
+pub struct RawModeGuard {
+    tty: fs::File,
+    original: Option<libc::termios>,
+}
+
+impl RawModeGuard {
+    /// Restores the original terminal settings now, reporting any error that
+    /// dropping the guard would have ignored.
+    pub fn restore(mut self) -> io::Result<()> {
+        match self.original.take() {
+            Some(ref original) => set_attr(self.tty.as_raw_fd(), original),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for RawModeGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawModeGuard")
+            .field("tty", &self.tty)
+            .finish()
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        if let Some(ref original) = self.original {
+            let _ = set_attr(self.tty.as_raw_fd(), original);
+        }
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Switches the underlying terminal into raw mode.  See
+    /// [`terminal::set_raw_mode`](terminal/fn.set_raw_mode.html).
+    pub fn set_raw_mode(&self) -> io::Result<RawModeGuard> {
+        set_raw_mode(self)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::os::unix::io::FromRawFd;
+
+    /// Opens a pseudoterminal, returning the master and the slave.
+    pub(crate) fn open_pty() -> io::Result<(fs::File, fs::File)> {
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+            if master < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = fs::File::from_raw_fd(master);
+            if libc::grantpt(master.as_raw_fd()) < 0 || libc::unlockpt(master.as_raw_fd()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let name = libc::ptsname(master.as_raw_fd());
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let slave = libc::open(name, libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+            if slave < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((master, fs::File::from_raw_fd(slave)))
+        }
+    }
+
+    #[test]
+    fn test_raw_mode() -> io::Result<()> {
+        let (_master, slave) = open_pty()?;
+        assert_ne!(get_attr(slave.as_raw_fd())?.c_lflag & libc::ICANON, 0);
+        let guard = set_raw_mode(&slave)?;
+        let raw = get_attr(slave.as_raw_fd())?;
+        assert_eq!(raw.c_lflag & (libc::ICANON | libc::ECHO), 0);
+        drop(guard);
+        assert_ne!(get_attr(slave.as_raw_fd())?.c_lflag & libc::ICANON, 0);
+        Ok(())
+    }
+}
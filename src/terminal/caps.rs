@@ -0,0 +1,176 @@
+//! Detection of terminal capabilities.
+
+use super::set_raw_mode;
+use crate::File;
+use std::time::Duration;
+use std::{env, fs, io};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// What a terminal is capable of, as far as can be told.  Created by
+/// [`TermCaps::from_env`](#method.from_env) or
+/// [`TermCaps::probe`](#method.probe).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TermCaps {
+    dumb: bool,
+    colors: u32,
+    unicode: bool,
+    wide_chars: Option<bool>,
+    device_attributes: Option<Vec<u32>>,
+}
+
+impl TermCaps {
+    /// Guesses the capabilities from the `TERM`, `COLORTERM`, and locale
+    /// environment variables alone.
+    pub fn from_env() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let dumb = term.is_empty() || term == "dumb";
+        let colorterm = var("COLORTERM");
+        let colors = if dumb {
+            0
+        } else if colorterm == "truecolor" || colorterm == "24bit" {
+            1 << 24
+        } else if term.contains("256color") {
+            256
+        } else {
+            8
+        };
+        // the first of these that is set determines the character set
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .map(|name| var(name))
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        TermCaps {
+            dumb,
+            colors,
+            unicode: locale.contains("utf-8") || locale.contains("utf8"),
+            wide_chars: None,
+            device_attributes: None,
+        }
+    }
+
+    /// Like [`from_env`](#method.from_env), but also queries the terminal
+    /// behind `tty` for its primary device attributes (DA1) and checks
+    /// whether it draws wide characters two columns wide, using a cursor
+    /// position report (DSR).  Replies that do not arrive within `timeout`
+    /// are treated as unsupported, so this is safe on terminals that ignore
+    /// the queries.
+    ///
+    /// The terminal is switched into raw mode while probing, and the probe
+    /// character is erased afterwards.  Nothing else should be reading from
+    /// the terminal at the same time.
+    pub async fn probe(tty: &File<fs::File>, timeout: Duration) -> io::Result<Self> {
+        let mut caps = TermCaps::from_env();
+        if caps.dumb {
+            return Ok(caps);
+        }
+        let _raw = set_raw_mode(tty)?;
+        let mut tty = tty;
+        // an emoji followed by DSR, then DA1, which every terminal answers
+        // last
+        tty.write_all("\r\u{1f600}\x1b[6n\r\x1b[K\x1b[c".as_bytes())
+            .await?;
+        let mut replies = Vec::new();
+        let mut buf = [0; 64];
+        let read_replies = async {
+            loop {
+                let n = tty.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok::<_, io::Error>(());
+                }
+                replies.extend_from_slice(&buf[..n]);
+                if parse_replies(&replies).1.is_some() {
+                    return Ok(());
+                }
+            }
+        };
+        if let Ok(r) = tokio::time::timeout(timeout, read_replies).await {
+            r?;
+        }
+        let (column, attributes) = parse_replies(&replies);
+        caps.wide_chars = column.map(|column| column == 3);
+        caps.device_attributes = attributes;
+        Ok(caps)
+    }
+
+    /// Whether the terminal is missing or `dumb`, in which case it should
+    /// be sent plain text only.
+    pub fn is_dumb(&self) -> bool {
+        self.dumb
+    }
+
+    /// The number of colors the terminal supports: 0, 8, 256, or 2^24.
+    pub fn colors(&self) -> u32 {
+        self.colors
+    }
+
+    /// Whether the terminal supports 24-bit colors.
+    pub fn truecolor(&self) -> bool {
+        self.colors >= 1 << 24
+    }
+
+    /// Whether the locale uses UTF-8, so that non-ASCII text can be shown.
+    pub fn unicode(&self) -> bool {
+        self.unicode
+    }
+
+    /// Whether wide characters such as emoji take up two columns, or `None`
+    /// if unknown.
+    pub fn wide_chars(&self) -> Option<bool> {
+        self.wide_chars
+    }
+
+    /// The parameters of the primary device attributes reply, the first of
+    /// which identifies the terminal class, or `None` if the terminal did
+    /// not reply.
+    pub fn device_attributes(&self) -> Option<&[u32]> {
+        self.device_attributes.as_deref()
+    }
+}
+
+/// Extracts the cursor column of a cursor position report and the
+/// parameters of a DA1 reply from the bytes read from a terminal.
+fn parse_replies(input: &[u8]) -> (Option<u32>, Option<Vec<u32>>) {
+    let mut column = None;
+    let mut attributes = None;
+    let mut rest = input;
+    while let Some(start) = rest.windows(2).position(|w| w == b"\x1b[") {
+        rest = &rest[start + 2..];
+        let end = match rest.iter().position(|b| (0x40..=0x7e).contains(b)) {
+            Some(end) => end,
+            None => break,
+        };
+        let (params, private) = match rest.first() {
+            Some(b'?') => (&rest[1..end], true),
+            _ => (&rest[..end], false),
+        };
+        let params: Vec<u32> = String::from_utf8_lossy(params)
+            .split(';')
+            .filter_map(|p| p.parse().ok())
+            .collect();
+        match rest[end] {
+            b'R' if !private && params.len() == 2 => column = Some(params[1]),
+            b'c' if private => attributes = Some(params),
+            _ => {}
+        }
+        rest = &rest[end + 1..];
+    }
+    (column, attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_replies() {
+        assert_eq!(parse_replies(b""), (None, None));
+        assert_eq!(
+            parse_replies(b"\x1b[12;3R\x1b[?62;22c"),
+            (Some(3), Some(vec![62, 22]))
+        );
+        assert_eq!(parse_replies(b"\x1b[?1;2c"), (None, Some(vec![1, 2])));
+    }
+}
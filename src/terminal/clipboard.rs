@@ -0,0 +1,86 @@
+//! Setting the clipboard of the terminal emulator (OSC 52).
+
+use crate::File;
+use std::{fs, io};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The largest amount of data accepted by
+/// [`copy_to_clipboard`](fn.copy_to_clipboard.html), which keeps the
+/// encoded sequence under the 100000 bytes that common terminals accept.
+pub const MAX_CLIPBOARD_LEN: usize = 74_994;
+
+/// The amount of encoded data written at once, so that a slow terminal is
+/// not handed one huge write.
+const CHUNK_LEN: usize = 4096;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize]);
+            } else {
+                out.push(b'=');
+            }
+        }
+    }
+    out
+}
+
+/// Asks the terminal emulator behind `tty` to put `data` on the system
+/// clipboard, using the OSC 52 escape sequence.  This works over SSH too,
+/// but the terminal may ignore the request, and there is no way to tell.
+///
+/// Fails with `InvalidInput` if `data` is longer than
+/// [`MAX_CLIPBOARD_LEN`](constant.MAX_CLIPBOARD_LEN.html).
+pub async fn write_clipboard<W>(tty: &mut W, data: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    if data.len() > MAX_CLIPBOARD_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too much data for the clipboard",
+        ));
+    }
+    tty.write_all(b"\x1b]52;c;").await?;
+    for chunk in base64(data).chunks(CHUNK_LEN) {
+        tty.write_all(chunk).await?;
+    }
+    tty.write_all(b"\x07").await?;
+    tty.flush().await
+}
+
+/// Like [`write_clipboard`](fn.write_clipboard.html), on the controlling
+/// terminal of the process (`/dev/tty`).
+///
+/// This must be called within the context of a Tokio runtime.
+pub async fn copy_to_clipboard(data: &[u8]) -> io::Result<()> {
+    let tty = File::new_nb_restoring(fs::OpenOptions::new().write(true).open("/dev/tty")?)?;
+    write_clipboard(&mut &tty, data).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_clipboard() -> io::Result<()> {
+        assert_eq!(base64(b""), b"");
+        assert_eq!(base64(b"f"), b"Zg==");
+        assert_eq!(base64(b"fo"), b"Zm8=");
+        assert_eq!(base64(b"foo"), b"Zm9v");
+        let mut out = Vec::new();
+        write_clipboard(&mut out, b"copied").await?;
+        assert_eq!(out, b"\x1b]52;c;Y29waWVk\x07");
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+//! Decoding key presses from a terminal in raw mode.
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use futures::StreamExt;
+//! use tokio_file_unix::terminal::input::{EventStream, KeyCode};
+//!
+//! let stdin = tokio_file_unix::File::new_nb(tokio_file_unix::raw_stdin()?)?;
+//! let _guard = stdin.set_raw_mode()?;
+//! let mut events = EventStream::new(stdin);
+//! while let Some(event) = events.next().await {
+//!     if event?.code() == KeyCode::Esc {
+//!         break;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_core::Stream;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Sleep;
+
+/// How long a lone `ESC` waits for the rest of an escape sequence before it
+/// is taken to be the Escape key.
+pub const DEFAULT_ESC_TIMEOUT: Duration = Duration::from_millis(50);
+
+const ESC: u8 = 0x1b;
+
+/// A key, without modifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    /// A character.  Control characters are reported as the letter with
+    /// `Modifiers::CTRL`, so Ctrl-C is `Char('c')`.
+    Char(char),
+    /// Enter or Return, sent as `\r`, or `\n` by some terminals.
+    Enter,
+    /// Tab; Shift-Tab is reported with `Modifiers::SHIFT`.
+    Tab,
+    /// Backspace, sent as `DEL` or `BS`.
+    Backspace,
+    /// Escape.
+    Esc,
+    /// The up arrow.
+    Up,
+    /// The down arrow.
+    Down,
+    /// The left arrow.
+    Left,
+    /// The right arrow.
+    Right,
+    /// Home.
+    Home,
+    /// End.
+    End,
+    /// Page Up.
+    PageUp,
+    /// Page Down.
+    PageDown,
+    /// Insert.
+    Insert,
+    /// Delete, as opposed to Backspace.
+    Delete,
+    /// A function key, from `F(1)` to `F(12)`.
+    F(u8),
+}
+
+flags! {
+    /// Modifier keys held during a key press, combined with `|`.
+    pub struct Modifiers(u8);
+
+    /// No modifiers.
+    const NONE = 0;
+    /// Shift.
+    const SHIFT = 1;
+    /// Alt, or Meta, which terminals send as a leading `ESC`.
+    const ALT = 2;
+    /// Control.
+    const CTRL = 4;
+}
+
+impl Modifiers {
+    /// Decodes the modifier parameter of a CSI sequence, which is one more
+    /// than the bits.
+    fn from_param(param: u32) -> Modifiers {
+        Modifiers((param.saturating_sub(1) & 7) as u8)
+    }
+}
+
+/// A key press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    code: KeyCode,
+    modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// Creates a key event.
+    pub fn new(code: KeyCode, modifiers: Modifiers) -> Self {
+        KeyEvent { code, modifiers }
+    }
+
+    /// The key that was pressed.
+    pub fn code(&self) -> KeyCode {
+        self.code
+    }
+
+    /// The modifiers that were held.
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+}
+
+enum Parsed {
+    Event(KeyEvent, usize),
+    /// Bytes that do not make up a key, such as an unknown sequence.
+    Skip(usize),
+    Incomplete,
+}
+
+fn key(code: KeyCode, modifiers: Modifiers, len: usize) -> Parsed {
+    Parsed::Event(KeyEvent::new(code, modifiers), len)
+}
+
+/// Decodes the key at the start of `input`.
+fn parse(input: &[u8]) -> Parsed {
+    let byte = match input.first() {
+        Some(&byte) => byte,
+        None => return Parsed::Incomplete,
+    };
+    match byte {
+        ESC => parse_escape(input),
+        b'\r' | b'\n' => key(KeyCode::Enter, Modifiers::NONE, 1),
+        b'\t' => key(KeyCode::Tab, Modifiers::NONE, 1),
+        0x08 | 0x7f => key(KeyCode::Backspace, Modifiers::NONE, 1),
+        0x00 => key(KeyCode::Char(' '), Modifiers::CTRL, 1),
+        0x01..=0x1a => key(KeyCode::Char((b'a' + byte - 1) as char), Modifiers::CTRL, 1),
+        0x1c..=0x1f => key(KeyCode::Char((byte + 0x18) as char), Modifiers::CTRL, 1),
+        _ => parse_utf8(input),
+    }
+}
+
+fn parse_utf8(input: &[u8]) -> Parsed {
+    let len = match input[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Parsed::Skip(1),
+    };
+    if input.len() < len {
+        return Parsed::Incomplete;
+    }
+    match std::str::from_utf8(&input[..len]) {
+        Ok(text) => {
+            let c = text.chars().next().unwrap();
+            key(KeyCode::Char(c), Modifiers::NONE, len)
+        }
+        Err(_) => Parsed::Skip(1),
+    }
+}
+
+fn parse_escape(input: &[u8]) -> Parsed {
+    match input.get(1) {
+        None => Parsed::Incomplete,
+        Some(b'[') => parse_csi(input),
+        Some(b'O') => match input.get(2) {
+            None => Parsed::Incomplete,
+            Some(&last) => match final_key(last) {
+                Some(code) => key(code, Modifiers::NONE, 3),
+                None => Parsed::Skip(3),
+            },
+        },
+        // Alt with another key
+        Some(_) => match parse(&input[1..]) {
+            Parsed::Event(event, len) => key(event.code, event.modifiers | Modifiers::ALT, len + 1),
+            Parsed::Skip(len) => Parsed::Skip(len + 1),
+            Parsed::Incomplete => Parsed::Incomplete,
+        },
+    }
+}
+
+/// The key of a sequence ending with `last`, with no parameters other than
+/// modifiers.
+fn final_key(last: u8) -> Option<KeyCode> {
+    Some(match last {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'R' => KeyCode::F(3),
+        b'S' => KeyCode::F(4),
+        _ => return None,
+    })
+}
+
+/// Parses a control sequence, `ESC [` followed by parameters and a final
+/// byte.
+fn parse_csi(input: &[u8]) -> Parsed {
+    let end = match input[2..].iter().position(|b| (0x40..=0x7e).contains(b)) {
+        Some(i) => i + 2,
+        None => return Parsed::Incomplete,
+    };
+    let len = end + 1;
+    let mut params = input[2..end]
+        .split(|&b| b == b';')
+        .map(|param| std::str::from_utf8(param).ok().and_then(|p| p.parse().ok()));
+    let first: Option<u32> = params.next().flatten();
+    let modifiers = Modifiers::from_param(params.next().flatten().unwrap_or(1));
+    let code = match input[end] {
+        b'Z' => return key(KeyCode::Tab, Modifiers::SHIFT, len),
+        b'~' => match first {
+            Some(1) | Some(7) => KeyCode::Home,
+            Some(2) => KeyCode::Insert,
+            Some(3) => KeyCode::Delete,
+            Some(4) | Some(8) => KeyCode::End,
+            Some(5) => KeyCode::PageUp,
+            Some(6) => KeyCode::PageDown,
+            Some(n @ 11..=15) => KeyCode::F((n - 10) as u8),
+            Some(n @ 17..=21) => KeyCode::F((n - 11) as u8),
+            Some(n @ 23..=24) => KeyCode::F((n - 12) as u8),
+            _ => return Parsed::Skip(len),
+        },
+        last => match final_key(last) {
+            Some(code) => code,
+            None => return Parsed::Skip(len),
+        },
+    };
+    key(code, modifiers, len)
+}
+
+/// A `Stream` of the keys read from a terminal in raw mode, see
+/// [`set_raw_mode`](../fn.set_raw_mode.html).
+///
+/// An `ESC` that is not followed by the rest of an escape sequence within
+/// the [timeout](#method.esc_timeout) is reported as the Escape key.  The
+/// stream ends when the reader does.
+#[derive(Debug)]
+pub struct EventStream<R> {
+    reader: R,
+    buf: Vec<u8>,
+    esc_timeout: Duration,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> EventStream<R> {
+    /// Decodes the keys read from `reader`.
+    pub fn new(reader: R) -> Self {
+        EventStream {
+            reader,
+            buf: Vec::new(),
+            esc_timeout: DEFAULT_ESC_TIMEOUT,
+            timer: None,
+        }
+    }
+
+    /// Sets how long a lone `ESC` waits for the rest of an escape sequence.
+    /// Defaults to [`DEFAULT_ESC_TIMEOUT`](constant.DEFAULT_ESC_TIMEOUT.html).
+    pub fn esc_timeout(mut self, timeout: Duration) -> Self {
+        self.esc_timeout = timeout;
+        self
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps the underlying reader, discarding any partial input.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reports the `ESC` at the start of the buffer as the Escape key.
+    fn take_esc(&mut self) -> Poll<Option<io::Result<KeyEvent>>> {
+        self.timer = None;
+        self.buf.remove(0);
+        Poll::Ready(Some(Ok(KeyEvent::new(KeyCode::Esc, Modifiers::NONE))))
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for EventStream<R> {
+    type Item = io::Result<KeyEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match parse(&this.buf) {
+                Parsed::Event(event, len) => {
+                    this.buf.drain(..len);
+                    this.timer = None;
+                    return Poll::Ready(Some(Ok(event)));
+                }
+                Parsed::Skip(len) => {
+                    this.buf.drain(..len);
+                    continue;
+                }
+                Parsed::Incomplete => {}
+            }
+            let mut data = [0; 256];
+            let mut dst = ReadBuf::new(&mut data);
+            match Pin::new(&mut this.reader).poll_read(cx, &mut dst) {
+                Poll::Ready(Ok(())) if dst.filled().is_empty() => {
+                    if this.buf.first() == Some(&ESC) {
+                        return this.take_esc();
+                    }
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Ok(())) => {
+                    this.buf.extend_from_slice(dst.filled());
+                    this.timer = None;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => {
+                    if this.buf.first() != Some(&ESC) {
+                        return Poll::Pending;
+                    }
+                    let timeout = this.esc_timeout;
+                    let timer = this
+                        .timer
+                        .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                    ready!(timer.as_mut().poll(cx));
+                    return this.take_esc();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    fn event(code: KeyCode, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() -> io::Result<()> {
+        let input: &[u8] = b"a\x1b[A\x1b[1;5C\x1bOP\x1b[15~\x1b[3~\xc3\xa9\x03\x1bx\r\x1b";
+        let events: Vec<KeyEvent> = EventStream::new(input).map(Result::unwrap).collect().await;
+        assert_eq!(
+            events,
+            vec![
+                event(KeyCode::Char('a'), Modifiers::NONE),
+                event(KeyCode::Up, Modifiers::NONE),
+                event(KeyCode::Right, Modifiers::CTRL),
+                event(KeyCode::F(1), Modifiers::NONE),
+                event(KeyCode::F(5), Modifiers::NONE),
+                event(KeyCode::Delete, Modifiers::NONE),
+                event(KeyCode::Char('é'), Modifiers::NONE),
+                event(KeyCode::Char('c'), Modifiers::CTRL),
+                event(KeyCode::Char('x'), Modifiers::ALT),
+                event(KeyCode::Enter, Modifiers::NONE),
+                event(KeyCode::Esc, Modifiers::NONE),
+            ]
+        );
+
+        // a lone ESC followed by nothing times out
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let mut events = EventStream::new(reader);
+        writer.write_all(b"\x1b").await?;
+        assert_eq!(
+            events.next().await.unwrap()?,
+            event(KeyCode::Esc, Modifiers::NONE)
+        );
+        writer.write_all(b"\x1b[B").await?;
+        assert_eq!(
+            events.next().await.unwrap()?,
+            event(KeyCode::Down, Modifiers::NONE)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! Reading a password without echoing it.
+
+use super::set_mode;
+use crate::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Prompts for a password on the controlling terminal, see
+/// [`raw_tty`](../fn.raw_tty.html), and reads one line with echo turned
+/// off, returning it without the line ending.
+///
+/// The newline is still echoed, so that whatever is printed next starts on
+/// a line of its own.  The terminal settings are restored when the line has
+/// been read, or if the future is dropped before that.  Fails with
+/// `UnexpectedEof` if the input ends before the line does, and with
+/// `InvalidData` if it is not UTF-8.
+///
+/// This must be called within the context of a Tokio runtime.
+pub async fn read_password(prompt: &str) -> io::Result<String> {
+    let tty = File::new_nb_restoring(crate::raw_tty()?)?;
+    read_password_on(&tty, prompt).await
+}
+
+async fn read_password_on<F: AsRawFd>(tty: &File<F>, prompt: &str) -> io::Result<String>
+where
+    for<'a> &'a F: io::Read + io::Write,
+{
+    let _echo = set_mode(tty, |termios| {
+        termios.c_lflag &= !libc::ECHO;
+        termios.c_lflag |= libc::ECHONL;
+    })?;
+    let mut writer = tty;
+    writer.write_all(prompt.as_bytes()).await?;
+    writer.flush().await?;
+    let mut reader = tty;
+    let mut line = Vec::new();
+    let mut buf = [0; 256];
+    while !line.ends_with(b"\n") {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.extend_from_slice(&buf[..n]);
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::get_attr;
+    use crate::terminal::tests::open_pty;
+    use std::io::{Read, Write};
+
+    #[tokio::test]
+    async fn test_read_password() -> io::Result<()> {
+        let (master, slave) = open_pty()?;
+        let tty = File::new_nb(slave)?;
+        let echo = get_attr(tty.as_raw_fd())?.c_lflag & libc::ECHO;
+        assert_ne!(echo, 0);
+        let typist = master.try_clone()?;
+        let typist = tokio::task::spawn_blocking(move || {
+            let mut prompt = [0; 10];
+            (&typist).read_exact(&mut prompt)?;
+            (&typist).write_all(b"hunter2\n")?;
+            Ok::<_, io::Error>(prompt)
+        });
+        let password = read_password_on(&tty, "Password: ").await?;
+        assert_eq!(&typist.await.unwrap()?, b"Password: ");
+        assert_eq!(password, "hunter2");
+        assert_eq!(get_attr(tty.as_raw_fd())?.c_lflag & libc::ECHO, echo);
+
+        // only the newline was echoed
+        let mut echoed = [0; 2];
+        (&master).read_exact(&mut echoed)?;
+        assert_eq!(&echoed, b"\r\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Asking for one line of input.
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Writes `text` to `output`, then reads one line from `input` and returns
+/// it without the line ending, or `None` if the input has ended.
+///
+/// The prompt is only written if `input` is a terminal, as a shell does not
+/// print its prompt when it reads commands from a pipe, where nothing is
+/// echoed to separate one prompt from the next.  If the input of a terminal
+/// ends, with Ctrl-D, a newline is written, so that whatever is printed
+/// next starts on a line of its own.  A last line without a line ending is
+/// still returned.  Fails with `InvalidData` if the line is not UTF-8.
+///
+/// The input must be buffered, such as with
+/// [`File::into_buf_reader`](../struct.File.html#method.into_buf_reader), so
+/// that the lines after the first are kept for the next call.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio_file_unix::{raw_stdin, terminal, File, Stdout};
+///
+/// let mut input = File::new_nb(raw_stdin()?)?.into_buf_reader(4096);
+/// let mut output = Stdout::new()?;
+/// while let Some(line) = terminal::prompt(&mut input, &mut output, "> ").await? {
+///     println!("got {:?}", line);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn prompt<R, W>(input: &mut R, output: &mut W, text: &str) -> io::Result<Option<String>>
+where
+    R: AsyncBufRead + AsRawFd + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let tty = unsafe { libc::isatty(input.as_raw_fd()) } == 1;
+    if tty {
+        output.write_all(text.as_bytes()).await?;
+        output.flush().await?;
+    }
+    let mut line = Vec::new();
+    if input.read_until(b'\n', &mut line).await? == 0 {
+        if tty {
+            output.write_all(b"\n").await?;
+            output.flush().await?;
+        }
+        return Ok(None);
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::tests::open_pty;
+    use crate::File;
+    use std::io::{Read, Write};
+
+    #[tokio::test]
+    async fn test_prompt() -> io::Result<()> {
+        let (mut master, slave) = open_pty()?;
+        let mut output = File::new_nb(slave.try_clone()?)?;
+        let mut input = File::new_nb(slave)?.into_buf_reader(64);
+        master.write_all(b"first\n")?;
+        let line = prompt(&mut input, &mut output, "> ").await?;
+        assert_eq!(line.as_deref(), Some("first"));
+        // the echo of the line comes before the prompt
+        let mut shown = [0; 9];
+        master.read_exact(&mut shown)?;
+        assert_eq!(&shown, b"first\r\n> ");
+
+        let (reader, writer) = crate::pipe()?;
+        let mut input = reader.into_inner().into_buf_reader(64);
+        writer.into_inner().try_write(b"one\ntwo")?;
+        let (shown, output) = crate::pipe()?;
+        let mut output = output.into_inner();
+        let line = prompt(&mut input, &mut output, "> ").await?;
+        assert_eq!(line.as_deref(), Some("one"));
+        let line = prompt(&mut input, &mut output, "> ").await?;
+        assert_eq!(line.as_deref(), Some("two"));
+        assert_eq!(prompt(&mut input, &mut output, "> ").await?, None);
+        // no prompts for input from a pipe
+        drop(output);
+        let mut shown = shown.into_inner();
+        let mut data = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(&mut shown, &mut data).await?;
+        assert_eq!(data, b"");
+        Ok(())
+    }
+}
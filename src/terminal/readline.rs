@@ -0,0 +1,418 @@
+//! Reading lines with editing, history, and completion, in the manner of
+//! GNU Readline (the `readline` feature).
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use futures::StreamExt;
+//! use tokio_file_unix::terminal::readline::Editor;
+//!
+//! let commands = ["help", "history", "quit"];
+//! let mut editor = Editor::stdio("> ")?.completer(move |line: &str, pos: usize| {
+//!     let candidates = commands
+//!         .iter()
+//!         .filter(|command| command.starts_with(&line[..pos]))
+//!         .map(|command| command.to_string())
+//!         .collect();
+//!     (0, candidates)
+//! });
+//! while let Some(line) = editor.next().await {
+//!     if line? == "quit" {
+//!         break;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::input::{EventStream, KeyCode, KeyEvent, Modifiers};
+use super::{set_raw_mode, RawModeGuard};
+use crate::File;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fmt, fs, io, mem};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Suggests completions for the word under the cursor when Tab is pressed.
+///
+/// Implemented for closures taking the line and the byte offset of the
+/// cursor in it.
+pub trait Completer: Send {
+    /// Returns the byte offset at which the word being completed starts,
+    /// and the candidates that could replace the line from there up to the
+    /// cursor.
+    fn complete(&mut self, line: &str, pos: usize) -> (usize, Vec<String>);
+}
+
+impl<F> Completer for F
+where
+    F: FnMut(&str, usize) -> (usize, Vec<String>) + Send,
+{
+    fn complete(&mut self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        self(line, pos)
+    }
+}
+
+/// A `Stream` of the lines typed on a terminal in raw mode, see
+/// [`set_raw_mode`](../fn.set_raw_mode.html), with the following keys:
+///
+/// | Key                      | Action                                   |
+/// |--------------------------|------------------------------------------|
+/// | Left, Right, Ctrl-B/F    | move the cursor by a character           |
+/// | Home, End, Ctrl-A/E      | move the cursor to the start or end      |
+/// | Backspace, Delete        | erase the character before or under it   |
+/// | Ctrl-U, Ctrl-K           | erase up to or from the cursor           |
+/// | Ctrl-W                   | erase the word before the cursor         |
+/// | Up, Down, Ctrl-P/N       | recall earlier or later lines            |
+/// | Tab                      | complete, see [`completer`](#method.completer) |
+/// | Enter                    | submit the line                          |
+/// | Ctrl-C                   | discard the line                         |
+/// | Ctrl-D                   | end the input if the line is empty       |
+///
+/// Each character is assumed to take up one column.  Submitted lines are
+/// added to the history unless empty or the same as the previous one.  The
+/// stream ends when the input does or Ctrl-D is pressed on an empty line.
+pub struct Editor<R, W> {
+    events: EventStream<R>,
+    output: W,
+    prompt: String,
+    completer: Option<Box<dyn Completer>>,
+    line: Vec<char>,
+    /// The position of the cursor in `line`.
+    cursor: usize,
+    history: Vec<String>,
+    /// The history entry being shown, or `history.len()` for the new line.
+    history_pos: usize,
+    /// The line being typed before browsing the history.
+    draft: Vec<char>,
+    /// Whether the prompt for the next line has yet to be drawn.
+    fresh: bool,
+    /// Output to be written before anything else happens.
+    pending: Vec<u8>,
+    /// The line submitted, returned once `pending` has been written.
+    submitted: Option<Option<String>>,
+    _raw: Option<RawModeGuard>,
+}
+
+impl Editor<File<fs::File>, File<fs::File>> {
+    /// Creates an `Editor` on the standard input and output of the process,
+    /// switching the terminal into raw mode for as long as it exists.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn stdio<P: Into<String>>(prompt: P) -> io::Result<Self> {
+        let input = File::new_nb_restoring(crate::raw_stdin()?)?;
+        let output = File::new_nb_restoring(crate::raw_stdout()?)?;
+        let raw = set_raw_mode(&input)?;
+        let mut editor = Editor::new(input, output, prompt);
+        editor._raw = Some(raw);
+        Ok(editor)
+    }
+}
+
+impl<R, W> Editor<R, W> {
+    /// Creates an `Editor` that reads keys from `input`, which should be a
+    /// terminal in raw mode, and draws on `output`.
+    pub fn new<P: Into<String>>(input: R, output: W, prompt: P) -> Self {
+        Editor {
+            events: EventStream::new(input),
+            output,
+            prompt: prompt.into(),
+            completer: None,
+            line: Vec::new(),
+            cursor: 0,
+            history: Vec::new(),
+            history_pos: 0,
+            draft: Vec::new(),
+            fresh: true,
+            pending: Vec::new(),
+            submitted: None,
+            _raw: None,
+        }
+    }
+
+    /// Completes the word under the cursor with `completer` when Tab is
+    /// pressed.  A single candidate replaces the word; otherwise the word
+    /// is extended to the longest prefix the candidates share, and if there
+    /// is none, they are listed below the line.
+    pub fn completer<C: Completer + 'static>(mut self, completer: C) -> Self {
+        self.completer = Some(Box::new(completer));
+        self
+    }
+
+    /// Changes the prompt, starting with the next line.
+    pub fn set_prompt<P: Into<String>>(&mut self, prompt: P) {
+        self.prompt = prompt.into();
+    }
+
+    /// The lines submitted so far, oldest first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Adds a line to the history, as if it had been submitted, for example
+    /// to restore the history of an earlier session.
+    pub fn add_history<L: Into<String>>(&mut self, line: L) {
+        let line = line.into();
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line);
+        }
+        self.history_pos = self.history.len();
+    }
+
+    /// Returns the input and the output.  The terminal is taken out of raw
+    /// mode, if the `Editor` switched it.
+    pub fn into_inner(self) -> (R, W) {
+        (self.events.into_inner(), self.output)
+    }
+
+    /// Draws the prompt and the line, leaving the terminal cursor where the
+    /// cursor of the line is.
+    fn redraw(&mut self) {
+        let line: String = self.line.iter().collect();
+        let text = format!("\r\x1b[K{}{}", self.prompt, line);
+        self.pending.extend_from_slice(text.as_bytes());
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            self.pending
+                .extend_from_slice(format!("\x1b[{}D", back).as_bytes());
+        }
+    }
+
+    fn insert(&mut self, text: &[char]) {
+        let tail = self.line.split_off(self.cursor);
+        self.line.extend_from_slice(text);
+        self.line.extend(tail);
+        self.cursor += text.len();
+    }
+
+    fn recall(&mut self, pos: usize) {
+        if self.history_pos == self.history.len() {
+            self.draft = mem::take(&mut self.line);
+        }
+        self.history_pos = pos;
+        self.line = match self.history.get(pos) {
+            Some(line) => line.chars().collect(),
+            None => mem::take(&mut self.draft),
+        };
+        self.cursor = self.line.len();
+    }
+
+    fn complete(&mut self) {
+        let completer = match self.completer {
+            Some(ref mut completer) => completer,
+            None => return,
+        };
+        let line: String = self.line.iter().collect();
+        let pos = line
+            .char_indices()
+            .nth(self.cursor)
+            .map_or(line.len(), |(i, _)| i);
+        let (start, candidates) = completer.complete(&line, pos);
+        let start = start.min(pos);
+        let word = &line[start..pos];
+        let replacement = match candidates.len() {
+            0 => return,
+            1 => candidates[0].clone(),
+            _ => {
+                let prefix = common_prefix(&candidates);
+                if prefix.len() > word.len() {
+                    prefix.to_owned()
+                } else {
+                    self.pending.extend_from_slice(b"\r\n");
+                    self.pending
+                        .extend_from_slice(candidates.join("  ").as_bytes());
+                    self.pending.extend_from_slice(b"\r\n");
+                    return;
+                }
+            }
+        };
+        let word_chars = word.chars().count();
+        self.line.drain(self.cursor - word_chars..self.cursor);
+        self.cursor -= word_chars;
+        self.insert(&replacement.chars().collect::<Vec<_>>());
+    }
+
+    fn submit(&mut self, line: Option<String>) {
+        self.pending.extend_from_slice(b"\r\n");
+        if let Some(ref line) = line {
+            self.add_history(line.clone());
+        }
+        self.line.clear();
+        self.draft.clear();
+        self.cursor = 0;
+        self.history_pos = self.history.len();
+        self.fresh = true;
+        self.submitted = Some(line);
+    }
+
+    /// Applies a key, redrawing the line if it changed.
+    fn apply(&mut self, event: KeyEvent) {
+        let ctrl = event.modifiers().contains(Modifiers::CTRL);
+        let code = match (event.code(), ctrl) {
+            (KeyCode::Char('a'), true) => KeyCode::Home,
+            (KeyCode::Char('e'), true) => KeyCode::End,
+            (KeyCode::Char('b'), true) => KeyCode::Left,
+            (KeyCode::Char('f'), true) => KeyCode::Right,
+            (KeyCode::Char('p'), true) => KeyCode::Up,
+            (KeyCode::Char('n'), true) => KeyCode::Down,
+            (KeyCode::Char('d'), true) if !self.line.is_empty() => KeyCode::Delete,
+            (code, _) => code,
+        };
+        match code {
+            KeyCode::Char('c') if ctrl => {
+                self.pending.extend_from_slice(b"^C\r\n");
+                self.line.clear();
+                self.cursor = 0;
+                self.history_pos = self.history.len();
+            }
+            KeyCode::Char('d') if ctrl => return self.submit(None),
+            KeyCode::Char('u') if ctrl => {
+                self.line.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            KeyCode::Char('k') if ctrl => self.line.truncate(self.cursor),
+            KeyCode::Char('w') if ctrl => {
+                let mut start = self.cursor;
+                while start > 0 && self.line[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.line[start - 1] != ' ' {
+                    start -= 1;
+                }
+                self.line.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            KeyCode::Char(c) if !ctrl && !event.modifiers().contains(Modifiers::ALT) => {
+                self.insert(&[c])
+            }
+            KeyCode::Enter => {
+                let line = self.line.iter().collect();
+                return self.submit(Some(line));
+            }
+            KeyCode::Tab if event.modifiers() == Modifiers::NONE => self.complete(),
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            }
+            KeyCode::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            KeyCode::Left if self.cursor > 0 => self.cursor -= 1,
+            KeyCode::Right if self.cursor < self.line.len() => self.cursor += 1,
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.line.len(),
+            KeyCode::Up if self.history_pos > 0 => self.recall(self.history_pos - 1),
+            KeyCode::Down if self.history_pos < self.history.len() => {
+                self.recall(self.history_pos + 1)
+            }
+            _ => return,
+        }
+        self.redraw();
+    }
+}
+
+/// The longest prefix shared by all of `strings`, which must not be empty.
+fn common_prefix(strings: &[String]) -> &str {
+    let first = &strings[0];
+    let mut len = first.len();
+    for s in &strings[1..] {
+        len = first
+            .char_indices()
+            .zip(s.chars())
+            .find(|&((_, a), b)| a != b)
+            .map_or(len.min(s.len()), |((i, _), _)| i.min(len));
+    }
+    &first[..len]
+}
+
+impl<R, W> fmt::Debug for Editor<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Editor")
+            .field("prompt", &self.prompt)
+            .field("line", &self.line.iter().collect::<String>())
+            .field("cursor", &self.cursor)
+            .finish()
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Stream for Editor<R, W> {
+    type Item = io::Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            while !this.pending.is_empty() {
+                let n = ready!(Pin::new(&mut this.output).poll_write(cx, &this.pending))?;
+                if n == 0 {
+                    return Poll::Ready(Some(Err(io::ErrorKind::WriteZero.into())));
+                }
+                this.pending.drain(..n);
+            }
+            ready!(Pin::new(&mut this.output).poll_flush(cx))?;
+            if let Some(line) = this.submitted.take() {
+                return Poll::Ready(line.map(Ok));
+            }
+            if this.fresh {
+                this.fresh = false;
+                this.redraw();
+                continue;
+            }
+            match ready!(Pin::new(&mut this.events).poll_next(cx)) {
+                Some(Ok(event)) => this.apply(event),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => this.submit(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_editor() -> io::Result<()> {
+        let keys: &[u8] = b"ab\x1b[Dc\r\
+            xyz\x01w\x05!\r\
+            \x1b[A\x1b[A\r\
+            tail\x1b[H\x1b[3~\x0b\r\
+            ex\t\r\
+            he\t\r\
+            \x04";
+        let words = ["exit", "help", "hello"];
+        let mut editor = Editor::new(keys, Vec::new(), "> ").completer(move |line: &str, pos| {
+            let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+            let candidates = words
+                .iter()
+                .filter(|word| word.starts_with(&line[start..pos]))
+                .map(|word| word.to_string())
+                .collect();
+            (start, candidates)
+        });
+        assert_eq!(editor.next().await.unwrap()?, "acb");
+        assert_eq!(editor.next().await.unwrap()?, "wxyz!");
+        assert_eq!(editor.next().await.unwrap()?, "acb");
+        // Home, Delete, Ctrl-K leave nothing
+        assert_eq!(editor.next().await.unwrap()?, "");
+        assert_eq!(editor.next().await.unwrap()?, "exit");
+        assert_eq!(editor.next().await.unwrap()?, "hel");
+        assert!(editor.next().await.is_none());
+        assert_eq!(editor.history(), ["acb", "wxyz!", "acb", "exit", "hel"]);
+
+        let (_, output) = editor.into_inner();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("\r\x1b[K> "), "{:?}", output);
+        // the cursor moved back over "b" after inserting "c"
+        assert!(output.contains("\r\x1b[K> acb\x1b[1D"), "{:?}", output);
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&strings(&["help", "hello"])), "hel");
+        assert_eq!(common_prefix(&strings(&["help", "he"])), "he");
+        assert_eq!(common_prefix(&strings(&["a", "b"])), "");
+    }
+}
@@ -0,0 +1,336 @@
+//! An interactive read-eval-print loop on a terminal.
+
+use super::{set_raw_mode, RawModeGuard};
+use crate::File;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{fmt, fs, io, mem};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const CTRL_U: u8 = 0x15;
+const ESC: u8 = 0x1b;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+struct Editor {
+    output: File<fs::File>,
+    prompt: String,
+    /// Whether the prompt for the current line has yet to be drawn.
+    fresh: bool,
+    line: String,
+    /// Trailing bytes of an incomplete UTF-8 sequence.
+    partial: Vec<u8>,
+    /// The bytes of an incomplete escape sequence, starting with `ESC`.
+    escape: Option<Vec<u8>>,
+    /// Keys typed ahead of the line being returned.
+    keys: VecDeque<Key>,
+    history: Vec<String>,
+    /// The history entry being shown, or `history.len()` for the new line.
+    history_pos: usize,
+}
+
+enum Key {
+    Text(String),
+    Enter,
+    Interrupt,
+    Eof,
+    Erase,
+    Kill,
+    Up,
+    Down,
+}
+
+impl Editor {
+    /// Splits raw input into keys, keeping incomplete sequences for later.
+    fn push_input(&mut self, input: &[u8]) {
+        let keys = &mut self.keys;
+        for &byte in input {
+            if let Some(mut escape) = self.escape.take() {
+                escape.push(byte);
+                let done = match escape.len() {
+                    2 => byte != b'[' && byte != b'O',
+                    _ => (0x40..=0x7e).contains(&byte),
+                };
+                if !done {
+                    self.escape = Some(escape);
+                } else if escape.len() == 3 && byte == b'A' {
+                    keys.push_back(Key::Up);
+                } else if escape.len() == 3 && byte == b'B' {
+                    keys.push_back(Key::Down);
+                }
+                continue;
+            }
+            let key = match byte {
+                b'\r' | b'\n' => Key::Enter,
+                CTRL_C => Key::Interrupt,
+                CTRL_D => Key::Eof,
+                CTRL_U => Key::Kill,
+                BACKSPACE | DELETE => Key::Erase,
+                ESC => {
+                    self.escape = Some(vec![byte]);
+                    continue;
+                }
+                0x00..=0x1f => continue,
+                _ => {
+                    self.partial.push(byte);
+                    match std::str::from_utf8(&self.partial) {
+                        Ok(text) => {
+                            let text = text.to_owned();
+                            self.partial.clear();
+                            Key::Text(text)
+                        }
+                        Err(ref e) if e.error_len().is_none() => continue,
+                        // invalid, drop it
+                        Err(_) => {
+                            self.partial.clear();
+                            continue;
+                        }
+                    }
+                }
+            };
+            keys.push_back(key);
+        }
+    }
+
+    async fn redraw(&mut self) -> io::Result<()> {
+        let text = format!("\r\x1b[K{}{}", self.prompt, self.line);
+        (&self.output).write_all(text.as_bytes()).await
+    }
+
+    fn recall(&mut self, pos: usize) {
+        self.history_pos = pos;
+        self.line = self.history.get(pos).cloned().unwrap_or_default();
+    }
+
+    /// Applies a key, returning the finished line for `Enter` and `None`
+    /// inside `Some` for EOF.
+    async fn apply(&mut self, key: Key) -> io::Result<Option<Option<String>>> {
+        match key {
+            Key::Text(text) => {
+                self.line.push_str(&text);
+                (&self.output).write_all(text.as_bytes()).await?;
+            }
+            Key::Erase => {
+                if self.line.pop().is_some() {
+                    (&self.output).write_all(b"\x08 \x08").await?;
+                }
+            }
+            Key::Kill => {
+                self.line.clear();
+                self.redraw().await?;
+            }
+            Key::Up if self.history_pos > 0 => {
+                self.recall(self.history_pos - 1);
+                self.redraw().await?;
+            }
+            Key::Down if self.history_pos < self.history.len() => {
+                self.recall(self.history_pos + 1);
+                self.redraw().await?;
+            }
+            Key::Up | Key::Down => {}
+            Key::Interrupt => {
+                (&self.output).write_all(b"^C\r\n").await?;
+                self.line.clear();
+                self.history_pos = self.history.len();
+                self.redraw().await?;
+            }
+            Key::Eof if self.line.is_empty() => {
+                (&self.output).write_all(b"\r\n").await?;
+                return Ok(Some(None));
+            }
+            Key::Eof => {}
+            Key::Enter => {
+                (&self.output).write_all(b"\r\n").await?;
+                let line = mem::take(&mut self.line);
+                if !line.is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.history_pos = self.history.len();
+                self.fresh = true;
+                return Ok(Some(Some(line)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+struct Shared {
+    input: File<fs::File>,
+    editor: Mutex<Editor>,
+}
+
+/// Reads the next line, or `None` at EOF.
+async fn next_line(shared: Arc<Shared>) -> io::Result<Option<String>> {
+    {
+        let mut editor = shared.editor.lock().await;
+        if editor.fresh {
+            editor.fresh = false;
+            editor.redraw().await?;
+        }
+    }
+    let mut buf = [0; 64];
+    loop {
+        {
+            let mut editor = shared.editor.lock().await;
+            while let Some(key) = editor.keys.pop_front() {
+                if let Some(line) = editor.apply(key).await? {
+                    return Ok(line);
+                }
+            }
+        }
+        let n = (&shared.input).read(&mut buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        shared.editor.lock().await.push_input(&buf[..n]);
+    }
+}
+
+type NextLine = Pin<Box<dyn Future<Output = io::Result<Option<String>>> + Send>>;
+
+/// A line-oriented prompt on a terminal, in the manner of a shell.
+///
+/// The terminal is switched into raw mode for as long as the `Repl` exists.
+/// Lines are read with basic editing: backspace, Ctrl-U to erase the line,
+/// Ctrl-C to discard it, the up and down arrows to browse the history, and
+/// Ctrl-D on an empty line to end the input.  Finished lines are yielded by
+/// the `Stream` implementation.
+///
+/// Output produced while the user is typing should go through a
+/// [`ReplPrinter`](struct.ReplPrinter.html), which moves the prompt and the
+/// partial line out of the way and redraws them afterwards.
+pub struct Repl {
+    shared: Arc<Shared>,
+    pending: Option<NextLine>,
+    _raw: RawModeGuard,
+}
+
+impl Repl {
+    /// Creates a `Repl` on the standard input and output of the process.
+    /// Their original modes are restored once the `Repl` and all of its
+    /// printers are dropped.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new<P: Into<String>>(prompt: P) -> io::Result<Self> {
+        let input = File::new_nb_restoring(crate::raw_stdin()?)?;
+        let output = File::new_nb_restoring(crate::raw_stdout()?)?;
+        Repl::with_files(input, output, prompt)
+    }
+
+    /// Creates a `Repl` that reads from the terminal `input` and draws on
+    /// `output`.
+    pub fn with_files<P: Into<String>>(
+        input: File<fs::File>,
+        output: File<fs::File>,
+        prompt: P,
+    ) -> io::Result<Self> {
+        let raw = set_raw_mode(&input)?;
+        Ok(Repl {
+            shared: Arc::new(Shared {
+                input,
+                editor: Mutex::new(Editor {
+                    output,
+                    prompt: prompt.into(),
+                    fresh: true,
+                    line: String::new(),
+                    partial: Vec::new(),
+                    escape: None,
+                    keys: VecDeque::new(),
+                    history: Vec::new(),
+                    history_pos: 0,
+                }),
+            }),
+            pending: None,
+            _raw: raw,
+        })
+    }
+
+    /// Returns a handle for printing without disturbing the prompt.
+    pub fn printer(&self) -> ReplPrinter {
+        ReplPrinter {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Repl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Repl")
+            .field("input", &self.shared.input)
+            .finish()
+    }
+}
+
+impl Stream for Repl {
+    type Item = io::Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let shared = self.shared.clone();
+        let pending = self
+            .pending
+            .get_or_insert_with(|| Box::pin(next_line(shared)));
+        let result = ready!(pending.as_mut().poll(cx));
+        self.pending = None;
+        Poll::Ready(result.transpose())
+    }
+}
+
+/// Prints above the prompt of a [`Repl`](struct.Repl.html).  Created by
+/// [`Repl::printer`](struct.Repl.html#method.printer).
+#[derive(Clone)]
+pub struct ReplPrinter {
+    shared: Arc<Shared>,
+}
+
+impl ReplPrinter {
+    /// Prints `text`, redrawing the prompt and the partial line below it if
+    /// the user is in the middle of typing.
+    pub async fn print(&self, text: &str) -> io::Result<()> {
+        let mut editor = self.shared.editor.lock().await;
+        // raw mode turns off the translation of newlines
+        let text = text.replace('\n', "\r\n");
+        if editor.fresh {
+            return (&editor.output).write_all(text.as_bytes()).await;
+        }
+        (&editor.output).write_all(b"\r\x1b[K").await?;
+        (&editor.output).write_all(text.as_bytes()).await?;
+        if !text.ends_with('\n') {
+            (&editor.output).write_all(b"\r\n").await?;
+        }
+        editor.redraw().await
+    }
+}
+
+impl fmt::Debug for ReplPrinter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplPrinter").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::tests::open_pty;
+    use futures::StreamExt;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_repl() -> io::Result<()> {
+        let (mut master, slave) = open_pty()?;
+        let output = File::new_nb(slave.try_clone()?)?;
+        let mut repl = Repl::with_files(File::new_nb(slave)?, output, "> ")?;
+        master.write_all(b"ls\x7f\x7fecho hi\rsecond\r\x1b[A\r\x04")?;
+        assert_eq!(repl.next().await.unwrap()?, "echo hi");
+        assert_eq!(repl.next().await.unwrap()?, "second");
+        assert_eq!(repl.next().await.unwrap()?, "second");
+        assert!(repl.next().await.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+//! Terminal size and resize notifications (`TIOCGWINSZ` and `SIGWINCH`).
+
+use crate::File;
+use futures_core::Stream;
+use std::os::unix::io::{AsRawFd, IntoRawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::{fs, io, mem, ptr};
+
+/// Gets the size of the terminal behind `file` as `(columns, rows)`.
+///
+/// Implementation detail: uses `ioctl` with `TIOCGWINSZ`.
+pub fn size<F: AsRawFd>(file: &F) -> io::Result<(u16, u16)> {
+    let mut winsize: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((winsize.ws_col, winsize.ws_row))
+}
+
+/// The number of `SIGWINCH` received so far.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// Whether the pipe holds an unread byte, so that the handler writes at
+/// most one and the write can never fail and clobber `errno`.
+static PENDING: AtomicBool = AtomicBool::new(false);
+/// The writing end of the pipe, for the handler.
+static NOTIFY_FD: AtomicI32 = AtomicI32::new(-1);
+/// The handler that was installed before ours, as a `sa_sigaction` value.
+static PREVIOUS: AtomicUsize = AtomicUsize::new(libc::SIG_DFL);
+static PREVIOUS_SIGINFO: AtomicBool = AtomicBool::new(false);
+/// The reading end of the pipe, once the handler is installed.
+static READER: Mutex<Option<fs::File>> = Mutex::new(None);
+
+extern "C" fn on_sigwinch(
+    signo: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    if !PENDING.swap(true, Ordering::SeqCst) {
+        let fd = NOTIFY_FD.load(Ordering::SeqCst);
+        unsafe { libc::write(fd, b"\0".as_ptr() as *const libc::c_void, 1) };
+    }
+    let previous = PREVIOUS.load(Ordering::SeqCst);
+    if previous == libc::SIG_DFL || previous == libc::SIG_IGN {
+        return;
+    }
+    unsafe {
+        if PREVIOUS_SIGINFO.load(Ordering::SeqCst) {
+            let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                mem::transmute(previous);
+            handler(signo, info, context);
+        } else {
+            let handler: extern "C" fn(libc::c_int) = mem::transmute(previous);
+            handler(signo);
+        }
+    }
+}
+
+/// Installs the `SIGWINCH` handler if need be, returning a duplicate of the
+/// reading end of the pipe it reports to.
+fn install() -> io::Result<fs::File> {
+    let mut reader = READER.lock().unwrap();
+    if reader.is_none() {
+        let (r, w) = crate::pipe::raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        let r = crate::move_fd_above(r, crate::STDIO_END)?;
+        let w = crate::move_fd_above(w, crate::STDIO_END)?;
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_sigwinch as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = mem::zeroed();
+            // the handler lives as long as the process, and so does the
+            // writing end of the pipe
+            NOTIFY_FD.store(w.into_raw_fd(), Ordering::SeqCst);
+            if libc::sigaction(libc::SIGWINCH, ptr::null(), &mut previous) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            PREVIOUS.store(previous.sa_sigaction, Ordering::SeqCst);
+            PREVIOUS_SIGINFO.store(previous.sa_flags & libc::SA_SIGINFO != 0, Ordering::SeqCst);
+            if libc::sigaction(libc::SIGWINCH, &action, ptr::null_mut()) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        *reader = Some(r);
+    }
+    let fd = reader.as_ref().unwrap().as_raw_fd();
+    unsafe { crate::dupe_file_from_fd(fd) }
+}
+
+/// A `Stream` of the sizes of a terminal as `(columns, rows)`, yielding one
+/// every time the process receives `SIGWINCH`.  Created by
+/// [`resize_events`](fn.resize_events.html).
+///
+/// Signals that arrive before the stream is polled are coalesced into a
+/// single item.
+#[derive(Debug)]
+pub struct ResizeEvents {
+    pipe: File<fs::File>,
+    tty: fs::File,
+    seen: usize,
+}
+
+/// Watches for the terminal behind `tty` being resized, installing a
+/// `SIGWINCH` handler the first time it is called.  The handler is never
+/// removed, and calls whichever handler was installed before it.
+///
+/// This works on every Unix, without blocking the signal as
+/// [`SignalFd`](../struct.SignalFd.html) would require.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn resize_events<F: AsRawFd>(tty: &F) -> io::Result<ResizeEvents> {
+    let tty = unsafe { crate::dupe_file_from_fd(tty.as_raw_fd())? };
+    let seen = GENERATION.load(Ordering::SeqCst);
+    Ok(ResizeEvents {
+        pipe: File::raw_new(install()?)?,
+        tty,
+        seen,
+    })
+}
+
+impl Stream for ResizeEvents {
+    type Item = io::Result<(u16, u16)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let generation = GENERATION.load(Ordering::SeqCst);
+            if generation != this.seen {
+                this.seen = generation;
+                return Poll::Ready(Some(size(&this.tty)));
+            }
+            let mut guard = ready!(this.pipe.poll_read_ready(cx))?;
+            PENDING.store(false, Ordering::SeqCst);
+            let mut buf = [0; 16];
+            loop {
+                match this.pipe.try_read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+            // another stream may have drained the byte first
+            if GENERATION.load(Ordering::SeqCst) == this.seen {
+                guard.clear_ready();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::tests::open_pty;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_resize_events() -> io::Result<()> {
+        let (master, slave) = open_pty()?;
+        let winsize = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        assert_eq!(
+            unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &winsize) },
+            0
+        );
+        assert_eq!(size(&slave)?, (80, 24));
+
+        let mut events = resize_events(&slave)?;
+        // the pseudoterminal is not our controlling terminal, so the kernel
+        // does not send the signal itself
+        unsafe { libc::raise(libc::SIGWINCH) };
+        assert_eq!(events.next().await.unwrap()?, (80, 24));
+        Ok(())
+    }
+}
@@ -0,0 +1,329 @@
+//! Helpers for testing code built on this crate, without real terminals or
+//! peers (the `testing` feature).
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//! use tokio_file_unix::testing::pipe_pair;
+//!
+//! let (mut client, mut server) = pipe_pair()?;
+//! client.write_all(b"ping").await?;
+//! let mut buf = [0; 4];
+//! server.read_exact(&mut buf).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{PipeReader, PipeWriter, ThrottledFile};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Creates two connected ends, each reading what the other writes, out of
+/// two pipes.
+///
+/// Unlike a socket pair, each direction is a real pipe, with the buffer
+/// size and hangup behavior that a program talking to a child process over
+/// its standard streams would see.
+///
+/// This must be called within the context of a Tokio runtime.
+pub fn pipe_pair() -> io::Result<(Loopback, Loopback)> {
+    let (a_reader, b_writer) = crate::pipe()?;
+    let (b_reader, a_writer) = crate::pipe()?;
+    Ok((
+        Loopback {
+            reader: a_reader,
+            writer: a_writer,
+        },
+        Loopback {
+            reader: b_reader,
+            writer: b_writer,
+        },
+    ))
+}
+
+/// One end of a [`pipe_pair`](fn.pipe_pair.html).
+#[derive(Debug)]
+pub struct Loopback {
+    reader: PipeReader,
+    writer: PipeWriter,
+}
+
+impl Loopback {
+    /// Returns the pipe read from and the pipe written to, for example to
+    /// close the writing end alone and let the other end see the input
+    /// end.
+    pub fn into_split(self) -> (PipeReader, PipeWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+impl AsyncRead for Loopback {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.reader).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Loopback {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// Creates a pipe whose writing end passes at most `bytes_per_sec` bytes
+/// per second, like
+/// [`File::throttle`](../struct.File.html#method.throttle), to feed code
+/// under test with input that trickles in.
+///
+/// The pacing follows Tokio's clock, so it is deterministic in tests that
+/// pause time.
+///
+/// Panics if `bytes_per_sec` is 0.  This must be called within the context
+/// of a Tokio runtime.
+pub fn slow_writer(bytes_per_sec: u64) -> io::Result<(PipeReader, ThrottledFile<fs::File>)> {
+    let (reader, writer) = crate::pipe()?;
+    Ok((reader, writer.into_inner().throttle(bytes_per_sec)))
+}
+
+/// Creates a descriptor whose readiness is set by hand, along with the
+/// handle that sets it.
+///
+/// The descriptor starts out writable but not readable.  Wrap it with
+/// [`File::new_nb`](../struct.File.html#method.new_nb) like any other, and
+/// drive the code under test through exactly the readiness states it has
+/// to handle.
+///
+/// Implementation detail: the descriptor is one end of a Unix socket pair,
+/// made readable by sending it a byte from the other end, and unwritable by
+/// filling its send buffer.
+pub fn fake_fd() -> io::Result<(FakeFd, ReadinessControl)> {
+    let (local, peer) = UnixStream::pair()?;
+    let local = FakeFd(local);
+    // keep the buffer that has to be filled small
+    let size: libc::c_int = 4096;
+    let r = unsafe {
+        libc::setsockopt(
+            local.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_SNDBUF,
+            &size as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let control = ReadinessControl {
+        local: local.0.try_clone()?,
+        peer,
+    };
+    Ok((local, control))
+}
+
+/// A descriptor whose readiness is set through a
+/// [`ReadinessControl`](struct.ReadinessControl.html), created by
+/// [`fake_fd`](fn.fake_fd.html).
+///
+/// What is read from it is filler, and what is written to it is discarded
+/// once it is made writable again; it stands in for a descriptor only as
+/// far as waiting for readiness goes.
+#[derive(Debug)]
+pub struct FakeFd(UnixStream);
+
+impl AsRawFd for FakeFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsFd for FakeFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl Read for FakeFd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for FakeFd {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sets the readiness of a [`FakeFd`](struct.FakeFd.html).
+///
+/// Readiness that the reactor has already reported stays cached by
+/// [`File`](../struct.File.html) until it is cleared, by a read or write
+/// that would block or by
+/// [`ReadyGuard::clear_ready`](../struct.ReadyGuard.html#method.clear_ready),
+/// as with any descriptor.  So a file made unready is only seen as such
+/// once the code under test has tried it.
+#[derive(Debug)]
+pub struct ReadinessControl {
+    /// A duplicate of the fake descriptor.
+    local: UnixStream,
+    peer: UnixStream,
+}
+
+impl ReadinessControl {
+    /// Makes the descriptor readable or not.
+    pub fn set_readable(&mut self, readable: bool) -> io::Result<()> {
+        let fd = self.local.as_raw_fd();
+        if !readable {
+            return drain(fd);
+        }
+        if !crate::poll_now(fd, libc::POLLIN)? {
+            send(self.peer.as_raw_fd(), &[0])?;
+        }
+        Ok(())
+    }
+
+    /// Makes the descriptor writable or not.
+    pub fn set_writable(&mut self, writable: bool) -> io::Result<()> {
+        if writable {
+            return drain(self.peer.as_raw_fd());
+        }
+        let filler = [0; 4096];
+        loop {
+            match send(self.local.as_raw_fd(), &filler) {
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Hangs up the other end, so that the descriptor reports a hangup and
+    /// reads reach the end of input once any pending byte has been read.
+    pub fn hang_up(self) {}
+}
+
+fn send(fd: RawFd, buf: &[u8]) -> io::Result<usize> {
+    let n = unsafe {
+        libc::send(
+            fd,
+            buf.as_ptr() as *const libc::c_void,
+            buf.len(),
+            libc::MSG_DONTWAIT,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// Discards everything waiting to be received on `fd`.
+fn drain(fd: RawFd) -> io::Result<()> {
+    let mut buf = [0; 4096];
+    loop {
+        let n = unsafe {
+            libc::recv(
+                fd,
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if n == 0 {
+            return Ok(());
+        }
+        if n < 0 {
+            return match io::Error::last_os_error() {
+                ref e if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+                ref e if e.kind() == io::ErrorKind::Interrupted => continue,
+                e => Err(e),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::File;
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_pipe_pair() -> io::Result<()> {
+        let (mut a, mut b) = pipe_pair()?;
+        a.write_all(b"ping").await?;
+        b.write_all(b"pong").await?;
+        let mut buf = [0; 4];
+        b.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"ping");
+        a.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"pong");
+
+        let (_, writer) = a.into_split();
+        drop(writer);
+        assert_eq!(b.read(&mut buf).await?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_slow_writer() -> io::Result<()> {
+        let (mut reader, mut writer) = slow_writer(10_000)?;
+        let start = Instant::now();
+        // a second's worth passes at once, the rest at the rate
+        let write = tokio::spawn(async move { writer.write_all(&[0; 11_000]).await });
+        let mut data = vec![0; 11_000];
+        reader.read_exact(&mut data).await?;
+        write.await.unwrap()?;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fake_fd() -> io::Result<()> {
+        let (fake, mut control) = fake_fd()?;
+        let file = File::new_nb(fake)?;
+        let wait = Duration::from_millis(50);
+        assert!(tokio::time::timeout(wait, file.readable()).await.is_err());
+        control.set_readable(true)?;
+        tokio::time::timeout(wait, file.readable()).await.unwrap()?;
+
+        control.set_writable(false)?;
+        // the cached readiness has to be cleared first
+        file.writable().await?.clear_ready();
+        assert!(tokio::time::timeout(wait, file.writable()).await.is_err());
+        control.set_writable(true)?;
+        tokio::time::timeout(wait, file.writable()).await.unwrap()?;
+
+        control.set_readable(false)?;
+        let e = file.try_read(&mut [0; 1]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+        control.hang_up();
+        assert_eq!(file.try_read(&mut [0; 1])?, 0);
+        Ok(())
+    }
+}
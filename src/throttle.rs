@@ -0,0 +1,178 @@
+//! Pacing reads and writes to a fixed rate.
+
+use crate::quota::read_limited;
+use crate::File;
+use std::future::Future;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+impl<F> File<F> {
+    /// Wraps the file so that reads and writes each pass at most
+    /// `bytes_per_sec` bytes per second, for example to pace data fed into
+    /// a slow consumer downstream.
+    ///
+    /// Each direction has its own token bucket holding up to a second's
+    /// worth of bytes, which starts out full, so up to `bytes_per_sec`
+    /// bytes may pass in a burst after the file has been idle.  Operations
+    /// that find the bucket empty wait on Tokio's timer until it refills.
+    ///
+    /// Panics if `bytes_per_sec` is 0.
+    pub fn throttle(self, bytes_per_sec: u64) -> ThrottledFile<F> {
+        assert!(bytes_per_sec > 0, "zero rate");
+        ThrottledFile {
+            inner: self,
+            read: Bucket::new(bytes_per_sec),
+            write: Bucket::new(bytes_per_sec),
+        }
+    }
+}
+
+/// A token bucket refilled at a fixed rate.
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+    /// Wakes a waiting operation once a byte's worth has been refilled.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Bucket {
+    fn new(bytes_per_sec: u64) -> Self {
+        Bucket {
+            rate: bytes_per_sec as f64,
+            tokens: bytes_per_sec as f64,
+            refilled: Instant::now(),
+            sleep: None,
+        }
+    }
+
+    /// Polls for the number of bytes that may pass now, at least 1.
+    fn poll_available(&mut self, cx: &mut Context<'_>) -> Poll<usize> {
+        loop {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+            self.refilled = now;
+            if self.tokens >= 1.0 {
+                self.sleep = None;
+                return Poll::Ready(self.tokens as usize);
+            }
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / self.rate);
+            let deadline = now + wait;
+            match self.sleep {
+                Some(ref mut sleep) => sleep.as_mut().reset(deadline),
+                None => self.sleep = Some(Box::pin(tokio::time::sleep_until(deadline))),
+            }
+            ready!(self.sleep.as_mut().unwrap().as_mut().poll(cx));
+        }
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.tokens -= n as f64;
+    }
+}
+
+/// A [`File`](struct.File.html) whose reads and writes are paced, created
+/// by [`File::throttle`](struct.File.html#method.throttle).
+#[derive(Debug)]
+pub struct ThrottledFile<F> {
+    inner: File<F>,
+    read: Bucket,
+    write: Bucket,
+}
+
+impl<F> ThrottledFile<F> {
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the file.  Reads and writes made through
+    /// it are not paced.
+    pub fn get_mut(&mut self) -> &mut File<F> {
+        &mut self.inner
+    }
+
+    /// Returns the file.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for ThrottledFile<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<F: AsRawFd + io::Read + Unpin> AsyncRead for ThrottledFile<F> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let limit = ready!(this.read.poll_available(cx));
+        let filled = buf.filled().len();
+        let inner = &mut this.inner;
+        ready!(read_limited(buf, limit, |buf| Pin::new(inner).poll_read(cx, buf)))?;
+        this.read.consume(buf.filled().len() - filled);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<F: AsRawFd + io::Write + Unpin> AsyncWrite for ThrottledFile<F> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        let limit = ready!(this.write.poll_available(cx));
+        let buf = &buf[..buf.len().min(limit)];
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.write.consume(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_throttle() -> io::Result<()> {
+        let (reader, writer) = crate::pipe()?;
+        let mut reader = reader.into_inner();
+        let mut writer = writer.into_inner().throttle(1000);
+        let start = Instant::now();
+        // a second's worth goes through at once, the rest at the rate
+        writer.write_all(&[0; 1100]).await?;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(90), "{:?}", elapsed);
+        let mut data = vec![0; 1100];
+        reader.read_exact(&mut data).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Kernel timers (`timerfd_create(2)`).
+
+use crate::File;
+use futures_core::Stream;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fs, io, mem};
+
+/// The clock that drives a [`TimerFd`](struct.TimerFd.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerClock {
+    /// `CLOCK_MONOTONIC`, which stops while the system is suspended.
+    Monotonic,
+    /// `CLOCK_BOOTTIME`, which keeps counting during suspend.
+    Boottime,
+    /// `CLOCK_REALTIME`, the wall clock.
+    Realtime,
+}
+
+impl TimerClock {
+    fn id(self) -> libc::clockid_t {
+        match self {
+            TimerClock::Monotonic => libc::CLOCK_MONOTONIC,
+            TimerClock::Boottime => libc::CLOCK_BOOTTIME,
+            TimerClock::Realtime => libc::CLOCK_REALTIME,
+        }
+    }
+}
+
+fn timespec(duration: Duration) -> libc::timespec {
+    libc::timespec {
+        tv_sec: duration.as_secs() as libc::time_t,
+        tv_nsec: duration.subsec_nanos() as _,
+    }
+}
+
+/// A timer backed by `timerfd_create(2)`, so that timing is driven by a
+/// kernel clock rather than Tokio's timer wheel.
+///
+/// The `Stream` implementation yields the number of expirations since the
+/// previous item, which is more than 1 if the consumer fell behind a
+/// periodic timer.  A disarmed timer never yields.
+///
+/// Only available on Linux.
+#[derive(Debug)]
+pub struct TimerFd {
+    file: File<fs::File>,
+}
+
+impl TimerFd {
+    /// Creates a disarmed timer on the given clock.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn new(clock: TimerClock) -> io::Result<Self> {
+        let fd =
+            unsafe { libc::timerfd_create(clock.id(), libc::TFD_NONBLOCK | libc::TFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+        Ok(TimerFd {
+            file: File::raw_new(file)?,
+        })
+    }
+
+    fn set(&self, value: Duration, interval: Duration) -> io::Result<()> {
+        let spec = libc::itimerspec {
+            it_value: timespec(value),
+            it_interval: timespec(interval),
+        };
+        let r = unsafe { libc::timerfd_settime(self.as_raw_fd(), 0, &spec, std::ptr::null_mut()) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Arms the timer to expire once after `delay`.  A zero `delay` is
+    /// rounded up to the smallest possible one, since zero would disarm.
+    pub fn set_oneshot(&self, delay: Duration) -> io::Result<()> {
+        self.set(delay.max(Duration::from_nanos(1)), Duration::ZERO)
+    }
+
+    /// Arms the timer to expire every `period`, starting one `period` from
+    /// now.
+    pub fn set_periodic(&self, period: Duration) -> io::Result<()> {
+        let period = period.max(Duration::from_nanos(1));
+        self.set(period, period)
+    }
+
+    /// Disarms the timer.
+    pub fn disarm(&self) -> io::Result<()> {
+        self.set(Duration::ZERO, Duration::ZERO)
+    }
+
+    /// Polls for the number of expirations since the last call.
+    pub fn poll_expired(&self, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let mut count = [0; mem::size_of::<u64>()];
+        loop {
+            let mut guard = ready!(self.file.poll_read_ready(cx))?;
+            match self.file.try_read(&mut count) {
+                Ok(_) => return Poll::Ready(Ok(u64::from_ne_bytes(count))),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl Stream for TimerFd {
+    type Item = io::Result<u64>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_expired(cx).map(Some)
+    }
+}
+
+impl AsRawFd for TimerFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for TimerFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_timerfd() -> io::Result<()> {
+        let mut timer = TimerFd::new(TimerClock::Boottime)?;
+        timer.set_periodic(Duration::from_millis(1))?;
+        assert!(timer.next().await.unwrap()? >= 1);
+        assert!(timer.next().await.unwrap()? >= 1);
+        timer.set_oneshot(Duration::ZERO)?;
+        assert_eq!(timer.next().await.unwrap()?, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Ready-made loops for small command-line tools, such as echoing standard
+//! input or running a program on a pseudoterminal, so that they can be
+//! embedded rather than copied from the examples.
+//!
+//! Requires the `tools` feature.
+//!
+//! ```
+//! # async fn run() -> std::io::Result<()> {
+//! let stdin = tokio_file_unix::File::new_nb(tokio_file_unix::raw_stdin()?)?;
+//! let mut stdout = tokio_file_unix::File::new_nb(tokio_file_unix::raw_stdout()?)?;
+//! tokio_file_unix::tools::echo_lines(stdin, &mut stdout, 64 * 1024).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::File;
+use futures_core::Stream;
+use std::future;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::pin::Pin;
+use std::process::{ExitStatus, Stdio};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+
+/// The character that makes a terminal in canonical mode report EOF.
+const EOF_CHAR: u8 = 0x04;
+
+/// Writes each line of `input` to `output` as soon as it is complete, and
+/// returns the number of lines.
+///
+/// Lines are read as by [`File::lines`](../struct.File.html#method.lines),
+/// so a line longer than `max_len` bytes or not valid UTF-8 fails with
+/// `InvalidData`.  Each line is written with a `\n` terminator, and the
+/// output is flushed after every line so that it can be used
+/// interactively.
+pub async fn echo_lines<F, W>(input: File<F>, output: &mut W, max_len: usize) -> io::Result<u64>
+where
+    F: AsRawFd + Read + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = input.lines(max_len);
+    let mut count = 0;
+    while let Some(line) = future::poll_fn(|cx| Pin::new(&mut lines).poll_next(cx)).await {
+        let mut line = line?;
+        line.push('\n');
+        output.write_all(line.as_bytes()).await?;
+        output.flush().await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Copies everything from `input` to `output` until EOF, then shuts down
+/// `output` so that its reader sees EOF too, and returns the number of
+/// bytes copied.
+///
+/// This is what a process in the middle of a shell pipeline does, with any
+/// pipes, FIFOs, or sockets at either end.
+pub async fn relay<R, W>(input: &mut R, output: &mut W) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let n = tokio::io::copy(input, output).await?;
+    output.shutdown().await?;
+    Ok(n)
+}
+
+/// Treats the `EIO` that the master of a pseudoterminal reports once the
+/// slave is closed as EOF.
+fn eio_as_eof<T: Default>(result: io::Result<T>) -> io::Result<T> {
+    match result {
+        Err(ref e) if e.raw_os_error() == Some(libc::EIO) => Ok(T::default()),
+        r => r,
+    }
+}
+
+/// Runs `command` with a new pseudoterminal as its standard streams, types
+/// `input` into it, copies what it prints to `output`, and returns its exit
+/// status.
+///
+/// Once `input` reaches EOF, an end-of-file character (`^D`) is typed, so
+/// that a program reading the terminal in the usual line-by-line mode sees
+/// EOF too.  Everything the program prints, including the echo of what was
+/// typed, is copied until every process using the terminal has closed it.
+///
+/// The standard streams of `command` are replaced.  This must be called
+/// within the context of a Tokio runtime.
+pub async fn run_in_pty<R, W>(
+    command: &mut Command,
+    input: &mut R,
+    output: &mut W,
+) -> io::Result<ExitStatus>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let (master, slave) = crate::pty::open()?;
+    command
+        .stdin(slave.open_blocking()?)
+        .stdout(slave.open_blocking()?)
+        .stderr(slave.open_blocking()?);
+    let spawned = command.spawn();
+    // the command holds on to the slave until its streams are replaced, and
+    // the master only reports EOF once all copies of the slave are closed
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    drop(slave);
+    let mut child = spawned?;
+
+    let (mut from_pty, mut to_pty) = master.into_inner().into_split();
+    let typing = async {
+        tokio::io::copy(input, &mut to_pty).await?;
+        to_pty.write_all(&[EOF_CHAR]).await
+    };
+    let printing = async {
+        eio_as_eof(tokio::io::copy(&mut from_pty, output).await)?;
+        output.flush().await
+    };
+    tokio::pin!(typing, printing);
+    let mut typed = false;
+    loop {
+        tokio::select! {
+            r = &mut typing, if !typed => {
+                eio_as_eof(r)?;
+                typed = true;
+            }
+            r = &mut printing => {
+                r?;
+                break;
+            }
+        }
+    }
+    child.wait().await
+}
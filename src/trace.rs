@@ -0,0 +1,357 @@
+//! Recording and replaying the traffic of a file with its timing.
+//!
+//! A [`TraceRecorder`](struct.TraceRecorder.html) registered as the
+//! observer of a [`File`](../struct.File.html) writes every completed read
+//! and write to a trace, which [`TraceReader`](struct.TraceReader.html) can
+//! later read back for analysis or [`replay`](fn.replay.html) with the
+//! original timing.
+//!
+//! # Format
+//!
+//! All integers are little-endian.  A trace begins with the eight bytes
+//! `TFUTRACE` followed by a one-byte format version, currently `1`.  Each
+//! record that follows consists of:
+//!
+//! | Size | Field                                                   |
+//! |------|---------------------------------------------------------|
+//! | 1    | direction: `0` for a read, `1` for a write              |
+//! | 4    | descriptor the operation was performed on (`i32`)       |
+//! | 8    | nanoseconds since the recorder was created (`u64`)      |
+//! | 4    | length of the data (`u32`)                              |
+//! | len  | the data                                                |
+//!
+//! A read of length zero marks EOF.  Failed operations are not recorded.
+
+use crate::IoObserver;
+use std::io::{Read, Write};
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, io};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+const MAGIC: &[u8; 8] = b"TFUTRACE";
+const VERSION: u8 = 1;
+
+/// Whether a record was read from or written to the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+/// A single read or write in a trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    direction: Direction,
+    fd: RawFd,
+    timestamp: Duration,
+    data: Vec<u8>,
+}
+
+impl Record {
+    /// Whether the data was read or written.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The descriptor the operation was performed on.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// When the operation completed, relative to the start of the trace.
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// The bytes that were transferred.  Empty for a read at EOF.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the bytes that were transferred.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// An [`IoObserver`](../trait.IoObserver.html) that writes the traffic of
+/// the files it observes to `sink` in the [trace format](index.html#format).
+///
+/// The recorder can be cloned to observe several files into the same trace;
+/// records are told apart by their descriptor.  Errors writing to the sink
+/// are ignored.
+///
+/// ```
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio_file_unix::trace::TraceRecorder;
+///
+/// let (reader, _writer) = tokio_file_unix::pipe()?;
+/// let mut file = reader.into_inner();
+/// file.set_observer(TraceRecorder::new(std::fs::File::create("pipe.trace")?)?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TraceRecorder<W> {
+    sink: Arc<Mutex<W>>,
+    start: Instant,
+}
+
+impl<W> Clone for TraceRecorder<W> {
+    fn clone(&self) -> Self {
+        TraceRecorder {
+            sink: self.sink.clone(),
+            start: self.start,
+        }
+    }
+}
+
+impl<W> fmt::Debug for TraceRecorder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceRecorder")
+            .field("start", &self.start)
+            .finish()
+    }
+}
+
+impl<W: Write> TraceRecorder<W> {
+    /// Writes the header of a new trace to `sink`.  Timestamps are measured
+    /// from now.
+    pub fn new(mut sink: W) -> io::Result<Self> {
+        sink.write_all(MAGIC)?;
+        sink.write_all(&[VERSION])?;
+        Ok(TraceRecorder {
+            sink: Arc::new(Mutex::new(sink)),
+            start: Instant::now(),
+        })
+    }
+
+    /// Flushes the sink.
+    pub fn flush(&self) -> io::Result<()> {
+        self.sink.lock().unwrap().flush()
+    }
+
+    /// Returns the sink, or the recorder itself if it is still shared with
+    /// a clone, such as one registered as the observer of a file.
+    pub fn into_inner(self) -> Result<W, Self> {
+        let start = self.start;
+        match Arc::try_unwrap(self.sink) {
+            Ok(sink) => Ok(sink.into_inner().unwrap()),
+            Err(sink) => Err(TraceRecorder { sink, start }),
+        }
+    }
+
+    fn record(&self, direction: Direction, fd: RawFd, data: &[u8]) -> io::Result<()> {
+        let nanos = self.start.elapsed().as_nanos() as u64;
+        let mut header = [0; 17];
+        header[0] = match direction {
+            Direction::Read => 0,
+            Direction::Write => 1,
+        };
+        header[1..5].copy_from_slice(&fd.to_le_bytes());
+        header[5..13].copy_from_slice(&nanos.to_le_bytes());
+        // split oversized transfers so the length always fits
+        let mut sink = self.sink.lock().unwrap();
+        let mut chunks = data.chunks(u32::MAX as usize);
+        let mut chunk = chunks.next().unwrap_or(&[]);
+        loop {
+            header[13..17].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+            sink.write_all(&header)?;
+            sink.write_all(chunk)?;
+            match chunks.next() {
+                Some(next) => chunk = next,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> IoObserver for TraceRecorder<W> {
+    fn on_read(&self, fd: RawFd, result: Result<&[u8], &io::Error>) {
+        if let Ok(data) = result {
+            let _ = self.record(Direction::Read, fd, data);
+        }
+    }
+
+    fn on_write(&self, fd: RawFd, result: Result<&[u8], &io::Error>) {
+        if let Ok(data) = result {
+            let _ = self.record(Direction::Write, fd, data);
+        }
+    }
+}
+
+/// Reads the records of a trace written by
+/// [`TraceRecorder`](struct.TraceRecorder.html).
+///
+/// Iterating yields each record in turn.  A trace cut off in the middle of
+/// a record fails with `UnexpectedEof`.
+#[derive(Debug)]
+pub struct TraceReader<R> {
+    inner: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Reads and checks the header of the trace in `inner`.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = [0; 9];
+        inner.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a trace"));
+        }
+        if header[8] != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported trace version {}", header[8]),
+            ));
+        }
+        Ok(TraceReader { inner })
+    }
+
+    /// Reads the next record, or returns `None` at the end of the trace.
+    pub fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut header = [0; 17];
+        let n = read_full(&mut self.inner, &mut header)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if n < header.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let direction = match header[0] {
+            0 => Direction::Read,
+            1 => Direction::Write,
+            d => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid direction {}", d),
+                ))
+            }
+        };
+        let mut fd = [0; 4];
+        let mut nanos = [0; 8];
+        let mut len = [0; 4];
+        fd.copy_from_slice(&header[1..5]);
+        nanos.copy_from_slice(&header[5..13]);
+        len.copy_from_slice(&header[13..17]);
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        self.inner.read_exact(&mut data)?;
+        Ok(Some(Record {
+            direction,
+            fd: RawFd::from_le_bytes(fd),
+            timestamp: Duration::from_nanos(u64::from_le_bytes(nanos)),
+            data,
+        }))
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Reads until `buf` is full or EOF, returning the number of bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Writes the data of the records in `trace` that went in `direction` to
+/// `writer`, waiting before each one until the same time has passed as when
+/// it was recorded.  Returns the number of bytes written.
+///
+/// Replaying the reads of a trace into a pipe reproduces what the reader saw,
+/// including how the data was split up over time, which is useful for
+/// reproducing bugs in timing-sensitive protocols.
+pub async fn replay<R, W>(
+    trace: TraceReader<R>,
+    direction: Direction,
+    writer: &mut W,
+) -> io::Result<u64>
+where
+    R: Read,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let start = tokio::time::Instant::now();
+    let mut total = 0;
+    for record in trace {
+        let record = record?;
+        if record.direction != direction || record.data.is_empty() {
+            continue;
+        }
+        tokio::time::sleep_until(start + record.timestamp).await;
+        writer.write_all(&record.data).await?;
+        writer.flush().await?;
+        total += record.data.len() as u64;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replay, Direction, TraceReader, TraceRecorder};
+    use crate::File;
+    use std::io;
+    use std::os::unix::net::UnixStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_trace_round_trip() -> io::Result<()> {
+        let (a, b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let mut b = File::new_nb(b)?;
+        let recorder = TraceRecorder::new(Vec::new())?;
+        a.set_observer(recorder.clone());
+        a.write_all(b"ping").await?;
+        b.write_all(b"pong").await?;
+        let mut buf = [0; 4];
+        a.read_exact(&mut buf).await?;
+        a.clear_observer();
+        let trace = recorder.into_inner().unwrap();
+
+        let records = TraceReader::new(&trace[..])?.collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction(), Direction::Write);
+        assert_eq!(records[0].data(), b"ping");
+        assert_eq!(records[1].direction(), Direction::Read);
+        assert_eq!(records[1].data(), b"pong");
+        assert!(records[0].timestamp() <= records[1].timestamp());
+
+        let mut replayed = Vec::new();
+        let n = replay(
+            TraceReader::new(&trace[..])?,
+            Direction::Read,
+            &mut replayed,
+        )
+        .await?;
+        assert_eq!(n, 4);
+        assert_eq!(replayed, b"pong");
+
+        assert_eq!(
+            TraceReader::new(&trace[..trace.len() - 1])?
+                .last()
+                .unwrap()
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert!(TraceReader::new(&b"NOTATRACE"[..]).is_err());
+        Ok(())
+    }
+}
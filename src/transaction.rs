@@ -0,0 +1,180 @@
+//! Request and response exchanges over one descriptor.
+
+use crate::{File, RecordTooLong};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+impl<F> File<F> {
+    /// Wraps the file for devices that answer each request written to them
+    /// with a response to be read back, such as `/dev/hidraw*` and many
+    /// serial protocols.  Responses longer than `max_len` bytes are
+    /// reported as a [`RecordTooLong`](struct.RecordTooLong.html) error.
+    pub fn into_transaction(self, max_len: usize) -> Transaction<F> {
+        Transaction {
+            inner: self,
+            buf: Vec::new(),
+            max_len,
+        }
+    }
+}
+
+/// Where a response read by [`Transaction::call`](struct.Transaction.html#method.call)
+/// ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Terminator {
+    /// With the given byte, such as `b'\n'` or `b'\r'`, which is included
+    /// in the response.
+    Byte(u8),
+    /// After exactly this many bytes.
+    Len(usize),
+    /// After one read, for devices such as `/dev/hidraw*` that return one
+    /// report per read.
+    Read,
+}
+
+/// A [`File`](struct.File.html) that takes turns writing a request and
+/// reading its response, created by
+/// [`File::into_transaction`](struct.File.html#method.into_transaction).
+///
+/// Data that arrives after the end of a response is kept and taken as the
+/// start of the next one.
+#[derive(Debug)]
+pub struct Transaction<F> {
+    inner: File<F>,
+    /// Data read but not returned in a response yet.
+    buf: Vec<u8>,
+    max_len: usize,
+}
+
+impl<F> Transaction<F> {
+    /// Gets a reference to the file.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the file.
+    ///
+    /// Reading from it directly skips over the data in the buffer.
+    pub fn get_mut(&mut self) -> &mut File<F> {
+        &mut self.inner
+    }
+
+    /// The data read after the end of the last response.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Discards the data read after the end of the last response, for
+    /// example to drop unsolicited output before the next request.
+    pub fn clear_buffer(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Returns the file.  Any data in the buffer is lost.
+    pub fn into_inner(self) -> File<F> {
+        self.inner
+    }
+}
+
+impl<F: AsRawFd + io::Read + io::Write + Unpin> Transaction<F> {
+    /// Writes all of `request`, then reads the response up to
+    /// `terminator`, appends it to `response`, and returns its length.
+    ///
+    /// Fails with `UnexpectedEof` if the file reaches EOF before the
+    /// response is complete.
+    ///
+    /// If the future is dropped before it completes, as in a `select!` that
+    /// times out, the request may have been written in part, but the part
+    /// of the response read so far stays in the buffer.
+    pub async fn call(
+        &mut self,
+        request: &[u8],
+        response: &mut Vec<u8>,
+        terminator: Terminator,
+    ) -> io::Result<usize> {
+        self.inner.write_all(request).await?;
+        self.inner.flush().await?;
+        let mut chunk = vec![0; self.max_len.clamp(1, 4096)];
+        let mut read_once = false;
+        loop {
+            let end = match terminator {
+                Terminator::Byte(byte) => self.buf.iter().position(|&b| b == byte).map(|i| i + 1),
+                Terminator::Len(len) if self.buf.len() >= len => Some(len),
+                Terminator::Len(_) => None,
+                Terminator::Read if read_once || !self.buf.is_empty() => Some(self.buf.len()),
+                Terminator::Read => None,
+            };
+            let len = end.unwrap_or(self.buf.len() + 1);
+            if len > self.max_len {
+                return Err(RecordTooLong {
+                    max_len: self.max_len,
+                }
+                .into());
+            }
+            if let Some(end) = end {
+                response.extend(self.buf.drain(..end));
+                return Ok(end);
+            }
+            let limit = match terminator {
+                Terminator::Read => chunk.len(),
+                // room for one more byte past the limit, to detect it
+                _ => chunk.len().min(self.max_len + 1 - self.buf.len()),
+            };
+            let n = self.inner.read(&mut chunk[..limit]).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+            read_once = true;
+        }
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for Transaction<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_transaction() -> io::Result<()> {
+        let (device, host) = UnixStream::pair()?;
+        let mut host = File::new_nb(host)?.into_transaction(16);
+        let device = tokio::spawn(async move {
+            let mut device = File::new_nb(device)?;
+            let mut command = [0; 4];
+            device.read_exact(&mut command).await?;
+            assert_eq!(&command, b"ATI\r");
+            device.write_all(b"OK\r\x01\x02").await?;
+            device.read_exact(&mut command).await?;
+            device.write_all(b"\x03").await?;
+            device.read_exact(&mut command).await?;
+            device.write_all(&[b'x'; 20]).await?;
+            io::Result::Ok(())
+        });
+        let mut response = Vec::new();
+        assert_eq!(
+            host.call(b"ATI\r", &mut response, Terminator::Byte(b'\r'))
+                .await?,
+            3
+        );
+        assert_eq!(response, b"OK\r");
+        response.clear();
+        host.call(b"GET\r", &mut response, Terminator::Len(3))
+            .await?;
+        assert_eq!(response, b"\x01\x02\x03");
+        let e = host
+            .call(b"BIG\r", &mut response, Terminator::Byte(b'\r'))
+            .await
+            .unwrap_err();
+        assert!(e.get_ref().unwrap().is::<RecordTooLong>());
+        device.await.unwrap()?;
+        Ok(())
+    }
+}
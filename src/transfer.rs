@@ -0,0 +1,189 @@
+//! Streaming regular files in a way that can be resumed.
+
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::{fs, io};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The amount of data read from the file at a time.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// The amount of data transferred between checkpoints.
+const CHECKPOINT_INTERVAL: u64 = 16 * 1024 * 1024;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// How far a [`transfer`](fn.transfer.html) got: the number of bytes
+/// delivered and a hash of them.
+///
+/// The hash is the 64-bit FNV-1a hash of the first `offset` bytes of the
+/// file.  It lets a transfer detect that the file changed before resuming,
+/// and lets the receiver check what it got, but offers no protection
+/// against tampering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    /// The number of bytes delivered.
+    pub offset: u64,
+    /// The hash of the bytes delivered.
+    pub hash: u64,
+}
+
+impl Checkpoint {
+    /// The checkpoint at the start of a file.
+    pub const START: Checkpoint = Checkpoint {
+        offset: 0,
+        hash: FNV_OFFSET_BASIS,
+    };
+}
+
+impl Default for Checkpoint {
+    fn default() -> Self {
+        Checkpoint::START
+    }
+}
+
+/// Hashes the first `len` bytes of `file`.
+fn hash_prefix(file: &fs::File, len: u64) -> io::Result<u64> {
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < len {
+        let want = (len - offset).min(buf.len() as u64) as usize;
+        let n = file.read_at(&mut buf[..want], offset)?;
+        if n == 0 {
+            break;
+        }
+        hash = fnv1a(hash, &buf[..n]);
+        offset += n as u64;
+    }
+    if offset < len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "file is shorter than the checkpoint",
+        ));
+    }
+    Ok(hash)
+}
+
+/// Writes the regular file `file` to `writer` starting at the checkpoint
+/// `from`, calling `on_checkpoint` every 16 MiB and once more at EOF, and
+/// returns the final checkpoint.
+///
+/// A checkpoint is only reported once all data up to it has been written
+/// and the writer flushed, so if the transfer is interrupted, by an error
+/// or by dropping the future, it can be resumed by passing the last
+/// checkpoint reported as `from`.  Pass `Checkpoint::START` to transfer the
+/// whole file.
+///
+/// When resuming, the part of the file before the checkpoint is hashed
+/// again, and if it no longer matches, the transfer fails with
+/// `InvalidData` rather than send the rest of a different file.
+///
+/// The file is read with `pread` on Tokio's blocking thread pool, so it does
+/// not matter where its offset is.
+pub async fn transfer<W, C>(
+    file: &fs::File,
+    writer: &mut W,
+    from: Checkpoint,
+    mut on_checkpoint: C,
+) -> io::Result<Checkpoint>
+where
+    W: AsyncWrite + Unpin,
+    C: FnMut(Checkpoint),
+{
+    // own the descriptor, since reads outlive the future if it is
+    // cancelled
+    let file = Arc::new(unsafe { crate::dupe_file_from_fd(file.as_raw_fd())? });
+    if from.offset > 0 {
+        let (f, len) = (file.clone(), from.offset);
+        let hash = tokio::task::spawn_blocking(move || hash_prefix(&f, len))
+            .await
+            .map_err(io::Error::other)??;
+        if hash != from.hash {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "file has changed since the checkpoint",
+            ));
+        }
+    }
+    let mut checkpoint = from;
+    let mut last = from.offset;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let (f, offset) = (file.clone(), checkpoint.offset);
+        let (r, chunk) = tokio::task::spawn_blocking(move || {
+            let r = f.read_at(&mut buf, offset);
+            (r, buf)
+        })
+        .await
+        .map_err(io::Error::other)?;
+        buf = chunk;
+        let n = match r {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            r => r?,
+        };
+        if n > 0 {
+            writer.write_all(&buf[..n]).await?;
+            checkpoint.offset += n as u64;
+            checkpoint.hash = fnv1a(checkpoint.hash, &buf[..n]);
+        }
+        if n == 0 || checkpoint.offset - last >= CHECKPOINT_INTERVAL {
+            writer.flush().await?;
+            last = checkpoint.offset;
+            on_checkpoint(checkpoint);
+        }
+        if n == 0 {
+            return Ok(checkpoint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::tempfile;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_transfer() -> io::Result<()> {
+        let mut file = tempfile("transfer")?;
+        let data: Vec<u8> = (0..CHUNK_SIZE * 3 / 2).map(|i| i as u8).collect();
+        file.write_all(&data)?;
+
+        let mut whole = Vec::new();
+        let mut checkpoints = Vec::new();
+        let end = transfer(&file, &mut whole, Checkpoint::START, |c| {
+            checkpoints.push(c)
+        })
+        .await?;
+        assert_eq!(whole, data);
+        assert_eq!(checkpoints, vec![end]);
+        assert_eq!(end.offset, data.len() as u64);
+
+        // resume from the middle, as if the first half had been delivered
+        let middle = Checkpoint {
+            offset: 1000,
+            hash: fnv1a(FNV_OFFSET_BASIS, &data[..1000]),
+        };
+        let mut rest = Vec::new();
+        assert_eq!(transfer(&file, &mut rest, middle, |_| {}).await?, end);
+        assert_eq!(rest, &data[1000..]);
+
+        let wrong = Checkpoint { hash: 0, ..middle };
+        let err = transfer(&file, &mut Vec::new(), wrong, |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! TUN and TAP network interfaces (`/dev/net/tun`).
+//!
+//! Each read returns one packet sent out through the interface, and each
+//! write injects one packet into it, so unlike with a byte stream the
+//! buffers passed to [`Tun::recv`](struct.Tun.html#method.recv) and
+//! [`Tun::send`](struct.Tun.html#method.send) must hold whole packets.
+//! Only available on Linux.
+
+use crate::File;
+use std::ffi::CStr;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::{fs, io, mem};
+
+/// The kind of interface to create.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// Carries IP packets (`IFF_TUN`).
+    Tun,
+    /// Carries Ethernet frames (`IFF_TAP`).
+    Tap,
+}
+
+/// Creates the interface `name`, or attaches to it if it already exists and
+/// this process may use it, returning it wrapped for use with Tokio.
+///
+/// The name may contain `%d`, which the kernel replaces with the lowest
+/// number not in use; see [`Tun::name`](struct.Tun.html#method.name) for
+/// the result.  An empty name works like `tun%d` or `tap%d`.  Packets come
+/// without the extra packet information header (`IFF_NO_PI`).  Creating an
+/// interface needs `CAP_NET_ADMIN`, and the interface starts out down.
+///
+/// This must be called within the context of a Tokio runtime.
+///
+/// Implementation detail: opens `/dev/net/tun` and uses `TUNSETIFF`.
+pub fn open(name: &str, mode: Mode) -> io::Result<Tun> {
+    let mut ifreq: libc::ifreq = unsafe { mem::zeroed() };
+    // leave room for the NUL terminator
+    if name.len() >= ifreq.ifr_name.len() || name.contains('\0') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid interface name",
+        ));
+    }
+    for (dst, &src) in ifreq.ifr_name.iter_mut().zip(name.as_bytes()) {
+        *dst = src as libc::c_char;
+    }
+    let flags = match mode {
+        Mode::Tun => libc::IFF_TUN,
+        Mode::Tap => libc::IFF_TAP,
+    };
+    ifreq.ifr_ifru.ifru_flags = (flags | libc::IFF_NO_PI) as libc::c_short;
+
+    let fd = unsafe {
+        libc::open(
+            b"/dev/net/tun\0".as_ptr() as *const libc::c_char,
+            libc::O_RDWR | libc::O_NONBLOCK | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let file = crate::move_fd_above(unsafe { fs::File::from_raw_fd(fd) }, crate::STDIO_END)?;
+    if unsafe { libc::ioctl(file.as_raw_fd(), libc::TUNSETIFF, &mut ifreq) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let name = unsafe { CStr::from_ptr(ifreq.ifr_name.as_ptr()) };
+    Ok(Tun {
+        file: File::raw_new(file)?,
+        name: name.to_string_lossy().into_owned(),
+    })
+}
+
+/// A TUN or TAP interface, created by [`open`](fn.open.html).
+#[derive(Debug)]
+pub struct Tun {
+    file: File<fs::File>,
+    name: String,
+}
+
+impl Tun {
+    /// The name of the interface, with any `%d` filled in.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Receives a packet sent out through the interface, waiting for one if
+    /// need be, and returns its length.  The rest of a packet that does not
+    /// fit into `buf` is lost, so make room for the MTU of the interface
+    /// (plus the Ethernet header, with `Mode::Tap`).
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.file.readable().await?;
+            match self.file.try_read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                r => return r,
+            }
+        }
+    }
+
+    /// Injects the packet in `buf` into the interface, as if it had
+    /// arrived over the network, waiting for room if need be.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.file.writable().await?;
+            match self.file.try_write(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => guard.clear_ready(),
+                r => return r,
+            }
+        }
+    }
+
+    /// Returns the file.
+    pub fn into_inner(self) -> File<fs::File> {
+        self.file
+    }
+}
+
+impl AsRawFd for Tun {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for Tun {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_tun() -> io::Result<()> {
+        let err = open("name-that-is-too-long", Mode::Tun).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let tun = match open("tfu%d", Mode::Tun) {
+            Ok(tun) => tun,
+            // no TUN driver or not allowed to create interfaces
+            Err(ref e)
+                if e.kind() == io::ErrorKind::NotFound
+                    || e.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        };
+        assert!(tun.name().starts_with("tfu"));
+        assert!(!tun.name().contains('%'));
+        // the interface is down, so nothing is sent out
+        let mut buf = [0; 1500];
+        let recv = tokio::time::timeout(Duration::from_millis(20), tun.recv(&mut buf));
+        assert!(recv.await.is_err());
+        Ok(())
+    }
+}
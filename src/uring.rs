@@ -0,0 +1,224 @@
+//! Servicing regular files through io_uring.
+
+use crate::blocking::{Buf, Op};
+use io_uring::{opcode, types, IoUring};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::{fmt, fs, io};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+
+/// The offset that makes a read or write use the file position.
+const CURRENT_POSITION: u64 = u64::MAX;
+
+/// A ring of a single file, which has at most one operation in flight.
+pub(crate) struct Ring {
+    // must be dropped before `ring` so that the descriptor is deregistered
+    // before it gets closed
+    completions: AsyncFd<RawFd>,
+    ring: Mutex<IoUring>,
+}
+
+impl Ring {
+    /// Sets up a ring, failing if the kernel does not support io_uring or
+    /// reads and writes at the file position (Linux 5.6).
+    pub(crate) fn new() -> io::Result<Self> {
+        let ring = IoUring::new(2)?;
+        if !ring.params().is_feature_rw_cur_pos() {
+            return Err(io::Error::from_raw_os_error(libc::ENOSYS));
+        }
+        // the ring is readable whenever there are completions
+        let completions = AsyncFd::with_interest(ring.as_raw_fd(), Interest::READABLE)?;
+        Ok(Ring {
+            completions,
+            ring: Mutex::new(ring),
+        })
+    }
+
+    fn submit(&self, entry: io_uring::squeue::Entry) -> io::Result<()> {
+        let mut ring = self.ring.lock().unwrap();
+        // the entry points into a buffer owned by the `InFlight`, which
+        // waits for the completion before letting go of it
+        unsafe { ring.submission().push(&entry) }
+            .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+        ring.submit()?;
+        Ok(())
+    }
+
+    fn poll_complete(&self, cx: &mut Context<'_>) -> Poll<io::Result<i32>> {
+        loop {
+            if let Some(entry) = self.ring.lock().unwrap().completion().next() {
+                return Poll::Ready(Ok(entry.result()));
+            }
+            let mut guard = ready!(self.completions.poll_read_ready(cx))?;
+            if let Some(entry) = self.ring.lock().unwrap().completion().next() {
+                return Poll::Ready(Ok(entry.result()));
+            }
+            guard.clear_ready();
+        }
+    }
+}
+
+impl fmt::Debug for Ring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ring")
+            .field("fd", self.completions.get_ref())
+            .finish()
+    }
+}
+
+fn result(r: i32) -> io::Result<usize> {
+    if r < 0 {
+        return Err(io::Error::from_raw_os_error(-r));
+    }
+    Ok(r as usize)
+}
+
+#[derive(Debug)]
+enum Kind {
+    Read,
+    Write { written: usize },
+    Sync,
+}
+
+/// An operation submitted to a [`Ring`], which owns the buffer until the
+/// operation completes, even if it is dropped.
+#[derive(Debug)]
+pub(crate) struct InFlight {
+    ring: Arc<Ring>,
+    file: Arc<fs::File>,
+    kind: Kind,
+    buf: Option<Buf>,
+}
+
+impl InFlight {
+    /// Reads up to `len` bytes at the file position into `buf`.
+    pub(crate) fn read(
+        ring: &Arc<Ring>,
+        file: &Arc<fs::File>,
+        mut buf: Buf,
+        len: usize,
+    ) -> io::Result<Self> {
+        buf.data.resize(len, 0);
+        buf.pos = 0;
+        let entry = opcode::Read::new(
+            types::Fd(file.as_raw_fd()),
+            buf.data.as_mut_ptr(),
+            len as u32,
+        )
+        .offset(CURRENT_POSITION)
+        .build();
+        InFlight::submit(ring, file, Kind::Read, buf, entry)
+    }
+
+    /// Writes all of `buf` at the file position.
+    pub(crate) fn write(ring: &Arc<Ring>, file: &Arc<fs::File>, buf: Buf) -> io::Result<Self> {
+        let entry = write_entry(file, &buf.data);
+        InFlight::submit(ring, file, Kind::Write { written: 0 }, buf, entry)
+    }
+
+    /// Makes the data of the file durable, like `fdatasync`.
+    pub(crate) fn sync(ring: &Arc<Ring>, file: &Arc<fs::File>, buf: Buf) -> io::Result<Self> {
+        let entry = opcode::Fsync::new(types::Fd(file.as_raw_fd()))
+            .flags(types::FsyncFlags::DATASYNC)
+            .build();
+        InFlight::submit(ring, file, Kind::Sync, buf, entry)
+    }
+
+    fn submit(
+        ring: &Arc<Ring>,
+        file: &Arc<fs::File>,
+        kind: Kind,
+        buf: Buf,
+        entry: io_uring::squeue::Entry,
+    ) -> io::Result<Self> {
+        ring.submit(entry)?;
+        Ok(InFlight {
+            ring: ring.clone(),
+            file: file.clone(),
+            kind,
+            buf: Some(buf),
+        })
+    }
+
+    pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<(Op, Buf)>> {
+        loop {
+            let r = result(ready!(self.ring.poll_complete(cx))?);
+            let mut buf = self.buf.take().expect("polled after completion");
+            let op = match self.kind {
+                Kind::Read => {
+                    buf.data.truncate(*r.as_ref().unwrap_or(&0));
+                    Op::Read(r)
+                }
+                Kind::Write { ref mut written } => match r {
+                    Ok(0) => Op::Write(Err(io::ErrorKind::WriteZero.into())),
+                    Ok(n) if *written + n < buf.data.len() => {
+                        *written += n;
+                        let entry = write_entry(&self.file, &buf.data[*written..]);
+                        let submitted = self.ring.submit(entry);
+                        self.buf = Some(buf);
+                        submitted?;
+                        continue;
+                    }
+                    r => {
+                        buf.data.clear();
+                        Op::Write(r.map(drop))
+                    }
+                },
+                Kind::Sync => Op::Sync(r.map(drop)),
+            };
+            return Poll::Ready(Ok((op, buf)));
+        }
+    }
+}
+
+fn write_entry(file: &fs::File, data: &[u8]) -> io_uring::squeue::Entry {
+    opcode::Write::new(
+        types::Fd(file.as_raw_fd()),
+        data.as_ptr(),
+        data.len() as u32,
+    )
+    .offset(CURRENT_POSITION)
+    .build()
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.buf.is_some() {
+            // the kernel may still write into the buffer; operations on
+            // regular files are short, so wait for it like a blocking call
+            let mut ring = self.ring.ring.lock().unwrap();
+            let _ = ring.submit_and_wait(1);
+            ring.completion().next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tempfile::tempfile;
+    use crate::File;
+    use std::io;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_uring() -> io::Result<()> {
+        if super::Ring::new().is_err() {
+            // not supported by the kernel or not permitted
+            return Ok(());
+        }
+        let raw = tempfile("uring")?;
+        let mut file = File::new_nb(raw)?;
+        assert!(file.blocking.as_ref().unwrap().has_ring());
+
+        let data: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        file.write_all(&data).await?;
+        file.flush().await?;
+        file.seek(io::SeekFrom::Start(0)).await?;
+        let mut read = Vec::new();
+        file.read_to_end(&mut read).await?;
+        assert_eq!(read, data);
+        Ok(())
+    }
+}
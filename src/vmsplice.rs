@@ -0,0 +1,278 @@
+//! Gather-writes into pipes with `vmsplice(2)`.
+
+use crate::File;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::io::{self, IoSlice};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::task::Poll;
+use std::{future, mem};
+
+/// The most buffers passed to one `vmsplice` or `writev`.
+const MAX_BUFS: usize = 1024;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn vmsplice(fd: RawFd, bufs: &[IoSlice<'_>], gift: bool) -> io::Result<usize> {
+    let mut flags = libc::SPLICE_F_NONBLOCK;
+    if gift {
+        flags |= libc::SPLICE_F_GIFT;
+    }
+    let n = unsafe { libc::vmsplice(fd, bufs.as_ptr() as *const libc::iovec, bufs.len(), flags) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn vmsplice(_fd: RawFd, _bufs: &[IoSlice<'_>], _gift: bool) -> io::Result<usize> {
+    Err(io::Error::from_raw_os_error(libc::EINVAL))
+}
+
+pub(crate) fn writev(fd: RawFd, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+    let len = bufs.len().min(libc::c_int::MAX as usize) as libc::c_int;
+    let n = unsafe { libc::writev(fd, bufs.as_ptr() as *const libc::iovec, len) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+/// The buffers given to the pipe by
+/// [`File::write_gift`](struct.File.html#method.write_gift) that it may
+/// still refer to.
+#[derive(Debug, Default)]
+pub(crate) struct Gifts {
+    /// Each buffer, along with the number of bytes written through
+    /// `write_gift` up to the end of the part of it in the pipe.
+    held: VecDeque<(u64, Bytes)>,
+    /// The number of bytes written through `write_gift` so far.
+    written: u64,
+}
+
+impl Gifts {
+    /// Lets go of the buffers that the reader of the pipe `fd` has consumed.
+    ///
+    /// Whatever is in the pipe was written after anything consumed, so a
+    /// buffer is consumed once fewer bytes are left in the pipe than were
+    /// gifted after it.  Data written by other means only makes this hold on
+    /// to buffers for longer.
+    fn release(&mut self, fd: RawFd) -> io::Result<()> {
+        if self.held.is_empty() {
+            return Ok(());
+        }
+        let mut unread: libc::c_int = 0;
+        if unsafe { libc::ioctl(fd, libc::FIONREAD, &mut unread) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        while let Some(&(end, _)) = self.held.front() {
+            if self.written - end < unread as u64 {
+                break;
+            }
+            self.held.pop_front();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Gifts {
+    fn drop(&mut self) {
+        // the pipe may still refer to them, so they must never be freed
+        for (_, buf) in self.held.drain(..) {
+            mem::forget(buf);
+        }
+    }
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Writes the contents of several buffers into a pipe without first
+    /// copying them into one contiguous buffer, returning the number of
+    /// bytes written.  Like `write`, this may write only part of the data.
+    ///
+    /// On Linux, the pages of the buffers are mapped into the pipe with
+    /// `vmsplice(2)` instead of being copied.  If the file is not a pipe, or
+    /// on other platforms, this falls back to an ordinary `writev`, which
+    /// copies.
+    ///
+    /// # Safety
+    ///
+    /// The pipe refers to the memory of `bufs` rather than a copy of it, so
+    /// the buffers must not be modified or freed until the reader has
+    /// consumed the data; otherwise the reader may observe the
+    /// modifications.
+    pub async unsafe fn write_gather_vmsplice(&self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let fd = self.as_raw_fd();
+        let mut spliceable = true;
+        future::poll_fn(|cx| loop {
+            let mut ready = ready!(self.poll_write_ready(cx))?;
+            let r = if spliceable {
+                vmsplice(fd, bufs, false)
+            } else {
+                writev(fd, bufs)
+            };
+            match r {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => ready.clear_ready(),
+                // vmsplice reports EBADF for files that are not pipes; if the
+                // descriptor is truly invalid, writev will say so again
+                Err(ref e)
+                    if spliceable
+                        && (e.raw_os_error() == Some(libc::EINVAL)
+                            || e.raw_os_error() == Some(libc::EBADF)) =>
+                {
+                    spliceable = false;
+                }
+                r => return Poll::Ready(r),
+            }
+        })
+        .await
+    }
+
+    /// Writes all of `bufs` into a pipe, handing the pages of the buffers
+    /// to the pipe instead of copying them, which saves copying
+    /// multi-megabyte transfers between processes.
+    ///
+    /// Buffers are let go of as later calls find them consumed.  Any that
+    /// the pipe may still refer to when the file is dropped are leaked
+    /// rather than freed, so finish with a call once the reader is done to
+    /// avoid that.  If the file is not a pipe, or on platforms other than
+    /// Linux, this falls back to `writev`, which copies.
+    ///
+    /// Implementation detail: uses `vmsplice(2)` with `SPLICE_F_GIFT`, and
+    /// `FIONREAD` to tell how much of the pipe has been consumed.
+    ///
+    /// # Safety
+    ///
+    /// As with [`write_gather_vmsplice`](#method.write_gather_vmsplice), the
+    /// pipe refers to the memory of `bufs` rather than a copy of it.  A
+    /// buffer is let go of once `FIONREAD` shows that its data has left the
+    /// pipe, so the reader must copy the data out, with `read` and the
+    /// like.  If it moves the data on to another pipe with `splice` or
+    /// `tee`, the pages are still referred to from there after the buffer
+    /// has been freed, and whatever reuses the memory changes the data in
+    /// flight.
+    pub async unsafe fn write_gift(&mut self, bufs: Vec<Bytes>) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        let mut spliceable = true;
+        // the buffer being written and the offset into it
+        let (mut first, mut offset) = (0, 0);
+        while first < bufs.len() {
+            if spliceable {
+                self.gifts.release(fd)?;
+            }
+            let slices: Vec<_> = bufs[first..]
+                .iter()
+                .take(MAX_BUFS)
+                .enumerate()
+                .map(|(i, buf)| IoSlice::new(if i == 0 { &buf[offset..] } else { buf }))
+                .collect();
+            let r = {
+                let mut ready = self.writable().await?;
+                let r = if spliceable {
+                    vmsplice(fd, &slices, true)
+                } else {
+                    writev(fd, &slices)
+                };
+                if matches!(r, Err(ref e) if e.kind() == io::ErrorKind::WouldBlock) {
+                    ready.clear_ready();
+                }
+                r
+            };
+            let mut n = match r {
+                Ok(0) if !slices.iter().all(|slice| slice.is_empty()) => {
+                    return Err(io::ErrorKind::WriteZero.into());
+                }
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(ref e)
+                    if spliceable
+                        && (e.raw_os_error() == Some(libc::EINVAL)
+                            || e.raw_os_error() == Some(libc::EBADF)) =>
+                {
+                    spliceable = false;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            while first < bufs.len() && (n > 0 || bufs[first].len() == offset) {
+                let taken = n.min(bufs[first].len() - offset);
+                n -= taken;
+                offset += taken;
+                if spliceable && taken > 0 {
+                    self.gifts.written += taken as u64;
+                    let held = (self.gifts.written, bufs[first].clone());
+                    self.gifts.held.push_back(held);
+                }
+                if offset == bufs[first].len() {
+                    first += 1;
+                    offset = 0;
+                }
+            }
+        }
+        if spliceable {
+            self.gifts.release(fd)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipe::raw_pipe;
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+
+    #[tokio::test]
+    async fn test_write_gather_vmsplice() -> io::Result<()> {
+        let (mut reader, writer) = raw_pipe(libc::O_NONBLOCK | libc::O_CLOEXEC)?;
+        let writer = File::raw_new(writer)?;
+        let bufs = [IoSlice::new(b"gath"), IoSlice::new(b"ered")];
+        assert_eq!(unsafe { writer.write_gather_vmsplice(&bufs).await? }, 8);
+        drop(writer);
+        let mut received = Vec::new();
+        reader.read_to_end(&mut received)?;
+        assert_eq!(received, b"gathered");
+
+        // falls back to writev for files that are not pipes
+        let (a, mut b) = UnixStream::pair()?;
+        let a = File::new_nb(a)?;
+        assert_eq!(unsafe { a.write_gather_vmsplice(&bufs).await? }, 8);
+        drop(a);
+        received.clear();
+        b.read_to_end(&mut received)?;
+        assert_eq!(received, b"gathered");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_gift() -> io::Result<()> {
+        use tokio::io::AsyncReadExt;
+        let (reader, writer) = crate::pipe()?;
+        let (mut reader, mut writer) = (reader.into_inner(), writer.into_inner());
+        let bufs: Vec<_> = (0..48u8).map(|i| Bytes::from(vec![i; 64 * 1024])).collect();
+        let expected: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        let read = tokio::spawn(async move {
+            let mut received = vec![0; expected.len()];
+            AsyncReadExt::read_exact(&mut reader, &mut received).await?;
+            assert!(received == expected);
+            Ok::<_, io::Error>(())
+        });
+        unsafe { writer.write_gift(bufs).await? };
+        read.await.unwrap()?;
+        // everything has been consumed by now
+        unsafe { writer.write_gift(vec![Bytes::new()]).await? };
+        assert!(writer.gifts.held.is_empty());
+
+        // falls back to writev for files that are not pipes
+        let (a, mut b) = UnixStream::pair()?;
+        let mut a = File::new_nb(a)?;
+        let bufs = vec![Bytes::from_static(b"gif"), Bytes::from_static(b"ted")];
+        unsafe { a.write_gift(bufs).await? };
+        assert!(a.gifts.held.is_empty());
+        drop(a);
+        let mut received = Vec::new();
+        b.read_to_end(&mut received)?;
+        assert_eq!(received, b"gifted");
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Changes to files (`EVFILT_VNODE` of `kqueue(2)`), the counterpart of the
+//! `inotify` module on macOS and the BSDs.
+//!
+//! Only available on macOS, iOS, FreeBSD, DragonFly BSD, NetBSD and
+//! OpenBSD.
+
+use crate::File;
+use futures_core::Stream;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{fs, io, mem, ptr};
+
+flags! {
+    /// Changes to a file, combined with `|`.
+    pub struct Events(u32);
+
+    /// The file was written to (`NOTE_WRITE`).
+    const WRITE = libc::NOTE_WRITE;
+    /// The file grew (`NOTE_EXTEND`).
+    const EXTEND = libc::NOTE_EXTEND;
+    /// The file was unlinked (`NOTE_DELETE`).
+    const DELETE = libc::NOTE_DELETE;
+    /// The file was renamed (`NOTE_RENAME`).
+    const RENAME = libc::NOTE_RENAME;
+    /// The attributes of the file changed (`NOTE_ATTRIB`).
+    const ATTRIB = libc::NOTE_ATTRIB;
+    /// The link count of the file changed (`NOTE_LINK`).
+    const LINK = libc::NOTE_LINK;
+    /// Access to the file was revoked, or its filesystem unmounted
+    /// (`NOTE_REVOKE`).
+    const REVOKE = libc::NOTE_REVOKE;
+}
+
+/// A `Stream` of the changes to a file, created by
+/// [`File::watch`](../struct.File.html#method.watch).
+///
+/// Changes that happen before the stream is polled are coalesced into a
+/// single item.
+#[derive(Debug)]
+pub struct Watch {
+    kqueue: File<fs::File>,
+    // the watch lasts as long as this descriptor stays open
+    _file: fs::File,
+}
+
+impl<F: AsRawFd> File<F> {
+    /// Watches the file for the changes in `events` through a kqueue of its
+    /// own, which is registered with the reactor.
+    ///
+    /// The watch follows the file rather than its path, so it carries on
+    /// after the file is renamed, and holds a duplicate of the descriptor,
+    /// so it does not borrow the `File`.
+    ///
+    /// This must be called within the context of a Tokio runtime.
+    pub fn watch(&self, events: Events) -> io::Result<Watch> {
+        let file = unsafe { crate::dupe_file_from_fd(self.as_raw_fd())? };
+        let kq = unsafe { libc::kqueue() };
+        if kq < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let kqueue = unsafe { fs::File::from_raw_fd(kq) };
+        if unsafe { libc::fcntl(kq, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut change: libc::kevent = unsafe { mem::zeroed() };
+        change.ident = file.as_raw_fd() as _;
+        change.filter = libc::EVFILT_VNODE;
+        // report each change once rather than until it is read
+        change.flags = libc::EV_ADD | libc::EV_CLEAR;
+        change.fflags = events.0;
+        let r = unsafe { libc::kevent(kq, &change, 1, ptr::null_mut(), 0, ptr::null()) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let kqueue = crate::move_fd_above(kqueue, crate::STDIO_END)?;
+        Ok(Watch {
+            kqueue: File::raw_new(kqueue)?,
+            _file: file,
+        })
+    }
+}
+
+impl Stream for Watch {
+    type Item = io::Result<Events>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = ready!(this.kqueue.poll_read_ready(cx))?;
+            let mut event: libc::kevent = unsafe { mem::zeroed() };
+            let timeout = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            let n = unsafe {
+                libc::kevent(
+                    this.kqueue.as_raw_fd(),
+                    ptr::null(),
+                    0,
+                    &mut event,
+                    1,
+                    &timeout,
+                )
+            };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Poll::Ready(Some(Err(e)));
+            }
+            if n == 0 {
+                guard.clear_ready();
+                continue;
+            }
+            if event.flags & libc::EV_ERROR != 0 {
+                let e = io::Error::from_raw_os_error(event.data as i32);
+                return Poll::Ready(Some(Err(e)));
+            }
+            return Poll::Ready(Some(Ok(Events(event.fflags))));
+        }
+    }
+}
+
+impl AsRawFd for Watch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.kqueue.as_raw_fd()
+    }
+}
+
+impl AsFd for Watch {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.kqueue.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempfile::TempPath;
+    use futures::StreamExt;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn test_watch() -> io::Result<()> {
+        let path = TempPath::new("vnode");
+        let file = File::raw_new(fs::File::create(&path)?)?;
+        let mut watch = file.watch(Events::WRITE | Events::EXTEND | Events::DELETE)?;
+        file.get_ref().write_all(b"changed")?;
+        let events = watch.next().await.unwrap()?;
+        assert!(events.contains(Events::WRITE));
+        fs::remove_file(&path)?;
+        let events = watch.next().await.unwrap()?;
+        assert!(events.contains(Events::DELETE));
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+//! Reads and writes of zero bytes.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// What a [`File`](struct.File.html) does when asked to read into an empty
+/// buffer or write no data, set with
+/// [`FileBuilder::zero_length`](struct.FileBuilder.html#method.zero_length).
+///
+/// Either way the operation completes right away, without waiting for the
+/// file to be ready or going through the thread pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZeroLengthMode {
+    /// Pass a zero-length `read` or `write` on to the descriptor, which
+    /// some device drivers treat as a keepalive.  Errors that the kernel
+    /// checks for regardless of length, such as a descriptor not open for
+    /// writing, are reported, but most files, pipes included, report
+    /// success.  If the operation would block, it succeeds.
+    #[default]
+    Probe,
+    /// Succeed without touching the descriptor.
+    NoOp,
+}
+
+impl<F: AsRawFd> File<F> {
+    pub(crate) fn read_empty(&self) -> io::Result<()> {
+        if self.zero_length == ZeroLengthMode::NoOp {
+            return Ok(());
+        }
+        let fd = self.as_raw_fd();
+        let r = unsafe { libc::read(fd, [0u8; 0].as_mut_ptr() as *mut libc::c_void, 0) };
+        would_block_ok(fd, self.identity, r).map(drop)
+    }
+
+    pub(crate) fn write_empty(&self) -> io::Result<usize> {
+        if self.zero_length == ZeroLengthMode::NoOp {
+            return Ok(0);
+        }
+        let fd = self.as_raw_fd();
+        let r = unsafe { libc::write(fd, [0u8; 0].as_ptr() as *const libc::c_void, 0) };
+        would_block_ok(fd, self.identity, r).map(|_| 0)
+    }
+}
+
+fn would_block_ok(
+    fd: RawFd,
+    identity: (libc::dev_t, libc::ino_t),
+    r: libc::ssize_t,
+) -> io::Result<()> {
+    if r < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::WouldBlock {
+            return crate::check_stale(fd, identity, Err(e));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileBuilder;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_zero_length() -> io::Result<()> {
+        let (reader, writer) = crate::pipe()?;
+        // completes even though nothing can be read
+        assert_eq!(reader.into_inner().read(&mut []).await?, 0);
+        assert_eq!(writer.into_inner().write(&[]).await?, 0);
+
+        let mut file = File::new_nb(std::fs::File::open("/dev/null")?)?;
+        assert!(file.write(&[]).await.is_err());
+        let mut file = FileBuilder::new()
+            .zero_length(ZeroLengthMode::NoOp)
+            .build(file.into_inner())?;
+        assert_eq!(file.write(&[]).await?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+//! Zero-copy sends (`MSG_ZEROCOPY`) with completion tracking.
+
+use crate::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use tokio::io::Interest;
+
+#[cfg(not(target_arch = "sparc64"))]
+const SO_ZEROCOPY: libc::c_int = 60;
+#[cfg(target_arch = "sparc64")]
+const SO_ZEROCOPY: libc::c_int = 0x3e;
+const SO_EE_ORIGIN_ZEROCOPY: u8 = 5;
+const SO_EE_CODE_ZEROCOPY_COPIED: u8 = 1;
+
+/// A batch of zero-copy sends that the kernel has finished with, as
+/// reported by
+/// [`ZeroCopySocket::completion`](struct.ZeroCopySocket.html#method.completion).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ZeroCopyCompletion {
+    first: u32,
+    last: u32,
+    copied: bool,
+}
+
+impl ZeroCopyCompletion {
+    /// The sequence number of the first completed send.
+    pub fn first(&self) -> u32 {
+        self.first
+    }
+
+    /// The sequence number of the last completed send (inclusive).
+    pub fn last(&self) -> u32 {
+        self.last
+    }
+
+    /// Whether the given sequence number is part of this batch, taking
+    /// wraparound into account.
+    pub fn contains(&self, id: u32) -> bool {
+        id.wrapping_sub(self.first) <= self.last.wrapping_sub(self.first)
+    }
+
+    /// Whether the kernel fell back to copying the data after all, which
+    /// happens for example on loopback.  If this is common, zero-copy mode
+    /// only adds overhead.
+    pub fn copied(&self) -> bool {
+        self.copied
+    }
+}
+
+/// A wrapped socket that sends with `MSG_ZEROCOPY`.
+///
+/// With zero-copy sends, the kernel transmits directly from the pages of the
+/// caller's buffer instead of copying it first, which pays off for large
+/// sends.  The price is that the buffer must stay untouched until the kernel
+/// reports through the socket's error queue that it is done with it.  Each
+/// successful [`send`](#method.send) is assigned a sequence number, starting
+/// at zero, and [`completion`](#method.completion) yields ranges of
+/// sequence numbers whose buffers may be reused.
+///
+/// Only available on Linux 4.14 and later, for TCP and UDP sockets.
+///
+/// Completions are signaled with `EPOLLERR`, which the Tokio reactor takes
+/// to mean that the write side has closed for good.  Once that has happened,
+/// `send` can no longer wait for write readiness and instead retries after
+/// yielding to the executor whenever the socket buffer is full.
+#[derive(Debug)]
+pub struct ZeroCopySocket<F> {
+    file: File<F>,
+    next_id: u32,
+}
+
+impl<F: AsRawFd> ZeroCopySocket<F> {
+    /// Enables zero-copy mode (`SO_ZEROCOPY`) on a wrapped socket.
+    pub fn new(file: File<F>) -> io::Result<Self> {
+        let on: libc::c_int = 1;
+        let r = unsafe {
+            libc::setsockopt(
+                file.as_raw_fd(),
+                libc::SOL_SOCKET,
+                SO_ZEROCOPY,
+                &on as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ZeroCopySocket { file, next_id: 0 })
+    }
+
+    /// Sends data from `buf` without copying it, returning the sequence
+    /// number assigned to this send and the number of bytes sent.
+    ///
+    /// # Safety
+    ///
+    /// The kernel keeps reading from `buf` after this returns.  The buffer
+    /// must not be modified or freed until a completion covering the
+    /// returned sequence number has been received.
+    pub async unsafe fn send(&mut self, buf: &[u8]) -> io::Result<(u32, usize)> {
+        let fd = self.file.as_raw_fd();
+        loop {
+            let mut guard = self.file.ready(Interest::WRITABLE).await?;
+            let n = libc::send(
+                fd,
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                libc::MSG_ZEROCOPY | libc::MSG_DONTWAIT,
+            );
+            if n >= 0 {
+                let id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                return Ok((id, n as usize));
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e);
+            }
+            if guard.ready().is_writable() {
+                guard.clear_ready();
+            } else {
+                // only the sticky write-closed state is left, see above
+                tokio::task::yield_now().await;
+            }
+        }
+    }
+
+    /// Waits for the next batch of completed sends.
+    pub async fn completion(&self) -> io::Result<ZeroCopyCompletion> {
+        loop {
+            let ee = self.file.recv_err().await?;
+            if ee.origin() == SO_EE_ORIGIN_ZEROCOPY {
+                return Ok(ZeroCopyCompletion {
+                    first: ee.info(),
+                    last: ee.data(),
+                    copied: ee.code() & SO_EE_CODE_ZEROCOPY_COPIED != 0,
+                });
+            }
+            if let Some(e) = ee.to_io_error() {
+                return Err(e);
+            }
+        }
+    }
+
+    /// Gets a reference to the wrapped socket.
+    pub fn get_ref(&self) -> &File<F> {
+        &self.file
+    }
+
+    /// Returns the wrapped socket.  Zero-copy mode stays enabled.
+    pub fn into_inner(self) -> File<F> {
+        self.file
+    }
+}
+
+impl<F: AsRawFd> AsRawFd for ZeroCopySocket<F> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    async fn test_zerocopy() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let client = TcpStream::connect(listener.local_addr()?)?;
+        let (mut server, _) = listener.accept()?;
+        let mut socket = match ZeroCopySocket::new(File::new_nb(client)?) {
+            Ok(socket) => socket,
+            // kernel too old
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOPROTOOPT) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let buf = b"zero-copy".to_vec();
+        let (id, n) = unsafe { socket.send(&buf).await? };
+        assert_eq!((id, n), (0, buf.len()));
+        let completion = socket.completion().await?;
+        assert!(completion.contains(id));
+        let mut received = vec![0; buf.len()];
+        server.read_exact(&mut received)?;
+        assert_eq!(received, buf);
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "tools")]
+
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tokio_file_unix::tools;
+
+#[tokio::test]
+async fn test_echo_lines() -> io::Result<()> {
+    let (reader, mut writer) = tokio_file_unix::pipe()?;
+    writer.write_all(b"first\r\nsecond").await?;
+    drop(writer);
+    let mut output = Vec::new();
+    let count = tools::echo_lines(reader.into_inner(), &mut output, 16).await?;
+    assert_eq!(count, 2);
+    assert_eq!(output, b"first\nsecond\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_relay() -> io::Result<()> {
+    let (mut a_reader, mut a_writer) = tokio_file_unix::pipe()?;
+    let (mut b_reader, mut b_writer) = tokio_file_unix::pipe()?;
+    a_writer.write_all(b"through the middle").await?;
+    drop(a_writer);
+    assert_eq!(tools::relay(&mut a_reader, &mut b_writer).await?, 18);
+    let mut received = String::new();
+    b_reader.read_to_string(&mut received).await?;
+    assert_eq!(received, "through the middle");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_in_pty() -> io::Result<()> {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg("read line; echo \"got $line\"; exit 3");
+    let mut output = Vec::new();
+    let status = tools::run_in_pty(&mut command, &mut &b"typed\n"[..], &mut output).await?;
+    assert_eq!(status.code(), Some(3));
+    let output = String::from_utf8_lossy(&output);
+    assert!(output.contains("got typed"), "output: {:?}", output);
+    Ok(())
+}